@@ -7,13 +7,150 @@ with the Tauri frontend via stdin/stdout.
 
 import sys
 import json
+import hashlib
+import platform
+import queue
+import threading
 import traceback
-from typing import Any, Dict
+from collections import OrderedDict
+from typing import Any, Callable, Dict
 
 from layerforge.image_processor import ImageProcessor
 from layerforge.heightmap import HeightMapGenerator
 from layerforge.mesh_generator import MeshGenerator
 from layerforge.color_planner import ColorPlanner
+from layerforge import __version__
+
+# Optional features reported by ping, so the app can hide what this core
+# doesn't support
+FEATURES = [
+    'mesh_format_obj',
+    'mesh_format_3mf',
+    'hollow_shell',
+    'stl_import',
+    'per_layer_preview',
+    'histogram',
+    'crop',
+    'turntable',
+    'heartbeat',
+]
+
+# Id of the request being handled, so long methods can tag progress frames
+_request_id = None
+
+# Ids of requests the app cancelled, added by the stdin reader while the
+# main thread works
+_cancelled = set()
+
+# Stream frames are written to while serving, stdout otherwise; the lock
+# keeps frames from other threads from interleaving
+_output = None
+_output_lock = threading.Lock()
+
+
+class Cancelled(Exception):
+    """Raised in a method whose request was cancelled, at its next stage."""
+
+
+def write_line(frame_json: str) -> None:
+    """Write one frame as a line and flush it."""
+    with _output_lock:
+        output = _output or sys.stdout
+        output.write(frame_json + '\n')
+        output.flush()
+
+
+def report_progress(progress: float, stage: str) -> None:
+    """Send a progress frame for the request being handled, if it has an id.
+    
+    This is also where a cancelled request stops: long methods report
+    every stage, so a cancel takes effect before the next one starts.
+    """
+    if _request_id is None:
+        return
+    if _request_id in _cancelled:
+        raise Cancelled(f'Request {_request_id} was cancelled')
+    write_line(json.dumps({'id': _request_id, 'progress': progress, 'stage': stage}))
+
+
+# Luminance of the most recently processed sources, so requests that only
+# change the tone settings skip decoding, cropping and downscaling
+LUMINANCE_CACHE_SIZE = 4
+_luminance_cache: 'OrderedDict[tuple, tuple]' = OrderedDict()
+
+
+def load_luminance(params: Dict[str, Any], luminance_method: str) -> tuple:
+    """Luminance of the source image of a process_image request.
+    
+    Cached by the file's contents together with the crop, downscale and
+    luminance method. Tone mapping runs before spike removal and smoothing,
+    so those can't be cached without changing the result.
+    
+    Returns:
+        (luminance array [0, 1], downscale factor applied)
+    """
+    image_path = params.get('image_path')
+    with open(image_path, 'rb') as source:
+        digest = hashlib.sha256(source.read()).hexdigest()
+    key = (
+        digest,
+        json.dumps(params.get('crop'), sort_keys=True),
+        params.get('max_dimension'),
+        luminance_method,
+    )
+    if key in _luminance_cache:
+        _luminance_cache.move_to_end(key)
+        return _luminance_cache[key]
+    
+    processor = ImageProcessor()
+    processor.load_image(image_path)
+    crop = params.get('crop')
+    if crop:
+        processor.crop(crop['x'], crop['y'], crop['width'], crop['height'])
+    scale = 1.0
+    if params.get('max_dimension'):
+        scale = processor.downscale(params['max_dimension'])
+    entry = (processor.to_luminance(method=luminance_method), scale)
+    
+    _luminance_cache[key] = entry
+    while len(_luminance_cache) > LUMINANCE_CACHE_SIZE:
+        _luminance_cache.popitem(last=False)
+    return entry
+
+
+def load_mask(mask_path: str, width: int, height: int):
+    """Areas of a mask image to keep, resampled to width x height.
+    
+    The alpha channel decides where the mask has one, otherwise its
+    brightness. Anything below half is masked out.
+    
+    Returns:
+        Boolean array of shape (height, width), True where kept
+    """
+    import numpy as np
+    from PIL import Image
+    
+    with Image.open(mask_path) as mask:
+        if 'A' in mask.getbands() or 'transparency' in mask.info:
+            channel = mask.convert('RGBA').getchannel('A')
+        else:
+            channel = mask.convert('L')
+        channel = channel.resize((width, height), Image.BILINEAR)
+    return np.asarray(channel) >= 128
+
+
+def load_heightmap(params: Dict[str, Any]) -> HeightMapGenerator:
+    """Load the heightmap a request refers to.
+    
+    Prefers the temp file in heightmap_path and falls back to the inline
+    heightmap_base64 string.
+    """
+    width = params.get('width')
+    height = params.get('height')
+    heightmap_path = params.get('heightmap_path')
+    if heightmap_path:
+        return HeightMapGenerator.from_file(heightmap_path, width, height)
+    return HeightMapGenerator.from_base64(params.get('heightmap_base64'), width, height)
 
 
 def process_image(params: Dict[str, Any]) -> Dict[str, Any]:
@@ -35,36 +172,55 @@ def process_image(params: Dict[str, Any]) -> Dict[str, Any]:
                 transfer_curve: [{x: float, y: float}, ...],
                 dynamic_depth: bool,
                 invert: bool
-            }
+            },
+            crop: {x: int, y: int, width: int, height: int} (optional),
+            max_dimension: int (optional, downscales the longer side, after
+                cropping, to at most this many pixels),
+            include_histogram: bool,
+            histogram_bins: int,
+            mask_path: str (optional, masked-out areas are set to 0 mm so
+                only the base is printed there)
         }
         
     Returns:
         {
             heightmap_base64: str,
             width: int,
-            height: int
+            height: int,
+            actual_min_depth_mm: float,
+            actual_max_depth_mm: float,
+            histogram: [int, ...] (only if include_histogram)
         }
     """
-    image_path = params.get('image_path')
+    import numpy as np
+    
     geometry = params.get('geometry', {})
+    luminance_method = geometry.get(
+        'luminance_method', geometry.get('luminanceMethod', 'rec601')
+    )
+    report_progress(0.0, 'loading')
+    luminance, scale = load_luminance(params, luminance_method)
     
     # Process image
+    report_progress(0.3, 'tone_mapping')
     processor = ImageProcessor()
-    processor.load_image(image_path)
     processed = processor.process(
         gamma=geometry.get('gamma', 1.0),
         contrast=geometry.get('contrast', 1.0),
         offset=geometry.get('offset', 0.0),
-        smoothing=geometry.get('smoothing', 0.0),
+        # Smoothing is in source pixels, so it shrinks with the image
+        smoothing=geometry.get('smoothing', 0.0) * scale,
         spike_removal=geometry.get('spike_removal', 'none'),
-        luminance_method=geometry.get('luminance_method', geometry.get('luminanceMethod', 'rec601')),
+        luminance_method=luminance_method,
         tone_mapping_mode=geometry.get('tone_mapping_mode', geometry.get('toneMappingMode', 'gamma')),
         transfer_curve=geometry.get('transfer_curve', geometry.get('transferCurve', None)),
         dynamic_depth=geometry.get('dynamic_depth', geometry.get('dynamicDepth', False)),
-        invert=geometry.get('invert', False)
+        invert=geometry.get('invert', False),
+        luminance=luminance.copy()
     )
     
     # Generate heightmap
+    report_progress(0.7, 'heightmap')
     heightmap_gen = HeightMapGenerator()
     heightmap = heightmap_gen.generate(
         processed,
@@ -73,11 +229,80 @@ def process_image(params: Dict[str, Any]) -> Dict[str, Any]:
     )
     
     width, height = heightmap_gen.get_dimensions()
+    if params.get('mask_path'):
+        keep = load_mask(params['mask_path'], width, height)
+        heightmap_gen.heightmap = np.where(keep, heightmap, 0.0)
+    stats = heightmap_gen.get_stats()
     
-    return {
+    report_progress(0.9, 'encoding')
+    result = {
         'heightmap_base64': heightmap_gen.to_base64(),
         'width': width,
-        'height': height
+        'height': height,
+        'actual_min_depth_mm': stats['min_mm'],
+        'actual_max_depth_mm': stats['max_mm']
+    }
+    if params.get('include_histogram'):
+        result['histogram'] = heightmap_gen.histogram(params.get('histogram_bins', 256))
+    return result
+
+
+# Longest side of the copy analyze_image examines
+ANALYSIS_DIMENSION = 256
+
+# Channel spread, out of 255, up to which a pixel still counts as gray;
+# JPEG artifacts alone leave a few levels between the channels
+GRAYSCALE_TOLERANCE = 8
+
+DOMINANT_COLOR_COUNT = 5
+
+
+def analyze_image(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Describe a source image quickly, without processing it.
+    
+    Args:
+        params: {image_path: str}
+        
+    Returns:
+        {
+            width: int,
+            height: int (of the full image),
+            is_grayscale: bool,
+            has_alpha: bool,
+            dominant_colors: [str, ...] ('#RRGGBB', most common first),
+            dynamic_range: float (luminance from the 1st to the 99th
+                percentile, as a share of the full range)
+        }
+    """
+    import numpy as np
+    from PIL import Image
+    
+    with Image.open(params.get('image_path')) as image:
+        width, height = image.size
+        has_alpha = 'A' in image.getbands() or 'transparency' in image.info
+        image.thumbnail((ANALYSIS_DIMENSION, ANALYSIS_DIMENSION))
+        rgb = image.convert('RGB')
+    
+    pixels = np.asarray(rgb, dtype=np.int16)
+    spread = pixels.max(axis=-1) - pixels.min(axis=-1)
+    luminance = pixels @ np.array([0.299, 0.587, 0.114])
+    low, high = np.percentile(luminance, [1, 99])
+    
+    palette = rgb.quantize(colors=DOMINANT_COLOR_COUNT)
+    counts = sorted(palette.getcolors(), reverse=True)
+    colors = palette.getpalette()
+    dominant = [
+        '#{:02X}{:02X}{:02X}'.format(*colors[3 * index:3 * index + 3])
+        for _, index in counts
+    ]
+    
+    return {
+        'width': width,
+        'height': height,
+        'is_grayscale': bool(np.percentile(spread, 99) <= GRAYSCALE_TOLERANCE),
+        'has_alpha': has_alpha,
+        'dominant_colors': dominant,
+        'dynamic_range': float((high - low) / 255.0),
     }
 
 
@@ -88,16 +313,27 @@ def generate_mesh(params: Dict[str, Any]) -> Dict[str, Any]:
         params: {
             request: {
                 heightmap_base64: str,
+                heightmap_path: str (optional, preferred over base64),
                 width: int,
                 height: int,
                 geometry: {...},
-                print_settings: {...}
+                print_settings: {...},
+                output_format: 'stl_binary' | 'stl_ascii' | 'obj' | '3mf'
             },
-            output_path: str
+            output_path: str,
+            dry_run: bool (optional, builds the mesh without writing it)
         }
         
     Returns:
-        {path: str}
+        {
+            path: str (None on a dry run),
+            stats: {
+                triangle_count: int,
+                vertex_count: int,
+                bounding_box_mm: {min: [x, y, z], max: [x, y, z]},
+                approx_volume_mm3: float
+            }
+        }
     """
     import numpy as np
     from scipy import ndimage
@@ -105,16 +341,14 @@ def generate_mesh(params: Dict[str, Any]) -> Dict[str, Any]:
     request = params.get('request', {})
     output_path = params.get('output_path')
     
-    heightmap_b64 = request.get('heightmap_base64')
-    width = request.get('width')
-    height = request.get('height')
     print_settings = request.get('print_settings', {})
     
     # Get target resolution (default to image resolution if not specified)
     mesh_resolution = print_settings.get('mesh_resolution')
     
     # Load heightmap
-    heightmap_gen = HeightMapGenerator.from_base64(heightmap_b64, width, height)
+    report_progress(0.0, 'loading')
+    heightmap_gen = load_heightmap(request)
     heightmap = heightmap_gen.heightmap
     
     # Downsample heightmap if resolution is specified and less than original
@@ -137,6 +371,7 @@ def generate_mesh(params: Dict[str, Any]) -> Dict[str, Any]:
     border_width_mm = print_settings.get('border_width_mm', 2) if has_border else 0
     
     # Generate mesh with offset if border is enabled
+    report_progress(0.2, 'triangulating')
     mesh_gen = MeshGenerator()
     mesh = mesh_gen.create_relief_mesh(
         heightmap,
@@ -144,11 +379,13 @@ def generate_mesh(params: Dict[str, Any]) -> Dict[str, Any]:
         height_mm=print_settings.get('height_mm', 100),
         base_layer_mm=print_settings.get('base_layer_mm', 0.16),
         offset_x=border_width_mm,
-        offset_y=border_width_mm
+        offset_y=border_width_mm,
+        shell_thickness_mm=print_settings.get('shell_thickness_mm')
     )
     
     # Add border if enabled
     if has_border:
+        report_progress(0.6, 'border')
         border = mesh_gen.add_border(
             width_mm=print_settings.get('width_mm', 100),
             height_mm=print_settings.get('height_mm', 100),
@@ -159,9 +396,170 @@ def generate_mesh(params: Dict[str, Any]) -> Dict[str, Any]:
         mesh_gen.mesh = mesh_gen.combine_meshes(mesh, border)
     
     # Export
-    saved_path = mesh_gen.export_stl(output_path, binary=True)
+    output_format = request.get('output_format', 'stl_binary')
+    if params.get('dry_run', False):
+        saved_path = None
+    else:
+        report_progress(0.8, 'writing')
+        saved_path = mesh_gen.export(output_path, output_format)
+    
+    stats = mesh_gen.get_stats()
+    volume, _, _ = mesh_gen.mesh.get_mass_properties()
+    
+    return {
+        'path': saved_path,
+        'stats': {
+            'triangle_count': stats['face_count'],
+            'vertex_count': stats['vertex_count'],
+            'bounding_box_mm': {
+                'min': [stats['x_min'], stats['y_min'], stats['z_min']],
+                'max': [stats['x_max'], stats['y_max'], stats['z_max']],
+            },
+            'approx_volume_mm3': abs(float(volume)),
+        }
+    }
+
+
+def import_stl_heightmap(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Sample the top surface of an existing STL into a heightmap.
+    
+    Heights are measured from the bed, so they include any base layer.
+    
+    Args:
+        params: {
+            stl_path: str,
+            samples_x: int,
+            samples_y: int
+        }
+        
+    Returns:
+        {
+            heightmap_base64: str,
+            width: int,
+            height: int,
+            actual_min_depth_mm: float,
+            actual_max_depth_mm: float
+        }
+    """
+    mesh_gen = MeshGenerator()
+    mesh_gen.load_stl(params.get('stl_path'))
+    
+    heightmap_gen = HeightMapGenerator()
+    heightmap_gen.heightmap = mesh_gen.sample_top_surface(
+        params.get('samples_x'),
+        params.get('samples_y')
+    )
+    
+    width, height = heightmap_gen.get_dimensions()
+    stats = heightmap_gen.get_stats()
+    
+    return {
+        'heightmap_base64': heightmap_gen.to_base64(),
+        'width': width,
+        'height': height,
+        'actual_min_depth_mm': stats['min_mm'],
+        'actual_max_depth_mm': stats['max_mm']
+    }
+
+
+def validate_mesh(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Check an STL for defects that keep slicers from reading it as a solid.
+    
+    Args:
+        params: {path: str}
+        
+    Returns:
+        {
+            face_count: int,
+            degenerate_faces: int,
+            non_manifold_edges: int,
+            boundary_edges: int,
+            holes: int,
+            flipped_normals: int
+        }
+    """
+    mesh_gen = MeshGenerator()
+    mesh_gen.load_stl(params.get('path'))
+    return mesh_gen.check_manifold()
+
+
+def measure_td(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Measure a filament's transmission distance from a swatch photo.
+    
+    Args:
+        params: {
+            image_path: str,
+            layer_height_mm: float
+        }
+        
+    Returns:
+        {td_mm: float}
+    """
+    processor = ImageProcessor()
+    processor.load_image(params.get('image_path'))
+    return {'td_mm': processor.measure_td(params.get('layer_height_mm'))}
+
+
+def slicer_color_changes_xml(slicer: str, color_changes: list) -> tuple:
+    """Color change metadata in the layout the slicer reads from a 3MF.
+    
+    Returns:
+        (entry name inside the package, XML text)
+    """
+    def code(attribute: str, change: Dict[str, Any]) -> str:
+        return (
+            f'<{attribute}="{change["print_z_mm"]}" type="0" extruder="1" '
+            f'color="{change["hex_color"]}" extra="" gcode="M600"/>'
+        )
     
-    return {'path': saved_path}
+    header = '<?xml version="1.0" encoding="utf-8"?>\n'
+    mode = '<mode value="SingleExtruder"/>'
+    if slicer == 'prusaslicer':
+        codes = '\n'.join(code('code print_z', change) for change in color_changes)
+        return (
+            'Metadata/Prusa_Slicer_custom_gcode_per_print_z.xml',
+            f'{header}<custom_gcodes_per_print_z>\n{codes}\n{mode}\n'
+            '</custom_gcodes_per_print_z>\n',
+        )
+    if slicer == 'orcaslicer':
+        codes = '\n'.join(code('layer top_z', change) for change in color_changes)
+        return (
+            'Metadata/custom_gcode_per_layer.xml',
+            f'{header}<custom_gcodes_per_layer>\n<plate>\n<plate_info id="1"/>\n'
+            f'{codes}\n{mode}\n</plate>\n</custom_gcodes_per_layer>\n',
+        )
+    raise ValueError(f'Unsupported slicer: {slicer}')
+
+
+def export_slicer_project(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Write a 3MF slicer project with the mesh and its color changes.
+    
+    Args:
+        params: {
+            request: {...} (as for generate_mesh),
+            output_path: str,
+            slicer: 'prusaslicer' | 'orcaslicer',
+            color_changes: [{layer: int, print_z_mm: float, hex_color: str,
+                             filament_name: str}, ...]
+        }
+        
+    Returns:
+        {path: str, stats: {...}} (as for generate_mesh)
+    """
+    import zipfile
+    
+    entry, xml = slicer_color_changes_xml(
+        params.get('slicer', ''), params.get('color_changes', [])
+    )
+    request = {**params.get('request', {}), 'output_format': '3mf'}
+    result = generate_mesh({'request': request, 'output_path': params.get('output_path')})
+    with zipfile.ZipFile(result['path'], 'a', zipfile.ZIP_DEFLATED) as package:
+        package.writestr(entry, xml)
+    return result
+
+
+# Progress frames compute_preview sends while coloring the rows
+PREVIEW_PROGRESS_STEPS = 20
 
 
 def compute_preview(params: Dict[str, Any]) -> Dict[str, Any]:
@@ -170,22 +568,28 @@ def compute_preview(params: Dict[str, Any]) -> Dict[str, Any]:
     Args:
         params: {
             heightmap_base64: str,
+            heightmap_path: str (optional, preferred over base64),
             width: int,
             height: int,
             filaments: [...],
             stops: [...],
-            geometry: {...}
+            geometry: {...},
+            bands: [{filament_id, from_z_mm, to_z_mm}, ...] (optional),
+            isolate_bands: [{filament_id, from_z_mm, to_z_mm}, ...]
+                (optional, only these bands keep their color)
         }
         
     Returns:
-        {preview_base64: str}
+        {
+            preview_base64: str,
+            layers: [str, ...] (one transparent PNG per band, if bands given)
+        }
     """
     import numpy as np
     from PIL import Image
     import base64
     import io
     
-    heightmap_b64 = params.get('heightmap_base64')
     width = params.get('width')
     height = params.get('height')
     filaments = params.get('filaments', [])
@@ -193,7 +597,8 @@ def compute_preview(params: Dict[str, Any]) -> Dict[str, Any]:
     geometry = params.get('geometry', {})
     
     # Load heightmap
-    heightmap_gen = HeightMapGenerator.from_base64(heightmap_b64, width, height)
+    report_progress(0.0, 'loading')
+    heightmap_gen = load_heightmap(params)
     heightmap = heightmap_gen.heightmap
     
     min_depth = geometry.get('min_depth_mm', 0.48)
@@ -212,7 +617,10 @@ def compute_preview(params: Dict[str, Any]) -> Dict[str, Any]:
     # Create output image
     preview = np.zeros((height, width, 3), dtype=np.uint8)
     
+    progress_rows = max(1, height // PREVIEW_PROGRESS_STEPS)
     for y in range(height):
+        if y % progress_rows == 0:
+            report_progress(0.1 + 0.8 * y / height, 'coloring')
         for x in range(width):
             h = heightmap[y, x]
             
@@ -252,13 +660,385 @@ def compute_preview(params: Dict[str, Any]) -> Dict[str, Any]:
                 gray = int(128 + 127 * (h - min_depth) / depth_range) if depth_range > 0 else 128
                 preview[y, x] = [gray, gray, gray]
     
-    # Convert to base64 PNG
-    img = Image.fromarray(preview, mode='RGB')
+    def band_mask(band: Dict[str, Any]) -> np.ndarray:
+        # The lowest band also takes pixels sitting exactly on min_depth
+        lower = band['from_z_mm']
+        above = heightmap >= lower if lower <= min_depth else heightmap > lower
+        return above & (heightmap <= band['to_z_mm'])
+    
+    # Everything outside the isolated bands is shown in gray
+    isolate_bands = params.get('isolate_bands')
+    if isolate_bands:
+        keep = np.zeros((height, width), dtype=bool)
+        for band in isolate_bands:
+            keep |= band_mask(band)
+        gray = (preview @ np.array([0.2126, 0.7152, 0.0722])).astype(np.uint8)
+        preview = np.where(keep[..., None], preview, gray[..., None])
+    
+    def to_data_url(pixels: np.ndarray, mode: str) -> str:
+        buffer = io.BytesIO()
+        Image.fromarray(pixels, mode=mode).save(buffer, format='PNG')
+        return 'data:image/png;base64,' + base64.b64encode(buffer.getvalue()).decode('utf-8')
+    
+    report_progress(0.9, 'encoding')
+    result = {'preview_base64': to_data_url(preview, 'RGB')}
+    
+    # Each band keeps the composite colors of its pixels and is transparent elsewhere
+    bands = params.get('bands')
+    if bands is not None:
+        layers = []
+        for band in bands:
+            mask = band_mask(band)
+            rgba = np.zeros((height, width, 4), dtype=np.uint8)
+            rgba[..., :3] = preview
+            rgba[..., 3] = np.where(mask, 255, 0)
+            layers.append(to_data_url(rgba, 'RGBA'))
+        result['layers'] = layers
+    
+    return result
+
+
+def srgb_to_lab(rgb):
+    """Convert an array of 8-bit sRGB colors to CIELAB under D65."""
+    import numpy as np
+    
+    linear = rgb.astype(np.float64) / 255.0
+    linear = np.where(linear <= 0.04045, linear / 12.92, ((linear + 0.055) / 1.055) ** 2.4)
+    xyz = linear @ np.array([
+        [0.4124, 0.2126, 0.0193],
+        [0.3576, 0.7152, 0.1192],
+        [0.1805, 0.0722, 0.9505],
+    ])
+    xyz = xyz / np.array([0.95047, 1.0, 1.08883])
+    f = np.where(xyz > 216 / 24389, np.cbrt(xyz), (24389 / 27 * xyz + 16) / 116)
+    return np.stack([
+        116 * f[..., 1] - 16,
+        500 * (f[..., 0] - f[..., 1]),
+        200 * (f[..., 1] - f[..., 2]),
+    ], axis=-1)
+
+
+# Delta E shown at full intensity in a difference map
+DIFF_MAP_MAX_DELTA_E = 25.0
+
+
+def preview_diff(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Compare the filament preview against the source image.
+    
+    The source is cropped like the heightmap and resized to its size, then
+    compared pixel by pixel with the CIE76 color difference.
+    
+    Args:
+        params: {
+            ... (as for compute_preview),
+            image_path: str,
+            crop: {x: int, y: int, width: int, height: int} (optional)
+        }
+        
+    Returns:
+        {
+            diff_base64: str (black where the colors match, through red to
+                yellow at DIFF_MAP_MAX_DELTA_E and above),
+            mean_delta_e: float,
+            max_delta_e: float
+        }
+    """
+    import numpy as np
+    from PIL import Image
+    import base64
+    import io
+    
+    preview_params = {
+        key: value for key, value in params.items()
+        if key not in ('bands', 'isolate_bands')
+    }
+    encoded = compute_preview(preview_params)['preview_base64'].split(',')[1]
+    preview = np.array(Image.open(io.BytesIO(base64.b64decode(encoded))).convert('RGB'))
+    height, width = preview.shape[:2]
+    
+    processor = ImageProcessor()
+    processor.load_image(params.get('image_path'))
+    crop = params.get('crop')
+    if crop:
+        processor.crop(crop['x'], crop['y'], crop['width'], crop['height'])
+    source = Image.fromarray((processor.original_image * 255).round().astype(np.uint8), mode='RGB')
+    source = np.array(source.resize((width, height), Image.LANCZOS))
+    
+    delta_e = np.linalg.norm(srgb_to_lab(source) - srgb_to_lab(preview), axis=-1)
+    t = np.clip(delta_e / DIFF_MAP_MAX_DELTA_E, 0.0, 1.0)
+    heat = np.stack([
+        np.minimum(1.0, 2 * t),
+        np.clip(2 * t - 1, 0.0, 1.0),
+        np.zeros_like(t),
+    ], axis=-1)
+    
+    buffer = io.BytesIO()
+    Image.fromarray((heat * 255).astype(np.uint8), mode='RGB').save(buffer, format='PNG')
+    encoded = base64.b64encode(buffer.getvalue()).decode('utf-8')
+    return {
+        'diff_base64': 'data:image/png;base64,' + encoded,
+        'mean_delta_e': float(delta_e.mean()),
+        'max_delta_e': float(delta_e.max()),
+    }
+
+
+# Frame rate of turntable animations, so a full turn lasts frames / fps
+# seconds
+TURNTABLE_FPS = 20
+
+# Tallest relief drawn in a turntable as a share of the longer side of the
+# model, exaggerated so the depth still shows at a thumbnail's size
+TURNTABLE_RELIEF = 0.15
+
+TURNTABLE_BACKGROUND = (32, 32, 32)
+
+
+def render_turntable_frame(colors, z, normals, angle: float, size: tuple):
+    """Draw the relief rotated by angle radians about its vertical axis.
+    
+    Every pixel becomes a point at its height, shaded by its slope against
+    a light above and to the left of the viewer. Where points overlap, the
+    nearest one is drawn.
+    """
+    import numpy as np
+    
+    canvas_w, canvas_h = size
+    height, width = z.shape
+    cos, sin = np.cos(angle), np.sin(angle)
+    ys, xs = np.mgrid[0:height, 0:width]
+    x = xs - (width - 1) / 2.0
+    
+    columns = np.round(x * cos + z * sin + (canvas_w - 1) / 2.0).astype(np.int64)
+    rows = ys + (canvas_h - height) // 2
+    depth = -x * sin + z * cos
+    
+    light = np.array([-0.4, -0.4, 0.82])
+    light /= np.linalg.norm(light)
+    facing = (
+        (normals[..., 0] * cos + normals[..., 2] * sin) * light[0]
+        + normals[..., 1] * light[1]
+        + (normals[..., 2] * cos - normals[..., 0] * sin) * light[2]
+    )
+    shaded = colors * (0.35 + 0.65 * np.clip(facing, 0.0, 1.0))[..., None]
+    
+    # Nearest point first within each canvas pixel, then keep only that one
+    index = (rows * canvas_w + columns).ravel()
+    order = np.lexsort((-depth.ravel(), index))
+    _, first = np.unique(index[order], return_index=True)
+    nearest = order[first]
+    
+    frame = np.empty((canvas_h, canvas_w, 3), dtype=np.uint8)
+    frame[:] = TURNTABLE_BACKGROUND
+    frame.reshape(-1, 3)[index[nearest]] = shaded.reshape(-1, 3)[nearest].astype(np.uint8)
+    return frame
+
+
+def export_turntable(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Write the filament preview turning a full circle as an animation.
+    
+    MP4 is encoded by ffmpeg, which has to be on the PATH. A progress frame
+    is reported for each rendered frame.
+    
+    Args:
+        params: {
+            ... (as for compute_preview),
+            frames: int (per full turn, at TURNTABLE_FPS),
+            format: 'gif' | 'mp4',
+            output_path: str
+        }
+        
+    Returns:
+        {path: str}
+    """
+    import numpy as np
+    from PIL import Image
+    import base64
+    import io
+    import shutil
+    import subprocess
+    
+    frames = int(params.get('frames', 36))
+    output_format = params.get('format', 'gif')
+    output_path = params.get('output_path')
+    if frames < 2:
+        raise ValueError(f'frames must be at least 2, got {frames}')
+    if output_format not in ('gif', 'mp4'):
+        raise ValueError(f'format must be gif or mp4, got {output_format}')
+    ffmpeg = shutil.which('ffmpeg') if output_format == 'mp4' else None
+    if output_format == 'mp4' and ffmpeg is None:
+        raise ValueError('MP4 export needs ffmpeg on the PATH')
+    
+    preview_params = {
+        key: value for key, value in params.items()
+        if key not in ('bands', 'isolate_bands')
+    }
+    encoded = compute_preview(preview_params)['preview_base64'].split(',')[1]
+    colors = np.array(
+        Image.open(io.BytesIO(base64.b64decode(encoded))).convert('RGB'), dtype=np.float64
+    )
+    heightmap = load_heightmap(params).heightmap
+    height, width = heightmap.shape
+    
+    max_depth = params.get('geometry', {}).get('max_depth_mm', 2.24)
+    z = heightmap / max_depth * TURNTABLE_RELIEF * max(width, height)
+    dz_dy, dz_dx = np.gradient(z)
+    normals = np.stack([-dz_dx, -dz_dy, np.ones_like(z)], axis=-1)
+    normals /= np.linalg.norm(normals, axis=-1, keepdims=True)
+    
+    # Wide enough for the relief seen edge-on, and even for the video encoder
+    canvas_w = int(np.ceil(np.hypot(width, float(z.max())))) + 2
+    size = (canvas_w + canvas_w % 2, height + 2 + height % 2)
+    
+    rendered = []
+    for index in range(frames):
+        angle = 2 * np.pi * index / frames
+        rendered.append(render_turntable_frame(colors, z, normals, angle, size))
+        report_progress((index + 1) / frames, 'rendering')
+    
+    report_progress(1.0, 'encoding')
+    if output_format == 'gif':
+        images = [Image.fromarray(frame, mode='RGB') for frame in rendered]
+        images[0].save(
+            output_path,
+            format='GIF',
+            save_all=True,
+            append_images=images[1:],
+            duration=round(1000 / TURNTABLE_FPS),
+            loop=0,
+        )
+    else:
+        result = subprocess.run(
+            [
+                ffmpeg, '-y', '-loglevel', 'error',
+                '-f', 'rawvideo', '-pix_fmt', 'rgb24',
+                '-s', f'{size[0]}x{size[1]}', '-r', str(TURNTABLE_FPS), '-i', '-',
+                '-vcodec', 'libx264', '-pix_fmt', 'yuv420p', output_path,
+            ],
+            input=b''.join(frame.tobytes() for frame in rendered),
+            capture_output=True,
+        )
+        if result.returncode != 0:
+            raise RuntimeError(f'ffmpeg failed: {result.stderr.decode(errors="replace")}')
+    return {'path': output_path}
+
+
+def contact_sheet(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Composite several heightmaps into one labeled grayscale grid.
+    
+    All tiles share one depth scale, so brighter means taller across the
+    whole sheet and presets can be compared directly.
+    
+    Args:
+        params: {
+            tiles: [{heightmap_base64 | heightmap_path, width, height,
+                     label}, ...],
+            tile_size: int (optional, longest side of a tile in px)
+        }
+        
+    Returns:
+        {image_base64: str}
+    """
+    import numpy as np
+    from PIL import Image, ImageDraw, ImageFont
+    import base64
+    import io
+    
+    tiles = params.get('tiles', [])
+    if not tiles:
+        raise ValueError("No tiles to composite")
+    tile_size = int(params.get('tile_size', 256))
+    label_height = 20
+    padding = 8
+    
+    heightmaps = [load_heightmap(tile).heightmap for tile in tiles]
+    low = min(float(np.min(h)) for h in heightmaps)
+    high = max(float(np.max(h)) for h in heightmaps)
+    span = high - low if high > low else 1.0
+    
+    columns = int(np.ceil(np.sqrt(len(tiles))))
+    rows = int(np.ceil(len(tiles) / columns))
+    cell_w = tile_size + padding
+    cell_h = tile_size + label_height + padding
+    sheet = Image.new('RGB', (columns * cell_w + padding, rows * cell_h + padding), 'white')
+    draw = ImageDraw.Draw(sheet)
+    font = ImageFont.load_default()
+    
+    for index, (tile, heightmap) in enumerate(zip(tiles, heightmaps)):
+        gray = (np.clip((heightmap - low) / span, 0.0, 1.0) * 255).astype(np.uint8)
+        image = Image.fromarray(gray, mode='L').convert('RGB')
+        scale = tile_size / max(image.size)
+        image = image.resize(
+            (max(1, round(image.width * scale)), max(1, round(image.height * scale))),
+            Image.LANCZOS,
+        )
+        
+        left = padding + (index % columns) * cell_w
+        top = padding + (index // columns) * cell_h
+        sheet.paste(image, (left, top))
+        draw.text((left, top + tile_size + 4), tile.get('label', ''), fill='black', font=font)
+    
     buffer = io.BytesIO()
-    img.save(buffer, format='PNG')
-    preview_b64 = base64.b64encode(buffer.getvalue()).decode('utf-8')
+    sheet.save(buffer, format='PNG')
+    encoded = base64.b64encode(buffer.getvalue()).decode('utf-8')
+    return {'image_base64': 'data:image/png;base64,' + encoded}
+
+
+def generate_legend(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Render a color key with one row per filament in print order.
+    
+    Each row shows the filament swatch, its name and hex color, and the
+    slicer layer and height at which it starts.
     
-    return {'preview_base64': f'data:image/png;base64,{preview_b64}'}
+    Args:
+        params: {
+            entries: [{layer: int, print_z_mm: float, hex_color: str,
+                       filament_name: str}, ...]
+        }
+        
+    Returns:
+        {image_base64: str}
+    """
+    from PIL import Image, ImageDraw, ImageFont
+    import base64
+    import io
+    
+    entries = params.get('entries', [])
+    if not entries:
+        raise ValueError("No filaments to show in the legend")
+    swatch = 32
+    padding = 8
+    width = 360
+    row_height = swatch + padding
+    
+    legend = Image.new('RGB', (width, len(entries) * row_height + padding), 'white')
+    draw = ImageDraw.Draw(legend)
+    font = ImageFont.load_default()
+    
+    for index, entry in enumerate(entries):
+        top = padding + index * row_height
+        draw.rectangle(
+            (padding, top, padding + swatch - 1, top + swatch - 1),
+            fill=entry['hex_color'],
+            outline='black',
+        )
+        left = 2 * padding + swatch
+        draw.text(
+            (left, top + 2),
+            f"{entry['filament_name']}  {entry['hex_color']}",
+            fill='black',
+            font=font,
+        )
+        draw.text(
+            (left, top + swatch // 2 + 2),
+            f"from layer {entry['layer']} (z={entry['print_z_mm']:.3f} mm)",
+            fill='dimgray',
+            font=font,
+        )
+    
+    buffer = io.BytesIO()
+    legend.save(buffer, format='PNG')
+    encoded = base64.b64encode(buffer.getvalue()).decode('utf-8')
+    return {'image_base64': 'data:image/png;base64,' + encoded}
 
 
 def compute_swaps(params: Dict[str, Any]) -> Dict[str, Any]:
@@ -273,7 +1053,8 @@ def compute_swaps(params: Dict[str, Any]) -> Dict[str, Any]:
         }
         
     Returns:
-        {swaps: [...]}
+        {swaps: [...], skipped: [{filament_id, threshold_z_mm, reason}]}
+        where skipped lists the stops that landed on an earlier stop's layer
     """
     stops = params.get('stops', [])
     layer_height = params.get('layer_height_mm', 0.08)
@@ -292,6 +1073,14 @@ def compute_swaps(params: Dict[str, Any]) -> Dict[str, Any]:
                 'filament_id': s.filament_id
             }
             for s in swaps
+        ],
+        'skipped': [
+            {
+                'filament_id': s.filament_id,
+                'threshold_z_mm': s.threshold_z_mm,
+                'reason': 'same_layer'
+            }
+            for s in planner.skipped
         ]
     }
 
@@ -353,44 +1142,619 @@ def export_plan(params: Dict[str, Any]) -> Dict[str, Any]:
     return {'path': path}
 
 
+def export_instructions(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Typeset an instruction sheet rendered by the app as a PDF.
+    
+    Markdown markers are dropped and each line is drawn as plain text on
+    A4 pages, starting a new page when one fills up.
+    
+    Args:
+        params: {
+            text: str (Markdown),
+            output_path: str
+        }
+        
+    Returns:
+        {path: str}
+    """
+    from PIL import Image, ImageDraw, ImageFont
+    
+    output_path = params.get('output_path')
+    lines = [line.lstrip('#- ').rstrip() for line in params.get('text', '').splitlines()]
+    
+    # A4 at 100 dpi
+    page_size = (827, 1169)
+    margin = 60
+    line_height = 22
+    lines_per_page = (page_size[1] - 2 * margin) // line_height
+    font = ImageFont.load_default()
+    
+    pages = []
+    for start in range(0, max(len(lines), 1), lines_per_page):
+        page = Image.new('RGB', page_size, 'white')
+        draw = ImageDraw.Draw(page)
+        for row, line in enumerate(lines[start:start + lines_per_page]):
+            draw.text((margin, margin + row * line_height), line, fill='black', font=font)
+        pages.append(page)
+    
+    pages[0].save(output_path, 'PDF', resolution=100.0, save_all=True, append_images=pages[1:])
+    return {'path': output_path}
+
+
+def fit_depth_bands(depths, count: int, iterations: int = 25):
+    """Split sorted depths into count bands with 1D k-means.
+    
+    Returns the band boundaries (count - 1 of them, ascending) and the RMS
+    distance in mm from each depth to its band's mean.
+    """
+    import numpy as np
+    
+    centers = np.quantile(depths, (np.arange(count) + 0.5) / count)
+    for _ in range(iterations):
+        bounds = (centers[:-1] + centers[1:]) / 2
+        labels = np.searchsorted(bounds, depths)
+        updated = np.array([
+            depths[labels == i].mean() if np.any(labels == i) else centers[i]
+            for i in range(count)
+        ])
+        if np.allclose(updated, centers):
+            break
+        centers = updated
+    bounds = (centers[:-1] + centers[1:]) / 2
+    labels = np.searchsorted(bounds, depths)
+    error = float(np.sqrt(np.mean((depths - centers[labels]) ** 2)))
+    return [float(b) for b in bounds], error
+
+
+def optimize_swap_count(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Score how closely 1 to max_bands color bands follow the heightmap.
+    
+    Each band count is fitted with 1D k-means over the heightmap's depths.
+    A count's thresholds are its band boundaries followed by max_depth_mm,
+    so the top band runs to the top of the relief.
+    
+    Args:
+        params: {
+            heightmap_base64: str,
+            heightmap_path: str (optional, preferred over base64),
+            width: int,
+            height: int,
+            geometry: {min_depth_mm: float, max_depth_mm: float},
+            max_bands: int
+        }
+        
+    Returns:
+        {scores: [{count: int, error_mm: float, thresholds: [float]}, ...]}
+    """
+    import numpy as np
+    
+    geometry = params.get('geometry', {})
+    max_depth = geometry.get('max_depth_mm', 2.24)
+    max_bands = params.get('max_bands', 1)
+    
+    depths = np.sort(load_heightmap(params).heightmap.astype(np.float64).ravel())
+    # Evenly strided samples of the sorted depths keep their distribution
+    max_samples = 100_000
+    if depths.size > max_samples:
+        depths = depths[::depths.size // max_samples + 1]
+    
+    scores = []
+    for count in range(1, max_bands + 1):
+        bounds, error = fit_depth_bands(depths, count)
+        scores.append({
+            'count': count,
+            'error_mm': error,
+            'thresholds': bounds + [max_depth],
+        })
+    return {'scores': scores}
+
+
+def estimate_usage(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Measure how much of each filament the relief takes.
+    
+    Every pixel is a column as tall as its height in the heightmap, over a
+    cell of the model's width times height divided by the pixel count.
+    Each stop's filament fills the columns from the previous stop's
+    threshold up to its own; the highest stop also fills everything above
+    it. The solid base, and the border when there is one, are reported
+    separately.
+    
+    Args:
+        params: {
+            request: {... (as for generate_mesh)},
+            stops: [{filament_id, threshold_z_mm}, ...]
+        }
+        
+    Returns:
+        {volumes_mm3: {filament_id: float}, base_volume_mm3: float}
+    """
+    import numpy as np
+    
+    request = params.get('request', {})
+    print_settings = request.get('print_settings', {})
+    width_mm = print_settings.get('width_mm', 100)
+    height_mm = print_settings.get('height_mm', 100)
+    base_layer = print_settings.get('base_layer_mm', 0.16)
+    
+    def threshold(stop: Dict[str, Any]) -> float:
+        return stop.get('threshold_z_mm', stop.get('thresholdZMm', 0))
+    
+    heightmap = load_heightmap(request).heightmap.astype(np.float64)
+    cell_mm2 = width_mm * height_mm / heightmap.size
+    stops = sorted(params.get('stops', []), key=threshold)
+    
+    volumes = {}
+    lower = 0.0
+    for index, stop in enumerate(stops):
+        upper = np.inf if index == len(stops) - 1 else max(threshold(stop), lower)
+        filled = np.clip(heightmap, lower, upper) - lower
+        fid = stop.get('filament_id', stop.get('filamentId', ''))
+        volumes[fid] = volumes.get(fid, 0.0) + float(filled.sum()) * cell_mm2
+        lower = upper
+    
+    base_volume = width_mm * height_mm * base_layer
+    if print_settings.get('has_border', False):
+        border = print_settings.get('border_width_mm', 2)
+        ring_mm2 = (width_mm + 2 * border) * (height_mm + 2 * border) - width_mm * height_mm
+        base_volume += ring_mm2 * (base_layer + print_settings.get('border_depth_mm', 2))
+    
+    return {'volumes_mm3': volumes, 'base_volume_mm3': base_volume}
+
+
+def suggest_stops(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Cluster the processed heightmap's depths into num_colors bands.
+    
+    The image is processed as by process_image and its depths fitted with
+    1D k-means like optimize_swap_count does. The thresholds are the band
+    boundaries followed by max_depth_mm, so the top band runs to the top
+    of the relief.
+    
+    Args:
+        params: {
+            image_path: str,
+            geometry: {... (as for process_image)},
+            crop, max_dimension, mask_path: (optional, as for process_image),
+            num_colors: int
+        }
+        
+    Returns:
+        {thresholds: [float, ...] (num_colors of them, ascending)}
+    """
+    import numpy as np
+    
+    geometry = params.get('geometry', {})
+    min_depth = geometry.get('min_depth_mm', 0.48)
+    max_depth = geometry.get('max_depth_mm', 2.24)
+    num_colors = params.get('num_colors', 1)
+    if num_colors < 1:
+        return {'thresholds': []}
+    
+    processed = process_image({
+        'image_path': params.get('image_path'),
+        'geometry': geometry,
+        'crop': params.get('crop'),
+        'max_dimension': params.get('max_dimension'),
+        'mask_path': params.get('mask_path'),
+    })
+    heightmap = HeightMapGenerator.from_base64(
+        processed['heightmap_base64'], processed['width'], processed['height']
+    ).heightmap
+    
+    report_progress(0.95, 'clustering')
+    depths = np.sort(heightmap.astype(np.float64).ravel())
+    max_samples = 100_000
+    if depths.size > max_samples:
+        depths = depths[::depths.size // max_samples + 1]
+    bounds, _ = fit_depth_bands(depths, num_colors)
+    low, high = min(min_depth, max_depth), max(min_depth, max_depth)
+    return {'thresholds': [min(max(b, low), high) for b in bounds] + [max_depth]}
+
+
+def ciede2000(lab1, lab2):
+    """CIEDE2000 color difference between arrays of CIELAB colors."""
+    import numpy as np
+    
+    L1, a1, b1 = np.moveaxis(np.asarray(lab1, dtype=np.float64), -1, 0)
+    L2, a2, b2 = np.moveaxis(np.asarray(lab2, dtype=np.float64), -1, 0)
+    
+    C_mean = (np.hypot(a1, b1) + np.hypot(a2, b2)) / 2
+    G = 0.5 * (1 - np.sqrt(C_mean ** 7 / (C_mean ** 7 + 25.0 ** 7)))
+    a1p, a2p = (1 + G) * a1, (1 + G) * a2
+    C1p, C2p = np.hypot(a1p, b1), np.hypot(a2p, b2)
+    h1p = np.degrees(np.arctan2(b1, a1p)) % 360
+    h2p = np.degrees(np.arctan2(b2, a2p)) % 360
+    
+    dLp = L2 - L1
+    dCp = C2p - C1p
+    dhp = h2p - h1p
+    dhp = np.where(dhp > 180, dhp - 360, np.where(dhp < -180, dhp + 360, dhp))
+    dhp = np.where(C1p * C2p == 0, 0.0, dhp)
+    dHp = 2 * np.sqrt(C1p * C2p) * np.sin(np.radians(dhp / 2))
+    
+    Lp_mean = (L1 + L2) / 2
+    Cp_mean = (C1p + C2p) / 2
+    hp_sum = h1p + h2p
+    hp_mean = np.where(
+        np.abs(h1p - h2p) <= 180, hp_sum / 2,
+        np.where(hp_sum < 360, (hp_sum + 360) / 2, (hp_sum - 360) / 2),
+    )
+    hp_mean = np.where(C1p * C2p == 0, hp_sum, hp_mean)
+    
+    T = (1 - 0.17 * np.cos(np.radians(hp_mean - 30))
+         + 0.24 * np.cos(np.radians(2 * hp_mean))
+         + 0.32 * np.cos(np.radians(3 * hp_mean + 6))
+         - 0.20 * np.cos(np.radians(4 * hp_mean - 63)))
+    d_theta = 30 * np.exp(-(((hp_mean - 275) / 25) ** 2))
+    R_C = 2 * np.sqrt(Cp_mean ** 7 / (Cp_mean ** 7 + 25.0 ** 7))
+    S_L = 1 + 0.015 * (Lp_mean - 50) ** 2 / np.sqrt(20 + (Lp_mean - 50) ** 2)
+    S_C = 1 + 0.045 * Cp_mean
+    S_H = 1 + 0.015 * Cp_mean * T
+    R_T = -np.sin(np.radians(2 * d_theta)) * R_C
+    
+    return np.sqrt(
+        (dLp / S_L) ** 2 + (dCp / S_C) ** 2 + (dHp / S_H) ** 2
+        + R_T * (dCp / S_C) * (dHp / S_H)
+    )
+
+
+def suggest_palette(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Match the image's dominant colors to filaments from a library.
+    
+    The image is quantized to count colors. Starting with the most common,
+    each takes the library filament closest to it by CIEDE2000 that no
+    more common color has taken yet.
+    
+    Args:
+        params: {
+            image_path: str,
+            library: [{id, hex_color, ...}, ...],
+            count: int
+        }
+        
+    Returns:
+        {matches: [{filament_id: str, distance: float}, ...] (most common
+            color first)}
+    """
+    import numpy as np
+    from PIL import Image
+    
+    library = params.get('library', [])
+    count = min(params.get('count', 0), len(library))
+    if count < 1:
+        return {'matches': []}
+    
+    with Image.open(params.get('image_path')) as image:
+        image.thumbnail((ANALYSIS_DIMENSION, ANALYSIS_DIMENSION))
+        palette = image.convert('RGB').quantize(colors=count)
+    colors = palette.getpalette()
+    dominant = np.array([
+        colors[3 * index:3 * index + 3]
+        for _, index in sorted(palette.getcolors(), reverse=True)
+    ], dtype=np.uint8)
+    
+    def filament_rgb(filament: Dict[str, Any]) -> list:
+        hex_color = filament.get('hex_color', filament.get('hexColor', '#FFFFFF')).lstrip('#')
+        return [int(hex_color[i:i + 2], 16) for i in (0, 2, 4)]
+    
+    library_lab = srgb_to_lab(np.array([filament_rgb(f) for f in library], dtype=np.uint8))
+    distances = ciede2000(srgb_to_lab(dominant)[:, None, :], library_lab[None, :, :])
+    
+    matches = []
+    taken = set()
+    for row in distances:
+        for index in np.argsort(row):
+            if index not in taken:
+                taken.add(index)
+                matches.append({
+                    'filament_id': library[index].get('id', ''),
+                    'distance': float(row[index]),
+                })
+                break
+    return {'matches': matches}
+
+
+# Assumptions behind the nominal print time: every layer is printed solid
+# in lines of this width at this speed, with a fixed pause between layers
+PRINT_SPEED_MM_S = 60.0
+PRINT_LINE_WIDTH_MM = 0.42
+LAYER_CHANGE_S = 2.0
+
+
+def estimate_print_time(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Estimate how long the model takes to print at nominal speed.
+    
+    The base, and the border when there is one, cover their whole area on
+    each of their layers. A relief layer covers the pixels that reach into
+    it. Each layer takes its area traced in lines of
+    PRINT_LINE_WIDTH_MM at PRINT_SPEED_MM_S, plus LAYER_CHANGE_S.
+    
+    Args:
+        params: {
+            request: {... (as for generate_mesh)}
+        }
+        
+    Returns:
+        {minutes: float, layers: int}
+    """
+    import numpy as np
+    
+    request = params.get('request', {})
+    print_settings = request.get('print_settings', {})
+    width_mm = print_settings.get('width_mm', 100)
+    height_mm = print_settings.get('height_mm', 100)
+    base_layer = print_settings.get('base_layer_mm', 0.16)
+    layer_height = print_settings.get('layer_height_mm', 0.08)
+    if layer_height <= 0:
+        raise ValueError(f'layer_height_mm must be positive, got {layer_height}')
+    
+    def layer_count(height):
+        # Like the app's layers_for_height, an exact multiple of the layer
+        # height doesn't round up an extra layer
+        layers = np.asarray(height, dtype=np.float64) / layer_height
+        rounded = np.round(layers)
+        return np.where(np.abs(layers - rounded) < 1e-4, rounded, np.ceil(layers)).astype(int)
+    
+    heightmap = load_heightmap(request).heightmap.astype(np.float64)
+    cell_mm2 = width_mm * height_mm / heightmap.size
+    pixel_layers = layer_count(base_layer + heightmap)
+    base_layers = int(layer_count(base_layer))
+    
+    ring_mm2 = 0.0
+    border_layers = 0
+    if print_settings.get('has_border', False):
+        border = print_settings.get('border_width_mm', 2)
+        ring_mm2 = (width_mm + 2 * border) * (height_mm + 2 * border) - width_mm * height_mm
+        border_layers = int(layer_count(base_layer + print_settings.get('border_depth_mm', 2)))
+    
+    layers = max(int(pixel_layers.max(initial=0)), base_layers, border_layers)
+    seconds = 0.0
+    for layer in range(layers):
+        if layer < base_layers:
+            area = width_mm * height_mm
+        else:
+            area = cell_mm2 * np.count_nonzero(pixel_layers > layer)
+        if layer < border_layers:
+            area += ring_mm2
+        seconds += area / (PRINT_LINE_WIDTH_MM * PRINT_SPEED_MM_S) + LAYER_CHANGE_S
+    
+    return {'minutes': float(seconds) / 60.0, 'layers': layers}
+
+
+def export_heightmap(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Write a heightmap as a grayscale PNG for use in other tools.
+    
+    The geometry's depth range is stretched over the full gray range, so
+    black is min_depth_mm and white is max_depth_mm.
+    
+    Args:
+        params: {
+            heightmap_base64: str,
+            heightmap_path: str (optional, preferred over base64),
+            width: int,
+            height: int,
+            geometry: {min_depth_mm: float, max_depth_mm: float},
+            output_path: str,
+            bit_depth: 8 | 16
+        }
+        
+    Returns:
+        {path: str}
+    """
+    import numpy as np
+    from PIL import Image
+    
+    geometry = params.get('geometry', {})
+    output_path = params.get('output_path')
+    bit_depth = params.get('bit_depth', 16)
+    if bit_depth not in (8, 16):
+        raise ValueError(f'bit_depth must be 8 or 16, got {bit_depth}')
+    
+    heightmap = load_heightmap(params).heightmap
+    min_depth = geometry.get('min_depth_mm', 0.48)
+    max_depth = geometry.get('max_depth_mm', 2.24)
+    depth_range = max_depth - min_depth
+    if depth_range > 0:
+        normalized = np.clip((heightmap - min_depth) / depth_range, 0.0, 1.0)
+    else:
+        normalized = np.zeros_like(heightmap)
+    
+    if bit_depth == 16:
+        pixels = np.round(normalized * 65535).astype('<u2')
+        image = Image.fromarray(pixels, mode='I;16')
+    else:
+        pixels = np.round(normalized * 255).astype(np.uint8)
+        image = Image.fromarray(pixels, mode='L')
+    image.save(output_path, format='PNG')
+    return {'path': output_path}
+
+
+def ping(params: Dict[str, Any]) -> Dict[str, Any]:
+    """Report the core's version and what it supports.
+    
+    Returns:
+        {version: str, python_version: str, available_methods: [str],
+         features: [str]}
+    """
+    return {
+        'version': __version__,
+        'python_version': platform.python_version(),
+        'available_methods': list(METHODS.keys()),
+        'features': FEATURES,
+    }
+
+
 # Method dispatcher
 METHODS = {
+    'ping': ping,
     'process_image': process_image,
+    'analyze_image': analyze_image,
+    'import_stl_heightmap': import_stl_heightmap,
+    'validate_mesh': validate_mesh,
+    'measure_td': measure_td,
     'generate_mesh': generate_mesh,
+    'export_slicer_project': export_slicer_project,
     'compute_preview': compute_preview,
+    'preview_diff': preview_diff,
+    'export_turntable': export_turntable,
+    'contact_sheet': contact_sheet,
+    'generate_legend': generate_legend,
     'compute_swaps': compute_swaps,
     'export_plan': export_plan,
+    'export_instructions': export_instructions,
+    'export_heightmap': export_heightmap,
+    'optimize_swap_count': optimize_swap_count,
+    'suggest_stops': suggest_stops,
+    'suggest_palette': suggest_palette,
+    'estimate_usage': estimate_usage,
+    'estimate_print_time': estimate_print_time,
 }
 
 
+def reply(request_id, body: Dict[str, Any]) -> str:
+    """Result or error frame for a request, tagged with its id if it had one."""
+    if request_id is not None:
+        body = {**body, 'id': request_id}
+    return json.dumps(body)
+
+
+def start_heartbeat(request_id, interval_ms: float) -> Callable[[], None]:
+    """Send heartbeat frames for a request from a thread of their own.
+    
+    The first one goes out right away, telling the app the request has
+    started. They keep coming while the method works, even between
+    progress frames, so the app can tell a slow method from a hung core.
+    
+    Returns:
+        Function that stops the heartbeats; none are sent once it returns
+    """
+    stopped = threading.Event()
+    
+    def beat():
+        while True:
+            write_line(json.dumps({'id': request_id, 'heartbeat': True}))
+            if stopped.wait(interval_ms / 1000):
+                return
+    
+    thread = threading.Thread(target=beat, daemon=True)
+    thread.start()
+    
+    def stop():
+        stopped.set()
+        thread.join()
+    return stop
+
+
 def handle_request(request_json: str) -> str:
     """Handle a JSON-RPC style request.
     
     Args:
-        request_json: JSON string with {method, params}
+        request_json: JSON string with {id, method, params}
         
     Returns:
-        JSON string with result or error
+        JSON string with result or error, echoing the request's id. A
+        request with an id and heartbeat_ms also gets heartbeat frames at
+        that interval until its reply is ready.
     """
+    global _request_id
+    request_id = None
+    stop_heartbeat = None
     try:
         request = json.loads(request_json)
+        request_id = request.get('id')
+        _request_id = request_id
         method = request.get('method')
         params = request.get('params', {})
+        if request_id is not None and request.get('heartbeat_ms'):
+            stop_heartbeat = start_heartbeat(request_id, request['heartbeat_ms'])
         
         if method not in METHODS:
-            return json.dumps({
+            return reply(request_id, {
                 'error': f'Unknown method: {method}',
                 'available_methods': list(METHODS.keys())
             })
         
         result = METHODS[method](params)
-        return json.dumps(result)
+        return reply(request_id, result)
         
     except Exception as e:
-        return json.dumps({
+        return reply(request_id, {
             'error': str(e),
+            'error_type': type(e).__name__,
             'traceback': traceback.format_exc()
         })
+    finally:
+        _request_id = None
+        if stop_heartbeat is not None:
+            stop_heartbeat()
+
+
+def peek_request(request_json: str) -> Dict[str, Any]:
+    """The request on a line, or an empty one if it isn't a JSON object."""
+    try:
+        request = json.loads(request_json)
+    except ValueError:
+        return {}
+    return request if isinstance(request, dict) else {}
+
+
+def read_requests(stdin, requests: 'queue.Queue') -> None:
+    """Queue the requests read from stdin for the main thread.
+    
+    Cancels are applied right away, so they reach a request that is
+    already running. Stops at EOF or a shutdown request, queueing None.
+    """
+    try:
+        for line in iter(stdin.readline, ''):
+            request_json = line.strip()
+            if not request_json:
+                continue
+            request = peek_request(request_json)
+            method = request.get('method')
+            if method == 'shutdown':
+                break
+            if method == 'cancel':
+                cancelled_id = (request.get('params') or {}).get('request_id')
+                if cancelled_id is not None:
+                    _cancelled.add(cancelled_id)
+                continue
+            requests.put(request_json)
+    finally:
+        requests.put(None)
+
+
+def serve(stdin=None, stdout=None) -> None:
+    """Answer requests, one JSON object per line, until stdin closes or a
+    shutdown request arrives.
+    
+    The app keeps a core running for each class of call, so every reply
+    is flushed as soon as it is written. Requests run one at a time while
+    a reader thread keeps reading stdin for cancels; the app sends the
+    next one only once the current one is answered.
+    """
+    global _output
+    _output = stdout
+    requests = queue.Queue()
+    reader = threading.Thread(
+        target=read_requests, args=(stdin or sys.stdin, requests), daemon=True
+    )
+    reader.start()
+    try:
+        while True:
+            request_json = requests.get()
+            if request_json is None:
+                return
+            request_id = peek_request(request_json).get('id')
+            if request_id in _cancelled:
+                reply_json = reply(request_id, {
+                    'error': f'Request {request_id} was cancelled',
+                    'error_type': Cancelled.__name__,
+                })
+            else:
+                reply_json = handle_request(request_json)
+            _cancelled.discard(request_id)
+            write_line(reply_json)
+    finally:
+        _output = None
 
 
 def main():
@@ -401,12 +1765,8 @@ def main():
         result = handle_request(request_json)
         print(result)
     else:
-        # Read single request from stdin (for sidecar mode)
-        # Read all input until EOF or first complete JSON
-        request_json = sys.stdin.readline().strip()
-        if request_json:
-            result = handle_request(request_json)
-            print(result)
+        # Sidecar mode: serve requests from stdin
+        serve()
 
 
 if __name__ == '__main__':
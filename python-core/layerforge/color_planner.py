@@ -1,10 +1,25 @@
 """Color planning module for Layerforge."""
 
 import json
+import math
 from typing import List, Dict, Optional
 from dataclasses import dataclass, asdict
 
 
+def layers_for_height(height_mm: float, layer_height_mm: float) -> int:
+    """Number of layers needed to reach a height, the way the app counts them.
+    
+    An exact multiple of the layer height doesn't round up an extra layer
+    because of float noise, so 0.3 mm at 0.1 mm layers is 3 layers.
+    """
+    if height_mm <= 0 or layer_height_mm <= 0:
+        return 0
+    layers = height_mm / layer_height_mm
+    if abs(layers - round(layers)) < 1e-4:
+        return round(layers)
+    return math.ceil(layers)
+
+
 @dataclass
 class Filament:
     """Represents a filament with color and transmission properties."""
@@ -38,6 +53,9 @@ class ColorPlanner:
         self.filaments: List[Filament] = []
         self.stops: List[ColorStop] = []
         self.swaps: List[SwapEntry] = []
+        # Stops the last compute_swaps dropped because an earlier stop
+        # already swaps on their layer
+        self.skipped: List[ColorStop] = []
 
     def set_filaments(self, filaments: List[Dict]) -> None:
         """Set available filaments.
@@ -90,6 +108,7 @@ class ColorPlanner:
             List of SwapEntry objects
         """
         self.swaps = []
+        self.skipped = []
         
         if not self.stops:
             return self.swaps
@@ -97,10 +116,11 @@ class ColorPlanner:
         # Sort stops by threshold
         sorted_stops = sorted(self.stops, key=lambda s: s.threshold_z_mm)
         
-        # Convert each stop's Z threshold to a layer number
+        # Each stop swaps on the first layer starting at or above its
+        # threshold, the layer the app's exports count from
         last_layer = -1
         for stop in sorted_stops:
-            layer = int(stop.threshold_z_mm / layer_height_mm)
+            layer = layers_for_height(stop.threshold_z_mm, layer_height_mm)
             
             # Only add if this is a new layer (avoid duplicates)
             if layer > last_layer:
@@ -111,6 +131,8 @@ class ColorPlanner:
                 )
                 self.swaps.append(swap)
                 last_layer = layer
+            else:
+                self.skipped.append(stop)
         
         return self.swaps
 
@@ -131,6 +131,23 @@ class HeightMapGenerator:
             'std_mm': float(np.std(self.heightmap)),
         }
 
+    def histogram(self, bins: int = 256) -> list:
+        """Count heightmap samples in equal bins over the depth range.
+        
+        Samples outside [min_depth, max_depth] are left out.
+        
+        Args:
+            bins: Number of bins
+            
+        Returns:
+            List of per-bin sample counts, lowest depth first
+        """
+        if self.heightmap is None:
+            raise ValueError("No heightmap generated")
+        
+        counts, _ = np.histogram(self.heightmap, bins=bins, range=(self.min_depth, self.max_depth))
+        return [int(c) for c in counts]
+
     def to_base64(self) -> str:
         """Export heightmap as base64 encoded float32 array.
         
@@ -171,6 +188,34 @@ class HeightMapGenerator:
         
         return instance
 
+    @classmethod
+    def from_file(
+        cls,
+        path: str,
+        width: int,
+        height: int
+    ) -> 'HeightMapGenerator':
+        """Create HeightMapGenerator from a raw float32 heightmap file.
+        
+        The file holds the same bytes that to_base64 encodes.
+        
+        Args:
+            path: Path to the float32 array file
+            width: Image width
+            height: Image height
+            
+        Returns:
+            HeightMapGenerator instance with loaded heightmap
+        """
+        instance = cls()
+        
+        heightmap = np.fromfile(path, dtype=np.float32)
+        instance.heightmap = heightmap.reshape((height, width))
+        instance.min_depth = float(np.min(instance.heightmap))
+        instance.max_depth = float(np.max(instance.heightmap))
+        
+        return instance
+
     def sample_at(self, x: float, y: float) -> float:
         """Sample heightmap value at normalized coordinates.
         
@@ -62,6 +62,57 @@ class ImageProcessor:
         self.original_image = np.array(img, dtype=np.float32) / 255.0
         return self.original_image
 
+    def crop(self, x: int, y: int, width: int, height: int) -> np.ndarray:
+        """Crop the loaded image to a region.
+        
+        Args:
+            x, y: Top-left corner in source pixels
+            width, height: Size of the region in pixels
+            
+        Returns:
+            NumPy array of the cropped image
+        """
+        if self.original_image is None:
+            raise ValueError("No image loaded")
+        
+        img_h, img_w = self.original_image.shape[:2]
+        if width <= 0 or height <= 0 or x < 0 or y < 0 or x + width > img_w or y + height > img_h:
+            raise ValueError(
+                f"Crop {width}x{height} at ({x}, {y}) is outside the {img_w}x{img_h} image"
+            )
+        
+        self.original_image = self.original_image[y:y + height, x:x + width]
+        return self.original_image
+
+    def downscale(self, max_dimension: int) -> float:
+        """Shrink the loaded image so its longer side is at most max_dimension.
+        
+        Each channel is resampled with a Lanczos filter in float precision.
+        Images already small enough are left alone.
+        
+        Args:
+            max_dimension: Largest allowed width or height in pixels
+            
+        Returns:
+            Scale factor applied, 1.0 if the image wasn't resized
+        """
+        if self.original_image is None:
+            raise ValueError("No image loaded")
+        
+        img_h, img_w = self.original_image.shape[:2]
+        longest = max(img_w, img_h)
+        if max_dimension <= 0 or longest <= max_dimension:
+            return 1.0
+        
+        scale = max_dimension / longest
+        size = (max(1, round(img_w * scale)), max(1, round(img_h * scale)))
+        channels = []
+        for c in range(self.original_image.shape[2]):
+            channel = Image.fromarray(self.original_image[:, :, c], mode='F')
+            channels.append(np.array(channel.resize(size, Image.LANCZOS)))
+        self.original_image = np.clip(np.stack(channels, axis=2), 0.0, 1.0).astype(np.float32)
+        return scale
+
     def to_luminance(
         self,
         img: Optional[np.ndarray] = None,
@@ -251,7 +302,8 @@ class ImageProcessor:
         
         Args:
             img: Input image array
-            level: 'none', 'light', 'medium', or 'strong'
+            level: 'none', 'light', 'medium', 'strong', or an odd
+                median kernel size in pixels such as '9'
             
         Returns:
             Filtered image array
@@ -263,7 +315,12 @@ class ImageProcessor:
             'strong': 7
         }
         
-        size = kernel_sizes.get(level, 0)
+        size = kernel_sizes.get(level)
+        if size is None:
+            try:
+                size = int(level)
+            except (TypeError, ValueError):
+                size = 0
         if size <= 0:
             return img
         
@@ -287,7 +344,8 @@ class ImageProcessor:
         tone_mapping_mode: str = "gamma",
         transfer_curve: Optional[list] = None,
         dynamic_depth: bool = False,
-        invert: bool = False
+        invert: bool = False,
+        luminance: Optional[np.ndarray] = None
     ) -> np.ndarray:
         """Run full processing pipeline.
         
@@ -298,15 +356,18 @@ class ImageProcessor:
             smoothing: Gaussian smoothing sigma
             spike_removal: Spike removal level
             invert: Whether to invert the result
+            luminance: Result of to_luminance to start from instead of the
+                loaded image, which then doesn't have to be loaded
             
         Returns:
             Processed grayscale image [0, 1]
         """
-        if self.original_image is None:
+        if luminance is not None:
+            result = luminance
+        elif self.original_image is None:
             raise ValueError("No image loaded")
-        
-        # Convert to luminance
-        result = self.to_luminance(method=luminance_method)
+        else:
+            result = self.to_luminance(method=luminance_method)
 
         # Tone mapping
         if tone_mapping_mode == "gamma":
@@ -333,6 +394,69 @@ class ImageProcessor:
         self.processed_image = result
         return result
 
+    def measure_td(self, layer_height_mm: float, opaque_tolerance: float = 0.05) -> float:
+        """Measure transmission distance from a photo of a backlit swatch.
+        
+        The swatch is a step wedge along the image's longer side, with equally
+        wide steps each one layer thicker than the last. The TD is the
+        thickness of the first step that lets no more light through than the
+        thickest one.
+        
+        Args:
+            layer_height_mm: Thickness each step adds
+            opaque_tolerance: Fraction of the brightness range within which a
+                step still counts as opaque
+            
+        Returns:
+            Transmission distance in mm
+        """
+        luminance = self.to_luminance()
+        img_h, img_w = luminance.shape
+        profile = luminance.mean(axis=0) if img_w >= img_h else luminance.mean(axis=1)
+        
+        steps = self._find_steps(profile)
+        if len(steps) < 3:
+            raise ValueError(
+                f"Could not detect the calibration swatch: found {len(steps)} steps, need at least 3"
+            )
+        # The thinnest step is the brightest, whichever end it is on
+        if steps[0][0] < steps[-1][0]:
+            steps.reverse()
+        
+        levels = [level for level, _ in steps]
+        brightest, darkest = levels[0], min(levels)
+        limit = darkest + opaque_tolerance * (brightest - darkest)
+        # Runs of identical steps merge, so count steps by width
+        step_width = float(np.median([width for _, width in steps]))
+        layers = 0
+        for index, (level, width) in enumerate(steps):
+            run = max(1, int(round(width / step_width)))
+            if level <= limit:
+                if index == len(steps) - 1 and run == 1:
+                    raise ValueError(
+                        "The calibration swatch never becomes opaque; print more steps"
+                    )
+                return (layers + 1) * layer_height_mm
+            layers += run
+        raise ValueError("Could not detect the calibration swatch")
+
+    @staticmethod
+    def _find_steps(profile: np.ndarray, min_fraction: float = 0.03):
+        """Split a brightness profile into flat runs.
+        
+        Returns:
+            List of (mean brightness, width in pixels), ignoring runs shorter
+            than min_fraction of the profile, such as blurred edges
+        """
+        span = float(profile.max() - profile.min())
+        if span < 0.05:
+            return []
+        
+        edges = np.flatnonzero(np.abs(np.diff(profile)) > 0.02 * span) + 1
+        runs = np.split(profile, edges)
+        min_width = max(2, int(len(profile) * min_fraction))
+        return [(float(run.mean()), len(run)) for run in runs if len(run) >= min_width]
+
     def get_dimensions(self) -> Tuple[int, int]:
         """Get dimensions of loaded image.
         
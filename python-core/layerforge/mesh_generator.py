@@ -20,13 +20,14 @@ class MeshGenerator:
         height_mm: float,
         base_layer_mm: float = 0.16,
         offset_x: float = 0.0,
-        offset_y: float = 0.0
+        offset_y: float = 0.0,
+        shell_thickness_mm: Optional[float] = None
     ) -> stl_mesh.Mesh:
         """Create a relief mesh from heightmap.
         
         Creates a solid mesh with:
         - Top surface following the heightmap
-        - Bottom surface at z=0
+        - Bottom surface at z=0, or shell_thickness_mm below the top when hollowed
         - Side walls connecting top and bottom
         
         Args:
@@ -36,6 +37,7 @@ class MeshGenerator:
             base_layer_mm: Thickness of solid base layer
             offset_x: X offset for positioning (for border support)
             offset_y: Y offset for positioning (for border support)
+            shell_thickness_mm: Wall thickness of a hollowed relief, or None for solid
             
         Returns:
             numpy-stl Mesh object
@@ -54,8 +56,11 @@ class MeshGenerator:
         # Top surface Z values (heightmap + base)
         Z_top = heightmap + base_layer_mm
         
-        # Bottom surface at z=0
-        Z_bottom = np.zeros_like(Z_top)
+        # Bottom surface at z=0, or following the top surface for a hollow shell
+        if shell_thickness_mm:
+            Z_bottom = np.maximum(Z_top - shell_thickness_mm, 0.0)
+        else:
+            Z_bottom = np.zeros_like(Z_top)
         
         # Generate triangles for top surface
         top_triangles = self._generate_surface_triangles(X, Y, Z_top, flip_normals=False)
@@ -327,6 +332,324 @@ class MeshGenerator:
         
         return path
 
+    def _indexed_faces(self) -> Tuple[np.ndarray, np.ndarray]:
+        """Merge shared triangle corners into a vertex list.
+        
+        Returns:
+            Tuple of (vertices, faces) where faces index into vertices
+        """
+        corners = self.mesh.vectors.reshape(-1, 3)
+        vertices, inverse = np.unique(corners, axis=0, return_inverse=True)
+        return vertices, inverse.reshape(-1, 3)
+
+    def export_obj(self, path: str) -> str:
+        """Export mesh to Wavefront OBJ file.
+        
+        Args:
+            path: Output file path
+            
+        Returns:
+            Path to saved file
+        """
+        if self.mesh is None:
+            raise ValueError("No mesh generated")
+        
+        vertices, faces = self._indexed_faces()
+        with open(path, 'w') as f:
+            f.write('# Layerforge lithophane, units: mm\n')
+            for x, y, z in vertices:
+                f.write(f'v {x:.6f} {y:.6f} {z:.6f}\n')
+            for a, b, c in faces:
+                f.write(f'f {a + 1} {b + 1} {c + 1}\n')
+        
+        return path
+
+    def export_3mf(self, path: str) -> str:
+        """Export mesh to a 3MF package (units in millimeters).
+        
+        Args:
+            path: Output file path
+            
+        Returns:
+            Path to saved file
+        """
+        import zipfile
+        
+        if self.mesh is None:
+            raise ValueError("No mesh generated")
+        
+        vertices, faces = self._indexed_faces()
+        vertex_xml = '\n'.join(
+            f'<vertex x="{x:.6f}" y="{y:.6f}" z="{z:.6f}"/>' for x, y, z in vertices
+        )
+        triangle_xml = '\n'.join(
+            f'<triangle v1="{a}" v2="{b}" v3="{c}"/>' for a, b, c in faces
+        )
+        model = (
+            '<?xml version="1.0" encoding="UTF-8"?>\n'
+            '<model unit="millimeter" xml:lang="en-US" '
+            'xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">\n'
+            '<resources>\n<object id="1" type="model">\n<mesh>\n'
+            f'<vertices>\n{vertex_xml}\n</vertices>\n'
+            f'<triangles>\n{triangle_xml}\n</triangles>\n'
+            '</mesh>\n</object>\n</resources>\n'
+            '<build>\n<item objectid="1"/>\n</build>\n'
+            '</model>\n'
+        )
+        content_types = (
+            '<?xml version="1.0" encoding="UTF-8"?>\n'
+            '<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">\n'
+            '<Default Extension="rels" '
+            'ContentType="application/vnd.openxmlformats-package.relationships+xml"/>\n'
+            '<Default Extension="model" '
+            'ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>\n'
+            '</Types>\n'
+        )
+        rels = (
+            '<?xml version="1.0" encoding="UTF-8"?>\n'
+            '<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">\n'
+            '<Relationship Target="/3D/3dmodel.model" Id="rel0" '
+            'Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>\n'
+            '</Relationships>\n'
+        )
+        
+        with zipfile.ZipFile(path, 'w', zipfile.ZIP_DEFLATED) as package:
+            package.writestr('[Content_Types].xml', content_types)
+            package.writestr('_rels/.rels', rels)
+            package.writestr('3D/3dmodel.model', model)
+        
+        return path
+
+    def export(self, path: str, output_format: str = 'stl_binary') -> str:
+        """Export mesh in the requested format.
+        
+        Args:
+            path: Output file path
+            output_format: 'stl_binary' | 'stl_ascii' | 'obj' | '3mf'
+            
+        Returns:
+            Path to saved file
+        """
+        if output_format == 'stl_binary':
+            return self.export_stl(path, binary=True)
+        if output_format == 'stl_ascii':
+            return self.export_stl(path, binary=False)
+        if output_format == 'obj':
+            return self.export_obj(path)
+        if output_format == '3mf':
+            return self.export_3mf(path)
+        raise ValueError(f"Unknown output format: {output_format}")
+
+    def load_stl(self, path: str) -> stl_mesh.Mesh:
+        """Load an existing STL file as the current mesh.
+        
+        Args:
+            path: Path to a binary or ASCII STL file
+            
+        Returns:
+            Loaded Mesh object
+        """
+        self.mesh = stl_mesh.Mesh.from_file(path)
+        return self.mesh
+
+    def sample_top_surface(
+        self,
+        samples_x: int,
+        samples_y: int,
+        max_overlap: float = 0.01
+    ) -> np.ndarray:
+        """Sample the top surface of the mesh into a heightmap.
+        
+        Each sample drops a vertical ray at a cell center of a grid spanning
+        the mesh footprint and keeps the highest upward-facing triangle it
+        hits. The result is flipped back to the orientation create_relief_mesh
+        expects, so generated reliefs round-trip.
+        
+        Args:
+            samples_x: Number of samples along X
+            samples_y: Number of samples along Y
+            max_overlap: Fraction of samples allowed to hit more than one
+                distinct upward-facing surface before the mesh is rejected
+            
+        Returns:
+            (samples_y, samples_x) float32 array of heights above the
+            lowest point of the mesh, in mm. Samples outside the footprint
+            are 0.
+            
+        Raises:
+            ValueError: If there is no mesh, it has no footprint, or it is
+                not a heightfield (overhangs, stacked parts)
+        """
+        if self.mesh is None or len(self.mesh.vectors) == 0:
+            raise ValueError("No mesh generated")
+        
+        tris = self.mesh.vectors.astype(np.float64)
+        x_min, x_max = tris[:, :, 0].min(), tris[:, :, 0].max()
+        y_min, y_max = tris[:, :, 1].min(), tris[:, :, 1].max()
+        z_floor = tris[:, :, 2].min()
+        if x_max <= x_min or y_max <= y_min:
+            raise ValueError("Mesh has no footprint to sample")
+        
+        dx = (x_max - x_min) / samples_x
+        dy = (y_max - y_min) / samples_y
+        xs = x_min + (np.arange(samples_x) + 0.5) * dx
+        ys = y_min + (np.arange(samples_y) + 0.5) * dy
+        
+        top = np.full((samples_y, samples_x), -np.inf)
+        lowest = np.full((samples_y, samples_x), np.inf)
+        hits = np.zeros((samples_y, samples_x), dtype=np.int32)
+        
+        # Twice the signed XY area; positive for upward-facing triangles
+        a, b, c = tris[:, 0], tris[:, 1], tris[:, 2]
+        areas = (b[:, 0] - a[:, 0]) * (c[:, 1] - a[:, 1]) - (b[:, 1] - a[:, 1]) * (c[:, 0] - a[:, 0])
+        
+        for tri, area in zip(tris, areas):
+            if area <= 1e-12:
+                continue
+            (ax, ay, az), (bx, by, bz), (cx, cy, cz) = tri
+            j0 = max(int(np.ceil((min(ax, bx, cx) - x_min) / dx - 0.5)), 0)
+            j1 = min(int(np.floor((max(ax, bx, cx) - x_min) / dx - 0.5)), samples_x - 1)
+            i0 = max(int(np.ceil((min(ay, by, cy) - y_min) / dy - 0.5)), 0)
+            i1 = min(int(np.floor((max(ay, by, cy) - y_min) / dy - 0.5)), samples_y - 1)
+            if j0 > j1 or i0 > i1:
+                continue
+            
+            px, py = np.meshgrid(xs[j0:j1 + 1], ys[i0:i1 + 1])
+            w0 = ((bx - px) * (cy - py) - (by - py) * (cx - px)) / area
+            w1 = ((cx - px) * (ay - py) - (cy - py) * (ax - px)) / area
+            w2 = 1.0 - w0 - w1
+            inside = (w0 >= 0) & (w1 >= 0) & (w2 >= 0)
+            z = w0 * az + w1 * bz + w2 * cz
+            
+            window = (slice(i0, i1 + 1), slice(j0, j1 + 1))
+            top[window] = np.where(inside, np.maximum(top[window], z), top[window])
+            lowest[window] = np.where(inside, np.minimum(lowest[window], z), lowest[window])
+            hits[window] += inside
+        
+        covered = hits > 0
+        if not covered.any():
+            raise ValueError("Mesh has no upward-facing surface to sample")
+        
+        # Samples on a shared edge hit two triangles at the same height;
+        # only distinct heights mean a second surface
+        tolerance = 1e-6 * max(1.0, float(top[covered].max() - z_floor))
+        stacked = covered & (top - lowest > tolerance)
+        overlap = stacked.sum() / covered.sum()
+        if overlap > max_overlap:
+            raise ValueError(
+                f"Mesh is not a heightfield: {overlap:.0%} of samples lie under "
+                "more than one upward-facing surface"
+            )
+        
+        heightmap = np.where(covered, top - z_floor, 0.0)
+        return np.fliplr(heightmap).astype(np.float32)
+
+    def check_manifold(self, tolerance: float = 1e-5) -> dict:
+        """Count the defects that keep the mesh from being a closed solid.
+        
+        STL stores the corners of every triangle separately, so corners
+        closer than `tolerance` are welded into shared vertices first.
+        Orientation is compared across every edge shared by exactly two
+        faces; within each connected part the faces wound against the
+        majority count as flipped.
+        
+        Args:
+            tolerance: Distance in mm under which two corners are the same vertex
+            
+        Returns:
+            Dictionary with face count, degenerate faces, non-manifold edges
+            (shared by more than two faces), boundary edges, holes (loops of
+            boundary edges) and flipped normals
+        """
+        if self.mesh is None:
+            return {}
+        from scipy.sparse import coo_matrix
+        from scipy.sparse.csgraph import breadth_first_order, connected_components
+        
+        corners = np.round(self.mesh.vectors.reshape(-1, 3) / tolerance).astype(np.int64)
+        _, vertex_ids = np.unique(corners, axis=0, return_inverse=True)
+        vertex_count = int(vertex_ids.max()) + 1 if len(vertex_ids) else 0
+        faces = vertex_ids.reshape(-1, 3)
+        degenerate = (
+            (faces[:, 0] == faces[:, 1])
+            | (faces[:, 1] == faces[:, 2])
+            | (faces[:, 2] == faces[:, 0])
+        )
+        faces = faces[~degenerate]
+        face_count = len(faces)
+        if face_count == 0:
+            return {
+                'face_count': len(self.mesh.vectors),
+                'degenerate_faces': int(degenerate.sum()),
+                'non_manifold_edges': 0,
+                'boundary_edges': 0,
+                'holes': 0,
+                'flipped_normals': 0,
+            }
+        
+        # Directed edges in winding order, three per face
+        starts = faces.reshape(-1)
+        ends = np.roll(faces, -1, axis=1).reshape(-1)
+        owners = np.repeat(np.arange(face_count), 3)
+        keys = np.stack([np.minimum(starts, ends), np.maximum(starts, ends)], axis=1)
+        _, edge_ids, uses = np.unique(keys, axis=0, return_inverse=True, return_counts=True)
+        edge_ids = edge_ids.reshape(-1)
+        
+        boundary = uses[edge_ids] == 1
+        boundary_graph = coo_matrix(
+            (np.ones(boundary.sum()), (starts[boundary], ends[boundary])),
+            shape=(vertex_count, vertex_count)
+        )
+        _, vertex_parts = connected_components(boundary_graph, directed=False)
+        holes = len(np.unique(vertex_parts[starts[boundary]]))
+        
+        # Pair up the two uses of each manifold edge. Two faces wound the same
+        # way traverse their shared edge in opposite directions.
+        order = np.argsort(edge_ids, kind='stable')
+        shared = order[uses[edge_ids[order]] == 2].reshape(-1, 2)
+        first, second = shared[:, 0], shared[:, 1]
+        pairs, index = np.unique(
+            np.stack([owners[first], owners[second]], axis=1), axis=0, return_index=True
+        )
+        same_direction = starts[first[index]] == starts[second[index]]
+        
+        # Walk a spanning forest from an extra root node joined to one face of
+        # each part, XOR-ing the winding flips along the way
+        adjacency = coo_matrix(
+            (np.ones(len(pairs)), (pairs[:, 0], pairs[:, 1])),
+            shape=(face_count, face_count)
+        )
+        part_count, face_parts = connected_components(adjacency, directed=False)
+        _, part_roots = np.unique(face_parts, return_index=True)
+        root = face_count
+        rows = np.concatenate([pairs[:, 0], np.full(part_count, root)])
+        cols = np.concatenate([pairs[:, 1], part_roots])
+        flips = np.concatenate([same_direction.astype(float) + 1, np.ones(part_count)])
+        forest = coo_matrix((flips, (rows, cols)), shape=(root + 1, root + 1)).tocsr()
+        forest = forest.maximum(forest.T)
+        _, parents = breadth_first_order(forest, root, directed=False)
+        parents[root] = root
+        flipped = np.zeros(root + 1, dtype=bool)
+        children = np.arange(face_count)
+        flipped[children] = np.asarray(forest[parents[children], children]).reshape(-1) == 2
+        while np.any(parents[:face_count] != root):
+            flipped[:face_count] ^= flipped[parents[:face_count]]
+            parents[:face_count] = parents[parents[:face_count]]
+        against_root = np.bincount(
+            face_parts, weights=flipped[:face_count].astype(float), minlength=part_count
+        )
+        part_sizes = np.bincount(face_parts, minlength=part_count)
+        flipped_normals = int(np.minimum(against_root, part_sizes - against_root).sum())
+        
+        return {
+            'face_count': len(self.mesh.vectors),
+            'degenerate_faces': int(degenerate.sum()),
+            'non_manifold_edges': int(np.sum(uses > 2)),
+            'boundary_edges': int(boundary.sum()),
+            'holes': int(holes),
+            'flipped_normals': flipped_normals,
+        }
+
     def get_stats(self) -> dict:
         """Get statistics about the mesh.
         
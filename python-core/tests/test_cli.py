@@ -1,16 +1,35 @@
 """Tests for CLI module."""
 
 import pytest
+import io
 import json
 import os
 
 from layerforge.cli import (
     handle_request,
+    serve,
     process_image,
+    analyze_image,
+    import_stl_heightmap,
+    validate_mesh,
     generate_mesh,
+    export_slicer_project,
     compute_preview,
+    preview_diff,
+    export_turntable,
+    contact_sheet,
+    generate_legend,
     compute_swaps,
     export_plan,
+    export_instructions,
+    export_heightmap,
+    optimize_swap_count,
+    suggest_stops,
+    suggest_palette,
+    estimate_usage,
+    estimate_print_time,
+    ping,
+    FEATURES,
     METHODS,
 )
 
@@ -56,6 +75,21 @@ class TestCLI:
         assert 'error' in parsed
         assert 'unknown_method' in parsed['error'].lower() or 'Unknown' in parsed['error']
 
+    def test_handle_request_echoes_the_id(self):
+        """Test results and errors carry the id of their request."""
+        pong = json.loads(handle_request(json.dumps({'id': 7, 'method': 'ping'})))
+        unknown = json.loads(handle_request(json.dumps({'id': 8, 'method': 'nope'})))
+        failed = json.loads(handle_request(json.dumps({
+            'id': 9,
+            'method': 'process_image',
+            'params': {'image_path': '/nonexistent/path/image.png'},
+        })))
+
+        assert pong['id'] == 7 and 'version' in pong
+        assert unknown['id'] == 8 and 'error' in unknown
+        assert failed['id'] == 9 and 'error' in failed
+        assert 'id' not in json.loads(handle_request(json.dumps({'method': 'ping'})))
+
     def test_handle_request_invalid_json(self):
         """Test handling of invalid JSON."""
         result = handle_request("not valid json {{{")
@@ -63,6 +97,148 @@ class TestCLI:
 
         assert 'error' in parsed
 
+    def test_serve_answers_requests_until_shutdown(self):
+        """Test one core answers every request sent over its stdin."""
+        requests = [
+            {'method': 'ping', 'params': {}},
+            {'method': 'unknown_method', 'params': {}},
+            {'method': 'shutdown', 'params': {}},
+            {'method': 'ping', 'params': {}},
+        ]
+        stdin = io.StringIO(''.join(json.dumps(r) + '\n' for r in requests))
+        stdout = io.StringIO()
+
+        serve(stdin, stdout)
+
+        replies = [json.loads(line) for line in stdout.getvalue().splitlines()]
+        assert len(replies) == 2
+        assert 'version' in replies[0]
+        assert 'error' in replies[1]
+
+    def test_serve_runs_until_stdin_closes(self):
+        """Test serve keeps reading after blank lines and stops at EOF."""
+        ping_line = json.dumps({'method': 'ping', 'params': {}})
+        stdout = io.StringIO()
+
+        serve(io.StringIO(ping_line + '\n\n' + ping_line + '\n'), stdout)
+
+        assert len(stdout.getvalue().splitlines()) == 2
+
+    def test_long_methods_report_progress(
+        self, sample_image_path, sample_filaments, sample_stops, temp_output_dir, capsys
+    ):
+        """Test image, preview and mesh requests send tagged progress frames."""
+        def frames(method, params, request_id):
+            reply = json.loads(handle_request(json.dumps({
+                'id': request_id, 'method': method, 'params': params
+            })))
+            lines = capsys.readouterr().out.splitlines()
+            progress = [json.loads(line) for line in lines]
+            assert all(frame['id'] == request_id for frame in progress)
+            return reply, [frame['stage'] for frame in progress]
+
+        geometry = {'min_depth_mm': 0.5, 'max_depth_mm': 2.0}
+        image, stages = frames(
+            'process_image', {'image_path': sample_image_path, 'geometry': geometry}, 1
+        )
+        assert stages == ['loading', 'tone_mapping', 'heightmap', 'encoding']
+
+        heightmap = {
+            'heightmap_base64': image['heightmap_base64'],
+            'width': image['width'],
+            'height': image['height'],
+        }
+        _, stages = frames('compute_preview', {
+            **heightmap,
+            'filaments': sample_filaments,
+            'stops': sample_stops,
+            'geometry': geometry,
+        }, 2)
+        assert stages[0] == 'loading' and stages[-1] == 'encoding'
+        assert 'coloring' in stages
+
+        _, stages = frames('generate_mesh', {
+            'request': {
+                **heightmap,
+                'geometry': geometry,
+                'print_settings': {'width_mm': 40, 'height_mm': 40, 'has_border': True},
+            },
+            'output_path': str(temp_output_dir / 'progress.stl'),
+        }, 3)
+        assert stages == ['loading', 'triangulating', 'border', 'writing']
+
+    def test_serve_skips_cancelled_requests(self):
+        """Test a request cancelled before it runs gets a cancelled error."""
+        requests = [
+            {'method': 'cancel', 'params': {'request_id': 2}},
+            {'id': 2, 'method': 'ping', 'params': {}},
+            {'id': 3, 'method': 'ping', 'params': {}},
+        ]
+        stdin = io.StringIO(''.join(json.dumps(r) + '\n' for r in requests))
+        stdout = io.StringIO()
+
+        serve(stdin, stdout)
+
+        replies = [json.loads(line) for line in stdout.getvalue().splitlines()]
+        assert [r['id'] for r in replies] == [2, 3]
+        assert replies[0]['error_type'] == 'Cancelled'
+        assert 'version' in replies[1]
+
+    def test_cancel_stops_a_running_method_at_its_next_stage(self, sample_image_path):
+        """Test a method whose request was cancelled fails when it reports progress."""
+        from layerforge import cli
+
+        cli._cancelled.add(4)
+        try:
+            result = json.loads(handle_request(json.dumps({
+                'id': 4,
+                'method': 'process_image',
+                'params': {'image_path': sample_image_path, 'geometry': {}},
+            })))
+        finally:
+            cli._cancelled.discard(4)
+
+        assert result['id'] == 4
+        assert result['error_type'] == 'Cancelled'
+
+    def test_requests_with_heartbeat_ms_get_heartbeats(self):
+        """Test a slow method sends heartbeats until its reply is written."""
+        import time
+        from layerforge import cli
+
+        cli.METHODS['stall'] = lambda params: time.sleep(0.25) or {'done': True}
+        try:
+            stdin = io.StringIO(json.dumps({
+                'id': 5, 'method': 'stall', 'params': {}, 'heartbeat_ms': 40
+            }) + '\n')
+            stdout = io.StringIO()
+            serve(stdin, stdout)
+        finally:
+            del cli.METHODS['stall']
+
+        frames = [json.loads(line) for line in stdout.getvalue().splitlines()]
+        assert all(frame['id'] == 5 for frame in frames)
+        assert frames[-1] == {'done': True, 'id': 5}
+        assert len(frames[:-1]) >= 3
+        assert all(frame == {'id': 5, 'heartbeat': True} for frame in frames[:-1])
+
+    def test_heartbeats_start_with_the_request(self):
+        """Test a request is acknowledged before its first interval passes."""
+        from layerforge import cli
+
+        cli.METHODS['quick'] = lambda params: {'done': True}
+        try:
+            stdin = io.StringIO(json.dumps({
+                'id': 6, 'method': 'quick', 'params': {}, 'heartbeat_ms': 60000
+            }) + '\n')
+            stdout = io.StringIO()
+            serve(stdin, stdout)
+        finally:
+            del cli.METHODS['quick']
+
+        frames = [json.loads(line) for line in stdout.getvalue().splitlines()]
+        assert frames == [{'id': 6, 'heartbeat': True}, {'done': True, 'id': 6}]
+
     def test_handle_request_missing_params(self):
         """Test handling of missing parameters."""
         request = json.dumps({
@@ -80,15 +256,40 @@ class TestCLI:
         """Test that all expected methods are registered."""
         expected_methods = [
             'process_image',
+            'analyze_image',
+            'import_stl_heightmap',
+            'validate_mesh',
+            'measure_td',
             'generate_mesh',
+            'export_slicer_project',
             'compute_preview',
+            'preview_diff',
+            'export_turntable',
+            'contact_sheet',
+            'generate_legend',
             'compute_swaps',
             'export_plan',
+            'export_instructions',
+            'export_heightmap',
+            'optimize_swap_count',
+            'suggest_stops',
+            'suggest_palette',
+            'estimate_usage',
+            'estimate_print_time',
         ]
 
         for method in expected_methods:
             assert method in METHODS
 
+    def test_ping_reports_methods_and_features(self):
+        """Test ping lists every method and the optional features."""
+        result = ping({})
+
+        assert result['version']
+        assert set(result['available_methods']) == set(METHODS)
+        assert 'stl_import' in result['features']
+        assert result['features'] == FEATURES
+
     def test_process_image_function(self, sample_image_path):
         """Test process_image function directly."""
         params = {
@@ -112,6 +313,16 @@ class TestCLI:
         assert 'height' in result
         assert result['width'] == 8
         assert result['height'] == 8
+        assert result['actual_min_depth_mm'] == pytest.approx(0.5, abs=1e-3)
+        assert result['actual_max_depth_mm'] == pytest.approx(2.0, abs=1e-3)
+        assert 'histogram' not in result
+
+        params['include_histogram'] = True
+        params['histogram_bins'] = 32
+        result = process_image(params)
+
+        assert len(result['histogram']) == 32
+        assert sum(result['histogram']) == 64
 
     def test_process_image_curve_and_dynamic_depth(self, sample_image_path):
         """Test process_image with curve mode and dynamic depth enabled."""
@@ -145,6 +356,277 @@ class TestCLI:
         assert result['width'] == 8
         assert result['height'] == 8
 
+    def test_process_image_reuses_luminance_for_tone_changes(
+        self, sample_image_path, monkeypatch
+    ):
+        """Test process_image only decodes the source once per crop."""
+        from layerforge import cli
+        from layerforge.image_processor import ImageProcessor
+
+        cli._luminance_cache.clear()
+        loads = []
+        load_image = ImageProcessor.load_image
+
+        def counting_load(processor, path):
+            loads.append(path)
+            return load_image(processor, path)
+
+        monkeypatch.setattr(ImageProcessor, 'load_image', counting_load)
+        params = {
+            'image_path': sample_image_path,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0, 'gamma': 1.0},
+        }
+
+        first = process_image(params)
+        params['geometry']['gamma'] = 2.2
+        brighter = process_image(params)
+        assert len(loads) == 1
+        assert brighter['heightmap_base64'] != first['heightmap_base64']
+
+        params['crop'] = {'x': 0, 'y': 0, 'width': 4, 'height': 4}
+        cropped = process_image(params)
+        assert len(loads) == 2
+        assert cropped['width'] == 4
+
+    def test_compute_preview_per_band_layers(
+        self, sample_heightmap, sample_filaments, sample_stops
+    ):
+        """Test compute_preview returns one masked image per requested band."""
+        import base64
+        import io
+        import numpy as np
+        from PIL import Image
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        heightmap_gen.heightmap = sample_heightmap
+        params = {
+            'heightmap_base64': heightmap_gen.to_base64(),
+            'width': 8,
+            'height': 8,
+            'filaments': sample_filaments,
+            'stops': sample_stops,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+        }
+
+        result = compute_preview(params)
+        assert 'layers' not in result
+
+        params['bands'] = [
+            {'filament_id': 'white', 'from_z_mm': 0.5, 'to_z_mm': 1.0},
+            {'filament_id': 'gray', 'from_z_mm': 1.0, 'to_z_mm': 2.0},
+        ]
+        result = compute_preview(params)
+
+        assert len(result['layers']) == 2
+        alphas = [
+            np.array(Image.open(io.BytesIO(base64.b64decode(layer.split(',')[1]))))[..., 3]
+            for layer in result['layers']
+        ]
+        # Every pixel belongs to exactly one band
+        assert np.array_equal((alphas[0] > 0).astype(int) + (alphas[1] > 0), np.ones((8, 8)))
+
+    def test_compute_preview_isolates_bands(
+        self, sample_heightmap, sample_filaments, sample_stops
+    ):
+        """Test compute_preview grays out everything outside isolate_bands."""
+        import base64
+        import io
+        import numpy as np
+        from PIL import Image
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        heightmap_gen.heightmap = sample_heightmap
+        params = {
+            'heightmap_base64': heightmap_gen.to_base64(),
+            'width': 8,
+            'height': 8,
+            'filaments': sample_filaments,
+            'stops': sample_stops,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+        }
+        band = {'filament_id': 'gray', 'from_z_mm': 1.0, 'to_z_mm': 1.5}
+
+        def pixels(result):
+            data = base64.b64decode(result['preview_base64'].split(',')[1])
+            return np.array(Image.open(io.BytesIO(data))).astype(int)
+
+        full = pixels(compute_preview(params))
+        params['isolate_bands'] = [band]
+        isolated = pixels(compute_preview(params))
+
+        inside = (sample_heightmap > 1.0) & (sample_heightmap <= 1.5)
+        assert np.array_equal(isolated[inside], full[inside])
+        outside = isolated[~inside]
+        assert np.all(outside[:, 0] == outside[:, 1])
+        assert np.all(outside[:, 1] == outside[:, 2])
+
+    def test_analyze_image_tells_gray_from_color(self, tmp_path):
+        """Test analyze_image reports color, alpha and the dynamic range."""
+        import numpy as np
+        from PIL import Image
+
+        gray = np.full((4, 8), 120, dtype=np.uint8)
+        gray[:, 4:] = 140
+        gray_path = str(tmp_path / 'gray.png')
+        Image.fromarray(gray, mode='L').save(gray_path)
+
+        rgba = np.zeros((4, 8, 4), dtype=np.uint8)
+        rgba[..., 0] = 255
+        rgba[:, 4:] = [0, 0, 255, 128]
+        color_path = str(tmp_path / 'color.png')
+        Image.fromarray(rgba, mode='RGBA').save(color_path)
+
+        result = analyze_image({'image_path': gray_path})
+        assert (result['width'], result['height']) == (8, 4)
+        assert result['is_grayscale'] and not result['has_alpha']
+        assert result['dynamic_range'] == pytest.approx(20 / 255, abs=0.01)
+
+        result = analyze_image({'image_path': color_path})
+        assert not result['is_grayscale'] and result['has_alpha']
+        assert set(result['dominant_colors']) == {'#FF0000', '#0000FF'}
+
+    def test_process_image_cuts_masked_areas_to_the_base(
+        self, sample_image_path, tmp_path
+    ):
+        """Test a mask is resampled and its dark half set to 0 mm."""
+        import base64
+        import numpy as np
+        from PIL import Image
+
+        pixels = np.zeros((2, 2), dtype=np.uint8)
+        pixels[:, 1] = 255
+        mask_path = str(tmp_path / 'mask.png')
+        Image.fromarray(pixels, mode='L').save(mask_path)
+
+        result = process_image({
+            'image_path': sample_image_path,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+            'mask_path': mask_path,
+        })
+
+        heightmap = np.frombuffer(
+            base64.b64decode(result['heightmap_base64']), dtype=np.float32
+        ).reshape(result['height'], result['width'])
+        assert np.all(heightmap[:, :2] == 0.0)
+        assert np.all(heightmap[:, -2:] >= 0.5)
+        assert result['actual_min_depth_mm'] == 0.0
+
+    def test_export_turntable_writes_one_frame_per_step(
+        self, sample_image_path, temp_output_dir
+    ):
+        """Test export_turntable animates the preview as a GIF."""
+        from PIL import Image
+
+        processed = process_image({
+            'image_path': sample_image_path,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+        })
+        output_path = os.path.join(temp_output_dir, 'turntable.gif')
+
+        result = export_turntable({
+            'heightmap_base64': processed['heightmap_base64'],
+            'width': processed['width'],
+            'height': processed['height'],
+            'filaments': [{'id': 'red', 'hex_color': '#ff0000', 'td': 1.0}],
+            'stops': [{'filament_id': 'red', 'threshold_z_mm': 2.0}],
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+            'frames': 4,
+            'format': 'gif',
+            'output_path': output_path,
+        })
+
+        assert result['path'] == output_path
+        with Image.open(output_path) as animation:
+            assert animation.n_frames == 4
+
+        with pytest.raises(ValueError):
+            export_turntable({'frames': 4, 'format': 'webm', 'output_path': output_path})
+
+    def test_preview_diff_measures_color_error(self, sample_image_path):
+        """Test preview_diff compares the preview with its source image."""
+        import base64
+        import io
+        from PIL import Image
+
+        processed = process_image({
+            'image_path': sample_image_path,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+        })
+        params = {
+            'heightmap_base64': processed['heightmap_base64'],
+            'width': processed['width'],
+            'height': processed['height'],
+            'filaments': [
+                {'id': 'black', 'hex_color': '#000000', 'td': 1.0},
+            ],
+            'stops': [{'filament_id': 'black', 'threshold_z_mm': 2.0}],
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+            'image_path': sample_image_path,
+        }
+
+        result = preview_diff(params)
+
+        data = base64.b64decode(result['diff_base64'].split(',')[1])
+        assert Image.open(io.BytesIO(data)).size == (8, 8)
+        assert result['max_delta_e'] >= result['mean_delta_e'] > 0
+
+        cropped = preview_diff({
+            **params,
+            'width': 4,
+            'height': 4,
+            'heightmap_base64': process_image({
+                'image_path': sample_image_path,
+                'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+                'crop': {'x': 0, 'y': 0, 'width': 4, 'height': 4},
+            })['heightmap_base64'],
+            'crop': {'x': 0, 'y': 0, 'width': 4, 'height': 4},
+        })
+        data = base64.b64decode(cropped['diff_base64'].split(',')[1])
+        assert Image.open(io.BytesIO(data)).size == (4, 4)
+
+    def test_contact_sheet_grids_tiles(self, sample_heightmap):
+        """Test contact_sheet lays tiles out in a near-square grid."""
+        import base64
+        import io
+        from PIL import Image
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        heightmap_gen.heightmap = sample_heightmap
+        tile = {'heightmap_base64': heightmap_gen.to_base64(), 'width': 8, 'height': 8}
+        tiles = [dict(tile, label=f'gamma {g}') for g in (0.8, 1.0, 1.2)]
+
+        result = contact_sheet({'tiles': tiles, 'tile_size': 64})
+
+        data = base64.b64decode(result['image_base64'].split(',')[1])
+        sheet = Image.open(io.BytesIO(data))
+        # Two columns, two rows of 64 px tiles plus labels and padding
+        assert sheet.size == (2 * 72 + 8, 2 * (64 + 20 + 8) + 8)
+
+    def test_generate_legend_has_a_row_per_entry(self):
+        """Test generate_legend draws each swatch in order."""
+        import base64
+        import io
+        from PIL import Image
+
+        entries = [
+            {'layer': 3, 'print_z_mm': 0.24, 'hex_color': '#FFFFFF', 'filament_name': 'White'},
+            {'layer': 9, 'print_z_mm': 0.72, 'hex_color': '#FF0000', 'filament_name': 'Red'},
+        ]
+
+        result = generate_legend({'entries': entries})
+
+        data = base64.b64decode(result['image_base64'].split(',')[1])
+        legend = Image.open(io.BytesIO(data)).convert('RGB')
+        assert legend.size == (360, 2 * 40 + 8)
+        assert legend.getpixel((24, 24)) == (255, 255, 255)
+        assert legend.getpixel((24, 64)) == (255, 0, 0)
+
+        with pytest.raises(ValueError):
+            generate_legend({'entries': []})
+
     def test_compute_swaps_function(self, sample_stops):
         """Test compute_swaps function directly."""
         params = {
@@ -159,6 +641,22 @@ class TestCLI:
         assert 'swaps' in result
         assert isinstance(result['swaps'], list)
 
+    def test_compute_swaps_reports_same_layer_stops(self):
+        """A stop on an earlier stop's layer is reported, not dropped silently."""
+        result = compute_swaps({
+            'stops': [
+                {'filament_id': 'a', 'threshold_z_mm': 0.78},
+                {'filament_id': 'b', 'threshold_z_mm': 0.8},
+                {'filament_id': 'c', 'threshold_z_mm': 1.6},
+            ],
+            'layer_height_mm': 0.08,
+        })
+
+        assert [s['filament_id'] for s in result['swaps']] == ['a', 'c']
+        assert result['skipped'] == [
+            {'filament_id': 'b', 'threshold_z_mm': 0.8, 'reason': 'same_layer'}
+        ]
+
     def test_generate_mesh_function(self, sample_image_path, temp_output_dir):
         """Test generate_mesh function with heightmap."""
         # First process an image to get heightmap
@@ -199,6 +697,116 @@ class TestCLI:
 
         assert 'path' in result
         assert os.path.exists(result['path'])
+        assert result['stats']['triangle_count'] > 0
+        assert result['stats']['approx_volume_mm3'] > 0
+        assert len(result['stats']['bounding_box_mm']['max']) == 3
+
+    def test_generate_mesh_dry_run_skips_write(self, sample_image_path, temp_output_dir):
+        """Test a dry run reports stats without writing the mesh."""
+        geometry = {
+            'min_depth_mm': 0.5,
+            'max_depth_mm': 2.0,
+            'gamma': 1.0,
+            'contrast': 1.0,
+            'offset': 0.0,
+            'smoothing': 0.0,
+            'spike_removal': 'none',
+            'invert': False,
+        }
+        img_result = process_image({'image_path': sample_image_path, 'geometry': geometry})
+        output_path = temp_output_dir / 'dry_run.stl'
+
+        result = generate_mesh({
+            'request': {
+                'heightmap_base64': img_result['heightmap_base64'],
+                'width': img_result['width'],
+                'height': img_result['height'],
+                'geometry': geometry,
+                'print_settings': {'width_mm': 100, 'height_mm': 80},
+            },
+            'output_path': str(output_path),
+            'dry_run': True,
+        })
+
+        assert result['path'] is None
+        assert not output_path.exists()
+        assert result['stats']['triangle_count'] > 0
+
+    def test_export_slicer_project_embeds_color_changes(
+        self, sample_heightmap, temp_output_dir
+    ):
+        """Test export_slicer_project adds each slicer's color change file."""
+        import zipfile
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        heightmap_gen.heightmap = sample_heightmap
+        request = {
+            'heightmap_base64': heightmap_gen.to_base64(),
+            'width': 8,
+            'height': 8,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+            'print_settings': {'width_mm': 100, 'height_mm': 80},
+        }
+        color_changes = [
+            {'layer': 6, 'print_z_mm': 0.48, 'hex_color': '#FFFFFF', 'filament_name': 'White'},
+            {'layer': 12, 'print_z_mm': 0.96, 'hex_color': '#000000', 'filament_name': 'Black'},
+        ]
+        entries = {
+            'prusaslicer': 'Metadata/Prusa_Slicer_custom_gcode_per_print_z.xml',
+            'orcaslicer': 'Metadata/custom_gcode_per_layer.xml',
+        }
+
+        for slicer, entry in entries.items():
+            result = export_slicer_project({
+                'request': request,
+                'output_path': str(temp_output_dir / f'{slicer}.3mf'),
+                'slicer': slicer,
+                'color_changes': color_changes,
+            })
+
+            with zipfile.ZipFile(result['path']) as package:
+                assert '3D/3dmodel.model' in package.namelist()
+                xml = package.read(entry).decode('utf-8')
+            assert xml.count('gcode="M600"') == 2
+            assert '="0.96"' in xml
+            assert result['stats']['triangle_count'] > 0
+
+        with pytest.raises(ValueError):
+            export_slicer_project({
+                'request': request,
+                'output_path': str(temp_output_dir / 'cura.3mf'),
+                'slicer': 'cura',
+            })
+
+    def test_import_stl_heightmap_function(self, sample_heightmap, temp_output_dir):
+        """Test import_stl_heightmap samples a saved relief."""
+        from layerforge.mesh_generator import MeshGenerator
+
+        generator = MeshGenerator()
+        generator.create_relief_mesh(sample_heightmap, width_mm=100.0, height_mm=80.0)
+        path = generator.export_stl(str(temp_output_dir / 'import.stl'))
+
+        result = import_stl_heightmap({'stl_path': path, 'samples_x': 10, 'samples_y': 6})
+
+        assert result['width'] == 10
+        assert result['height'] == 6
+        assert len(result['heightmap_base64']) > 0
+
+    def test_validate_mesh_function(self, sample_heightmap, temp_output_dir):
+        """Test validate_mesh reports a generated relief as closed."""
+        from layerforge.mesh_generator import MeshGenerator
+
+        generator = MeshGenerator()
+        generator.create_relief_mesh(sample_heightmap, width_mm=100.0, height_mm=80.0)
+        path = generator.export_stl(str(temp_output_dir / 'validate.stl'))
+
+        result = validate_mesh({'path': path})
+
+        assert result['face_count'] == len(generator.mesh.vectors)
+        assert result['non_manifold_edges'] == 0
+        assert result['holes'] == 0
+        assert result['flipped_normals'] == 0
 
     def test_export_plan_function_txt(
         self, sample_filaments, sample_stops, temp_output_dir
@@ -254,6 +862,188 @@ class TestCLI:
             data = json.load(f)
             assert 'swaps' in data
 
+    def test_export_instructions_writes_pdf(self, temp_output_dir):
+        """Test export_instructions typesets the sheet across pages."""
+        text = '# Layerforge print instructions\n\n' + ''.join(
+            f'{i}. At layer {i} (z=0.080 mm), change to White (#FFFFFF)\n'
+            for i in range(1, 80)
+        )
+
+        result = export_instructions({
+            'text': text,
+            'output_path': str(temp_output_dir / 'instructions.pdf'),
+        })
+
+        with open(result['path'], 'rb') as f:
+            data = f.read()
+        assert data.startswith(b'%PDF')
+
+    def test_export_heightmap_uses_the_full_gray_range(
+        self, sample_heightmap, temp_output_dir
+    ):
+        """Test export_heightmap maps the depth range to 8 and 16 bit gray."""
+        import numpy as np
+        from PIL import Image
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        heightmap_gen.heightmap = sample_heightmap
+        params = {
+            'heightmap_base64': heightmap_gen.to_base64(),
+            'width': 8,
+            'height': 8,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+        }
+
+        for bit_depth, white in ((8, 255), (16, 65535)):
+            output_path = str(temp_output_dir / f'heightmap{bit_depth}.png')
+            result = export_heightmap(
+                {**params, 'output_path': output_path, 'bit_depth': bit_depth}
+            )
+            pixels = np.array(Image.open(result['path']))
+            assert pixels.shape == (8, 8)
+            assert pixels[0, 0] == 0
+            assert pixels[7, 7] == white
+
+        with pytest.raises(ValueError):
+            export_heightmap({**params, 'output_path': output_path, 'bit_depth': 12})
+
+    def test_optimize_swap_count_scores_each_band_count(self):
+        """Test optimize_swap_count finds a three-level heightmap's bands."""
+        import numpy as np
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        levels = np.array([0.5, 1.0, 2.0], dtype=np.float32)
+        heightmap_gen.heightmap = np.repeat(levels, 8).reshape(3, 8)
+
+        result = optimize_swap_count({
+            'heightmap_base64': heightmap_gen.to_base64(),
+            'width': 8,
+            'height': 3,
+            'geometry': {'min_depth_mm': 0.5, 'max_depth_mm': 2.0},
+            'max_bands': 4,
+        })
+
+        scores = result['scores']
+        assert [s['count'] for s in scores] == [1, 2, 3, 4]
+        errors = [s['error_mm'] for s in scores]
+        assert errors[0] > errors[1] > errors[2]
+        assert errors[2] == pytest.approx(0.0, abs=1e-6)
+        assert scores[2]['thresholds'] == pytest.approx([0.75, 1.5, 2.0], abs=1e-6)
+
+    def test_suggest_stops_splits_an_image_at_its_tones(self, tmp_path):
+        """Test suggest_stops puts a threshold between each pair of tones."""
+        import numpy as np
+        from PIL import Image
+
+        pixels = np.repeat(np.array([0, 128, 255], dtype=np.uint8), 6).reshape(3, 6)
+        image_path = str(tmp_path / 'tones.png')
+        Image.fromarray(pixels, mode='L').convert('RGB').save(image_path)
+        geometry = {'min_depth_mm': 0.5, 'max_depth_mm': 2.0}
+
+        result = suggest_stops({
+            'image_path': image_path,
+            'geometry': geometry,
+            'num_colors': 3,
+        })
+
+        # The tones sit at both ends of the range and near its middle
+        thresholds = result['thresholds']
+        assert len(thresholds) == 3
+        assert 0.5 < thresholds[0] < 1.25 < thresholds[1] < 2.0
+        assert thresholds[2] == 2.0
+
+    def test_suggest_palette_matches_dominant_colors(self, tmp_path):
+        """Test suggest_palette picks a distinct filament per dominant color."""
+        import numpy as np
+        from PIL import Image
+        from layerforge.cli import ciede2000
+
+        pixels = np.zeros((4, 4, 3), dtype=np.uint8)
+        pixels[:, :3] = [200, 30, 30]
+        pixels[:, 3:] = [20, 40, 200]
+        image_path = str(tmp_path / 'red_and_blue.png')
+        Image.fromarray(pixels, mode='RGB').save(image_path)
+        library = [
+            {'id': 'white', 'hex_color': '#FFFFFF'},
+            {'id': 'red', 'hex_color': '#C81E1E'},
+            {'id': 'navy', 'hex_color': '#1E2878'},
+            {'id': 'blue', 'hex_color': '#1428C8'},
+        ]
+
+        result = suggest_palette({'image_path': image_path, 'library': library, 'count': 2})
+
+        # Red covers more of the image, so it comes first
+        matches = result['matches']
+        assert [m['filament_id'] for m in matches] == ['red', 'blue']
+        assert matches[0]['distance'] == pytest.approx(0.0, abs=0.5)
+
+        # Reference pair from Sharma, Wu and Dalal's CIEDE2000 test data
+        assert ciede2000([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485]) == pytest.approx(
+            2.0425, abs=1e-4
+        )
+
+    def test_estimate_usage_integrates_each_band(self):
+        """Test estimate_usage splits the relief volume at the stops."""
+        import numpy as np
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        heightmap_gen.heightmap = np.array([[0.5, 1.0], [1.5, 2.0]], dtype=np.float32)
+        request = {
+            'heightmap_base64': heightmap_gen.to_base64(),
+            'width': 2,
+            'height': 2,
+            'print_settings': {'width_mm': 20, 'height_mm': 10, 'base_layer_mm': 0.2},
+        }
+        stops = [
+            {'filament_id': 'black', 'threshold_z_mm': 2.0},
+            {'filament_id': 'white', 'threshold_z_mm': 1.0},
+        ]
+
+        result = estimate_usage({'request': request, 'stops': stops})
+
+        # Each pixel covers 50 mm2; white fills up to 1 mm, black the rest
+        assert result['volumes_mm3']['white'] == pytest.approx(50 * (0.5 + 1 + 1 + 1))
+        assert result['volumes_mm3']['black'] == pytest.approx(50 * (0 + 0 + 0.5 + 1))
+        assert result['base_volume_mm3'] == pytest.approx(20 * 10 * 0.2)
+
+        request['print_settings'].update(
+            has_border=True, border_width_mm=1, border_depth_mm=1.8
+        )
+        result = estimate_usage({'request': request, 'stops': stops})
+        assert result['base_volume_mm3'] == pytest.approx(40 + (22 * 12 - 200) * 2.0)
+
+    def test_estimate_print_time_counts_the_area_of_each_layer(self):
+        """Test estimate_print_time shrinks layers to the pixels still standing."""
+        import numpy as np
+        from layerforge import cli
+        from layerforge.heightmap import HeightMapGenerator
+
+        heightmap_gen = HeightMapGenerator()
+        heightmap_gen.heightmap = np.array([[0.2, 0.4]], dtype=np.float32)
+        request = {
+            'heightmap_base64': heightmap_gen.to_base64(),
+            'width': 2,
+            'height': 1,
+            'print_settings': {
+                'width_mm': 42, 'height_mm': 60, 'base_layer_mm': 0.2, 'layer_height_mm': 0.2,
+            },
+        }
+
+        result = estimate_print_time({'request': request})
+
+        # One base layer and one relief layer over the whole area, then one
+        # over half of it
+        assert result['layers'] == 3
+        line_s = 42 * 60 / (cli.PRINT_LINE_WIDTH_MM * cli.PRINT_SPEED_MM_S)
+        expected_s = 2.5 * line_s + 3 * cli.LAYER_CHANGE_S
+        assert result['minutes'] == pytest.approx(expected_s / 60)
+
+        request['print_settings']['layer_height_mm'] = 0.1
+        assert estimate_print_time({'request': request})['layers'] == 6
+
     def test_error_returns_traceback(self):
         """Test that errors include traceback for debugging."""
         request = json.dumps({
@@ -268,6 +1058,7 @@ class TestCLI:
         parsed = json.loads(result)
 
         assert 'error' in parsed
+        assert parsed['error_type'] == 'FileNotFoundError'
         # Should include traceback for debugging
         if 'traceback' in parsed:
             assert len(parsed['traceback']) > 0
@@ -100,6 +100,26 @@ class TestColorPlanner:
         assert swaps[0].layer == 10
         assert swaps[1].layer == 20
 
+    def test_compute_swaps_rounds_mid_layer_stops_up(self):
+        """Test a stop inside a layer swaps on the next one, like the exports."""
+        planner = ColorPlanner()
+        planner.set_stops([{'filament_id': 'a', 'threshold_z_mm': 0.81}])
+        swaps = planner.compute_swaps(
+            layer_height_mm=0.08,
+            min_depth_mm=0.0,
+            max_depth_mm=2.0
+        )
+        assert swaps[0].layer == 11
+
+        # 0.3 / 0.1 is just under 3 in floating point
+        planner.set_stops([{'filament_id': 'a', 'threshold_z_mm': 0.3}])
+        swaps = planner.compute_swaps(
+            layer_height_mm=0.1,
+            min_depth_mm=0.0,
+            max_depth_mm=2.0
+        )
+        assert swaps[0].layer == 3
+
     def test_get_filament_by_id(self, sample_filaments):
         """Test getting filament by ID."""
         planner = ColorPlanner()
@@ -90,6 +90,31 @@ class TestHeightMapGenerator:
             restored.heightmap, sample_heightmap, decimal=5
         )
 
+    def test_from_file_matches_base64(self, sample_heightmap, tmp_path):
+        """Test that a raw float32 file loads the same heightmap."""
+        path = tmp_path / 'heightmap.f32'
+        sample_heightmap.astype(np.float32).tofile(path)
+
+        height, width = sample_heightmap.shape
+        restored = HeightMapGenerator.from_file(str(path), width, height)
+
+        np.testing.assert_array_almost_equal(
+            restored.heightmap, sample_heightmap, decimal=5
+        )
+
+    def test_histogram_covers_depth_range(self, sample_grayscale_array):
+        """Test that the histogram counts every sample in the depth range."""
+        generator = HeightMapGenerator()
+        generator.generate(sample_grayscale_array, min_depth_mm=0.5, max_depth_mm=2.0)
+
+        counts = generator.histogram(bins=16)
+
+        assert len(counts) == 16
+        assert sum(counts) == 64
+        # The corner samples sit at each end of the range
+        assert counts[0] >= 1
+        assert counts[-1] >= 1
+
     def test_to_base64_raises_without_heightmap(self):
         """Test that to_base64 raises error without heightmap."""
         generator = HeightMapGenerator()
@@ -5,9 +5,14 @@ import numpy as np
 from PIL import Image
 import io
 import base64
+import json
+import os
 
+from layerforge.heightmap import HeightMapGenerator
 from layerforge.image_processor import ImageProcessor
 
+TONE_CURVE_FIXTURE = os.path.join(os.path.dirname(__file__), 'fixtures', 'tone_curve.json')
+
 
 class TestImageProcessor:
     """Tests for ImageProcessor class."""
@@ -33,6 +38,37 @@ class TestImageProcessor:
         assert isinstance(result, np.ndarray)
         assert result.shape == (8, 8, 3)
 
+    def test_crop(self, sample_image_path):
+        """Test cropping the loaded image to a region."""
+        processor = ImageProcessor()
+        full = processor.load_image(sample_image_path).copy()
+        result = processor.crop(2, 1, 5, 3)
+
+        assert result.shape == (3, 5, 3)
+        assert np.array_equal(result, full[1:4, 2:7])
+        assert processor.get_dimensions() == (5, 3)
+
+    def test_downscale_limits_longer_side(self, sample_rgb_array):
+        """Test downscaling keeps the aspect ratio and value range."""
+        processor = ImageProcessor()
+        processor.original_image = np.repeat(sample_rgb_array, 2, axis=1)
+
+        scale = processor.downscale(8)
+
+        assert scale == pytest.approx(0.5)
+        assert processor.get_dimensions() == (8, 4)
+        assert processor.original_image.min() >= 0.0
+        assert processor.original_image.max() <= 1.0
+        assert processor.downscale(8) == 1.0
+
+    def test_crop_outside_image_raises(self, sample_image_path):
+        """Test that a crop past the image edge is rejected."""
+        processor = ImageProcessor()
+        processor.load_image(sample_image_path)
+
+        with pytest.raises(ValueError, match="outside"):
+            processor.crop(4, 0, 5, 8)
+
     def test_to_luminance(self, sample_rgb_array):
         """Test RGB to luminance conversion."""
         processor = ImageProcessor()
@@ -188,6 +224,44 @@ class TestImageProcessor:
             result = processor.remove_spikes(sample_grayscale_array, level)
             assert result.shape == sample_grayscale_array.shape
 
+    def test_remove_spikes_kernel_size_matches_level(self, sample_grayscale_array):
+        """Test a numeric kernel size filters like the matching level."""
+        processor = ImageProcessor()
+
+        np.testing.assert_array_equal(
+            processor.remove_spikes(sample_grayscale_array, '5'),
+            processor.remove_spikes(sample_grayscale_array, 'medium'),
+        )
+
+    def test_process_matches_tone_curve_fixture(self):
+        """Test the pipeline maps luminance to the depths the app plots.
+
+        The app replicates this mapping for its tone curve preview and
+        checks itself against the same fixture.
+        """
+        with open(TONE_CURVE_FIXTURE) as f:
+            fixture = json.load(f)
+        inputs = np.array([fixture['inputs']], dtype=np.float32)
+
+        for case in fixture['cases']:
+            geometry = case['geometry']
+            processor = ImageProcessor()
+            processor.original_image = np.repeat(inputs[..., np.newaxis], 3, axis=2)
+            processed = processor.process(
+                gamma=geometry['gamma'],
+                contrast=geometry['contrast'],
+                offset=geometry['offset'],
+                luminance_method='max_channel',
+                tone_mapping_mode=geometry.get('tone_mapping_mode', 'gamma'),
+                transfer_curve=geometry.get('transfer_curve'),
+                invert=geometry['invert'],
+            )
+            depths = HeightMapGenerator().generate(
+                processed, geometry['min_depth_mm'], geometry['max_depth_mm']
+            )
+
+            np.testing.assert_allclose(depths[0], case['depths_mm'], atol=1e-5, err_msg=case['name'])
+
     def test_process_full_pipeline(self, sample_image_path):
         """Test complete processing pipeline."""
         processor = ImageProcessor()
@@ -252,6 +326,35 @@ class TestImageProcessor:
         decoded = base64.b64decode(b64)
         assert len(decoded) > 0
 
+    @staticmethod
+    def _swatch(levels, step_width=20, height=12):
+        """RGB photo of a step wedge with the given brightness per step."""
+        row = np.repeat(np.array(levels, dtype=np.float32), step_width)
+        gray = np.tile(row, (height, 1))
+        return np.stack([gray, gray, gray], axis=-1)
+
+    def test_measure_td(self):
+        """Test TD is the thickness of the first opaque step."""
+        processor = ImageProcessor()
+        processor.original_image = self._swatch([0.9, 0.6, 0.4, 0.25, 0.15, 0.1, 0.1, 0.1])
+
+        assert processor.measure_td(0.08) == pytest.approx(0.48)
+
+        # Swatches photographed the other way round give the same result
+        processor.original_image = processor.original_image[:, ::-1]
+        assert processor.measure_td(0.08) == pytest.approx(0.48)
+
+    def test_measure_td_rejects_unusable_swatches(self):
+        """Test swatches that are blank or never opaque are rejected."""
+        processor = ImageProcessor()
+        processor.original_image = self._swatch([0.5, 0.5, 0.5])
+        with pytest.raises(ValueError, match="Could not detect"):
+            processor.measure_td(0.08)
+
+        processor.original_image = self._swatch([0.9, 0.7, 0.5, 0.3])
+        with pytest.raises(ValueError, match="never becomes opaque"):
+            processor.measure_td(0.08)
+
     def test_rgba_image_handling(self, tmp_path):
         """Test handling of RGBA images with transparency."""
         # Create RGBA image with transparency
@@ -4,6 +4,8 @@ import pytest
 import numpy as np
 import os
 
+from stl import mesh as stl_mesh
+
 from layerforge.mesh_generator import MeshGenerator
 
 
@@ -46,6 +48,27 @@ class TestMeshGenerator:
         assert stats['face_count'] > 0
         assert stats['vertex_count'] > 0
 
+    def test_create_relief_mesh_hollow_shell(self, sample_heightmap):
+        """Test that a shelled relief follows the top surface underneath."""
+        solid = MeshGenerator()
+        solid.create_relief_mesh(sample_heightmap, width_mm=100.0, height_mm=80.0)
+        solid_volume, _, _ = solid.mesh.get_mass_properties()
+
+        hollow = MeshGenerator()
+        hollow.create_relief_mesh(
+            sample_heightmap,
+            width_mm=100.0,
+            height_mm=80.0,
+            base_layer_mm=0.16,
+            shell_thickness_mm=0.4
+        )
+        hollow_volume, _, _ = hollow.mesh.get_mass_properties()
+
+        stats = hollow.get_stats()
+        assert stats['z_min'] == pytest.approx(0.26, abs=0.01)
+        assert abs(hollow_volume) < abs(solid_volume)
+        assert abs(hollow_volume) == pytest.approx(100.0 * 80.0 * 0.4, rel=0.01)
+
     def test_create_relief_mesh_no_degenerate_triangles(self, sample_heightmap):
         """Test that mesh has no degenerate (zero-area) triangles."""
         generator = MeshGenerator()
@@ -172,6 +195,58 @@ class TestMeshGenerator:
             first_line = f.readline()
             assert first_line.startswith('solid')
 
+    def test_export_obj(self, sample_heightmap, temp_output_dir):
+        """Test exporting mesh as OBJ with shared vertices."""
+        generator = MeshGenerator()
+        generator.create_relief_mesh(
+            sample_heightmap,
+            width_mm=100.0,
+            height_mm=80.0
+        )
+
+        output_path = str(temp_output_dir / "test_mesh.obj")
+        result = generator.export(output_path, 'obj')
+
+        assert result == output_path
+        with open(output_path, 'r') as f:
+            lines = f.readlines()
+        vertex_lines = [l for l in lines if l.startswith('v ')]
+        face_lines = [l for l in lines if l.startswith('f ')]
+        assert len(face_lines) == len(generator.mesh.vectors)
+        assert len(vertex_lines) < len(face_lines) * 3
+
+    def test_export_3mf(self, sample_heightmap, temp_output_dir):
+        """Test exporting mesh as a 3MF package."""
+        import zipfile
+
+        generator = MeshGenerator()
+        generator.create_relief_mesh(
+            sample_heightmap,
+            width_mm=100.0,
+            height_mm=80.0
+        )
+
+        output_path = str(temp_output_dir / "test_mesh.3mf")
+        generator.export(output_path, '3mf')
+
+        with zipfile.ZipFile(output_path) as package:
+            assert '3D/3dmodel.model' in package.namelist()
+            model = package.read('3D/3dmodel.model').decode('utf-8')
+        assert 'unit="millimeter"' in model
+        assert model.count('<triangle ') == len(generator.mesh.vectors)
+
+    def test_export_rejects_unknown_format(self, sample_heightmap, temp_output_dir):
+        """Test that an unknown output format raises an error."""
+        generator = MeshGenerator()
+        generator.create_relief_mesh(
+            sample_heightmap,
+            width_mm=100.0,
+            height_mm=80.0
+        )
+
+        with pytest.raises(ValueError, match="Unknown output format"):
+            generator.export(str(temp_output_dir / "test.ply"), 'ply')
+
     def test_export_stl_raises_without_mesh(self, temp_output_dir):
         """Test that export raises error without mesh."""
         generator = MeshGenerator()
@@ -179,6 +254,35 @@ class TestMeshGenerator:
         with pytest.raises(ValueError, match="No mesh generated"):
             generator.export_stl(str(temp_output_dir / "test.stl"))
 
+    def test_sample_top_surface_round_trips(self, sample_heightmap, temp_output_dir):
+        """Test that sampling a saved relief recovers its top surface."""
+        generator = MeshGenerator()
+        generator.create_relief_mesh(sample_heightmap, width_mm=100.0, height_mm=80.0)
+        path = generator.export_stl(str(temp_output_dir / 'relief.stl'))
+
+        loaded = MeshGenerator()
+        loaded.load_stl(path)
+        sampled = loaded.sample_top_surface(8, 8)
+
+        assert sampled.shape == (8, 8)
+        assert sampled.dtype == np.float32
+        assert sampled.min() >= 0.5 + 0.16 - 1e-4
+        assert sampled.max() <= 2.0 + 0.16 + 1e-4
+        # Same orientation as the source heightmap
+        assert np.all(np.diff(sampled, axis=0) > 0)
+        assert np.all(np.diff(sampled, axis=1) > 0)
+
+    def test_sample_top_surface_rejects_stacked_parts(self, sample_heightmap):
+        """Test that a mesh with overlapping top surfaces is rejected."""
+        generator = MeshGenerator()
+        relief = generator.create_relief_mesh(sample_heightmap, width_mm=100.0, height_mm=80.0)
+        raised = stl_mesh.Mesh(relief.data.copy())
+        raised.z += 5.0
+        generator.mesh = generator.combine_meshes(relief, raised)
+
+        with pytest.raises(ValueError, match="not a heightfield"):
+            generator.sample_top_surface(8, 8)
+
     def test_get_stats(self, sample_heightmap):
         """Test getting mesh statistics."""
         generator = MeshGenerator()
@@ -199,6 +303,32 @@ class TestMeshGenerator:
         assert 'z_min' in stats
         assert 'z_max' in stats
 
+    def test_check_manifold_finds_holes_and_flipped_faces(self, sample_heightmap):
+        """Test check_manifold counts each kind of defect."""
+        generator = MeshGenerator()
+        relief = generator.create_relief_mesh(sample_heightmap, width_mm=100.0, height_mm=80.0)
+
+        clean = generator.check_manifold()
+        assert clean['face_count'] == len(relief.vectors)
+        assert clean['boundary_edges'] == 0
+        assert clean['holes'] == 0
+        assert clean['flipped_normals'] == 0
+        assert clean['non_manifold_edges'] == 0
+
+        holed = stl_mesh.Mesh(relief.data[1:].copy())
+        generator.mesh = holed
+        result = generator.check_manifold()
+        assert result['boundary_edges'] == 3
+        assert result['holes'] == 1
+
+        flipped = stl_mesh.Mesh(relief.data.copy())
+        flipped.vectors[0] = flipped.vectors[0][::-1].copy()
+        generator.mesh = flipped
+        assert generator.check_manifold()['flipped_normals'] == 1
+
+        generator.mesh = generator.combine_meshes(relief, stl_mesh.Mesh(relief.data[:1].copy()))
+        assert generator.check_manifold()['non_manifold_edges'] == 3
+
     def test_get_stats_empty(self):
         """Test stats with no mesh."""
         generator = MeshGenerator()
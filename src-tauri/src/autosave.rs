@@ -0,0 +1,160 @@
+//! Periodic autosaves for crash recovery.
+//!
+//! Each autosave is written atomically to its own timestamped file in the
+//! autosave directory and only the newest [`AUTOSAVE_SLOTS`] are kept, so a
+//! bad autosave never replaces the last good one. Explicit saves leave a
+//! marker; an autosave is only offered for recovery if it is newer.
+//! Autosaves and the marker share one stamp that never goes backwards, so
+//! their order holds even when the clock doesn't.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::CommandError;
+use crate::fs_util::{now_millis, write_atomic};
+use crate::project::ProjectFile;
+
+/// Subdirectory of the app data directory holding autosaves.
+pub const AUTOSAVE_DIR: &str = "autosave";
+pub const AUTOSAVE_SLOTS: usize = 2;
+const AUTOSAVE_PREFIX: &str = "autosave-";
+const AUTOSAVE_EXTENSION: &str = ".lfp";
+const LAST_SAVE_MARKER: &str = "last-save";
+
+/// Autosaves in `dir` with their timestamps, newest first.
+fn autosaves(dir: &Path) -> Vec<(u64, PathBuf)> {
+    let mut found: Vec<(u64, PathBuf)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let stamp = name
+                .strip_prefix(AUTOSAVE_PREFIX)?
+                .strip_suffix(AUTOSAVE_EXTENSION)?
+                .parse()
+                .ok()?;
+            Some((stamp, entry.path()))
+        })
+        .collect();
+    found.sort_by_key(|(stamp, _)| std::cmp::Reverse(*stamp));
+    found
+}
+
+/// Stamp of the last explicit save, 0 if there was none.
+fn last_save(dir: &Path) -> u64 {
+    std::fs::read_to_string(dir.join(LAST_SAVE_MARKER))
+        .ok()
+        .and_then(|stamp| stamp.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Stamp for the next autosave or explicit save: the current time, but
+/// later than every stamp already in `dir` even if two saves land in the
+/// same millisecond or the clock went back.
+fn next_stamp(dir: &Path) -> u64 {
+    let newest = autosaves(dir).first().map_or(0, |(stamp, _)| *stamp);
+    now_millis().max(newest + 1).max(last_save(dir) + 1)
+}
+
+/// Writes `project_json` as the newest autosave and prunes the old ones.
+pub fn write_autosave(dir: &Path, project_json: &str) -> Result<PathBuf, CommandError> {
+    std::fs::create_dir_all(dir).map_err(|e| CommandError::io(dir.to_string_lossy(), e))?;
+    let stamp = next_stamp(dir);
+    let path = dir.join(format!(
+        "{}{}{}",
+        AUTOSAVE_PREFIX, stamp, AUTOSAVE_EXTENSION
+    ));
+    write_atomic(&path, project_json.as_bytes())
+        .map_err(|e| CommandError::io(path.to_string_lossy(), e))?;
+
+    for (_, old) in autosaves(dir).into_iter().skip(AUTOSAVE_SLOTS) {
+        let _ = std::fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+/// Records that the project was just saved explicitly.
+pub fn mark_saved(dir: &Path) -> Result<(), CommandError> {
+    std::fs::create_dir_all(dir).map_err(|e| CommandError::io(dir.to_string_lossy(), e))?;
+    let marker = dir.join(LAST_SAVE_MARKER);
+    write_atomic(&marker, next_stamp(dir).to_string().as_bytes())
+        .map_err(|e| CommandError::io(marker.to_string_lossy(), e))
+}
+
+/// Contents of the newest autosave that is newer than the last explicit
+/// save and still loads as a project. An autosave that doesn't, e.g. one
+/// cut short by a crash, is skipped in favour of the slot before it.
+pub fn find_recovery(dir: &Path) -> Result<Option<String>, CommandError> {
+    let last_save = last_save(dir);
+    for (_, path) in autosaves(dir)
+        .into_iter()
+        .take_while(|(stamp, _)| *stamp > last_save)
+    {
+        let loaded = std::fs::read_to_string(&path)
+            .map_err(|e| CommandError::io(path.to_string_lossy(), e))
+            .and_then(|json| ProjectFile::parse(&json).map(|_| json));
+        match loaded {
+            Ok(json) => return Ok(Some(json)),
+            Err(e) => eprintln!("Skipping autosave {}: {}", path.display(), e),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn keeps_only_the_newest_autosaves() {
+        let dir = temp_dir("autosave");
+        for i in 0..4 {
+            write_autosave(&dir, &format!("{{\"n\": {}}}", i)).unwrap();
+        }
+
+        assert_eq!(autosaves(&dir).len(), AUTOSAVE_SLOTS);
+        assert_eq!(find_recovery(&dir).unwrap().as_deref(), Some("{\"n\": 3}"));
+    }
+
+    #[test]
+    fn explicit_save_supersedes_older_autosaves() {
+        let dir = temp_dir("autosave-saved");
+        assert_eq!(find_recovery(&dir).unwrap(), None);
+
+        write_autosave(&dir, "{}").unwrap();
+        std::fs::write(dir.join(LAST_SAVE_MARKER), u64::MAX.to_string()).unwrap();
+        assert_eq!(find_recovery(&dir).unwrap(), None);
+
+        std::fs::write(dir.join(LAST_SAVE_MARKER), "0").unwrap();
+        assert_eq!(find_recovery(&dir).unwrap().as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn falls_back_to_the_previous_slot_when_the_newest_is_damaged() {
+        let dir = temp_dir("autosave-damaged");
+        write_autosave(&dir, "{\"n\": 1}").unwrap();
+        write_autosave(&dir, "{\"n\": 2").unwrap();
+        assert_eq!(find_recovery(&dir).unwrap().as_deref(), Some("{\"n\": 1}"));
+    }
+
+    #[test]
+    fn saves_stay_ordered_when_autosaves_run_ahead_of_the_clock() {
+        let dir = temp_dir("autosave-ahead");
+        let ahead = now_millis() + 60_000;
+        std::fs::write(
+            dir.join(format!(
+                "{}{}{}",
+                AUTOSAVE_PREFIX, ahead, AUTOSAVE_EXTENSION
+            )),
+            "{}",
+        )
+        .unwrap();
+
+        mark_saved(&dir).unwrap();
+        assert_eq!(find_recovery(&dir).unwrap(), None);
+
+        write_autosave(&dir, "{\"n\": 1}").unwrap();
+        assert_eq!(find_recovery(&dir).unwrap().as_deref(), Some("{\"n\": 1}"));
+    }
+}
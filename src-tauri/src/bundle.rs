@@ -0,0 +1,273 @@
+//! Self-contained project bundles (`.lfpz`): a zip holding the project, its
+//! source image and an optional thumbnail, so a project can be moved to
+//! another machine without breaking its `imagePath`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use serde::Serialize;
+use serde_json::Value;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::error::CommandError;
+use crate::fs_util::write_atomic;
+use crate::project::{ProjectFile, ProjectLoadResult};
+
+const PROJECT_ENTRY: &str = "project.lfp";
+const THUMBNAIL_ENTRY: &str = "thumbnail.png";
+const IMAGE_ENTRY_STEM: &str = "image";
+
+/// A bundle extracted to a temp directory. `project.imagePath` points at
+/// the extracted image.
+#[derive(Debug, Serialize)]
+pub struct ProjectBundle {
+    pub project: ProjectFile,
+    pub warnings: Vec<String>,
+    pub image_path: String,
+    pub thumbnail_path: Option<String>,
+}
+
+fn zip_error(path: &str, err: zip::result::ZipError) -> CommandError {
+    CommandError::Io {
+        path: Some(path.to_string()),
+        message: err.to_string(),
+    }
+}
+
+/// Decodes an image handed over as plain base64 or as a data URL.
+pub fn decode_image_data(data: &str) -> Result<Vec<u8>, CommandError> {
+    let data = data.split_once("base64,").map_or(data, |(_, rest)| rest);
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| CommandError::serialization(format!("Invalid image data: {}", e)))
+}
+
+/// Writes `project` with `image_path` and an optional PNG thumbnail (plain
+/// base64 or a data URL) into a bundle at `output_path`. `progress` is
+/// called with the fraction done and the stage as each stage starts; the
+/// fractions are coarse, weighted towards the image.
+pub fn write_bundle(
+    mut project: ProjectFile,
+    image_path: &str,
+    thumbnail_base64: Option<&str>,
+    output_path: &str,
+    mut progress: impl FnMut(f64, &'static str),
+) -> Result<(), CommandError> {
+    progress(0.0, "reading_image");
+    let image = std::fs::read(image_path).map_err(|e| CommandError::io(image_path, e))?;
+    let extension = Path::new(image_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("png")
+        .to_ascii_lowercase();
+    let image_entry = format!("{}.{}", IMAGE_ENTRY_STEM, extension);
+    project
+        .data
+        .insert("imagePath".to_string(), Value::String(image_entry.clone()));
+
+    let thumbnail = thumbnail_base64.map(decode_image_data).transpose()?;
+
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut add = |name: &str, options, bytes: &[u8]| -> Result<(), CommandError> {
+        zip.start_file(name, options)
+            .map_err(|e| zip_error(output_path, e))?;
+        zip.write_all(bytes)
+            .map_err(|e| CommandError::io(output_path, e))
+    };
+    progress(0.25, "writing_project");
+    add(PROJECT_ENTRY, deflated, project.to_json()?.as_bytes())?;
+    // Images are already compressed, so they are stored as they are
+    progress(0.3, "packing_image");
+    add(&image_entry, stored, &image)?;
+    if let Some(thumbnail) = &thumbnail {
+        progress(0.7, "writing_thumbnail");
+        add(THUMBNAIL_ENTRY, stored, thumbnail)?;
+    }
+    let bytes = zip
+        .finish()
+        .map_err(|e| zip_error(output_path, e))?
+        .into_inner();
+
+    progress(0.75, "saving");
+    write_atomic(Path::new(output_path), &bytes).map_err(|e| CommandError::io(output_path, e))?;
+    progress(1.0, "done");
+    Ok(())
+}
+
+/// Directory a bundle is extracted to; stable per bundle path so reopening
+/// the same bundle reuses it.
+fn extract_dir(input_path: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    input_path.hash(&mut hasher);
+    std::env::temp_dir()
+        .join("layerforge-bundles")
+        .join(format!("{:016x}", hasher.finish()))
+}
+
+/// Extracts the bundle at `input_path` and loads its project. Only the
+/// entries a bundle is known to contain are extracted. `progress` is called
+/// as in [`write_bundle`].
+pub fn read_bundle(
+    input_path: &str,
+    mut progress: impl FnMut(f64, &'static str),
+) -> Result<ProjectBundle, CommandError> {
+    progress(0.0, "reading_project");
+    let file = File::open(input_path).map_err(|e| CommandError::io(input_path, e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| zip_error(input_path, e))?;
+    let dir = extract_dir(input_path);
+    std::fs::create_dir_all(&dir).map_err(|e| CommandError::io(dir.to_string_lossy(), e))?;
+
+    let mut read_entry = |name: &str| -> Result<Vec<u8>, CommandError> {
+        let mut entry = archive
+            .by_name(name)
+            .map_err(|e| zip_error(input_path, e))?;
+        let mut bytes = Vec::new();
+        entry
+            .read_to_end(&mut bytes)
+            .map_err(|e| CommandError::io(input_path, e))?;
+        Ok(bytes)
+    };
+
+    let json = String::from_utf8(read_entry(PROJECT_ENTRY)?)
+        .map_err(|e| CommandError::serialization(e.to_string()))?;
+    let ProjectLoadResult {
+        mut project,
+        warnings,
+        ..
+    } = ProjectLoadResult::from_json(&json)?;
+
+    let image_entry = project
+        .data
+        .get("imagePath")
+        .and_then(Value::as_str)
+        .filter(|name| {
+            Path::new(name).parent() == Some(Path::new(""))
+                && Path::new(name).file_stem() == Some(IMAGE_ENTRY_STEM.as_ref())
+        })
+        .map(str::to_string)
+        .ok_or_else(|| CommandError::Io {
+            path: Some(input_path.to_string()),
+            message: "Bundle has no image".to_string(),
+        })?;
+    progress(0.2, "extracting_image");
+    let image_path = dir.join(&image_entry);
+    write_atomic(&image_path, &read_entry(&image_entry)?)
+        .map_err(|e| CommandError::io(image_path.to_string_lossy(), e))?;
+    let image_path = image_path.to_string_lossy().into_owned();
+    project
+        .data
+        .insert("imagePath".to_string(), Value::String(image_path.clone()));
+
+    let thumbnail_path = match read_entry(THUMBNAIL_ENTRY) {
+        Ok(bytes) => {
+            progress(0.8, "extracting_thumbnail");
+            let path = dir.join(THUMBNAIL_ENTRY);
+            write_atomic(&path, &bytes).map_err(|e| CommandError::io(path.to_string_lossy(), e))?;
+            Some(path.to_string_lossy().into_owned())
+        }
+        Err(_) => None,
+    };
+
+    progress(1.0, "done");
+    Ok(ProjectBundle {
+        project,
+        warnings,
+        image_path,
+        thumbnail_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn project() -> ProjectFile {
+        ProjectFile::parse_current(r#"{"imagePath": "/elsewhere/photo.PNG", "filaments": []}"#)
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips_project_image_and_thumbnail() {
+        let dir = temp_dir("bundle");
+        let image = dir.join("photo.PNG");
+        std::fs::write(&image, b"image bytes").unwrap();
+        let bundle_path = dir.join("photo.lfpz").to_string_lossy().into_owned();
+        let thumbnail = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(b"thumb")
+        );
+
+        let mut stages = Vec::new();
+        write_bundle(
+            project(),
+            &image.to_string_lossy(),
+            Some(&thumbnail),
+            &bundle_path,
+            |progress, stage| stages.push((progress, stage)),
+        )
+        .unwrap();
+        let bundle = read_bundle(&bundle_path, |progress, stage| {
+            stages.push((progress, stage))
+        })
+        .unwrap();
+
+        assert!(bundle.image_path.ends_with("image.png"));
+        assert_eq!(std::fs::read(&bundle.image_path).unwrap(), b"image bytes");
+        assert_eq!(
+            bundle.project.data["imagePath"].as_str(),
+            Some(bundle.image_path.as_str())
+        );
+        let thumbnail_path = bundle.thumbnail_path.unwrap();
+        assert_eq!(std::fs::read(thumbnail_path).unwrap(), b"thumb");
+
+        // Each of the save and the load climbs from 0 to 1
+        let (save, load) = stages.split_at(stages.iter().rposition(|s| s.0 == 0.0).unwrap());
+        for run in [save, load] {
+            assert!(run.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert_eq!(run.last(), Some(&(1.0, "done")));
+        }
+        assert!(save.iter().any(|s| s.1 == "writing_thumbnail"));
+        assert!(load.iter().any(|s| s.1 == "extracting_thumbnail"));
+    }
+
+    #[test]
+    fn thumbnail_is_optional() {
+        let dir = temp_dir("bundle-no-thumb");
+        let image = dir.join("photo.jpg");
+        std::fs::write(&image, b"jpeg").unwrap();
+        let bundle_path = dir.join("photo.lfpz").to_string_lossy().into_owned();
+
+        write_bundle(
+            project(),
+            &image.to_string_lossy(),
+            None,
+            &bundle_path,
+            |_, _| {},
+        )
+        .unwrap();
+        let bundle = read_bundle(&bundle_path, |_, _| {}).unwrap();
+
+        assert!(bundle.thumbnail_path.is_none());
+        assert!(bundle.image_path.ends_with("image.jpg"));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_bundles() {
+        let dir = temp_dir("bundle-invalid");
+        let path = dir.join("plain.lfpz");
+        std::fs::write(&path, b"{}").unwrap();
+
+        assert!(matches!(
+            read_bundle(&path.to_string_lossy(), |_, _| {}),
+            Err(CommandError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,228 @@
+//! In-memory cache of processed heightmaps, so re-running `process_image`
+//! with an image and settings it has already seen skips the sidecar.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::commands::ProcessImageResponse;
+use crate::error::CommandError;
+
+pub const MAX_CACHED_HEIGHTMAPS: usize = 16;
+const MAX_CACHED_FILE_HASHES: usize = 64;
+
+/// Map that evicts its least recently used entry once `capacity` is reached.
+struct LruMap<K, V> {
+    capacity: usize,
+    entries: HashMap<K, V>,
+    order: VecDeque<K>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruMap<K, V> {
+    fn new(capacity: usize) -> Self {
+        LruMap {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(pos).unwrap();
+            self.order.push_back(key);
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if self.entries.contains_key(key) {
+            self.touch(key);
+        }
+        self.entries.get(key)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.entries.insert(key.clone(), value).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Size and modification time, used to tell whether a file changed since it
+/// was last hashed without reading it again.
+#[derive(Debug, Clone, PartialEq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HeightmapKey {
+    image_hash: u64,
+    settings: String,
+}
+
+struct CacheState {
+    file_hashes: LruMap<PathBuf, (FileStamp, u64)>,
+    heightmaps: LruMap<HeightmapKey, ProcessImageResponse>,
+}
+
+pub struct HeightmapCache {
+    state: Mutex<CacheState>,
+}
+
+impl Default for HeightmapCache {
+    fn default() -> Self {
+        HeightmapCache::new(MAX_CACHED_HEIGHTMAPS)
+    }
+}
+
+impl HeightmapCache {
+    pub fn new(capacity: usize) -> Self {
+        HeightmapCache {
+            state: Mutex::new(CacheState {
+                file_hashes: LruMap::new(MAX_CACHED_FILE_HASHES),
+                heightmaps: LruMap::new(capacity),
+            }),
+        }
+    }
+
+    /// Builds the cache key for processing `path` with `settings`, the
+    /// request fields that affect the heightmap. The image is only read if
+    /// it changed since it was last hashed.
+    pub fn key(&self, path: &str, settings: &impl Serialize) -> Result<HeightmapKey, CommandError> {
+        Ok(HeightmapKey {
+            image_hash: self.file_hash(path)?,
+            settings: serde_json::to_string(settings)?,
+        })
+    }
+
+    /// Hash of the contents of the file at `path`, read again only if the
+    /// file changed since it was last hashed.
+    pub fn file_hash(&self, path: &str) -> Result<u64, CommandError> {
+        let metadata = std::fs::metadata(path).map_err(|e| CommandError::io(path, e))?;
+        let stamp = FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        };
+        let path_buf = PathBuf::from(path);
+
+        let cached = match self.state.lock().unwrap().file_hashes.get(&path_buf) {
+            Some((cached_stamp, hash)) if *cached_stamp == stamp => Some(*hash),
+            _ => None,
+        };
+        match cached {
+            Some(hash) => Ok(hash),
+            None => {
+                let hash = hash_file(path)?;
+                self.state
+                    .lock()
+                    .unwrap()
+                    .file_hashes
+                    .insert(path_buf, (stamp, hash));
+                Ok(hash)
+            }
+        }
+    }
+
+    pub fn get(&self, key: &HeightmapKey) -> Option<ProcessImageResponse> {
+        self.state.lock().unwrap().heightmaps.get(key).cloned()
+    }
+
+    pub fn insert(&self, key: HeightmapKey, response: ProcessImageResponse) {
+        self.state.lock().unwrap().heightmaps.insert(key, response);
+    }
+}
+
+fn hash_file(path: &str) -> Result<u64, CommandError> {
+    let bytes = std::fs::read(path).map_err(|e| CommandError::io(path, e))?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelGeometrySettings;
+    use crate::test_util::temp_dir;
+
+    fn geometry(gamma: f64) -> ModelGeometrySettings {
+        ModelGeometrySettings {
+            gamma,
+            ..crate::test_util::geometry()
+        }
+    }
+
+    fn response(width: u32) -> ProcessImageResponse {
+        ProcessImageResponse {
+            heightmap_base64: String::new(),
+            width,
+            height: 1,
+            heightmap_path: None,
+            histogram: None,
+            actual_min_depth_mm: None,
+            actual_max_depth_mm: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut map = LruMap::new(2);
+        map.insert("a", 1);
+        map.insert("b", 2);
+        assert_eq!(map.get(&"a"), Some(&1));
+        map.insert("c", 3);
+
+        assert_eq!(map.get(&"b"), None);
+        assert_eq!(map.get(&"a"), Some(&1));
+        assert_eq!(map.get(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn key_depends_on_image_contents_and_geometry() {
+        let cache = HeightmapCache::default();
+        let path = temp_dir("cache-key").join("key.png");
+        std::fs::write(&path, b"first").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let first = cache.key(&path, &geometry(1.0)).unwrap();
+        assert_eq!(cache.key(&path, &geometry(1.0)).unwrap(), first);
+        assert_ne!(cache.key(&path, &geometry(2.0)).unwrap(), first);
+
+        std::fs::write(&path, b"second, longer").unwrap();
+        assert_ne!(
+            cache.key(&path, &geometry(1.0)).unwrap().image_hash,
+            first.image_hash
+        );
+    }
+
+    #[test]
+    fn returns_cached_responses() {
+        let cache = HeightmapCache::new(1);
+        let path = temp_dir("cache-hit").join("cached.png");
+        std::fs::write(&path, b"image").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let key = cache.key(&path, &geometry(1.0)).unwrap();
+        assert!(cache.get(&key).is_none());
+
+        cache.insert(key.clone(), response(7));
+        assert_eq!(cache.get(&key).unwrap().width, 7);
+
+        cache.insert(cache.key(&path, &geometry(3.0)).unwrap(), response(8));
+        assert!(cache.get(&key).is_none());
+    }
+}
@@ -0,0 +1,207 @@
+//! Perceptual color distance between filaments, computed without the
+//! sidecar.
+
+use serde::Serialize;
+
+use crate::error::CommandError;
+use crate::models::Filament;
+use crate::validation::{normalize_filament_colors, normalize_hex};
+
+/// A library filament with its CIEDE2000 distance from the target color.
+#[derive(Debug, Serialize)]
+pub struct FilamentMatch {
+    #[serde(flatten)]
+    pub filament: Filament,
+    pub distance: f64,
+}
+
+/// CIELAB coordinates under D65.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+/// Converts a hex color (anything [`normalize_hex`] accepts) to CIELAB.
+pub fn hex_to_lab(hex: &str) -> Result<Lab, CommandError> {
+    let hex = normalize_hex(hex)?;
+    let channel = |i: usize| {
+        let v = u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0) as f64 / 255.0;
+        if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (channel(1), channel(3), channel(5));
+
+    // Linear sRGB to XYZ, normalized by the D65 white point
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    Ok(Lab {
+        l: 116.0 * fy - 16.0,
+        a: 500.0 * (fx - fy),
+        b: 200.0 * (fy - fz),
+    })
+}
+
+/// CIEDE2000 color difference with the standard weights (kL = kC = kH = 1).
+pub fn ciede2000(lab1: Lab, lab2: Lab) -> f64 {
+    let c1 = lab1.a.hypot(lab1.b);
+    let c2 = lab2.a.hypot(lab2.b);
+    let c_bar7 = ((c1 + c2) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + 25f64.powi(7))).sqrt());
+
+    let a1 = (1.0 + g) * lab1.a;
+    let a2 = (1.0 + g) * lab2.a;
+    let c1 = a1.hypot(lab1.b);
+    let c2 = a2.hypot(lab2.b);
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let h1 = hue(lab1.b, a1);
+    let h2 = hue(lab2.b, a2);
+
+    let dl = lab2.l - lab1.l;
+    let dc = c2 - c1;
+    let dh = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let dh_big = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+    let l_bar = (lab1.l + lab2.l) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let d_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let c_bar7 = c_bar.powi(7);
+    let r_c = 2.0 * (c_bar7 / (c_bar7 + 25f64.powi(7))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -(2.0 * d_theta).to_radians().sin() * r_c;
+
+    ((dl / s_l).powi(2)
+        + (dc / s_c).powi(2)
+        + (dh_big / s_h).powi(2)
+        + r_t * (dc / s_c) * (dh_big / s_h))
+        .sqrt()
+}
+
+/// The `n` filaments in `library` closest to `target_hex`, nearest first.
+pub fn closest_filaments(
+    target_hex: &str,
+    mut library: Vec<Filament>,
+    n: usize,
+) -> Result<Vec<FilamentMatch>, CommandError> {
+    let target = hex_to_lab(target_hex)?;
+    normalize_filament_colors(&mut library)?;
+    let mut matches = library
+        .into_iter()
+        .map(|filament| {
+            let distance = ciede2000(target, hex_to_lab(&filament.hex_color)?);
+            Ok(FilamentMatch { filament, distance })
+        })
+        .collect::<Result<Vec<_>, CommandError>>()?;
+    matches.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    matches.truncate(n);
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lab(l: f64, a: f64, b: f64) -> Lab {
+        Lab { l, a, b }
+    }
+
+    #[test]
+    fn matches_published_ciede2000_values() {
+        // Test pairs from Sharma, Wu and Dalal (2005)
+        let pairs = [
+            (
+                lab(50.0, 2.6772, -79.7751),
+                lab(50.0, 0.0, -82.7485),
+                2.0425,
+            ),
+            (lab(50.0, 0.0, 0.0), lab(50.0, -1.0, 2.0), 2.3669),
+            (lab(50.0, 2.5, 0.0), lab(73.0, 25.0, -18.0), 27.1492),
+            (
+                lab(2.0776, 0.0795, -1.1350),
+                lab(0.9033, -0.0636, -0.5514),
+                0.9082,
+            ),
+        ];
+        for (a, b, expected) in pairs {
+            assert!((ciede2000(a, b) - expected).abs() < 1e-4, "{:?} {:?}", a, b);
+            assert!((ciede2000(b, a) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn converts_hex_to_lab() {
+        let white = hex_to_lab("#FFF").unwrap();
+        assert!((white.l - 100.0).abs() < 0.01 && white.a.abs() < 0.01);
+        let red = hex_to_lab("ff0000").unwrap();
+        assert!((red.l - 53.24).abs() < 0.01);
+        assert!((red.a - 80.09).abs() < 0.01);
+        assert!((red.b - 67.20).abs() < 0.01);
+    }
+
+    #[test]
+    fn returns_the_nearest_filaments_first() {
+        let filament = |id: &str, hex_color: &str| Filament {
+            id: id.to_string(),
+            name: id.to_string(),
+            hex_color: hex_color.to_string(),
+            td: 1.0,
+            enabled: true,
+            order_index: 0,
+        };
+        let library = vec![
+            filament("white", "#FFFFFF"),
+            filament("crimson", "#DC143C"),
+            filament("black", "#000000"),
+            filament("red", "#FF0000"),
+        ];
+
+        let matches = closest_filaments("#F00", library, 2).unwrap();
+        let ids: Vec<&str> = matches.iter().map(|m| m.filament.id.as_str()).collect();
+        assert_eq!(ids, ["red", "crimson"]);
+        assert!(matches[0].distance < 1e-6);
+        assert!(closest_filaments("red", Vec::new(), 3).is_err());
+    }
+}
@@ -0,0 +1,1032 @@
+//! Color stop and swap logic that runs on the Rust side of the sidecar.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::models::{ColorStop, Filament, ModelGeometrySettings, SwapEntry};
+use crate::plan::layers_for_height;
+
+/// Gap used when a request doesn't set one. Swaps are never closer than
+/// one layer apart, so this never warns.
+pub const DEFAULT_MIN_SWAP_GAP_LAYERS: u32 = 1;
+
+/// Stops closer together than this are treated as the same stop.
+pub const STOP_EPSILON_MM: f64 = 1e-3;
+
+/// A library filament picked for the image, with the CIEDE2000 distance
+/// between it and the dominant color it was matched to.
+#[derive(Debug, Serialize)]
+pub struct PaletteMatch {
+    #[serde(flatten)]
+    pub filament: Filament,
+    pub distance: f64,
+}
+
+/// Match reported by the sidecar's `suggest_palette` method.
+#[derive(Debug, Deserialize)]
+pub struct SidecarPaletteMatch {
+    pub filament_id: String,
+    pub distance: f64,
+}
+
+/// Turns the thresholds the sidecar found in the heightmap into color stops.
+///
+/// Thresholds are clamped to the geometry's depth range and assigned in
+/// ascending order to the enabled filaments sorted by `order_index`. Extra
+/// thresholds beyond the number of enabled filaments are dropped.
+pub fn stops_from_thresholds(
+    thresholds: &[f64],
+    filaments: &[Filament],
+    geometry: &ModelGeometrySettings,
+) -> Vec<ColorStop> {
+    let (low, high) = if geometry.min_depth_mm <= geometry.max_depth_mm {
+        (geometry.min_depth_mm, geometry.max_depth_mm)
+    } else {
+        (geometry.max_depth_mm, geometry.min_depth_mm)
+    };
+    let mut thresholds: Vec<f64> = thresholds
+        .iter()
+        .filter(|t| t.is_finite())
+        .map(|t| t.clamp(low, high))
+        .collect();
+    thresholds.sort_by(f64::total_cmp);
+
+    let mut enabled: Vec<&Filament> = filaments.iter().filter(|f| f.enabled).collect();
+    enabled.sort_by_key(|f| f.order_index);
+
+    enabled
+        .into_iter()
+        .zip(thresholds)
+        .map(|(filament, threshold_z_mm)| ColorStop {
+            filament_id: filament.id.clone(),
+            threshold_z_mm,
+        })
+        .collect()
+}
+
+/// Relative luminance of a `#RRGGBB` color, or `None` if it doesn't parse.
+fn hex_luminance(hex: &str) -> Option<f64> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if digits.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(digits.get(i..i + 2)?, 16)
+            .ok()
+            .map(|v| v as f64 / 255.0)
+    };
+    Some(0.2126 * channel(0)? + 0.7152 * channel(2)? + 0.0722 * channel(4)?)
+}
+
+/// Resolves the sidecar's matches against `library` and orders the picked
+/// filaments light to dark (then by `td`), renumbering `order_index` so they
+/// can be dropped into a project as-is. A filament matched to several
+/// dominant colors keeps its best distance.
+pub fn palette_from_matches(
+    matches: &[SidecarPaletteMatch],
+    library: &[Filament],
+) -> Vec<PaletteMatch> {
+    let mut palette: Vec<PaletteMatch> = Vec::new();
+    for m in matches {
+        let Some(filament) = library.iter().find(|f| f.id == m.filament_id) else {
+            continue;
+        };
+        match palette.iter_mut().find(|p| p.filament.id == filament.id) {
+            Some(existing) => existing.distance = existing.distance.min(m.distance),
+            None => palette.push(PaletteMatch {
+                filament: filament.clone(),
+                distance: m.distance,
+            }),
+        }
+    }
+
+    palette.sort_by(|a, b| {
+        let lum = |p: &PaletteMatch| hex_luminance(&p.filament.hex_color).unwrap_or(0.0);
+        lum(b)
+            .total_cmp(&lum(a))
+            .then(a.filament.td.total_cmp(&b.filament.td))
+    });
+    for (index, entry) in palette.iter_mut().enumerate() {
+        entry.filament.order_index = index as i32;
+        entry.filament.enabled = true;
+    }
+    palette
+}
+
+/// Why a stop was left out of a swap plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// No filament in the request has the stop's id.
+    MissingFilament,
+    DisabledFilament,
+    /// An earlier stop already swaps on the layer this one lands on.
+    SameLayer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedStop {
+    pub filament_id: String,
+    pub threshold_z_mm: f64,
+    pub reason: SkipReason,
+}
+
+impl SkippedStop {
+    fn new(stop: &ColorStop, reason: SkipReason) -> Self {
+        Self {
+            filament_id: stop.filament_id.clone(),
+            threshold_z_mm: stop.threshold_z_mm,
+            reason,
+        }
+    }
+}
+
+/// Splits `stops` into those whose filament is in `filaments` and enabled,
+/// and the rest, which are returned as skipped.
+pub fn drop_unusable_stops(
+    stops: Vec<ColorStop>,
+    filaments: &[Filament],
+) -> (Vec<ColorStop>, Vec<SkippedStop>) {
+    let mut skipped = Vec::new();
+    let usable = stops
+        .into_iter()
+        .filter(|stop| {
+            let reason = match filaments.iter().find(|f| f.id == stop.filament_id) {
+                Some(filament) if filament.enabled => return true,
+                Some(_) => SkipReason::DisabledFilament,
+                None => SkipReason::MissingFilament,
+            };
+            skipped.push(SkippedStop::new(stop, reason));
+            false
+        })
+        .collect();
+    (usable, skipped)
+}
+
+/// Rejects a depth range [`normalize_stops`] can't clamp into.
+pub fn validate_depth_range(min_depth_mm: f64, max_depth_mm: f64) -> Result<(), CommandError> {
+    for (field, value) in [
+        ("min_depth_mm", min_depth_mm),
+        ("max_depth_mm", max_depth_mm),
+    ] {
+        if !value.is_finite() {
+            return Err(CommandError::invalid_settings(field, "must be a number"));
+        }
+    }
+    if min_depth_mm > max_depth_mm {
+        return Err(CommandError::invalid_settings(
+            "min_depth_mm",
+            format!(
+                "{} mm is greater than max_depth_mm ({} mm)",
+                min_depth_mm, max_depth_mm
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Sorts `stops` by threshold, clamps them into the depth range and
+/// collapses stops at the same height, keeping the one defined last.
+/// Returns the cleaned stops and a warning for each stop that was clamped.
+/// The range must pass [`validate_depth_range`].
+pub fn normalize_stops(
+    stops: &[ColorStop],
+    min_depth_mm: f64,
+    max_depth_mm: f64,
+) -> (Vec<ColorStop>, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut clamped: Vec<ColorStop> = stops
+        .iter()
+        .map(|stop| {
+            let z = stop.threshold_z_mm.clamp(min_depth_mm, max_depth_mm);
+            if z != stop.threshold_z_mm {
+                warnings.push(format!(
+                    "Stop for {} at {} mm is outside {}-{} mm and was moved to {} mm",
+                    stop.filament_id, stop.threshold_z_mm, min_depth_mm, max_depth_mm, z
+                ));
+            }
+            ColorStop {
+                filament_id: stop.filament_id.clone(),
+                threshold_z_mm: z,
+            }
+        })
+        .collect();
+    // Stable, so stops at the same height stay in definition order
+    clamped.sort_by(|a, b| a.threshold_z_mm.total_cmp(&b.threshold_z_mm));
+
+    let mut cleaned: Vec<ColorStop> = Vec::with_capacity(clamped.len());
+    for stop in clamped {
+        match cleaned.last_mut() {
+            Some(last) if stop.threshold_z_mm - last.threshold_z_mm < STOP_EPSILON_MM => {
+                last.filament_id = stop.filament_id
+            }
+            _ => cleaned.push(stop),
+        }
+    }
+    (cleaned, warnings)
+}
+
+/// Collapses stops less than `tolerance_mm` above the lowest stop of their
+/// group into it. The merged stop keeps that lowest threshold and the
+/// filament of whichever stop in the group was listed last in `stops`.
+/// Returns the stops sorted by threshold.
+pub fn merge_close_stops(stops: &[ColorStop], tolerance_mm: f64) -> Vec<ColorStop> {
+    let mut indexed: Vec<(usize, &ColorStop)> = stops.iter().enumerate().collect();
+    indexed.sort_by(|a, b| a.1.threshold_z_mm.total_cmp(&b.1.threshold_z_mm));
+
+    let mut merged: Vec<(usize, ColorStop)> = Vec::with_capacity(indexed.len());
+    for (index, stop) in indexed {
+        match merged.last_mut() {
+            Some((last_index, last))
+                if stop.threshold_z_mm - last.threshold_z_mm < tolerance_mm =>
+            {
+                if index > *last_index {
+                    *last_index = index;
+                    last.filament_id = stop.filament_id.clone();
+                }
+            }
+            _ => merged.push((index, stop.clone())),
+        }
+    }
+    merged.into_iter().map(|(_, stop)| stop).collect()
+}
+
+/// Height range drawn with one filament in a per-layer preview.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreviewBand {
+    pub filament_id: String,
+    pub from_z_mm: f64,
+    pub to_z_mm: f64,
+}
+
+/// One band of a per-layer preview, rendered on its own.
+#[derive(Debug, Serialize)]
+pub struct LayerPreview {
+    #[serde(flatten)]
+    pub band: PreviewBand,
+    pub image_base64: String,
+}
+
+/// Bands between consecutive stops, lowest first. Each stop's filament
+/// runs from the previous threshold (or `min_depth_mm`) up to its own; the
+/// last one runs to `max_depth_mm` so the whole relief is covered. Bands
+/// left empty by clamping to the depth range are dropped.
+pub fn preview_bands(stops: &[ColorStop], geometry: &ModelGeometrySettings) -> Vec<PreviewBand> {
+    let mut stops: Vec<&ColorStop> = stops.iter().collect();
+    stops.sort_by(|a, b| a.threshold_z_mm.total_cmp(&b.threshold_z_mm));
+    let (min, max) = (geometry.min_depth_mm, geometry.max_depth_mm);
+
+    let mut bands = Vec::new();
+    let mut from = min;
+    for (index, stop) in stops.iter().enumerate() {
+        let to = if index + 1 == stops.len() {
+            max
+        } else {
+            stop.threshold_z_mm.clamp(min, max)
+        };
+        if to > from {
+            bands.push(PreviewBand {
+                filament_id: stop.filament_id.clone(),
+                from_z_mm: from,
+                to_z_mm: to,
+            });
+            from = to;
+        }
+    }
+    bands
+}
+
+/// Layers printed at `layer_height_mm` from `from_z_mm` (above the base)
+/// up to the next segment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerHeightSegment {
+    pub from_z_mm: f64,
+    pub layer_height_mm: f64,
+}
+
+/// Thinnest layer a profile segment may use. Anything thinner is a typo no
+/// printer can follow, and would slice a relief into millions of layers.
+const MIN_LAYER_HEIGHT_MM: f64 = 0.01;
+
+/// Bottom of each layer sliced with `profile`, up to the first one at or
+/// above `max_z_mm`. Segments may be given in any order; heights below
+/// the first segment use its layer height.
+fn layer_bottoms(profile: &[LayerHeightSegment], max_z_mm: f64) -> Result<Vec<f64>, CommandError> {
+    let mut segments = profile.to_vec();
+    segments.sort_by(|a, b| a.from_z_mm.total_cmp(&b.from_z_mm));
+    if segments.is_empty()
+        || segments
+            .iter()
+            .any(|s| s.layer_height_mm.is_nan() || s.layer_height_mm < MIN_LAYER_HEIGHT_MM)
+    {
+        return Err(CommandError::invalid_settings(
+            "layer_profile",
+            format!(
+                "needs at least one segment and every layer height must be at least {} mm",
+                MIN_LAYER_HEIGHT_MM
+            ),
+        ));
+    }
+    if !max_z_mm.is_finite() {
+        return Err(CommandError::invalid_settings(
+            "stops",
+            format!("thresholds must be finite, got {} mm", max_z_mm),
+        ));
+    }
+
+    let mut bottoms = Vec::new();
+    let mut z = 0.0;
+    loop {
+        bottoms.push(z);
+        if z >= max_z_mm - 1e-9 {
+            break;
+        }
+        let segment = segments
+            .iter()
+            .rev()
+            .find(|s| s.from_z_mm <= z + 1e-9)
+            .unwrap_or(&segments[0]);
+        z += segment.layer_height_mm;
+    }
+    Ok(bottoms)
+}
+
+/// Swaps for `stops` on a variable layer height `profile`, assigned like
+/// the sidecar does for a constant height: each stop swaps on the first
+/// layer starting at or above its threshold, and a stop landing on an
+/// earlier swap's layer is skipped.
+pub fn swaps_for_profile(
+    stops: &[ColorStop],
+    profile: &[LayerHeightSegment],
+) -> Result<(Vec<SwapEntry>, Vec<SkippedStop>), CommandError> {
+    let mut stops: Vec<&ColorStop> = stops.iter().collect();
+    stops.sort_by(|a, b| a.threshold_z_mm.total_cmp(&b.threshold_z_mm));
+    let max_z = stops.last().map_or(0.0, |stop| stop.threshold_z_mm);
+    let bottoms = layer_bottoms(profile, max_z)?;
+
+    let mut swaps: Vec<SwapEntry> = Vec::new();
+    let mut skipped = Vec::new();
+    for stop in stops {
+        let layer = bottoms
+            .iter()
+            .position(|bottom| *bottom >= stop.threshold_z_mm - 1e-9)
+            .unwrap_or(bottoms.len() - 1);
+        if swaps.last().is_some_and(|last| layer as i32 <= last.layer) {
+            skipped.push(SkippedStop::new(stop, SkipReason::SameLayer));
+            continue;
+        }
+        swaps.push(SwapEntry {
+            layer: layer as i32,
+            z_mm: (bottoms[layer] * 1000.0).round() / 1000.0,
+            filament_id: stop.filament_id.clone(),
+        });
+    }
+    Ok((swaps, skipped))
+}
+
+/// Reorders `filaments` by `td` and renumbers `order_index` to match.
+/// Ascending puts the most opaque filament first, at the bottom of the
+/// print. Equal `td`s keep their current order.
+pub fn sort_by_td(mut filaments: Vec<Filament>, ascending: bool) -> Vec<Filament> {
+    filaments.sort_by(|a, b| {
+        let by_td = a.td.total_cmp(&b.td);
+        let by_td = if ascending { by_td } else { by_td.reverse() };
+        by_td.then(a.order_index.cmp(&b.order_index))
+    });
+    for (index, filament) in filaments.iter_mut().enumerate() {
+        filament.order_index = index as i32;
+    }
+    filaments
+}
+
+/// What a plan asks of the person at the printer.
+#[derive(Debug, Serialize)]
+pub struct SwapSummary {
+    /// Swaps left after merging consecutive ones to the same filament.
+    pub swap_count: usize,
+    /// Names of the filaments in the order they are loaded.
+    pub sequence: Vec<String>,
+    /// Filament changes made during the print. The first filament is loaded
+    /// before it starts, so this is one less than the sequence length.
+    pub filament_changes: usize,
+}
+
+/// Summarizes `swaps` in print order. Filaments missing from `filaments`
+/// are listed by id.
+pub fn summarize_swaps(swaps: &[SwapEntry], filaments: &[Filament]) -> SwapSummary {
+    let mut ordered: Vec<&SwapEntry> = swaps.iter().collect();
+    ordered.sort_by(|a, b| a.z_mm.total_cmp(&b.z_mm));
+    ordered.dedup_by(|next, previous| next.filament_id == previous.filament_id);
+
+    let sequence: Vec<String> = ordered
+        .iter()
+        .map(|swap| {
+            filaments
+                .iter()
+                .find(|f| f.id == swap.filament_id)
+                .map_or_else(|| swap.filament_id.clone(), |f| f.name.clone())
+        })
+        .collect();
+    SwapSummary {
+        swap_count: sequence.len(),
+        filament_changes: sequence.len().saturating_sub(1),
+        sequence,
+    }
+}
+
+/// Sorts `swaps` by height, recomputes their layer numbers from
+/// `layer_height_mm` the same way the sidecar assigns them, and returns a
+/// warning for each consecutive pair closer than `min_gap_layers`. A
+/// `layer_height_mm` of 0 keeps the layers as they are, for swaps on a
+/// variable layer height profile.
+pub fn check_swap_spacing(
+    swaps: &mut [SwapEntry],
+    layer_height_mm: f64,
+    min_gap_layers: u32,
+) -> Vec<String> {
+    swaps.sort_by(|a, b| a.z_mm.total_cmp(&b.z_mm));
+    if layer_height_mm > 0.0 {
+        for swap in swaps.iter_mut() {
+            swap.layer = layers_for_height(swap.z_mm, layer_height_mm);
+        }
+    }
+
+    swaps
+        .windows(2)
+        .filter(|pair| pair[1].layer - pair[0].layer < min_gap_layers as i32)
+        .map(|pair| {
+            format!(
+                "Swaps to {} at layer {} and {} at layer {} are {} layer(s) apart, less than the minimum of {}",
+                pair[0].filament_id,
+                pair[0].layer,
+                pair[1].filament_id,
+                pair[1].layer,
+                pair[1].layer - pair[0].layer,
+                min_gap_layers
+            )
+        })
+        .collect()
+}
+
+/// Share of the one-band error an extra band has to remove to be worth the
+/// swap it adds.
+pub const MIN_BAND_GAIN: f64 = 0.05;
+
+/// How closely `count` color bands follow a heightmap, as scored by the
+/// sidecar's `optimize_swap_count`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapCountScore {
+    pub count: usize,
+    /// RMS distance in mm from each pixel's depth to the mean of its band.
+    pub error_mm: f64,
+    /// Stop thresholds for this count, lowest first.
+    pub thresholds: Vec<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SwapOptimization {
+    pub recommended_count: usize,
+    pub scores: Vec<SwapCountScore>,
+    /// Stops for the recommended count.
+    pub stops: Vec<ColorStop>,
+}
+
+/// The smallest count after which one more band removes no more than
+/// [`MIN_BAND_GAIN`] of the one-band error, or the largest count if every
+/// band still pays off. `scores` are sorted by count; an empty list gives 0.
+pub fn recommend_swap_count(scores: &[SwapCountScore]) -> usize {
+    let Some(first) = scores.first() else {
+        return 0;
+    };
+    let min_gain = first.error_mm * MIN_BAND_GAIN;
+    scores
+        .windows(2)
+        .find(|pair| pair[0].error_mm - pair[1].error_mm <= min_gain)
+        .map_or_else(|| scores[scores.len() - 1].count, |pair| pair[0].count)
+}
+
+/// Luminance drop between a band and the one below it that is still
+/// treated as the same brightness.
+pub const LUMINANCE_TOLERANCE: f64 = 0.05;
+
+/// Whether a stacking order can show its colors, with one warning per
+/// problem found.
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub warnings: Vec<String>,
+}
+
+/// Checks the bands `stops` stack, bottom to top, against how light passes
+/// through them. Each filament has to be at least as translucent (higher
+/// `td`) and about as light as the one below it, or it hides the colors it
+/// is printed over. Stops should also follow `order_index`, which is the
+/// order `stops_from_thresholds` assigns them in.
+pub fn validate_filament_order(filaments: &[Filament], stops: &[ColorStop]) -> ValidationReport {
+    let mut warnings = Vec::new();
+    let mut ordered: Vec<&ColorStop> = stops.iter().collect();
+    ordered.sort_by(|a, b| a.threshold_z_mm.total_cmp(&b.threshold_z_mm));
+    ordered.dedup_by(|next, previous| next.filament_id == previous.filament_id);
+
+    let mut bands: Vec<(&ColorStop, &Filament)> = Vec::new();
+    for stop in ordered {
+        match filaments.iter().find(|f| f.id == stop.filament_id) {
+            Some(filament) => {
+                if !filament.enabled {
+                    warnings.push(format!(
+                        "{} is used by the stop at {:.2} mm but is disabled",
+                        filament.name, stop.threshold_z_mm
+                    ));
+                }
+                bands.push((stop, filament));
+            }
+            None => warnings.push(format!(
+                "The stop at {:.2} mm uses unknown filament {}",
+                stop.threshold_z_mm, stop.filament_id
+            )),
+        }
+    }
+
+    for pair in bands.windows(2) {
+        let (lower_stop, lower) = pair[0];
+        let (upper_stop, upper) = pair[1];
+        if upper.order_index < lower.order_index {
+            warnings.push(format!(
+                "{} at {:.2} mm comes before {} at {:.2} mm in the filament order",
+                upper.name, upper_stop.threshold_z_mm, lower.name, lower_stop.threshold_z_mm
+            ));
+        }
+        if upper.td < lower.td {
+            warnings.push(format!(
+                "{} (TD {}) is more opaque than {} (TD {}) below it and will hide it",
+                upper.name, upper.td, lower.name, lower.td
+            ));
+        }
+        if let (Some(upper_luminance), Some(lower_luminance)) = (
+            hex_luminance(&upper.hex_color),
+            hex_luminance(&lower.hex_color),
+        ) {
+            if upper_luminance < lower_luminance - LUMINANCE_TOLERANCE {
+                warnings.push(format!(
+                    "{} is darker than {} below it, so the colors will look muddy",
+                    upper.name, lower.name
+                ));
+            }
+        }
+    }
+
+    ValidationReport {
+        valid: warnings.is_empty(),
+        warnings,
+    }
+}
+
+/// Smallest spacing between stops that still shows as a change in
+/// brightness, in whole layers of `layer_height_mm`.
+///
+/// Light through a filament falls off as `exp(-td * depth)`, so a change
+/// in depth changes the transmitted light by the same share at any depth.
+/// A band has to be thick enough for that share to exceed
+/// [`LUMINANCE_TOLERANCE`]. The most translucent enabled filament needs
+/// the thickest band, so it sets the spacing.
+pub fn color_resolution_mm(
+    filaments: &[Filament],
+    layer_height_mm: f64,
+) -> Result<f64, CommandError> {
+    if layer_height_mm <= 0.0 {
+        return Err(CommandError::invalid_settings(
+            "layer_height_mm",
+            format!("must be positive, got {}", layer_height_mm),
+        ));
+    }
+    let lowest_td = filaments
+        .iter()
+        .filter(|filament| filament.enabled && filament.td > 0.0)
+        .map(|filament| filament.td)
+        .min_by(f64::total_cmp)
+        .ok_or_else(|| {
+            CommandError::invalid_settings("filaments", "no enabled filament has a positive td")
+        })?;
+    let depth_mm = -(1.0 - LUMINANCE_TOLERANCE).ln() / lowest_td;
+    let layers = layers_for_height(depth_mm, layer_height_mm).max(1);
+    Ok((layers as f64 * layer_height_mm * 1000.0).round() / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::geometry;
+
+    fn filament(id: &str, order_index: i32, enabled: bool) -> Filament {
+        Filament {
+            id: id.to_string(),
+            name: id.to_string(),
+            hex_color: "#FFFFFF".to_string(),
+            td: 1.0,
+            enabled,
+            order_index,
+        }
+    }
+
+    #[test]
+    fn assigns_sorted_thresholds_to_enabled_filaments_in_order() {
+        let filaments = [
+            filament("top", 2, true),
+            filament("off", 0, false),
+            filament("bottom", 1, true),
+        ];
+        let stops = stops_from_thresholds(&[1.5, 0.9, 2.0], &filaments, &geometry());
+
+        assert_eq!(stops.len(), 2);
+        assert_eq!(stops[0].filament_id, "bottom");
+        assert_eq!(stops[0].threshold_z_mm, 0.9);
+        assert_eq!(stops[1].filament_id, "top");
+        assert_eq!(stops[1].threshold_z_mm, 1.5);
+    }
+
+    #[test]
+    fn clamps_thresholds_to_the_depth_range() {
+        let filaments = [filament("a", 0, true), filament("b", 1, true)];
+        let stops = stops_from_thresholds(&[0.1, 9.0, f64::NAN], &filaments, &geometry());
+
+        assert_eq!(stops.len(), 2);
+        assert_eq!(stops[0].threshold_z_mm, 0.48);
+        assert_eq!(stops[1].threshold_z_mm, 2.24);
+    }
+
+    fn palette_match(filament_id: &str, distance: f64) -> SidecarPaletteMatch {
+        SidecarPaletteMatch {
+            filament_id: filament_id.to_string(),
+            distance,
+        }
+    }
+
+    #[test]
+    fn palette_is_ordered_light_to_dark_and_deduplicated() {
+        let mut black = filament("black", 7, false);
+        black.hex_color = "#000000".to_string();
+        let mut red = filament("red", 3, true);
+        red.hex_color = "#FF0000".to_string();
+        let library = [black, red, filament("white", 5, true)];
+
+        let palette = palette_from_matches(
+            &[
+                palette_match("black", 4.0),
+                palette_match("red", 9.0),
+                palette_match("unknown", 1.0),
+                palette_match("white", 2.0),
+                palette_match("black", 3.0),
+            ],
+            &library,
+        );
+
+        let ids: Vec<&str> = palette.iter().map(|p| p.filament.id.as_str()).collect();
+        assert_eq!(ids, ["white", "red", "black"]);
+        assert_eq!(palette[2].distance, 3.0);
+        assert_eq!(palette[2].filament.order_index, 2);
+        assert!(palette[2].filament.enabled);
+    }
+
+    #[test]
+    fn sorts_by_td_with_stable_ties() {
+        let filament = |id: &str, td: f64, order_index: i32, enabled: bool| Filament {
+            id: id.to_string(),
+            name: id.to_string(),
+            hex_color: "#FFFFFF".to_string(),
+            td,
+            enabled,
+            order_index,
+        };
+        let filaments = vec![
+            filament("clear", 6.0, 0, true),
+            filament("tie-late", 1.5, 3, true),
+            filament("opaque", 0.5, 1, false),
+            filament("tie-early", 1.5, 2, true),
+        ];
+
+        let ids =
+            |sorted: &[Filament]| -> Vec<String> { sorted.iter().map(|f| f.id.clone()).collect() };
+        let ascending = sort_by_td(filaments.clone(), true);
+        assert_eq!(
+            ids(&ascending),
+            ["opaque", "tie-early", "tie-late", "clear"]
+        );
+        assert_eq!(
+            ascending.iter().map(|f| f.order_index).collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+        assert!(!ascending[0].enabled);
+
+        let descending = sort_by_td(filaments, false);
+        assert_eq!(
+            ids(&descending),
+            ["clear", "tie-early", "tie-late", "opaque"]
+        );
+    }
+
+    #[test]
+    fn parses_hex_luminance() {
+        assert_eq!(hex_luminance("#FFFFFF"), Some(1.0));
+        assert_eq!(hex_luminance("000000"), Some(0.0));
+        assert_eq!(hex_luminance("#FFF"), None);
+        assert_eq!(hex_luminance("#GG0000"), None);
+    }
+
+    fn swap_at(z_mm: f64, filament_id: &str) -> SwapEntry {
+        SwapEntry {
+            layer: 0,
+            z_mm,
+            filament_id: filament_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn swap_spacing_sorts_and_recomputes_layers() {
+        let mut swaps = [swap_at(0.8, "b"), swap_at(0.24, "a")];
+        let warnings = check_swap_spacing(&mut swaps, 0.08, DEFAULT_MIN_SWAP_GAP_LAYERS);
+
+        assert!(warnings.is_empty());
+        assert_eq!(swaps[0].filament_id, "a");
+        assert_eq!(swaps[0].layer, 3);
+        assert_eq!(swaps[1].layer, 10);
+    }
+
+    fn stop(filament_id: &str, threshold_z_mm: f64) -> ColorStop {
+        ColorStop {
+            filament_id: filament_id.to_string(),
+            threshold_z_mm,
+        }
+    }
+
+    #[test]
+    fn merges_stops_within_the_tolerance_of_the_lowest() {
+        let stops = [
+            stop("c", 1.08),
+            stop("a", 1.0),
+            stop("b", 1.04),
+            stop("d", 1.12),
+            stop("e", 2.0),
+        ];
+        let merged = merge_close_stops(&stops, 0.1);
+
+        let summary: Vec<(&str, f64)> = merged
+            .iter()
+            .map(|stop| (stop.filament_id.as_str(), stop.threshold_z_mm))
+            .collect();
+        // 1.12 is within 0.1 of 1.08 but not of the group's 1.0
+        assert_eq!(summary, vec![("b", 1.0), ("d", 1.12), ("e", 2.0)]);
+    }
+
+    #[test]
+    fn merging_with_zero_tolerance_only_sorts() {
+        let stops = [stop("b", 1.5), stop("a", 0.5), stop("c", 1.5)];
+        let merged = merge_close_stops(&stops, 0.0);
+        let ids: Vec<&str> = merged.iter().map(|s| s.filament_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn depth_ranges_must_be_ordered_numbers() {
+        assert!(validate_depth_range(0.48, 2.24).is_ok());
+        assert!(validate_depth_range(1.0, 1.0).is_ok());
+        for (min, max) in [(2.24, 0.48), (f64::NAN, 2.24), (0.48, f64::INFINITY)] {
+            assert!(matches!(
+                validate_depth_range(min, max),
+                Err(CommandError::InvalidSettings { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn normalizes_duplicate_and_out_of_range_stops() {
+        let stops = [
+            stop("b", 1.0),
+            stop("a", 0.8),
+            stop("c", 1.0004),
+            stop("low", 0.1),
+            stop("high", 5.0),
+        ];
+        let (cleaned, warnings) = normalize_stops(&stops, 0.48, 2.24);
+
+        let summary: Vec<(&str, f64)> = cleaned
+            .iter()
+            .map(|stop| (stop.filament_id.as_str(), stop.threshold_z_mm))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("low", 0.48), ("a", 0.8), ("c", 1.0), ("high", 2.24)]
+        );
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("low"));
+    }
+
+    #[test]
+    fn preview_bands_follow_sorted_stops() {
+        let stops = [
+            stop("b", 1.0),
+            stop("a", 0.8),
+            stop("x", 2.5),
+            stop("c", 3.0),
+        ];
+        let bands = preview_bands(&stops, &geometry());
+
+        let ranges: Vec<(&str, f64, f64)> = bands
+            .iter()
+            .map(|band| (band.filament_id.as_str(), band.from_z_mm, band.to_z_mm))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![("a", 0.48, 0.8), ("b", 0.8, 1.0), ("x", 1.0, 2.24)]
+        );
+    }
+
+    #[test]
+    fn variable_layer_heights_shift_swap_layers() {
+        let stops = [stop("b", 1.0), stop("a", 0.2), stop("c", 1.02)];
+        let constant = [LayerHeightSegment {
+            from_z_mm: 0.0,
+            layer_height_mm: 0.08,
+        }];
+        let fine_then_coarse = [
+            LayerHeightSegment {
+                from_z_mm: 0.4,
+                layer_height_mm: 0.2,
+            },
+            LayerHeightSegment {
+                from_z_mm: 0.0,
+                layer_height_mm: 0.04,
+            },
+        ];
+
+        let (swaps, skipped) = swaps_for_profile(&stops, &constant).unwrap();
+        let layers: Vec<i32> = swaps.iter().map(|swap| swap.layer).collect();
+        assert_eq!(layers, vec![3, 13]);
+        assert_eq!(swaps[1].z_mm, 1.04);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].filament_id, "c");
+        assert_eq!(skipped[0].reason, SkipReason::SameLayer);
+
+        let (swaps, _) = swaps_for_profile(&stops, &fine_then_coarse).unwrap();
+        let layers: Vec<i32> = swaps.iter().map(|swap| swap.layer).collect();
+        assert_eq!(layers, vec![5, 13, 14]);
+        assert_eq!(swaps[0].filament_id, "a");
+        assert_eq!(swaps[1].z_mm, 1.0);
+        assert_eq!(swaps[2].z_mm, 1.2);
+    }
+
+    #[test]
+    fn drops_stops_for_missing_or_disabled_filaments() {
+        let filaments = [filament("a", 0, true), filament("b", 1, false)];
+        let (usable, skipped) = drop_unusable_stops(
+            vec![stop("a", 0.5), stop("b", 1.0), stop("gone", 1.5)],
+            &filaments,
+        );
+
+        assert_eq!(usable.len(), 1);
+        assert_eq!(usable[0].filament_id, "a");
+        let reasons: Vec<(&str, SkipReason)> = skipped
+            .iter()
+            .map(|s| (s.filament_id.as_str(), s.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("b", SkipReason::DisabledFilament),
+                ("gone", SkipReason::MissingFilament)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_empty_or_flat_profiles() {
+        let stops = [stop("a", 0.5)];
+        assert!(swaps_for_profile(&stops, &[]).is_err());
+        let flat = [LayerHeightSegment {
+            from_z_mm: 0.0,
+            layer_height_mm: 0.0,
+        }];
+        assert!(matches!(
+            swaps_for_profile(&stops, &flat),
+            Err(CommandError::InvalidSettings { .. })
+        ));
+        let hairline = [LayerHeightSegment {
+            from_z_mm: 0.0,
+            layer_height_mm: 1e-12,
+        }];
+        assert!(swaps_for_profile(&stops, &hairline).is_err());
+        let fine = [LayerHeightSegment {
+            from_z_mm: 0.0,
+            layer_height_mm: 0.08,
+        }];
+        assert!(swaps_for_profile(&[stop("a", f64::INFINITY)], &fine).is_err());
+    }
+
+    #[test]
+    fn swap_spacing_warns_about_close_pairs() {
+        let mut swaps = [swap_at(0.24, "a"), swap_at(0.32, "b"), swap_at(1.6, "c")];
+        let warnings = check_swap_spacing(&mut swaps, 0.08, 3);
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("a at layer 3 and b at layer 4"));
+    }
+
+    #[test]
+    fn summary_merges_repeated_filaments_and_names_them() {
+        let filaments = [filament("a", 0, true), filament("b", 1, true)];
+        let swaps = [
+            swap_at(0.8, "b"),
+            swap_at(0.0, "a"),
+            swap_at(1.6, "b"),
+            swap_at(2.4, "ghost"),
+        ];
+        let summary = summarize_swaps(&swaps, &filaments);
+
+        assert_eq!(summary.sequence, ["a", "b", "ghost"]);
+        assert_eq!(summary.swap_count, 3);
+        assert_eq!(summary.filament_changes, 2);
+        assert_eq!(summarize_swaps(&[], &filaments).filament_changes, 0);
+    }
+
+    fn band(id: &str, order_index: i32, hex_color: &str, td: f64) -> Filament {
+        Filament {
+            hex_color: hex_color.to_string(),
+            td,
+            ..filament(id, order_index, true)
+        }
+    }
+
+    #[test]
+    fn accepts_dark_opaque_filaments_under_light_translucent_ones() {
+        let filaments = [
+            band("white", 2, "#FFFFFF", 5.0),
+            band("black", 0, "#000000", 0.5),
+            band("grey", 1, "#808080", 2.0),
+        ];
+        let stops = [stop("white", 1.6), stop("black", 0.48), stop("grey", 1.0)];
+        let report = validate_filament_order(&filaments, &stops);
+        assert!(report.valid, "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn warns_about_each_problem_between_adjacent_bands() {
+        let mut filaments = vec![
+            band("white", 0, "#FFFFFF", 5.0),
+            band("black", 1, "#000000", 0.5),
+        ];
+        filaments[1].enabled = false;
+        let stops = [stop("white", 0.48), stop("black", 1.0), stop("gone", 1.6)];
+        let report = validate_filament_order(&filaments, &stops);
+        assert!(!report.valid);
+        assert_eq!(report.warnings.len(), 4, "{:?}", report.warnings);
+        assert!(report.warnings[0].contains("disabled"));
+        assert!(report.warnings[1].contains("unknown filament gone"));
+        assert!(report.warnings[2].contains("more opaque"));
+        assert!(report.warnings[3].contains("darker"));
+
+        filaments[1].enabled = true;
+        filaments[1].order_index = -1;
+        let report = validate_filament_order(&filaments, &stops[..2]);
+        assert!(report.warnings[0].contains("comes before white"));
+    }
+
+    #[test]
+    fn recommends_the_count_where_extra_bands_stop_paying_off() {
+        let scores = |errors: &[f64]| -> Vec<SwapCountScore> {
+            errors
+                .iter()
+                .enumerate()
+                .map(|(index, &error_mm)| SwapCountScore {
+                    count: index + 1,
+                    error_mm,
+                    thresholds: Vec::new(),
+                })
+                .collect()
+        };
+        assert_eq!(
+            recommend_swap_count(&scores(&[1.0, 0.5, 0.2, 0.18, 0.17])),
+            3
+        );
+        assert_eq!(recommend_swap_count(&scores(&[1.0, 0.6, 0.3])), 3);
+        assert_eq!(recommend_swap_count(&scores(&[0.0, 0.0])), 1);
+        assert_eq!(recommend_swap_count(&[]), 0);
+    }
+
+    #[test]
+    fn the_most_translucent_filament_sets_the_color_resolution() {
+        let with_td = |id: &str, td: f64, enabled: bool| Filament {
+            td,
+            ..filament(id, 0, enabled)
+        };
+        let filaments = [
+            with_td("opaque", 2.0, true),
+            with_td("clear", 0.5, true),
+            with_td("unused", 0.01, false),
+        ];
+
+        // 5% of the light needs 0.10 mm of the clear filament, so 2 layers
+        assert_eq!(color_resolution_mm(&filaments, 0.08).unwrap(), 0.16);
+        assert_eq!(color_resolution_mm(&filaments[..1], 0.08).unwrap(), 0.08);
+        assert!(color_resolution_mm(&filaments[2..], 0.08).is_err());
+        assert!(color_resolution_mm(&filaments, 0.0).is_err());
+    }
+}
@@ -1,114 +1,320 @@
-use serde::{Deserialize, Serialize};
-use tauri_plugin_shell::ShellExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-fn default_luminance_method() -> String {
-    "rec601".to_string()
-}
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tauri_plugin_dialog::DialogExt;
 
-fn default_tone_mapping_mode() -> String {
-    "gamma".to_string()
-}
+use crate::autosave::{find_recovery, mark_saved, write_autosave, AUTOSAVE_DIR};
+use crate::bundle::{read_bundle, write_bundle, ProjectBundle};
+use crate::cache::HeightmapCache;
+use crate::color::{closest_filaments, FilamentMatch};
+use crate::color_plan::{
+    self, check_swap_spacing, drop_unusable_stops, normalize_stops, palette_from_matches,
+    preview_bands, recommend_swap_count, sort_by_td, stops_from_thresholds, swaps_for_profile,
+    validate_depth_range, LayerHeightSegment, LayerPreview, PaletteMatch, PreviewBand,
+    SidecarPaletteMatch, SkippedStop, SwapCountScore, SwapOptimization, SwapSummary,
+    ValidationReport, DEFAULT_MIN_SWAP_GAP_LAYERS,
+};
+use crate::error::CommandError;
+use crate::export_dirs::{
+    export_file_name, last_export_dir, remember_export_dir, EXPORT_DIRS_NAME,
+};
+use crate::fs_util::{now_millis, reveal_command, write_atomic};
+use crate::heightmap::{
+    downsample, encode_samples, fit_within, load_samples, prefer_heightmap_file, store_heightmap,
+};
+use crate::history::ProjectHistory;
+use crate::hueforge::project_from_hueforge;
+use crate::library::{
+    delete_from_library, filaments_from_csv, filaments_to_csv, read_library, write_library,
+    FILAMENT_LIBRARY_NAME,
+};
+use crate::models::{
+    ColorStop, CropRect, ExportKind, Filament, MeshFormat, ModelGeometrySettings, PrintSettings,
+    SwapEntry,
+};
+use crate::plan::{
+    self, color_changes, render_instructions, render_plan, swap_layers, PROJECT_SLICERS,
+};
+use crate::presets::{
+    get_preset, list_presets, save_preset, GeometryPreset, GEOMETRY_PRESETS_NAME,
+};
+use crate::project::{
+    backup_path, rotate_backups, verify_checksum as verify_project_checksum, write_project,
+    ProjectFile, ProjectLoadResult, DEFAULT_MAX_BACKUPS,
+};
+use crate::project_diff::{self, ProjectDiff};
+use crate::project_lock::{self, acquire_lock, check_lock};
+use crate::recent::{add_recent, clear_recent, read_recent, RecentEntry, RECENT_FILES_NAME};
+use crate::settings::{read_settings, write_settings, AppSettings, SETTINGS_NAME};
+use crate::sidecar::{
+    CallOptions, SidecarHandle, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_TIMEOUT, MESH_TIMEOUT,
+    PING_TIMEOUT,
+};
+use crate::sidecar_log::SidecarLog;
+use crate::sidecar_versions::{
+    list_versions, read_active, resolve_version, write_active, SidecarVersion, ACTIVE_SIDECAR_NAME,
+    SIDECAR_VERSIONS_DIR,
+};
+use crate::snapshot::build_snapshot;
+use crate::tone_curve::tone_curve;
+use crate::usage::{
+    validate_speed_factor, BandVolumes, FilamentMaterial, PrintTimeEstimate, UsageReport,
+};
+use crate::validation::{
+    canonicalize_spike_removal, check_output_extension, check_overwrite, mesh_extension_warning,
+    normalize_filament_colors, validate_crop, validate_image_path, validate_mask,
+    validate_shell_thickness, validate_stl_path,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct TransferCurvePoint {
-    pub x: f64,
-    pub y: f64,
-}
+/// Number of bins in a `process_image` histogram.
+pub const HISTOGRAM_BINS: usize = 256;
 
-fn default_transfer_curve() -> Vec<TransferCurvePoint> {
-    vec![
-        TransferCurvePoint { x: 0.0, y: 0.0 },
-        TransferCurvePoint { x: 0.25, y: 0.25 },
-        TransferCurvePoint { x: 0.5, y: 0.5 },
-        TransferCurvePoint { x: 0.75, y: 0.75 },
-        TransferCurvePoint { x: 1.0, y: 1.0 },
-    ]
-}
+/// Largest heightmap side `import_stl_heightmap` will sample.
+const MAX_STL_SAMPLES: u32 = 4096;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ModelGeometrySettings {
-    pub min_depth_mm: f64,
-    pub max_depth_mm: f64,
-    pub gamma: f64,
-    pub contrast: f64,
-    pub offset: f64,
-    pub smoothing: f64,
-    pub spike_removal: String,
-    #[serde(default = "default_luminance_method")]
-    pub luminance_method: String,
-    #[serde(default = "default_tone_mapping_mode")]
-    pub tone_mapping_mode: String,
-    #[serde(default = "default_transfer_curve")]
-    pub transfer_curve: Vec<TransferCurvePoint>,
-    #[serde(default)]
-    pub dynamic_depth: bool,
-    pub invert: bool,
-}
+/// Largest number of presets `compare_presets` puts on one sheet.
+const MAX_COMPARED_PRESETS: usize = 16;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PrintSettings {
-    pub layer_height_mm: f64,
-    pub base_layer_mm: f64,
-    pub width_mm: f64,
-    pub height_mm: f64,
-    pub border_width_mm: f64,
-    pub border_depth_mm: f64,
-    pub has_border: bool,
-}
+/// Longest side images are downscaled to for a contact sheet tile.
+const COMPARED_PRESET_DIMENSION: u32 = 512;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Filament {
-    pub id: String,
-    pub name: String,
-    pub hex_color: String,
-    pub td: f64,
-    pub enabled: bool,
-    pub order_index: i32,
-}
+/// Most frames `export_turntable` renders for one turn.
+const MAX_TURNTABLE_FRAMES: u32 = 120;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ColorStop {
-    pub filament_id: String,
-    pub threshold_z_mm: f64,
-}
+/// Luminance spread, as a share of the full range, below which
+/// `analyze_image` suggests boosting the contrast.
+const LOW_DYNAMIC_RANGE: f64 = 0.5;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SwapEntry {
-    pub layer: i32,
-    pub z_mm: f64,
-    pub filament_id: String,
-}
+/// File `batch_generate` writes next to the first job's output.
+pub const BATCH_MANIFEST_NAME: &str = "manifest.json";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProcessImageRequest {
     pub image_path: String,
     pub geometry: ModelGeometrySettings,
+    /// Processes only this region of the image. The response's `width` and
+    /// `height` are then those of the region.
+    #[serde(default)]
+    pub crop: Option<CropRect>,
+    /// Downscales the image, after cropping, so its longer side is at most
+    /// this many pixels. The response's `width` and `height` are the
+    /// downscaled ones. Tone mapping and depths are per pixel and
+    /// `smoothing` is scaled along with the image, so a scaled-down preview
+    /// matches the full-resolution result; only spike removal, whose kernel
+    /// is a whole number of pixels, acts slightly stronger on it.
+    #[serde(default)]
+    pub max_dimension: Option<u32>,
+    /// Also return a histogram of the heightmap.
+    #[serde(default)]
+    pub include_histogram: bool,
+    /// Image whose dark (or, with an alpha channel, transparent) areas are
+    /// cut down to the base, for shaped lithophanes. It is resampled to the
+    /// processed size, after the crop and downscale.
+    #[serde(default)]
+    pub mask_path: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProcessImageResponse {
     pub heightmap_base64: String,
     pub width: u32,
     pub height: u32,
+    /// Temp file holding the same heightmap, to pass to later calls instead
+    /// of the base64 string.
+    #[serde(default)]
+    pub heightmap_path: Option<String>,
+    /// Sample counts in [`HISTOGRAM_BINS`] equal bins from `min_depth_mm` to
+    /// `max_depth_mm`, when the request asked for it.
+    #[serde(default)]
+    pub histogram: Option<Vec<u32>>,
+    /// Range actually present in the heightmap after tone mapping and
+    /// spike removal, which can fall short of the requested depths.
+    #[serde(default)]
+    pub actual_min_depth_mm: Option<f64>,
+    #[serde(default)]
+    pub actual_max_depth_mm: Option<f64>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerateMeshRequest {
+    #[serde(default)]
     pub heightmap_base64: String,
+    #[serde(default)]
+    pub heightmap_path: Option<String>,
     pub width: u32,
     pub height: u32,
     pub geometry: ModelGeometrySettings,
     pub print_settings: PrintSettings,
+    /// `None` uses the export format from the app settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<MeshFormat>,
+}
+
+impl GenerateMeshRequest {
+    fn prefer_heightmap_file(&mut self) -> Result<(), CommandError> {
+        prefer_heightmap_file(
+            &mut self.heightmap_path,
+            &mut self.heightmap_base64,
+            self.width,
+            self.height,
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+/// Size of a generated mesh. Everything but `file_size_bytes` is reported
+/// by the sidecar, which already has the mesh in memory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeshStats {
+    pub triangle_count: u64,
+    pub vertex_count: u64,
+    pub bounding_box_mm: BoundingBox,
+    pub approx_volume_mm3: f64,
+    pub file_size_bytes: u64,
+}
+
+/// Defects the sidecar found in a mesh file, which slicers may refuse or
+/// repair unpredictably.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeshValidation {
+    pub face_count: u64,
+    /// Edges shared by more than two faces.
+    pub non_manifold_edges: u64,
+    /// Faces wound against their neighbours, so they face inward.
+    pub flipped_normals: u64,
+    /// Loops of boundary edges, each an opening in the surface.
+    pub holes: u64,
+    /// Edges belonging to only one face.
+    pub boundary_edges: u64,
+    /// Faces with zero area, which slicers drop.
+    pub degenerate_faces: u64,
+    /// Whether the mesh is a closed, consistently wound surface.
+    #[serde(skip_deserializing)]
+    pub is_clean: bool,
+}
+
+/// What the source image looks like before any processing.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageAnalysis {
+    pub width: u32,
+    pub height: u32,
+    pub is_grayscale: bool,
+    pub has_alpha: bool,
+    /// Most common colors as `#RRGGBB`, most common first.
+    pub dominant_colors: Vec<String>,
+    /// Spread between the darkest and lightest percent of the luminance,
+    /// from 0 for a flat image to 1 for the full range.
+    pub dynamic_range: f64,
+    /// Advice for the user, such as boosting the contrast of a flat image.
+    #[serde(default, skip_deserializing)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GeneratedMesh {
+    pub path: String,
+    pub stats: MeshStats,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub image_path: String,
+    pub output_path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub image_path: String,
+    pub output_path: String,
+    /// Path of the written STL when the job succeeded.
+    pub path: Option<String>,
+    pub stats: Option<MeshStats>,
+    /// Swaps below the top of the mesh, when the batch was given stops.
+    pub swap_count: Option<usize>,
+    pub error: Option<CommandError>,
+}
+
+/// Record of a batch run, rewritten after each job so it covers every job
+/// that finished even if a later one fails.
+#[derive(Debug, Serialize)]
+pub struct BatchManifest<'a> {
+    pub created_at_ms: u64,
+    pub total: usize,
+    pub jobs: &'a [BatchResult],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub index: usize,
+    pub total: usize,
+    pub image_path: String,
+    pub stage: &'static str,
+}
+
+/// Emitted as `bundle-progress` while a bundle is saved or loaded.
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleProgress {
+    pub path: String,
+    pub progress: f64,
+    pub stage: &'static str,
+}
+
+/// Emitted as `file-written` whenever a command finishes writing an output
+/// file, so every window can react to it.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileWritten {
+    /// `mesh`, `slicer_project`, `turntable`, `heightmap`, `plan`,
+    /// `instructions`, `project`, `bundle`, `snapshot` or `manifest`.
+    pub kind: &'static str,
+    pub path: String,
+    pub size_bytes: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ComputePreviewRequest {
+    #[serde(default)]
     pub heightmap_base64: String,
+    #[serde(default)]
+    pub heightmap_path: Option<String>,
     pub width: u32,
     pub height: u32,
     pub filaments: Vec<Filament>,
     pub stops: Vec<ColorStop>,
     pub geometry: ModelGeometrySettings,
+    /// Also render each color band on its own.
+    #[serde(default)]
+    pub per_layer: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComputePreviewResponse {
+    pub preview_base64: String,
+    /// One image per band, lowest first, when the request set `per_layer`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layers: Option<Vec<LayerPreview>>,
+}
+
+/// How far the filament preview strays from the source image.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreviewDiff {
+    /// Black where the colors match, through red to yellow at a delta E of
+    /// 25 and above.
+    pub diff_base64: String,
+    /// CIE76 color difference averaged over the image.
+    pub mean_delta_e: f64,
+    pub max_delta_e: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -117,169 +323,1916 @@ pub struct ComputeSwapsRequest {
     pub layer_height_mm: f64,
     pub min_depth_mm: f64,
     pub max_depth_mm: f64,
+    #[serde(default)]
+    pub min_swap_gap_layers: Option<u32>,
+    /// Variable layer heights. Without one every layer is
+    /// `layer_height_mm` tall.
+    #[serde(default)]
+    pub layer_profile: Option<Vec<LayerHeightSegment>>,
+    /// Filaments the stops refer to. When given, stops for a filament that
+    /// is missing or disabled are skipped instead of planned.
+    #[serde(default)]
+    pub filaments: Option<Vec<Filament>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComputeSwapsResponse {
+    pub swaps: Vec<SwapEntry>,
+    /// The request's stops after sorting, clamping and removing duplicates.
+    pub stops: Vec<ColorStop>,
+    /// Stops that got no swap, with the reason for each.
+    pub skipped: Vec<SkippedStop>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SidecarInfo {
+    pub version: String,
+    pub python_version: String,
+    pub available_methods: Vec<String>,
+    /// Optional features the core supports; older cores don't report any.
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+/// What the running core supports, so the frontend can hide features it
+/// would only fail on.
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    pub core_version: String,
+    pub methods: Vec<String>,
+    pub export_obj: bool,
+    pub export_3mf: bool,
+    pub hollow_shell: bool,
+    pub stl_import: bool,
+    pub per_layer_preview: bool,
+    pub histogram: bool,
+    pub crop: bool,
+    /// Whether the core sends heartbeats, which enables hang detection.
+    pub heartbeat: bool,
+    /// Planned by the app itself, so available with any core.
+    pub variable_layer_height: bool,
+}
+
+impl From<SidecarInfo> for Capabilities {
+    fn from(info: SidecarInfo) -> Self {
+        let has = |feature: &str| info.features.iter().any(|f| f == feature);
+        Self {
+            export_obj: has("mesh_format_obj"),
+            export_3mf: has("mesh_format_3mf"),
+            hollow_shell: has("hollow_shell"),
+            stl_import: has("stl_import"),
+            per_layer_preview: has("per_layer_preview"),
+            histogram: has("histogram"),
+            crop: has("crop"),
+            heartbeat: has("heartbeat"),
+            variable_layer_height: true,
+            core_version: info.version,
+            methods: info.available_methods,
+        }
+    }
 }
 
 async fn call_python_sidecar(
     app: tauri::AppHandle,
     method: &str,
     params: serde_json::Value,
-) -> Result<serde_json::Value, String> {
-    use tauri_plugin_shell::process::CommandEvent;
-    use std::sync::Arc;
-    use tokio::sync::Mutex;
+    options: CallOptions,
+) -> Result<serde_json::Value, CommandError> {
+    let sidecar = app.state::<SidecarHandle>();
+    let reply = sidecar.call(&app, method, params, options).await?;
+    let response = reply.frame;
     
-    let request = serde_json::json!({
-        "method": method,
-        "params": params
-    });
-    let request_str = request.to_string();
+    // Check if Python returned an error
+    if let Some(error) = response.get("error") {
+        let text_field = |name: &str| {
+            response
+                .get(name)
+                .and_then(|value| value.as_str())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_string())
+        };
+        return Err(CommandError::PythonError {
+            message: error.as_str().map_or_else(|| error.to_string(), |e| e.to_string()),
+            traceback: text_field("traceback"),
+            error_type: text_field("error_type"),
+            stderr: reply.stderr,
+        });
+    }
+
+    // Warnings on stderr don't fail an otherwise valid result
+    if let Some(stderr) = reply.stderr {
+        eprintln!("Sidecar stderr during {}: {}", method, stderr);
+    }
+    
+    Ok(response)
+}
 
-    let shell = app.shell();
-    let sidecar = shell
-        .sidecar("layerforge-core")
-        .map_err(|e| format!("Failed to create sidecar command: {}", e))?;
+/// Checks everything `process_image` is handed before the sidecar sees it
+/// and brings the spike removal setting into the form the core reads.
+/// Returns the warnings about the mask, if any.
+fn prepare_process_request(request: &mut ProcessImageRequest) -> Result<Vec<String>, CommandError> {
+    request.geometry.validate()?;
+    validate_image_path(&request.image_path)?;
+    canonicalize_spike_removal(&mut request.geometry)?;
+    if let Some(crop) = &request.crop {
+        validate_crop(&request.image_path, crop)?;
+    }
+    if request.max_dimension == Some(0) {
+        return Err(CommandError::invalid_settings(
+            "max_dimension",
+            "must be greater than 0",
+        ));
+    }
+    let mut warnings = Vec::new();
+    if let Some(mask_path) = &request.mask_path {
+        warnings.extend(validate_mask(
+            &request.image_path,
+            request.crop.as_ref(),
+            mask_path,
+        )?);
+    }
+    Ok(warnings)
+}
 
-    let (mut rx, mut child) = sidecar
-        .spawn()
-        .map_err(|e| format!("Failed to spawn sidecar: {}", e))?;
+#[tauri::command]
+pub async fn process_image(
+    app: tauri::AppHandle,
+    mut request: ProcessImageRequest,
+    op_id: Option<String>,
+) -> Result<ProcessImageResponse, CommandError> {
+    let warnings = prepare_process_request(&mut request)?;
+    let mask_hash = match &request.mask_path {
+        Some(mask_path) => Some(app.state::<HeightmapCache>().file_hash(mask_path)?),
+        None => None,
+    };
+    let cache_key = app.state::<HeightmapCache>().key(
+        &request.image_path,
+        &(
+            &request.geometry,
+            &request.crop,
+            request.max_dimension,
+            request.include_histogram,
+            mask_hash,
+        ),
+    )?;
+    if let Some(cached) = app.state::<HeightmapCache>().get(&cache_key) {
+        return Ok(cached);
+    }
 
-    // Write request to stdin
-    child
-        .write((request_str + "\n").as_bytes())
-        .map_err(|e| format!("Failed to write to stdin: {}", e))?;
+    let mut params = serde_json::to_value(&request)?;
+    params["histogram_bins"] = serde_json::json!(HISTOGRAM_BINS);
+    let options = CallOptions::new(DEFAULT_TIMEOUT)
+        .with_progress("image-progress")
+        .with_op_id(op_id);
+    let response = call_python_sidecar(app.clone(), "process_image", params, options).await?;
+    let mut response: ProcessImageResponse = serde_json::from_value(response)?;
+    match store_heightmap(&response.heightmap_base64) {
+        Ok(path) => response.heightmap_path = Some(path),
+        Err(e) => eprintln!("Could not store heightmap file: {}", e),
+    }
+    response.warnings.splice(0..0, warnings);
+    app.state::<HeightmapCache>().insert(cache_key, response.clone());
+    Ok(response)
+}
 
-    // Collect stdout
-    let stdout_data = Arc::new(Mutex::new(Vec::new()));
-    let stderr_data = Arc::new(Mutex::new(Vec::new()));
-    
-    let stdout_clone = stdout_data.clone();
-    let stderr_clone = stderr_data.clone();
-    
-    while let Some(event) = rx.recv().await {
-        match event {
-            CommandEvent::Stdout(line) => {
-                stdout_clone.lock().await.extend_from_slice(&line);
-            }
-            CommandEvent::Stderr(line) => {
-                stderr_clone.lock().await.extend_from_slice(&line);
-            }
-            CommandEvent::Terminated(_) => break,
-            _ => {}
+/// Processes `image_path` once per preset, downscaled to tile size, and has
+/// the sidecar lay the heightmaps out side by side, each labeled with its
+/// settings. Returns the sheet as a PNG data URL.
+#[tauri::command]
+pub async fn compare_presets(
+    app: tauri::AppHandle,
+    image_path: String,
+    presets: Vec<ModelGeometrySettings>,
+) -> Result<String, CommandError> {
+    if presets.is_empty() || presets.len() > MAX_COMPARED_PRESETS {
+        return Err(CommandError::invalid_settings(
+            "presets",
+            format!(
+                "{} presets is outside 1..={}",
+                presets.len(),
+                MAX_COMPARED_PRESETS
+            ),
+        ));
+    }
+
+    let mut tiles = Vec::with_capacity(presets.len());
+    for geometry in presets {
+        let label = preset_label(&geometry);
+        let request = ProcessImageRequest {
+            image_path: image_path.clone(),
+            geometry,
+            crop: None,
+            max_dimension: Some(COMPARED_PRESET_DIMENSION),
+            include_histogram: false,
+            mask_path: None,
+        };
+        let image = process_image(app.clone(), request, None).await?;
+        let mut tile = serde_json::json!({
+            "width": image.width,
+            "height": image.height,
+            "label": label
+        });
+        match image.heightmap_path {
+            Some(path) => tile["heightmap_path"] = path.into(),
+            None => tile["heightmap_base64"] = image.heightmap_base64.into(),
         }
+        tiles.push(tile);
     }
 
-    let stdout = String::from_utf8_lossy(&stdout_data.lock().await).to_string();
-    let stderr = String::from_utf8_lossy(&stderr_data.lock().await).to_string();
+    let params = serde_json::json!({ "tiles": tiles });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "contact_sheet", params, options).await?;
+    response["image_base64"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| CommandError::sidecar("No image in response"))
+}
 
-    if !stderr.is_empty() && stdout.is_empty() {
-        return Err(format!("Sidecar error: {}", stderr));
+/// The settings that tell compared presets apart.
+fn preset_label(geometry: &ModelGeometrySettings) -> String {
+    let mut parts = Vec::new();
+    if geometry.tone_mapping_mode == "curve" {
+        parts.push("curve".to_string());
+    } else {
+        parts.push(format!("gamma {}", geometry.gamma));
+        parts.push(format!("contrast {}", geometry.contrast));
+        parts.push(format!("offset {}", geometry.offset));
     }
+    parts.push(format!("{}-{} mm", geometry.min_depth_mm, geometry.max_depth_mm));
+    if geometry.invert {
+        parts.push("inverted".to_string());
+    }
+    parts.join(", ")
+}
 
-    let response: serde_json::Value = serde_json::from_str(&stdout)
-        .map_err(|e| format!("Failed to parse response: {} - stdout: {}", e, stdout))?;
-    
-    // Check if Python returned an error
-    if let Some(error) = response.get("error") {
-        let traceback = response.get("traceback")
-            .and_then(|t| t.as_str())
-            .unwrap_or("");
-        return Err(format!("Python error: {} \n{}", error, traceback));
+/// Samples the top surface of an existing relief STL into a
+/// `samples_x` x `samples_y` heightmap, so models without their source
+/// image can go through the preview and swap pipeline. The sidecar fails
+/// on meshes that aren't a single-valued heightfield.
+#[tauri::command]
+pub async fn import_stl_heightmap(
+    app: tauri::AppHandle,
+    stl_path: String,
+    samples_x: u32,
+    samples_y: u32,
+) -> Result<ProcessImageResponse, CommandError> {
+    validate_stl_path(&stl_path)?;
+    if samples_x == 0 || samples_y == 0 || samples_x.max(samples_y) > MAX_STL_SAMPLES {
+        return Err(CommandError::invalid_settings(
+            "samples",
+            format!(
+                "{}x{} is outside 1..={} per side",
+                samples_x, samples_y, MAX_STL_SAMPLES
+            ),
+        ));
+    }
+
+    let params = serde_json::json!({
+        "stl_path": stl_path,
+        "samples_x": samples_x,
+        "samples_y": samples_y
+    });
+    let options = CallOptions::new(MESH_TIMEOUT).heavy();
+    let response = call_python_sidecar(app, "import_stl_heightmap", params, options).await?;
+    let mut response: ProcessImageResponse = serde_json::from_value(response)?;
+    match store_heightmap(&response.heightmap_base64) {
+        Ok(path) => response.heightmap_path = Some(path),
+        Err(e) => eprintln!("Could not store heightmap file: {}", e),
     }
-    
     Ok(response)
 }
 
+/// Measures a filament's transmission distance in mm from a backlit photo
+/// of a step-wedge swatch whose steps are `layer_height_mm` apart. Fails if
+/// no swatch is found in the image or it never becomes opaque.
 #[tauri::command]
-pub async fn process_image(
+pub async fn measure_td(
     app: tauri::AppHandle,
-    request: ProcessImageRequest,
-) -> Result<ProcessImageResponse, String> {
-    let params = serde_json::to_value(&request).map_err(|e| e.to_string())?;
-    let response = call_python_sidecar(app, "process_image", params).await?;
-    serde_json::from_value(response).map_err(|e| e.to_string())
+    calibration_image_path: String,
+    layer_height_mm: f64,
+) -> Result<f64, CommandError> {
+    validate_image_path(&calibration_image_path)?;
+    if layer_height_mm.is_nan() || layer_height_mm <= 0.0 {
+        return Err(CommandError::invalid_settings(
+            "layer height",
+            format!("must be positive, got {} mm", layer_height_mm),
+        ));
+    }
+
+    let params = serde_json::json!({
+        "image_path": calibration_image_path,
+        "layer_height_mm": layer_height_mm
+    });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "measure_td", params, options).await?;
+    response["td_mm"]
+        .as_f64()
+        .ok_or_else(|| CommandError::sidecar("No td_mm in response"))
 }
 
 #[tauri::command]
 pub async fn generate_mesh(
     app: tauri::AppHandle,
-    request: GenerateMeshRequest,
+    mut request: GenerateMeshRequest,
     output_path: String,
-) -> Result<String, String> {
+    op_id: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<GeneratedMesh, CommandError> {
+    let settings = app_settings(&app);
+    let output_path = settings.output_path(&output_path);
+    let format = *request
+        .output_format
+        .get_or_insert(settings.export_format.unwrap_or_default());
+    prepare_mesh_request(&mut request, &output_path, overwrite)?;
+    let warnings: Vec<String> = mesh_extension_warning(&output_path, format)
+        .into_iter()
+        .collect();
     let params = serde_json::json!({
         "request": request,
         "output_path": output_path
     });
-    let response = call_python_sidecar(app, "generate_mesh", params).await?;
-    response["path"]
+    let options = CallOptions::new(MESH_TIMEOUT)
+        .heavy()
+        .with_progress("mesh-progress")
+        .with_op_id(op_id);
+    let response = call_python_sidecar(app.clone(), "generate_mesh", params, options).await?;
+    let mesh = written_mesh(&response, warnings)?;
+    emit_file_written(&app, "mesh", &mesh.path);
+    Ok(mesh)
+}
+
+/// Checks shared by the commands that have the sidecar write a mesh.
+fn prepare_mesh_request(
+    request: &mut GenerateMeshRequest,
+    output_path: &str,
+    overwrite: Option<bool>,
+) -> Result<(), CommandError> {
+    request.geometry.validate()?;
+    request.print_settings.validate()?;
+    check_overwrite(output_path, overwrite.unwrap_or(false))?;
+    canonicalize_spike_removal(&mut request.geometry)?;
+    validate_shell_thickness(&request.geometry, &request.print_settings)?;
+    request.prefer_heightmap_file()
+}
+
+/// The mesh a sidecar response says it wrote, with its size on disk.
+fn written_mesh(
+    response: &serde_json::Value,
+    warnings: Vec<String>,
+) -> Result<GeneratedMesh, CommandError> {
+    let path = response["path"]
         .as_str()
         .map(|s| s.to_string())
-        .ok_or_else(|| "No path in response".to_string())
+        .ok_or_else(|| CommandError::sidecar("No path in response"))?;
+    let mut stats: MeshStats = match response.get("stats") {
+        Some(stats) => serde_json::from_value(stats.clone())?,
+        None => MeshStats::default(),
+    };
+    stats.file_size_bytes = std::fs::metadata(&path)
+        .map_err(|e| CommandError::io(&path, e))?
+        .len();
+    Ok(GeneratedMesh {
+        path,
+        stats,
+        warnings,
+    })
+}
+
+/// Writes a 3MF project for `slicer` holding the mesh and a color change
+/// at every swap, so the print only needs slicing. `slicer` is one of
+/// `PROJECT_SLICERS`.
+#[tauri::command]
+pub async fn export_slicer_project(
+    app: tauri::AppHandle,
+    mut request: GenerateMeshRequest,
+    swaps: Vec<SwapEntry>,
+    mut filaments: Vec<Filament>,
+    output_path: String,
+    slicer: String,
+    overwrite: Option<bool>,
+) -> Result<GeneratedMesh, CommandError> {
+    let slicer = slicer.to_ascii_lowercase();
+    if !PROJECT_SLICERS.contains(&slicer.as_str()) {
+        return Err(CommandError::invalid_settings(
+            "slicer",
+            format!(
+                "{} is not supported, expected one of {}",
+                slicer,
+                PROJECT_SLICERS.join(", ")
+            ),
+        ));
+    }
+    check_output_extension(&output_path, "3mf")?;
+    request.output_format = Some(MeshFormat::ThreeMF);
+    prepare_mesh_request(&mut request, &output_path, overwrite)?;
+    normalize_filament_colors(&mut filaments)?;
+    let changes = color_changes(&swaps, &filaments, &request.print_settings)?;
+
+    let params = serde_json::json!({
+        "request": request,
+        "output_path": output_path,
+        "slicer": slicer,
+        "color_changes": changes
+    });
+    let options = CallOptions::new(MESH_TIMEOUT)
+        .heavy()
+        .with_progress("mesh-progress");
+    let response =
+        call_python_sidecar(app.clone(), "export_slicer_project", params, options).await?;
+    let mesh = written_mesh(&response, Vec::new())?;
+    emit_file_written(&app, "slicer_project", &mesh.path);
+    Ok(mesh)
+}
+
+/// Samples the luminance to depth mapping of `geometry`, for plotting the
+/// curve before processing an image.
+#[tauri::command]
+pub async fn export_tone_curve(
+    geometry: ModelGeometrySettings,
+    samples: usize,
+) -> Result<Vec<(f64, f64)>, CommandError> {
+    geometry.validate()?;
+    tone_curve(&geometry, samples)
+}
+
+/// Builds the mesh `generate_mesh` would write and reports its size without
+/// writing anything, so the UI can warn about huge meshes before asking for
+/// a save location. `file_size_bytes` is left at zero.
+#[tauri::command]
+pub async fn preview_mesh_stats(
+    app: tauri::AppHandle,
+    mut request: GenerateMeshRequest,
+    op_id: Option<String>,
+) -> Result<MeshStats, CommandError> {
+    request.geometry.validate()?;
+    request.print_settings.validate()?;
+    canonicalize_spike_removal(&mut request.geometry)?;
+    validate_shell_thickness(&request.geometry, &request.print_settings)?;
+    request.prefer_heightmap_file()?;
+    let params = serde_json::json!({
+        "request": request,
+        "dry_run": true
+    });
+    let options = CallOptions::new(MESH_TIMEOUT)
+        .heavy()
+        .with_progress("mesh-progress")
+        .with_op_id(op_id);
+    let response = call_python_sidecar(app, "generate_mesh", params, options).await?;
+    match response.get("stats") {
+        Some(stats) => Ok(serde_json::from_value(stats.clone())?),
+        None => Err(CommandError::sidecar("No stats in response")),
+    }
+}
+
+/// Looks at the source image without processing it, so the UI can give
+/// advice before the first preview. Only a downscaled copy is examined.
+#[tauri::command]
+pub async fn analyze_image(
+    app: tauri::AppHandle,
+    image_path: String,
+) -> Result<ImageAnalysis, CommandError> {
+    validate_image_path(&image_path)?;
+    let params = serde_json::json!({ "image_path": image_path });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "analyze_image", params, options).await?;
+    let mut analysis: ImageAnalysis = serde_json::from_value(response)?;
+    if !analysis.is_grayscale {
+        analysis.warnings.push(
+            "This is a color image. It is converted to grayscale by luminance, so colors of \
+             similar brightness end up at the same depth"
+                .to_string(),
+        );
+    }
+    if analysis.dynamic_range < LOW_DYNAMIC_RANGE {
+        analysis.warnings.push(format!(
+            "This is a low-contrast image using {:.0}% of the brightness range, consider \
+             boosting the contrast",
+            analysis.dynamic_range * 100.0
+        ));
+    }
+    Ok(analysis)
+}
+
+/// Checks a written STL for non-manifold edges, flipped normals and holes.
+#[tauri::command]
+pub async fn validate_mesh(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<MeshValidation, CommandError> {
+    validate_stl_path(&path)?;
+    let params = serde_json::json!({ "path": path });
+    let options = CallOptions::new(MESH_TIMEOUT).heavy();
+    let response = call_python_sidecar(app, "validate_mesh", params, options).await?;
+    let mut validation: MeshValidation = serde_json::from_value(response)?;
+    validation.is_clean = validation.non_manifold_edges == 0
+        && validation.flipped_normals == 0
+        && validation.boundary_edges == 0;
+    Ok(validation)
 }
 
+/// Renders the preview of the current plan. While one is waiting to run, a
+/// newer request replaces it and the older one fails with
+/// `CommandError::Superseded`, so dragging a slider only computes the latest
+/// settings.
 #[tauri::command]
 pub async fn compute_preview(
     app: tauri::AppHandle,
     request: ComputePreviewRequest,
-) -> Result<String, String> {
-    let params = serde_json::to_value(&request).map_err(|e| e.to_string())?;
-    let response = call_python_sidecar(app, "compute_preview", params).await?;
-    response["preview_base64"]
+    op_id: Option<String>,
+) -> Result<ComputePreviewResponse, CommandError> {
+    let options = CallOptions::new(DEFAULT_TIMEOUT)
+        .with_progress("preview-progress")
+        .with_op_id(op_id)
+        .coalescing("compute_preview");
+    render_preview(app, request, None, options).await
+}
+
+/// Compares the preview with the image the heightmap was processed from,
+/// cropped by `crop` when the heightmap was.
+#[tauri::command]
+pub async fn preview_diff(
+    app: tauri::AppHandle,
+    mut request: ComputePreviewRequest,
+    image_path: String,
+    crop: Option<CropRect>,
+) -> Result<PreviewDiff, CommandError> {
+    request.geometry.validate()?;
+    validate_image_path(&image_path)?;
+    if let Some(crop) = &crop {
+        validate_crop(&image_path, crop)?;
+    }
+    canonicalize_spike_removal(&mut request.geometry)?;
+    normalize_filament_colors(&mut request.filaments)?;
+    prefer_heightmap_file(
+        &mut request.heightmap_path,
+        &mut request.heightmap_base64,
+        request.width,
+        request.height,
+    )?;
+    let mut params = serde_json::to_value(&request)?;
+    params["image_path"] = image_path.into();
+    params["crop"] = serde_json::to_value(crop)?;
+    let options = CallOptions::new(DEFAULT_TIMEOUT).coalescing("preview_diff");
+    let response = call_python_sidecar(app, "preview_diff", params, options).await?;
+    Ok(serde_json::from_value(response)?)
+}
+
+/// Renders the preview turning a full circle and writes it as a `gif` or
+/// `mp4` animation. This is slow, so a `turntable-progress` event is
+/// emitted for each rendered frame.
+#[tauri::command]
+pub async fn export_turntable(
+    app: tauri::AppHandle,
+    mut request: ComputePreviewRequest,
+    frames: u32,
+    format: String,
+    output_path: String,
+    overwrite: Option<bool>,
+) -> Result<String, CommandError> {
+    if format != "gif" && format != "mp4" {
+        return Err(CommandError::invalid_settings(
+            "format",
+            format!("expected gif or mp4, got {}", format),
+        ));
+    }
+    if !(2..=MAX_TURNTABLE_FRAMES).contains(&frames) {
+        return Err(CommandError::invalid_settings(
+            "frames",
+            format!(
+                "must be between 2 and {}, got {}",
+                MAX_TURNTABLE_FRAMES, frames
+            ),
+        ));
+    }
+    check_output_extension(&output_path, &format)?;
+    check_overwrite(&output_path, overwrite.unwrap_or(false))?;
+
+    request.geometry.validate()?;
+    canonicalize_spike_removal(&mut request.geometry)?;
+    normalize_filament_colors(&mut request.filaments)?;
+    prefer_heightmap_file(
+        &mut request.heightmap_path,
+        &mut request.heightmap_base64,
+        request.width,
+        request.height,
+    )?;
+    request.per_layer = false;
+    let mut params = serde_json::to_value(&request)?;
+    params["frames"] = frames.into();
+    params["format"] = format.into();
+    params["output_path"] = output_path.into();
+    let options = CallOptions::new(MESH_TIMEOUT)
+        .heavy()
+        .with_progress("turntable-progress");
+    let response = call_python_sidecar(app.clone(), "export_turntable", params, options).await?;
+    let path = response["path"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| CommandError::sidecar("No path in response"))?;
+    emit_file_written(&app, "turntable", &path);
+    Ok(path)
+}
+
+/// Shared by the preview commands. With `isolate`, only that filament's
+/// bands are colored.
+async fn render_preview(
+    app: tauri::AppHandle,
+    mut request: ComputePreviewRequest,
+    isolate: Option<&str>,
+    options: CallOptions,
+) -> Result<ComputePreviewResponse, CommandError> {
+    request.geometry.validate()?;
+    canonicalize_spike_removal(&mut request.geometry)?;
+    normalize_filament_colors(&mut request.filaments)?;
+    prefer_heightmap_file(
+        &mut request.heightmap_path,
+        &mut request.heightmap_base64,
+        request.width,
+        request.height,
+    )?;
+    let bands = request
+        .per_layer
+        .then(|| preview_bands(&request.stops, &request.geometry));
+    let mut params = serde_json::to_value(&request)?;
+    if let Some(bands) = &bands {
+        params["bands"] = serde_json::to_value(bands)?;
+    }
+    if let Some(filament_id) = isolate {
+        let isolated: Vec<PreviewBand> = preview_bands(&request.stops, &request.geometry)
+            .into_iter()
+            .filter(|band| band.filament_id == filament_id)
+            .collect();
+        if isolated.is_empty() {
+            return Err(CommandError::invalid_settings(
+                "filament_id",
+                format!("{} has no band in the depth range", filament_id),
+            ));
+        }
+        params["isolate_bands"] = serde_json::to_value(isolated)?;
+    }
+    let response = call_python_sidecar(app, "compute_preview", params, options).await?;
+    let preview_base64 = response["preview_base64"]
         .as_str()
         .map(|s| s.to_string())
-        .ok_or_else(|| "No preview in response".to_string())
+        .ok_or_else(|| CommandError::sidecar("No preview in response"))?;
+
+    let layers = match bands {
+        Some(bands) => {
+            let images: Vec<String> = serde_json::from_value(response["layers"].clone())?;
+            if images.len() != bands.len() {
+                return Err(CommandError::sidecar(format!(
+                    "Expected {} layer previews, got {}",
+                    bands.len(),
+                    images.len()
+                )));
+            }
+            Some(
+                bands
+                    .into_iter()
+                    .zip(images)
+                    .map(|(band, image_base64)| LayerPreview { band, image_base64 })
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+    Ok(ComputePreviewResponse {
+        preview_base64,
+        layers,
+    })
 }
 
+/// Renders a preview no larger than `max_size` on either side, for project
+/// browsing. The heightmap is downsampled before it reaches the sidecar so
+/// this stays quick for large images.
 #[tauri::command]
-pub async fn compute_swaps(
+pub async fn generate_thumbnail(
     app: tauri::AppHandle,
-    request: ComputeSwapsRequest,
-) -> Result<Vec<SwapEntry>, String> {
-    let params = serde_json::to_value(&request).map_err(|e| e.to_string())?;
-    let response = call_python_sidecar(app, "compute_swaps", params).await?;
-    serde_json::from_value(response["swaps"].clone()).map_err(|e| e.to_string())
+    mut request: ComputePreviewRequest,
+    max_size: u32,
+) -> Result<String, CommandError> {
+    let (width, height) = fit_within(request.width, request.height, max_size);
+    if (width, height) != (request.width, request.height) {
+        prefer_heightmap_file(
+            &mut request.heightmap_path,
+            &mut request.heightmap_base64,
+            request.width,
+            request.height,
+        )?;
+        let samples = load_samples(request.heightmap_path.as_deref(), &request.heightmap_base64)?;
+        let small = downsample(&samples, request.width, request.height, width, height);
+        request.heightmap_base64 = encode_samples(&small);
+        request.heightmap_path = None;
+        request.width = width;
+        request.height = height;
+    }
+    request.per_layer = false;
+    let options = CallOptions::new(DEFAULT_TIMEOUT).with_progress("preview-progress");
+    let preview = render_preview(app, request, None, options).await?;
+    Ok(preview.preview_base64)
 }
 
+/// Renders the preview with only `filament_id`'s band in color, in the same
+/// height range a per-layer preview draws it, and the rest of the relief in
+/// gray. A filament used by several stops has all of its bands colored.
 #[tauri::command]
-pub async fn export_stl(
+pub async fn preview_band(
     app: tauri::AppHandle,
-    request: GenerateMeshRequest,
-    output_path: String,
-) -> Result<String, String> {
-    generate_mesh(app, request, output_path).await
+    mut request: ComputePreviewRequest,
+    filament_id: String,
+) -> Result<String, CommandError> {
+    request.per_layer = false;
+    let options = CallOptions::new(DEFAULT_TIMEOUT)
+        .with_progress("preview-progress")
+        .coalescing("preview_band");
+    let preview = render_preview(app, request, Some(&filament_id), options).await?;
+    Ok(preview.preview_base64)
 }
 
 #[tauri::command]
-pub async fn export_plan(
+pub async fn compute_swaps(
     app: tauri::AppHandle,
-    swaps: Vec<SwapEntry>,
-    filaments: Vec<Filament>,
+    mut request: ComputeSwapsRequest,
+) -> Result<ComputeSwapsResponse, CommandError> {
+    validate_depth_range(request.min_depth_mm, request.max_depth_mm)?;
+    let (stops, mut warnings) =
+        normalize_stops(&request.stops, request.min_depth_mm, request.max_depth_mm);
+    request.stops = stops;
+    let min_gap = request
+        .min_swap_gap_layers
+        .unwrap_or(DEFAULT_MIN_SWAP_GAP_LAYERS);
+
+    let mut skipped = Vec::new();
+    let mut usable = request.stops.clone();
+    if let Some(filaments) = &request.filaments {
+        (usable, skipped) = drop_unusable_stops(usable, filaments);
+    }
+
+    let mut swaps = match &request.layer_profile {
+        Some(profile) => {
+            let (swaps, same_layer) = swaps_for_profile(&usable, profile)?;
+            skipped.extend(same_layer);
+            swaps
+        }
+        None => {
+            let mut params = serde_json::to_value(&request)?;
+            params["stops"] = serde_json::to_value(&usable)?;
+            let options = CallOptions::new(DEFAULT_TIMEOUT);
+            let response = call_python_sidecar(app, "compute_swaps", params, options).await?;
+            // Cores from before skip reporting send only the swaps
+            let sidecar_skipped: Option<Vec<SkippedStop>> =
+                serde_json::from_value(response["skipped"].clone())?;
+            let sidecar_warnings: Option<Vec<String>> =
+                serde_json::from_value(response["warnings"].clone())?;
+            skipped.extend(sidecar_skipped.unwrap_or_default());
+            warnings.extend(sidecar_warnings.unwrap_or_default());
+            serde_json::from_value(response["swaps"].clone())?
+        }
+    };
+    let layer_height_mm = match request.layer_profile {
+        Some(_) => 0.0,
+        None => request.layer_height_mm,
+    };
+    warnings.extend(check_swap_spacing(&mut swaps, layer_height_mm, min_gap));
+    Ok(ComputeSwapsResponse {
+        swaps,
+        stops: request.stops,
+        skipped,
+        warnings,
+    })
+}
+
+/// `stops` with each threshold moved to the nearest layer top, so the
+/// preview bands match the layers the swaps happen on.
+#[tauri::command]
+pub async fn snap_stops_to_layers(
+    stops: Vec<ColorStop>,
+    print_settings: PrintSettings,
+) -> Result<Vec<ColorStop>, CommandError> {
+    print_settings.validate()?;
+    Ok(plan::snap_stops_to_layers(&stops, &print_settings))
+}
+
+/// Swaps for `stops` on slicer layers counted from the build plate,
+/// including the base layers, computed in Rust from `print_settings`.
+#[tauri::command]
+pub async fn compute_swap_layers(
+    stops: Vec<ColorStop>,
     print_settings: PrintSettings,
+) -> Result<Vec<SwapEntry>, CommandError> {
+    print_settings.validate()?;
+    Ok(swap_layers(&stops, &print_settings))
+}
+
+/// Runs `process_image` then `generate_mesh` for each job in turn, sharing
+/// the geometry, print settings and `stops`. A failed job is reported in its
+/// result and doesn't stop the rest of the batch. Each result is emitted as
+/// `batch-result` when its job finishes.
+///
+/// With `write_manifest`, a `BatchManifest` is written as
+/// `BATCH_MANIFEST_NAME` in the first job's output directory after every
+/// job. Failing to write it stops the batch.
+#[tauri::command]
+pub async fn batch_generate(
+    app: tauri::AppHandle,
+    jobs: Vec<BatchJob>,
     geometry: ModelGeometrySettings,
-    output_path: String,
-    format: String,
-) -> Result<String, String> {
+    print_settings: PrintSettings,
+    stops: Option<Vec<ColorStop>>,
+    overwrite: Option<bool>,
+    write_manifest: Option<bool>,
+) -> Result<Vec<BatchResult>, CommandError> {
+    print_settings.validate()?;
+    let total = jobs.len();
+    let manifest_path = match (write_manifest.unwrap_or(false), jobs.first()) {
+        (true, Some(job)) => Some(
+            Path::new(&job.output_path)
+                .with_file_name(BATCH_MANIFEST_NAME)
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        _ => None,
+    };
+    // The manifest is rewritten after every job, so it is only checked once
+    if let Some(manifest_path) = &manifest_path {
+        check_overwrite(manifest_path, overwrite.unwrap_or(false))?;
+    }
+    let swaps = stops.map(|stops| swap_layers(&stops, &print_settings));
+    let created_at_ms = now_millis();
+    let mut results = Vec::with_capacity(total);
+    for (index, job) in jobs.into_iter().enumerate() {
+        let progress = |stage: &'static str| {
+            let _ = app.emit(
+                "batch-progress",
+                BatchProgress {
+                    index,
+                    total,
+                    image_path: job.image_path.clone(),
+                    stage,
+                },
+            );
+        };
+
+        progress("processing");
+        let outcome = match process_image(
+            app.clone(),
+            ProcessImageRequest {
+                image_path: job.image_path.clone(),
+                geometry: geometry.clone(),
+                crop: None,
+                max_dimension: None,
+                include_histogram: false,
+                mask_path: None,
+            },
+            None,
+        )
+        .await
+        {
+            Ok(image) => {
+                progress("meshing");
+                let request = GenerateMeshRequest {
+                    heightmap_base64: image.heightmap_base64,
+                    heightmap_path: image.heightmap_path,
+                    width: image.width,
+                    height: image.height,
+                    geometry: geometry.clone(),
+                    print_settings: print_settings.clone(),
+                    output_format: None,
+                };
+                let output_path = job.output_path.clone();
+                generate_mesh(app.clone(), request, output_path, None, overwrite).await
+            }
+            Err(e) => Err(e),
+        };
+        progress(if outcome.is_ok() { "done" } else { "failed" });
+
+        let result = match outcome {
+            Ok(mesh) => {
+                let top_mm = mesh.stats.bounding_box_mm.max[2];
+                BatchResult {
+                    image_path: job.image_path,
+                    output_path: job.output_path,
+                    path: Some(mesh.path),
+                    swap_count: swaps
+                        .as_ref()
+                        .map(|swaps| swaps.iter().filter(|s| s.z_mm < top_mm).count()),
+                    stats: Some(mesh.stats),
+                    error: None,
+                }
+            }
+            Err(e) => BatchResult {
+                image_path: job.image_path,
+                output_path: job.output_path,
+                path: None,
+                stats: None,
+                swap_count: None,
+                error: Some(e),
+            },
+        };
+        let _ = app.emit("batch-result", &result);
+        results.push(result);
+
+        if let Some(manifest_path) = &manifest_path {
+            let manifest = BatchManifest {
+                created_at_ms,
+                total,
+                jobs: &results,
+            };
+            let json = serde_json::to_vec_pretty(&manifest)?;
+            write_atomic(Path::new(manifest_path), &json)
+                .map_err(|e| CommandError::io(manifest_path.clone(), e))?;
+            emit_file_written(&app, "manifest", manifest_path);
+        }
+    }
+    Ok(results)
+}
+
+/// Asks the sidecar to cluster the processed heightmap into `num_colors`
+/// depth thresholds and maps them onto the enabled filaments.
+#[tauri::command]
+pub async fn suggest_stops(
+    app: tauri::AppHandle,
+    mut request: ProcessImageRequest,
+    num_colors: usize,
+    filaments: Vec<Filament>,
+) -> Result<Vec<ColorStop>, CommandError> {
+    let enabled = filaments.iter().filter(|f| f.enabled).count();
+    let num_colors = num_colors.min(enabled);
+    if num_colors == 0 {
+        return Err(CommandError::invalid_settings(
+            "num_colors",
+            "needs at least one color and one enabled filament",
+        ));
+    }
+    for warning in prepare_process_request(&mut request)? {
+        eprintln!("Suggesting stops: {}", warning);
+    }
     let params = serde_json::json!({
-        "swaps": swaps,
-        "filaments": filaments,
-        "print_settings": print_settings,
-        "geometry": geometry,
-        "output_path": output_path,
-        "format": format
+        "image_path": request.image_path,
+        "geometry": request.geometry,
+        "crop": request.crop,
+        "max_dimension": request.max_dimension,
+        "mask_path": request.mask_path,
+        "num_colors": num_colors
     });
-    let response = call_python_sidecar(app, "export_plan", params).await?;
-    response["path"]
-        .as_str()
-        .map(|s| s.to_string())
-        .ok_or_else(|| "No path in response".to_string())
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "suggest_stops", params, options).await?;
+    let thresholds: Vec<f64> = serde_json::from_value(response["thresholds"].clone())?;
+    Ok(stops_from_thresholds(&thresholds, &filaments, &request.geometry))
 }
 
+/// Scores 1 to `max_swaps` color bands on the processed heightmap and
+/// recommends the count past which more swaps barely change the result,
+/// together with stops for it. Counts are capped at the number of enabled
+/// filaments.
 #[tauri::command]
-pub async fn save_project(project_json: String, output_path: String) -> Result<String, String> {
-    std::fs::write(&output_path, &project_json).map_err(|e| e.to_string())?;
-    Ok(output_path)
+pub async fn optimize_swap_count(
+    app: tauri::AppHandle,
+    mut request: ComputePreviewRequest,
+    max_swaps: usize,
+) -> Result<SwapOptimization, CommandError> {
+    request.geometry.validate()?;
+    let enabled = request.filaments.iter().filter(|f| f.enabled).count();
+    let max_bands = max_swaps.min(enabled);
+    if max_bands == 0 {
+        return Err(CommandError::invalid_settings(
+            "max_swaps",
+            "needs at least one swap and one enabled filament",
+        ));
+    }
+    prefer_heightmap_file(
+        &mut request.heightmap_path,
+        &mut request.heightmap_base64,
+        request.width,
+        request.height,
+    )?;
+    let params = serde_json::json!({
+        "heightmap_base64": request.heightmap_base64,
+        "heightmap_path": request.heightmap_path,
+        "width": request.width,
+        "height": request.height,
+        "geometry": request.geometry,
+        "max_bands": max_bands
+    });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "optimize_swap_count", params, options).await?;
+    let mut scores: Vec<SwapCountScore> = serde_json::from_value(response["scores"].clone())?;
+    scores.sort_by_key(|score| score.count);
+
+    let recommended_count = recommend_swap_count(&scores);
+    let stops = scores
+        .iter()
+        .find(|score| score.count == recommended_count)
+        .map(|score| {
+            stops_from_thresholds(&score.thresholds, &request.filaments, &request.geometry)
+        })
+        .unwrap_or_default();
+    Ok(SwapOptimization {
+        recommended_count,
+        scores,
+        stops,
+    })
+}
+
+/// Picks the `count` library filaments closest to the image's dominant
+/// colors.
+#[tauri::command]
+pub async fn suggest_palette(
+    app: tauri::AppHandle,
+    request: ProcessImageRequest,
+    library: Vec<Filament>,
+    count: usize,
+) -> Result<Vec<PaletteMatch>, CommandError> {
+    validate_image_path(&request.image_path)?;
+    let params = serde_json::json!({
+        "image_path": request.image_path,
+        "geometry": request.geometry,
+        "library": library,
+        "count": count
+    });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "suggest_palette", params, options).await?;
+    let matches: Vec<SidecarPaletteMatch> = serde_json::from_value(response["matches"].clone())?;
+    Ok(palette_from_matches(&matches, &library))
+}
+
+/// Returns `filaments` ordered by transmission distance with `order_index`
+/// reassigned, for a one-click "optimize order".
+#[tauri::command]
+pub async fn sort_filaments_by_td(filaments: Vec<Filament>, ascending: bool) -> Vec<Filament> {
+    sort_by_td(filaments, ascending)
+}
+
+/// Collapses near-duplicate stops, see `color_plan::merge_close_stops`.
+#[tauri::command]
+pub async fn merge_close_stops(
+    stops: Vec<ColorStop>,
+    tolerance_mm: f64,
+) -> Result<Vec<ColorStop>, CommandError> {
+    if !tolerance_mm.is_finite() || tolerance_mm < 0.0 {
+        return Err(CommandError::invalid_settings(
+            "tolerance_mm",
+            format!("must not be negative, got {} mm", tolerance_mm),
+        ));
+    }
+    Ok(color_plan::merge_close_stops(&stops, tolerance_mm))
+}
+
+#[tauri::command]
+pub async fn summarize_swaps(swaps: Vec<SwapEntry>, filaments: Vec<Filament>) -> SwapSummary {
+    color_plan::summarize_swaps(&swaps, &filaments)
+}
+
+/// Warns about stacking orders that hide colors, see
+/// `color_plan::validate_filament_order`.
+#[tauri::command]
+pub async fn validate_filament_order(
+    filaments: Vec<Filament>,
+    stops: Vec<ColorStop>,
+) -> ValidationReport {
+    color_plan::validate_filament_order(&filaments, &stops)
+}
+
+/// Smallest `threshold_z_mm` spacing at which two stops still look
+/// different, see `color_plan::color_resolution_mm`.
+#[tauri::command]
+pub async fn compute_color_resolution(
+    filaments: Vec<Filament>,
+    layer_height_mm: f64,
+) -> Result<f64, CommandError> {
+    color_plan::color_resolution_mm(&filaments, layer_height_mm)
+}
+
+/// The `n` library filaments perceptually closest to `target_hex`.
+#[tauri::command]
+pub async fn find_closest_filaments(
+    target_hex: String,
+    library: Vec<Filament>,
+    n: usize,
+) -> Result<Vec<FilamentMatch>, CommandError> {
+    closest_filaments(&target_hex, library, n)
+}
+
+#[tauri::command]
+pub async fn estimate_usage(
+    app: tauri::AppHandle,
+    mut request: GenerateMeshRequest,
+    mut filaments: Vec<Filament>,
+    stops: Vec<ColorStop>,
+    materials: Option<Vec<FilamentMaterial>>,
+) -> Result<UsageReport, CommandError> {
+    request.geometry.validate()?;
+    request.print_settings.validate()?;
+    let geometry = &request.geometry;
+    validate_depth_range(geometry.min_depth_mm, geometry.max_depth_mm)?;
+    let (stops, warnings) = normalize_stops(&stops, geometry.min_depth_mm, geometry.max_depth_mm);
+    for warning in warnings {
+        eprintln!("Estimating usage: {}", warning);
+    }
+    normalize_filament_colors(&mut filaments)?;
+    request.prefer_heightmap_file()?;
+    let params = serde_json::json!({
+        "request": request,
+        "stops": stops
+    });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "estimate_usage", params, options).await?;
+    let volumes: BandVolumes = serde_json::from_value(response)?;
+    Ok(UsageReport::from_volumes(
+        &volumes,
+        &filaments,
+        &stops,
+        materials.as_deref().unwrap_or_default(),
+    ))
+}
+
+#[tauri::command]
+pub async fn estimate_print_time(
+    app: tauri::AppHandle,
+    mut request: GenerateMeshRequest,
+    speed_factor: Option<f64>,
+) -> Result<PrintTimeEstimate, CommandError> {
+    request.print_settings.validate()?;
+    let speed_factor = speed_factor.unwrap_or(1.0);
+    validate_speed_factor(speed_factor)?;
+    request.prefer_heightmap_file()?;
+    let params = serde_json::json!({ "request": request });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "estimate_print_time", params, options).await?;
+    let minutes = response["minutes"]
+        .as_f64()
+        .ok_or_else(|| CommandError::sidecar("No minutes in response"))?;
+    let layers = response["layers"]
+        .as_i64()
+        .ok_or_else(|| CommandError::sidecar("No layers in response"))?;
+    Ok(PrintTimeEstimate::new(
+        &request.print_settings,
+        layers as i32,
+        minutes,
+        speed_factor,
+    ))
+}
+
+#[tauri::command]
+pub async fn export_stl(
+    app: tauri::AppHandle,
+    request: GenerateMeshRequest,
+    output_path: String,
+    op_id: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<GeneratedMesh, CommandError> {
+    generate_mesh(app, request, output_path, op_id, overwrite).await
+}
+
+/// Processes the image and writes its heightmap as an 8 or 16 bit grayscale
+/// PNG, black at `min_depth_mm` and white at `max_depth_mm`. 16 bits avoid
+/// the banding 8 bits show in smooth gradients.
+#[tauri::command]
+pub async fn export_heightmap(
+    app: tauri::AppHandle,
+    request: ProcessImageRequest,
+    output_path: String,
+    bit_depth: u8,
+    overwrite: Option<bool>,
+) -> Result<String, CommandError> {
+    if bit_depth != 8 && bit_depth != 16 {
+        return Err(CommandError::invalid_settings(
+            "bit_depth",
+            format!("must be 8 or 16, got {}", bit_depth),
+        ));
+    }
+    check_output_extension(&output_path, "png")?;
+    check_overwrite(&output_path, overwrite.unwrap_or(false))?;
+
+    let geometry = request.geometry.clone();
+    let image = process_image(app.clone(), request, None).await?;
+    let params = serde_json::json!({
+        "heightmap_base64": image.heightmap_base64,
+        "heightmap_path": image.heightmap_path,
+        "width": image.width,
+        "height": image.height,
+        "geometry": geometry,
+        "output_path": output_path,
+        "bit_depth": bit_depth
+    });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app.clone(), "export_heightmap", params, options).await?;
+    let path = response["path"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| CommandError::sidecar("No path in response"))?;
+    emit_file_written(&app, "heightmap", &path);
+    Ok(path)
+}
+
+// Tauri commands take their arguments individually
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn export_plan(
+    app: tauri::AppHandle,
+    swaps: Vec<SwapEntry>,
+    mut filaments: Vec<Filament>,
+    print_settings: Option<PrintSettings>,
+    geometry: ModelGeometrySettings,
+    output_path: String,
+    format: String,
+    overwrite: Option<bool>,
+) -> Result<String, CommandError> {
+    let settings = app_settings(&app);
+    let print_settings = settings.print_settings(print_settings)?;
+    print_settings.validate()?;
+    let output_path = settings.output_path(&output_path);
+    check_overwrite(&output_path, overwrite.unwrap_or(false))?;
+    normalize_filament_colors(&mut filaments)?;
+    if let Some(contents) = render_plan(&format, &swaps, &filaments, &print_settings) {
+        write_atomic(Path::new(&output_path), contents.as_bytes())
+            .map_err(|e| CommandError::io(&output_path, e))?;
+        emit_file_written(&app, "plan", &output_path);
+        return Ok(output_path);
+    }
+
+    let params = serde_json::json!({
+        "swaps": swaps,
+        "filaments": filaments,
+        "print_settings": print_settings,
+        "geometry": geometry,
+        "output_path": output_path,
+        "format": format
+    });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app.clone(), "export_plan", params, options).await?;
+    let path = response["path"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| CommandError::sidecar("No path in response"))?;
+    emit_file_written(&app, "plan", &path);
+    Ok(path)
+}
+
+/// Writes a printable instruction sheet for the plan, as Markdown (`md`) or
+/// as a PDF (`pdf`) typeset by the sidecar from the same text.
+// Tauri commands take their arguments individually
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn export_instructions(
+    app: tauri::AppHandle,
+    swaps: Vec<SwapEntry>,
+    mut filaments: Vec<Filament>,
+    print_settings: PrintSettings,
+    geometry: ModelGeometrySettings,
+    output_path: String,
+    format: String,
+    overwrite: Option<bool>,
+) -> Result<String, CommandError> {
+    if format != "md" && format != "pdf" {
+        return Err(CommandError::invalid_settings(
+            "format",
+            format!("expected md or pdf, got {}", format),
+        ));
+    }
+    check_output_extension(&output_path, &format)?;
+    check_overwrite(&output_path, overwrite.unwrap_or(false))?;
+    print_settings.validate()?;
+    normalize_filament_colors(&mut filaments)?;
+    let text = render_instructions(&swaps, &filaments, &print_settings, &geometry);
+    if format == "md" {
+        write_atomic(Path::new(&output_path), text.as_bytes())
+            .map_err(|e| CommandError::io(&output_path, e))?;
+        emit_file_written(&app, "instructions", &output_path);
+        return Ok(output_path);
+    }
+
+    let params = serde_json::json!({
+        "text": text,
+        "output_path": output_path
+    });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app.clone(), "export_instructions", params, options).await?;
+    let path = response["path"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| CommandError::sidecar("No path in response"))?;
+    emit_file_written(&app, "instructions", &path);
+    Ok(path)
+}
+
+/// Renders a color key of the filaments in print order, each with the
+/// slicer layer and height it starts at, as a PNG data URL.
+#[tauri::command]
+pub async fn generate_legend(
+    app: tauri::AppHandle,
+    swaps: Vec<SwapEntry>,
+    mut filaments: Vec<Filament>,
+    print_settings: PrintSettings,
+) -> Result<String, CommandError> {
+    print_settings.validate()?;
+    normalize_filament_colors(&mut filaments)?;
+    let entries = color_changes(&swaps, &filaments, &print_settings)?;
+    if entries.is_empty() {
+        return Err(CommandError::invalid_settings("swaps", "no swaps to show"));
+    }
+    let params = serde_json::json!({ "entries": entries });
+    let options = CallOptions::new(DEFAULT_TIMEOUT);
+    let response = call_python_sidecar(app, "generate_legend", params, options).await?;
+    response["image_base64"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| CommandError::sidecar("No image in response"))
+}
+
+/// Asks the user where to export a file of `kind`, starting in the
+/// directory last used for that kind. Returns `None` if the dialog was
+/// cancelled.
+#[tauri::command]
+pub async fn pick_export_path(
+    app: tauri::AppHandle,
+    default_name: String,
+    kind: ExportKind,
+) -> Result<Option<String>, CommandError> {
+    let dirs_path = app_config_file(&app, EXPORT_DIRS_NAME)?;
+    let mut dialog = app
+        .dialog()
+        .file()
+        .set_file_name(export_file_name(&default_name, kind))
+        .add_filter(kind.filter_name(), &[kind.extension()]);
+    if let Some(dir) = last_export_dir(&dirs_path, kind) {
+        dialog = dialog.set_directory(dir);
+    }
+
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    dialog.save_file(move |picked| {
+        let _ = sender.send(picked);
+    });
+    let Some(picked) = receiver.await.ok().flatten() else {
+        return Ok(None);
+    };
+    let path = picked.into_path().map_err(|e| CommandError::Io {
+        path: None,
+        message: format!("Unsupported save location: {}", e),
+    })?;
+    if let Err(e) = remember_export_dir(&dirs_path, kind, &path) {
+        eprintln!("Could not remember export directory: {}", e);
+    }
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+/// Opens the folder containing `path` in the OS file manager with the file
+/// selected.
+#[tauri::command]
+pub async fn reveal_in_file_manager(path: String) -> Result<(), CommandError> {
+    let target = Path::new(&path);
+    if !target.exists() {
+        return Err(CommandError::Io {
+            path: Some(path),
+            message: "File does not exist".to_string(),
+        });
+    }
+    let mut child = reveal_command(&std::path::absolute(target)?)
+        .spawn()
+        .map_err(|e| CommandError::Io {
+            path: Some(path),
+            message: format!("Could not open the file manager: {}", e),
+        })?;
+    // Explorer exits with a failure status even when it worked, so the exit
+    // status is ignored; it is only waited on so it doesn't linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn ping_sidecar(app: tauri::AppHandle) -> Result<SidecarInfo, CommandError> {
+    let options = CallOptions::new(PING_TIMEOUT);
+    let response = call_python_sidecar(app, "ping", serde_json::json!({}), options).await?;
+    Ok(serde_json::from_value(response)?)
+}
+
+#[tauri::command]
+pub async fn get_capabilities(app: tauri::AppHandle) -> Result<Capabilities, CommandError> {
+    Ok(ping_sidecar(app).await?.into())
+}
+
+/// The bundled core and the user-provided ones it can be switched to.
+#[tauri::command]
+pub async fn list_sidecar_versions(
+    app: tauri::AppHandle,
+) -> Result<Vec<SidecarVersion>, CommandError> {
+    let dir = app_data_subdir(&app, SIDECAR_VERSIONS_DIR)?;
+    let active = read_active(&app_config_file(&app, ACTIVE_SIDECAR_NAME)?);
+    Ok(list_versions(&dir, &active))
+}
+
+/// Restarts the core as `version_id` and remembers the choice for later
+/// launches. Hang detection is turned on only if the new core sends
+/// heartbeats. A core that doesn't answer a ping is switched back from and
+/// the choice isn't saved.
+#[tauri::command]
+pub async fn set_active_sidecar(
+    app: tauri::AppHandle,
+    version_id: String,
+) -> Result<Capabilities, CommandError> {
+    let binary = resolve_version(&app_data_subdir(&app, SIDECAR_VERSIONS_DIR)?, &version_id)?;
+    let config_path = app_config_file(&app, ACTIVE_SIDECAR_NAME)?;
+    let sidecar = app.state::<SidecarHandle>();
+    let previous = (sidecar.binary(), sidecar.heartbeat_interval());
+
+    sidecar.set_heartbeat_interval(None);
+    sidecar.switch_binary(binary).await;
+    let capabilities = match get_capabilities(app.clone()).await {
+        Ok(capabilities) => capabilities,
+        Err(e) => {
+            sidecar.switch_binary(previous.0).await;
+            sidecar.set_heartbeat_interval(previous.1);
+            return Err(e);
+        }
+    };
+    if capabilities.heartbeat {
+        sidecar.set_heartbeat_interval(Some(DEFAULT_HEARTBEAT_INTERVAL));
+    }
+    write_active(&config_path, &version_id)?;
+    Ok(capabilities)
+}
+
+/// Changes how often the core is asked for heartbeats, or turns hang
+/// detection off with `None`. Fails for cores that don't send heartbeats.
+#[tauri::command]
+pub async fn set_heartbeat_interval(
+    app: tauri::AppHandle,
+    interval_ms: Option<u64>,
+) -> Result<(), CommandError> {
+    if let Some(interval_ms) = interval_ms {
+        if interval_ms == 0 {
+            return Err(CommandError::invalid_settings(
+                "heartbeat interval",
+                "must be positive",
+            ));
+        }
+        if !get_capabilities(app.clone()).await?.heartbeat {
+            return Err(CommandError::invalid_settings(
+                "heartbeat interval",
+                "the sidecar doesn't send heartbeats",
+            ));
+        }
+    }
+    app.state::<SidecarHandle>()
+        .set_heartbeat_interval(interval_ms.map(Duration::from_millis));
+    Ok(())
+}
+
+/// Path of the sidecar log, for users to attach to bug reports.
+#[tauri::command]
+pub async fn get_log_path(app: tauri::AppHandle) -> Result<String, CommandError> {
+    let log = app
+        .try_state::<SidecarLog>()
+        .ok_or_else(|| CommandError::Io {
+            path: None,
+            message: "Sidecar logging is unavailable: no app log directory".to_string(),
+        })?;
+    Ok(log.path().to_string_lossy().into_owned())
+}
+
+/// Cancels the in-flight call started with `op_id`, which then resolves with
+/// `CommandError::Cancelled`. Returns false if it had already finished.
+#[tauri::command]
+pub async fn cancel_operation(app: tauri::AppHandle, op_id: String) -> bool {
+    app.state::<SidecarHandle>().cancel(&op_id).await
+}
+
+/// Saves the project, refusing with `CommandError::Locked` while another
+/// window holds its lock. With `release_lock` the window's lock is dropped
+/// after the save, for closing the project.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_project(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    project_json: String,
+    output_path: String,
+    max_backups: Option<usize>,
+    overwrite: Option<bool>,
+    release_lock: Option<bool>,
+) -> Result<String, CommandError> {
+    check_overwrite(&output_path, overwrite.unwrap_or(false))?;
+    check_lock(Path::new(&output_path), window.label())?;
+    let mut project = ProjectFile::parse_current(&project_json)?;
+    let previous = std::fs::read_to_string(&output_path)
+        .ok()
+        .and_then(|json| ProjectFile::parse(&json).ok())
+        .and_then(|existing| existing.meta());
+    project.touch_meta(previous, now_millis())?;
+    let project_json = project.to_json()?;
+    let max_backups = max_backups.unwrap_or(DEFAULT_MAX_BACKUPS);
+    rotate_backups(Path::new(&output_path), max_backups)
+        .map_err(|e| CommandError::io(&output_path, e))?;
+    write_project(Path::new(&output_path), &project_json)?;
+    if release_lock.unwrap_or(false) {
+        if let Err(e) = project_lock::release_lock(Path::new(&output_path), window.label()) {
+            eprintln!("Could not release project lock: {}", e);
+        }
+    }
+    remember_recent(&app, &output_path);
+    emit_file_written(&app, "project", &output_path);
+    if let Err(e) = app_data_subdir(&app, AUTOSAVE_DIR).and_then(|dir| mark_saved(&dir)) {
+        eprintln!("Could not record save for autosave recovery: {}", e);
+    }
+    Ok(output_path)
+}
+
+/// Loads a project, first checking it against its saved checksum unless
+/// `verify_checksum` is false.
+///
+/// The window takes the project's lock, failing with
+/// `CommandError::Locked` if another window holds it. `read_only` opens
+/// the project without the lock, and `force` takes the lock over.
+#[tauri::command]
+pub async fn load_project(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    input_path: String,
+    verify_checksum: Option<bool>,
+    read_only: Option<bool>,
+    force: Option<bool>,
+) -> Result<ProjectLoadResult, CommandError> {
+    let json = std::fs::read_to_string(&input_path).map_err(|e| CommandError::io(&input_path, e))?;
+    if verify_checksum.unwrap_or(true) {
+        verify_project_checksum(Path::new(&input_path), &json)?;
+    }
+    let result = ProjectLoadResult::from_json(&json)?;
+    if !read_only.unwrap_or(false) {
+        let force = force.unwrap_or(false);
+        match acquire_lock(Path::new(&input_path), window.label(), force) {
+            Err(e @ CommandError::Locked { .. }) => return Err(e),
+            // The lock is advisory, so e.g. a read-only folder doesn't
+            // stop the project from opening
+            Err(e) => eprintln!("Could not lock project: {}", e),
+            Ok(()) => {}
+        }
+    }
+    remember_recent(&app, &input_path);
+    app.state::<ProjectHistory>().clear();
+    Ok(result)
+}
+
+/// Drops the window's lock on the project at `path`, e.g. when the project
+/// is closed without saving. Returns false if the window didn't hold it.
+#[tauri::command]
+pub async fn release_lock(window: tauri::Window, path: String) -> Result<bool, CommandError> {
+    project_lock::release_lock(Path::new(&path), window.label())
+}
+
+/// Loads a project handed over as bytes, e.g. by a parent process or after
+/// a download, migrating and validating it exactly like `load_project`.
+#[tauri::command]
+pub async fn load_project_from_bytes(
+    app: tauri::AppHandle,
+    bytes: Vec<u8>,
+) -> Result<ProjectLoadResult, CommandError> {
+    let result = ProjectLoadResult::from_bytes(&bytes)?;
+    app.state::<ProjectHistory>().clear();
+    Ok(result)
+}
+
+/// Converts a HueForge project into a new, unsaved project. Its fields
+/// without a Layerforge equivalent come back as warnings.
+#[tauri::command]
+pub async fn import_hueforge(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<ProjectLoadResult, CommandError> {
+    let json = std::fs::read_to_string(&path).map_err(|e| CommandError::io(&path, e))?;
+    let result = project_from_hueforge(&json)?;
+    app.state::<ProjectHistory>().clear();
+    Ok(result)
+}
+
+/// Returns the bytes `save_project` would write, with the metadata
+/// stamped, without touching the filesystem.
+#[tauri::command]
+pub async fn save_project_to_bytes(project_json: String) -> Result<Vec<u8>, CommandError> {
+    let mut project = ProjectFile::parse_current(&project_json)?;
+    project.touch_meta(None, now_millis())?;
+    Ok(project.to_json()?.into_bytes())
+}
+
+/// Returns the project resized to `new_width_mm` wide, see
+/// `ProjectFile::rescale`.
+#[tauri::command]
+pub async fn rescale_project(
+    project_json: String,
+    new_width_mm: f64,
+    lock_aspect: bool,
+    scale_depth: bool,
+) -> Result<String, CommandError> {
+    let mut project = ProjectFile::parse_current(&project_json)?;
+    project.rescale(new_width_mm, lock_aspect, scale_depth)?;
+    project.to_json()
+}
+
+/// Saves the project together with its source image and an optional
+/// thumbnail into a portable `.lfpz` bundle, emitting `bundle-progress`
+/// as it goes.
+#[tauri::command]
+pub async fn save_project_bundle(
+    app: tauri::AppHandle,
+    project_json: String,
+    image_path: String,
+    output_path: String,
+    thumbnail_base64: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<String, CommandError> {
+    check_overwrite(&output_path, overwrite.unwrap_or(false))?;
+    validate_image_path(&image_path)?;
+    let project = ProjectFile::parse_current(&project_json)?;
+    write_bundle(
+        project,
+        &image_path,
+        thumbnail_base64.as_deref(),
+        &output_path,
+        |progress, stage| emit_bundle_progress(&app, &output_path, progress, stage),
+    )?;
+    remember_recent(&app, &output_path);
+    emit_file_written(&app, "bundle", &output_path);
+    Ok(output_path)
+}
+
+/// Extracts a `.lfpz` bundle, emitting `bundle-progress` as it goes.
+#[tauri::command]
+pub async fn load_project_bundle(
+    app: tauri::AppHandle,
+    input_path: String,
+) -> Result<ProjectBundle, CommandError> {
+    let bundle = read_bundle(&input_path, |progress, stage| {
+        emit_bundle_progress(&app, &input_path, progress, stage)
+    })?;
+    remember_recent(&app, &input_path);
+    app.state::<ProjectHistory>().clear();
+    Ok(bundle)
+}
+
+/// Writes a read-only snapshot of the project for sharing: its settings,
+/// filaments and swap plan with `preview_base64` embedded, but not the
+/// source image.
+#[tauri::command]
+pub async fn export_snapshot(
+    app: tauri::AppHandle,
+    project_json: String,
+    preview_base64: String,
+    output_path: String,
+    overwrite: Option<bool>,
+) -> Result<String, CommandError> {
+    check_overwrite(&output_path, overwrite.unwrap_or(false))?;
+    let project = ProjectFile::parse_current(&project_json)?;
+    let snapshot = build_snapshot(&project, &preview_base64)?;
+    let json = serde_json::to_string_pretty(&snapshot)?;
+    write_atomic(Path::new(&output_path), json.as_bytes())
+        .map_err(|e| CommandError::io(&output_path, e))?;
+    emit_file_written(&app, "snapshot", &output_path);
+    Ok(output_path)
+}
+
+/// Path of `name` inside the app config directory, which is created if
+/// needed.
+fn app_config_file(app: &tauri::AppHandle, name: &str) -> Result<PathBuf, CommandError> {
+    let dir = app.path().app_config_dir().map_err(|e| CommandError::Io {
+        path: None,
+        message: format!("No app config directory: {}", e),
+    })?;
+    std::fs::create_dir_all(&dir).map_err(|e| CommandError::io(dir.to_string_lossy(), e))?;
+    Ok(dir.join(name))
+}
+
+/// Directory `name` inside the app data directory, created if needed.
+fn app_data_subdir(app: &tauri::AppHandle, name: &str) -> Result<PathBuf, CommandError> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| CommandError::Io {
+            path: None,
+            message: format!("No app data directory: {}", e),
+        })?
+        .join(name);
+    std::fs::create_dir_all(&dir).map_err(|e| CommandError::io(dir.to_string_lossy(), e))?;
+    Ok(dir)
+}
+
+/// The saved app settings. Unreadable settings shouldn't block the command
+/// consulting them, so the defaults are used and the error only logged.
+fn app_settings(app: &tauri::AppHandle) -> AppSettings {
+    match app_config_file(app, SETTINGS_NAME).and_then(|path| read_settings(&path)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Using default settings: {}", e);
+            AppSettings::default()
+        }
+    }
+}
+
+/// Records a project in the recent-files list. Failing to do so shouldn't
+/// fail the load or save that triggered it, so errors are only logged.
+fn remember_recent(app: &tauri::AppHandle, path: &str) {
+    let result = app_config_file(app, RECENT_FILES_NAME).and_then(|list| add_recent(&list, path));
+    if let Err(e) = result {
+        eprintln!("Could not update recent files: {}", e);
+    }
+}
+
+/// Emits `file-written` for a file a command just wrote. The file is there
+/// whether or not anyone listens, so failures are only logged.
+fn emit_file_written(app: &tauri::AppHandle, kind: &'static str, path: &str) {
+    let size_bytes = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            eprintln!("Could not read size of written file {}: {}", path, e);
+            return;
+        }
+    };
+    let payload = FileWritten {
+        kind,
+        path: path.to_string(),
+        size_bytes,
+    };
+    if let Err(e) = app.emit("file-written", payload) {
+        eprintln!("Could not emit file-written for {}: {}", path, e);
+    }
+}
+
+fn emit_bundle_progress(app: &tauri::AppHandle, path: &str, progress: f64, stage: &'static str) {
+    let payload = BundleProgress {
+        path: path.to_string(),
+        progress,
+        stage,
+    };
+    if let Err(e) = app.emit("bundle-progress", payload) {
+        eprintln!("Could not emit bundle progress: {}", e);
+    }
+}
+
+/// Snapshots the current project for crash recovery.
+#[tauri::command]
+pub async fn autosave(app: tauri::AppHandle, project_json: String) -> Result<(), CommandError> {
+    let project_json = ProjectFile::parse_current(&project_json)?.to_json()?;
+    write_autosave(&app_data_subdir(&app, AUTOSAVE_DIR)?, &project_json)?;
+    Ok(())
+}
+
+/// Returns the newest autosave that loads and is newer than the last
+/// explicit save, so the frontend can offer to restore it at startup.
+#[tauri::command]
+pub async fn check_for_recovery(app: tauri::AppHandle) -> Result<Option<String>, CommandError> {
+    find_recovery(&app_data_subdir(&app, AUTOSAVE_DIR)?)
+}
+
+#[tauri::command]
+pub async fn add_recent_file(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<Vec<RecentEntry>, CommandError> {
+    add_recent(&app_config_file(&app, RECENT_FILES_NAME)?, &path)
+}
+
+#[tauri::command]
+pub async fn get_recent_files(app: tauri::AppHandle) -> Result<Vec<RecentEntry>, CommandError> {
+    Ok(read_recent(&app_config_file(&app, RECENT_FILES_NAME)?))
+}
+
+#[tauri::command]
+pub async fn clear_recent_files(app: tauri::AppHandle) -> Result<(), CommandError> {
+    clear_recent(&app_config_file(&app, RECENT_FILES_NAME)?)
+}
+
+/// Compares two saved projects after bringing both to the current schema.
+#[tauri::command]
+pub async fn diff_projects(path_a: String, path_b: String) -> Result<ProjectDiff, CommandError> {
+    let load = |path: &str| -> Result<ProjectFile, CommandError> {
+        let json = std::fs::read_to_string(path).map_err(|e| CommandError::io(path, e))?;
+        Ok(ProjectLoadResult::from_json(&json)?.project)
+    };
+    project_diff::diff_projects(&load(&path_a)?, &load(&path_b)?)
+}
+
+#[tauri::command]
+pub async fn get_settings(app: tauri::AppHandle) -> Result<AppSettings, CommandError> {
+    read_settings(&app_config_file(&app, SETTINGS_NAME)?)
+}
+
+/// Replaces the saved settings. The sidecar timeout applies from the next
+/// call on.
+#[tauri::command]
+pub async fn save_settings(
+    app: tauri::AppHandle,
+    settings: AppSettings,
+) -> Result<(), CommandError> {
+    write_settings(&app_config_file(&app, SETTINGS_NAME)?, &settings)?;
+    app.state::<SidecarHandle>()
+        .set_default_timeout(settings.sidecar_timeout());
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn save_filament_library(
+    app: tauri::AppHandle,
+    filaments: Vec<Filament>,
+) -> Result<(), CommandError> {
+    write_library(&app_config_file(&app, FILAMENT_LIBRARY_NAME)?, filaments)
+}
+
+#[tauri::command]
+pub async fn load_filament_library(app: tauri::AppHandle) -> Result<Vec<Filament>, CommandError> {
+    read_library(&app_config_file(&app, FILAMENT_LIBRARY_NAME)?)
+}
+
+#[tauri::command]
+pub async fn delete_filament_from_library(
+    app: tauri::AppHandle,
+    id: String,
+) -> Result<Vec<Filament>, CommandError> {
+    delete_from_library(&app_config_file(&app, FILAMENT_LIBRARY_NAME)?, &id)
+}
+
+/// Reads filaments from a `name, hex_color, td` CSV. They are returned for
+/// review, not added to the library.
+#[tauri::command]
+pub async fn import_filament_library_csv(path: String) -> Result<Vec<Filament>, CommandError> {
+    let text = std::fs::read_to_string(&path).map_err(|e| CommandError::io(&path, e))?;
+    filaments_from_csv(&text)
+}
+
+#[tauri::command]
+pub async fn export_filament_library_csv(
+    filaments: Vec<Filament>,
+    path: String,
+    overwrite: Option<bool>,
+) -> Result<String, CommandError> {
+    check_overwrite(&path, overwrite.unwrap_or(false))?;
+    write_atomic(Path::new(&path), filaments_to_csv(&filaments).as_bytes())
+        .map_err(|e| CommandError::io(&path, e))?;
+    Ok(path)
+}
+
+/// Built-in geometry presets followed by the saved ones.
+#[tauri::command]
+pub async fn list_geometry_presets(
+    app: tauri::AppHandle,
+) -> Result<Vec<GeometryPreset>, CommandError> {
+    list_presets(&app_config_file(&app, GEOMETRY_PRESETS_NAME)?)
+}
+
+#[tauri::command]
+pub async fn get_geometry_preset(
+    app: tauri::AppHandle,
+    name: String,
+) -> Result<ModelGeometrySettings, CommandError> {
+    get_preset(&app_config_file(&app, GEOMETRY_PRESETS_NAME)?, &name)
+}
+
+/// Saves `settings` as a custom preset, replacing one of the same name.
+/// Built-in names can't be reused.
+#[tauri::command]
+pub async fn save_geometry_preset(
+    app: tauri::AppHandle,
+    name: String,
+    settings: ModelGeometrySettings,
+) -> Result<(), CommandError> {
+    let path = app_config_file(&app, GEOMETRY_PRESETS_NAME)?;
+    save_preset(&path, &name, settings)
+}
+
+/// Loads the `index`-th backup of a project, where 1 is the most recent.
+#[tauri::command]
+pub async fn restore_backup(
+    project_path: String,
+    index: usize,
+) -> Result<ProjectLoadResult, CommandError> {
+    let path = backup_path(Path::new(&project_path), index);
+    let json =
+        std::fs::read_to_string(&path).map_err(|e| CommandError::io(path.to_string_lossy(), e))?;
+    ProjectLoadResult::from_json(&json)
+}
+
+/// Records the project after an edit so it can be undone.
+#[tauri::command]
+pub async fn push_history(app: tauri::AppHandle, project_json: String) {
+    app.state::<ProjectHistory>().push(project_json);
+}
+
+/// The project as it was before the current state, if there is one.
+#[tauri::command]
+pub async fn undo(app: tauri::AppHandle) -> Option<String> {
+    app.state::<ProjectHistory>().undo()
+}
+
+/// The project as it was before the last undo, if there is one.
+#[tauri::command]
+pub async fn redo(app: tauri::AppHandle) -> Option<String> {
+    app.state::<ProjectHistory>().redo()
+}
+
+#[tauri::command]
+pub async fn can_undo(app: tauri::AppHandle) -> bool {
+    app.state::<ProjectHistory>().can_undo()
 }
 
 #[tauri::command]
-pub async fn load_project(input_path: String) -> Result<String, String> {
-    std::fs::read_to_string(&input_path).map_err(|e| e.to_string())
+pub async fn can_redo(app: tauri::AppHandle) -> bool {
+    app.state::<ProjectHistory>().can_redo()
 }
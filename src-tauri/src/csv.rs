@@ -0,0 +1,79 @@
+//! Minimal CSV reading and writing for plan exports and the filament
+//! library: comma separated, with RFC 4180 style double-quoted fields.
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Splits `text` into rows of fields. Quoted fields may contain commas,
+/// doubled quotes and line breaks; fields are trimmed of surrounding
+/// whitespace and blank lines are skipped.
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    let mut end_row = |row: &mut Vec<String>, field: &mut String| {
+        row.push(field.trim().to_string());
+        field.clear();
+        if !(row.len() == 1 && row[0].is_empty()) {
+            rows.push(std::mem::take(row));
+        }
+        row.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field).trim().to_string()),
+            '\r' if !quoted => {}
+            '\n' if !quoted => end_row(&mut row, &mut field),
+            _ => field.push(c),
+        }
+    }
+    end_row(&mut row, &mut field);
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn parses_quoted_fields_and_skips_blank_lines() {
+        let rows = parse_csv("name,td\r\n\"Red, matte\",1.5\n\n\"say \"\"hi\"\"\", 2\n");
+        assert_eq!(
+            rows,
+            vec![
+                vec!["name", "td"],
+                vec!["Red, matte", "1.5"],
+                vec!["say \"hi\"", "2"],
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_written_fields() {
+        let values = ["a,b", "line\nbreak", "\"quoted\""];
+        let line: Vec<String> = values.iter().map(|v| csv_field(v)).collect();
+        assert_eq!(parse_csv(&line.join(",")), vec![values.to_vec()]);
+    }
+}
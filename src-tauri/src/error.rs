@@ -0,0 +1,251 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// Error returned by every Tauri command.
+///
+/// Serialized as an object tagged with `kind`, so the frontend can tell the
+/// failure classes apart and decide which ones are worth retrying.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommandError {
+    /// The core could not be started even after retrying.
+    SidecarSpawn {
+        message: String,
+        attempts: u32,
+    },
+    SidecarTimeout {
+        method: String,
+        seconds: u64,
+    },
+    /// The core stopped sending heartbeats for `seconds` and was killed.
+    SidecarHung {
+        method: String,
+        seconds: u64,
+    },
+    /// The core died mid-call with a failing exit `code` or a `signal`.
+    SidecarCrashed {
+        code: Option<i32>,
+        signal: Option<i32>,
+        out_of_memory: bool,
+        stderr: Option<String>,
+    },
+    Sidecar {
+        message: String,
+        stderr: Option<String>,
+    },
+    Io {
+        path: Option<String>,
+        message: String,
+    },
+    InvalidImage {
+        path: String,
+        reason: String,
+    },
+    InvalidSettings {
+        field: String,
+        reason: String,
+    },
+    /// A `ModelGeometrySettings` value the sidecar can't work with.
+    InvalidGeometry {
+        field: String,
+        reason: String,
+    },
+    /// An exception raised by the core, kept apart so the message can be
+    /// shown on its own and the traceback on request.
+    PythonError {
+        message: String,
+        traceback: Option<String>,
+        /// Class name of the exception, e.g. `ValueError`.
+        error_type: Option<String>,
+        stderr: Option<String>,
+    },
+    Serialization {
+        message: String,
+    },
+    Cancelled {
+        op_id: String,
+    },
+    /// A newer call of the same kind replaced this one while it was queued.
+    Superseded {
+        method: String,
+    },
+    UnsupportedProjectVersion {
+        version: u32,
+        supported: u32,
+    },
+    /// The output already exists and the caller didn't allow overwriting it.
+    WouldOverwrite {
+        path: String,
+    },
+    /// The project no longer matches the checksum saved next to it.
+    ChecksumMismatch {
+        path: String,
+    },
+    /// Another window holds the project's lock. `since` is when it was
+    /// taken, in milliseconds since the Unix epoch.
+    Locked {
+        held_by_pid: u32,
+        since: u64,
+    },
+}
+
+impl CommandError {
+    pub fn io(path: impl Into<String>, err: std::io::Error) -> Self {
+        CommandError::Io {
+            path: Some(path.into()),
+            message: err.to_string(),
+        }
+    }
+
+    pub fn sidecar(message: impl Into<String>) -> Self {
+        CommandError::Sidecar {
+            message: message.into(),
+            stderr: None,
+        }
+    }
+
+    pub fn invalid_settings(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        CommandError::InvalidSettings {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn invalid_geometry(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        CommandError::InvalidGeometry {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn serialization(message: impl Into<String>) -> Self {
+        CommandError::Serialization {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::SidecarSpawn { message, attempts } => {
+                write!(f, "{} (gave up after {} attempts)", message, attempts)
+            }
+            CommandError::SidecarTimeout { method, seconds } => {
+                write!(
+                    f,
+                    "Sidecar timed out after {} seconds during {}",
+                    seconds, method
+                )
+            }
+            CommandError::SidecarHung { method, seconds } => write!(
+                f,
+                "Sidecar stopped responding during {} (no heartbeat for {} seconds)",
+                method, seconds
+            ),
+            CommandError::SidecarCrashed {
+                code,
+                signal,
+                out_of_memory,
+                stderr,
+            } => {
+                match (signal, code) {
+                    (Some(signal), _) => write!(f, "Sidecar was killed by signal {}", signal)?,
+                    (None, Some(code)) => write!(f, "Sidecar crashed with exit code {}", code)?,
+                    (None, None) => write!(f, "Sidecar crashed")?,
+                }
+                if *out_of_memory {
+                    write!(
+                        f,
+                        ", probably out of memory. Try downscaling the image first"
+                    )?;
+                }
+                match stderr {
+                    Some(stderr) => write!(f, "\n{}", stderr),
+                    None => Ok(()),
+                }
+            }
+            CommandError::Sidecar {
+                message,
+                stderr: Some(stderr),
+            } => {
+                write!(f, "Sidecar error: {}\n{}", message, stderr)
+            }
+            CommandError::Sidecar {
+                message,
+                stderr: None,
+            } => write!(f, "Sidecar error: {}", message),
+            CommandError::Io {
+                path: Some(path),
+                message,
+            } => write!(f, "{}: {}", path, message),
+            CommandError::Io {
+                path: None,
+                message,
+            } => write!(f, "{}", message),
+            CommandError::InvalidImage { path, reason } => {
+                write!(f, "Invalid image {}: {}", path, reason)
+            }
+            CommandError::InvalidSettings { field, reason } => {
+                write!(f, "Invalid {}: {}", field, reason)
+            }
+            CommandError::InvalidGeometry { field, reason } => {
+                write!(f, "Invalid geometry {}: {}", field, reason)
+            }
+            CommandError::PythonError {
+                message,
+                traceback,
+                error_type,
+                ..
+            } => {
+                match error_type {
+                    Some(error_type) => write!(f, "Python {}: {}", error_type, message)?,
+                    None => write!(f, "Python error: {}", message)?,
+                }
+                match traceback {
+                    Some(traceback) => write!(f, "\n{}", traceback),
+                    None => Ok(()),
+                }
+            }
+            CommandError::Serialization { message } => write!(f, "{}", message),
+            CommandError::Cancelled { op_id } => write!(f, "Operation {} was cancelled", op_id),
+            CommandError::Superseded { method } => {
+                write!(f, "{} was superseded by a newer request", method)
+            }
+            CommandError::UnsupportedProjectVersion { version, supported } => write!(
+                f,
+                "Project uses schema version {} but this app supports up to {}; please update Layerforge",
+                version, supported
+            ),
+            CommandError::WouldOverwrite { path } => write!(f, "{} already exists", path),
+            CommandError::ChecksumMismatch { path } => write!(
+                f,
+                "{} does not match its saved checksum; it may be corrupted or edited by hand",
+                path
+            ),
+            CommandError::Locked { held_by_pid, .. } => write!(
+                f,
+                "The project is already open in another window (process {})",
+                held_by_pid
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<std::io::Error> for CommandError {
+    fn from(err: std::io::Error) -> Self {
+        CommandError::Io {
+            path: None,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(err: serde_json::Error) -> Self {
+        CommandError::serialization(err.to_string())
+    }
+}
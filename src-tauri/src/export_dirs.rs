@@ -0,0 +1,80 @@
+//! Last directory used for each kind of export, so save dialogs reopen
+//! where the user last saved that kind of file.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::CommandError;
+use crate::fs_util::write_atomic;
+use crate::models::ExportKind;
+
+/// File name of the directory list inside the app config directory.
+pub const EXPORT_DIRS_NAME: &str = "export-dirs.json";
+
+fn read_dirs(list_path: &Path) -> HashMap<ExportKind, PathBuf> {
+    std::fs::read_to_string(list_path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Directory `kind` was last exported to, if it still exists.
+pub fn last_export_dir(list_path: &Path, kind: ExportKind) -> Option<PathBuf> {
+    read_dirs(list_path)
+        .remove(&kind)
+        .filter(|dir| dir.is_dir())
+}
+
+/// Records the directory containing `exported` as the last one for `kind`.
+pub fn remember_export_dir(
+    list_path: &Path,
+    kind: ExportKind,
+    exported: &Path,
+) -> Result<(), CommandError> {
+    let Some(dir) = exported.parent() else {
+        return Ok(());
+    };
+    let mut dirs = read_dirs(list_path);
+    dirs.insert(kind, dir.to_path_buf());
+    let json = serde_json::to_string_pretty(&dirs)?;
+    write_atomic(list_path, json.as_bytes())
+        .map_err(|e| CommandError::io(list_path.to_string_lossy(), e))
+}
+
+/// `default_name` with the extension `kind` is saved with, replacing any it
+/// already has.
+pub fn export_file_name(default_name: &str, kind: ExportKind) -> String {
+    let stem = Path::new(default_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or_else(|| "untitled".to_string());
+    format!("{}.{}", stem, kind.extension())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn names_files_with_the_kind_extension() {
+        assert_eq!(export_file_name("cat", ExportKind::Stl), "cat.stl");
+        assert_eq!(export_file_name("cat.lfp", ExportKind::ThreeMF), "cat.3mf");
+        assert_eq!(export_file_name("", ExportKind::Gcode), "untitled.gcode");
+    }
+
+    #[test]
+    fn remembers_the_directory_per_kind() {
+        let dir = temp_dir("export-dirs");
+        let list = dir.join(EXPORT_DIRS_NAME);
+        assert_eq!(last_export_dir(&list, ExportKind::Stl), None);
+
+        remember_export_dir(&list, ExportKind::Stl, &dir.join("cat.stl")).unwrap();
+        remember_export_dir(&list, ExportKind::Csv, Path::new("/no/such/dir/plan.csv")).unwrap();
+
+        assert_eq!(last_export_dir(&list, ExportKind::Stl), Some(dir.clone()));
+        assert_eq!(last_export_dir(&list, ExportKind::Csv), None);
+        assert_eq!(last_export_dir(&list, ExportKind::Project), None);
+    }
+}
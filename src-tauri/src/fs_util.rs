@@ -0,0 +1,139 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Numbers the temporary files of this process, so concurrent writes to
+/// the same path never share one.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Writes `contents` to `path` so that readers see either the old file or
+/// the complete new one, never a truncated mix.
+///
+/// The data goes to a temporary file in the same directory, is flushed to
+/// disk and then renamed over the target. The directory is synced too, so
+/// the rename itself survives a power loss.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = temp_path_for(path);
+
+    let result = write_synced(&tmp, contents)
+        .and_then(|()| fs::rename(&tmp, path))
+        .and_then(|()| sync_parent(path));
+
+    if tmp.exists() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Flushes the directory entry of `path` to disk. Windows can't open a
+/// directory as a file, and commits renames on its own.
+fn sync_parent(path: &Path) -> io::Result<()> {
+    if cfg!(target_os = "windows") {
+        return Ok(());
+    }
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Command that shows `path` in the platform's file manager, selecting it
+/// where the file manager supports that. On Linux there is no common way
+/// to select a file, so the containing directory is opened instead.
+pub fn reveal_command(path: &Path) -> Command {
+    if cfg!(target_os = "windows") {
+        let mut command = Command::new("explorer");
+        let mut arg = std::ffi::OsString::from("/select,");
+        arg.push(path);
+        command.arg(arg);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    } else {
+        let dir = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(path)
+        };
+        let mut command = Command::new("xdg-open");
+        command.arg(dir);
+        command
+    }
+}
+
+/// Current time in milliseconds since the Unix epoch, as stored in the
+/// app's metadata files.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn replaces_existing_file_and_leaves_no_temp_file() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("project.lfp");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, b"new contents").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new contents");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn concurrent_writes_to_one_path_each_land_whole() {
+        let dir = temp_dir("atomic-concurrent");
+        let path = dir.join("autosave.lfp");
+        let contents: Vec<String> = (0..8).map(|i| format!("write {}", i).repeat(500)).collect();
+
+        std::thread::scope(|scope| {
+            for data in &contents {
+                let path = &path;
+                scope.spawn(move || write_atomic(path, data.as_bytes()).unwrap());
+            }
+        });
+
+        assert!(contents.contains(&fs::read_to_string(&path).unwrap()));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn fails_without_touching_anything_when_directory_is_missing() {
+        let dir = temp_dir("atomic-missing");
+        let path = dir.join("missing").join("project.lfp");
+
+        assert!(write_atomic(&path, b"data").is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Heightmaps stored as temp files so follow-up calls can hand the sidecar a
+//! path instead of re-sending a multi-megabyte base64 string.
+//!
+//! The file holds the same bytes the sidecar base64-encodes: a row-major
+//! float32 buffer of `width * height` samples.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+
+use crate::error::CommandError;
+use crate::fs_util::write_atomic;
+
+pub fn heightmap_dir() -> PathBuf {
+    std::env::temp_dir().join("layerforge-heightmaps")
+}
+
+/// Decodes `heightmap_base64` into a file under [`heightmap_dir`] and
+/// returns its path. Files are named after their contents, so storing the
+/// same heightmap twice reuses the first file.
+pub fn store_heightmap(heightmap_base64: &str) -> Result<String, CommandError> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(heightmap_base64)
+        .map_err(|e| CommandError::serialization(format!("Invalid heightmap data: {}", e)))?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+
+    let dir = heightmap_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| CommandError::io(dir.to_string_lossy(), e))?;
+    let path = dir.join(format!("{:016x}.f32", hasher.finish()));
+    let path_str = path.to_string_lossy().into_owned();
+    if !path.exists() {
+        // A concurrent call storing the same heightmap may win the rename.
+        if let Err(e) = write_atomic(&path, &bytes) {
+            if !path.exists() {
+                return Err(CommandError::io(&path_str, e));
+            }
+        }
+    }
+    Ok(path_str)
+}
+
+/// Picks how a request passes its heightmap to the sidecar. A stored file
+/// of the right size wins and the inline base64 is cleared; otherwise the
+/// path is dropped and the base64 is used, which must then be present.
+pub fn prefer_heightmap_file(
+    heightmap_path: &mut Option<String>,
+    heightmap_base64: &mut String,
+    width: u32,
+    height: u32,
+) -> Result<(), CommandError> {
+    let Some(path) = heightmap_path.as_deref() else {
+        return Ok(());
+    };
+
+    let expected = width as u64 * height as u64 * 4;
+    let problem = match std::fs::metadata(Path::new(path)) {
+        Ok(metadata) if metadata.len() == expected => None,
+        Ok(metadata) => Some(format!(
+            "Heightmap file is {} bytes, expected {} for {}x{}",
+            metadata.len(),
+            expected,
+            width,
+            height
+        )),
+        Err(e) => Some(e.to_string()),
+    };
+
+    match problem {
+        None => heightmap_base64.clear(),
+        Some(_) if !heightmap_base64.is_empty() => *heightmap_path = None,
+        Some(message) => {
+            return Err(CommandError::Io {
+                path: Some(path.to_string()),
+                message,
+            })
+        }
+    }
+    Ok(())
+}
+
+/// Reads the samples of a heightmap from its file, or from the base64
+/// string when there is no file.
+pub fn load_samples(
+    heightmap_path: Option<&str>,
+    heightmap_base64: &str,
+) -> Result<Vec<f32>, CommandError> {
+    let bytes = match heightmap_path {
+        Some(path) => std::fs::read(path).map_err(|e| CommandError::io(path, e))?,
+        None => base64::engine::general_purpose::STANDARD
+            .decode(heightmap_base64)
+            .map_err(|e| CommandError::serialization(format!("Invalid heightmap data: {}", e)))?,
+    };
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+pub fn encode_samples(samples: &[f32]) -> String {
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+/// Largest size with the same aspect ratio that fits in `max_size` on both
+/// sides. Images that already fit are left as they are.
+pub fn fit_within(width: u32, height: u32, max_size: u32) -> (u32, u32) {
+    let max_size = max_size.max(1);
+    if width <= max_size && height <= max_size {
+        return (width, height);
+    }
+    let scale = max_size as f64 / width.max(height) as f64;
+    let scaled = |v: u32| ((v as f64 * scale).round() as u32).clamp(1, max_size);
+    (scaled(width), scaled(height))
+}
+
+/// Box-filters a `width` x `height` heightmap down to `new_width` x
+/// `new_height`, averaging the samples that fall into each output cell.
+pub fn downsample(
+    samples: &[f32],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+) -> Vec<f32> {
+    let (w, h) = (width as usize, height as usize);
+    let (nw, nh) = (new_width.max(1) as usize, new_height.max(1) as usize);
+    let mut out = Vec::with_capacity(nw * nh);
+    for y in 0..nh {
+        let (y0, y1) = (y * h / nh, ((y + 1) * h / nh).max(y * h / nh + 1).min(h));
+        for x in 0..nw {
+            let (x0, x1) = (x * w / nw, ((x + 1) * w / nw).max(x * w / nw + 1).min(w));
+            let mut sum = 0.0f64;
+            let mut count = 0usize;
+            for row in y0..y1 {
+                for sample in samples.get(row * w + x0..row * w + x1).unwrap_or(&[]) {
+                    sum += *sample as f64;
+                    count += 1;
+                }
+            }
+            out.push(if count > 0 {
+                (sum / count as f64) as f32
+            } else {
+                0.0
+            });
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_decoded_bytes_once() {
+        let data = encode_samples(&[0.5, 1.0, 1.5, 2.0]);
+        let path = store_heightmap(&data).unwrap();
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 16);
+        assert_eq!(store_heightmap(&data).unwrap(), path);
+    }
+
+    #[test]
+    fn prefers_a_matching_file_over_base64() {
+        let data = encode_samples(&[0.25, 0.75]);
+        let mut path = Some(store_heightmap(&data).unwrap());
+        let mut base64 = data.clone();
+
+        prefer_heightmap_file(&mut path, &mut base64, 2, 1).unwrap();
+        assert!(path.is_some());
+        assert!(base64.is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_base64_when_the_file_is_unusable() {
+        let data = encode_samples(&[0.125, 0.625]);
+        let mut path = Some(store_heightmap(&data).unwrap());
+        let mut base64 = data.clone();
+
+        prefer_heightmap_file(&mut path, &mut base64, 3, 1).unwrap();
+        assert!(path.is_none());
+        assert_eq!(base64, data);
+
+        let mut path = Some("/definitely/not/here.f32".to_string());
+        let mut empty = String::new();
+        let err = prefer_heightmap_file(&mut path, &mut empty, 2, 1).unwrap_err();
+        assert!(matches!(err, CommandError::Io { .. }));
+    }
+
+    #[test]
+    fn fits_keeping_aspect_ratio() {
+        assert_eq!(fit_within(1000, 500, 200), (200, 100));
+        assert_eq!(fit_within(300, 1200, 256), (64, 256));
+        assert_eq!(fit_within(100, 50, 256), (100, 50));
+        assert_eq!(fit_within(1000, 1, 100), (100, 1));
+    }
+
+    #[test]
+    fn downsample_averages_blocks() {
+        let samples = [1.0, 3.0, 5.0, 7.0, 1.0, 3.0, 5.0, 7.0];
+        assert_eq!(downsample(&samples, 4, 2, 2, 1), vec![2.0, 6.0]);
+    }
+
+    #[test]
+    fn loads_samples_from_base64_or_file() {
+        let data = encode_samples(&[0.5, 1.25]);
+        assert_eq!(load_samples(None, &data).unwrap(), vec![0.5, 1.25]);
+
+        let path = store_heightmap(&data).unwrap();
+        assert_eq!(load_samples(Some(&path), "").unwrap(), vec![0.5, 1.25]);
+    }
+}
@@ -0,0 +1,138 @@
+//! Undo/redo history of project states, shared by every panel.
+//!
+//! The frontend pushes the project JSON after each edit. Undo steps back to
+//! the state before the current one and redo steps forward again; pushing
+//! after an undo discards the states that could have been redone.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+pub const MAX_HISTORY_DEPTH: usize = 50;
+
+#[derive(Default)]
+struct HistoryState {
+    past: VecDeque<String>,
+    current: Option<String>,
+    future: Vec<String>,
+}
+
+pub struct ProjectHistory {
+    depth: usize,
+    state: Mutex<HistoryState>,
+}
+
+impl Default for ProjectHistory {
+    fn default() -> Self {
+        ProjectHistory::new(MAX_HISTORY_DEPTH)
+    }
+}
+
+impl ProjectHistory {
+    /// History keeping at most `depth` states to undo to.
+    pub fn new(depth: usize) -> Self {
+        ProjectHistory {
+            depth: depth.max(1),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Records `project_json` as the current state. Pushing the current
+    /// state again is ignored, so it doesn't create an undo step.
+    pub fn push(&self, project_json: String) {
+        let mut state = self.state.lock().unwrap();
+        if state.current.as_ref() == Some(&project_json) {
+            return;
+        }
+        if let Some(previous) = state.current.replace(project_json) {
+            state.past.push_back(previous);
+            if state.past.len() > self.depth {
+                state.past.pop_front();
+            }
+        }
+        state.future.clear();
+    }
+
+    /// Steps back one state and returns it, if there is one.
+    pub fn undo(&self) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let previous = state.past.pop_back()?;
+        if let Some(current) = state.current.replace(previous.clone()) {
+            state.future.push(current);
+        }
+        Some(previous)
+    }
+
+    /// Steps forward to the state last undone and returns it, if any.
+    pub fn redo(&self) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let next = state.future.pop()?;
+        if let Some(current) = state.current.replace(next.clone()) {
+            state.past.push_back(current);
+        }
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.state.lock().unwrap().past.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.state.lock().unwrap().future.is_empty()
+    }
+
+    /// Forgets every state, e.g. when another project is loaded.
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = HistoryState::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pushed(history: &ProjectHistory, states: &[&str]) {
+        for state in states {
+            history.push(state.to_string());
+        }
+    }
+
+    #[test]
+    fn undoes_and_redoes_in_order() {
+        let history = ProjectHistory::default();
+        assert!(!history.can_undo());
+        pushed(&history, &["a", "b", "c"]);
+
+        assert_eq!(history.undo().as_deref(), Some("b"));
+        assert_eq!(history.undo().as_deref(), Some("a"));
+        assert_eq!(history.undo(), None);
+        assert!(history.can_redo());
+        assert_eq!(history.redo().as_deref(), Some("b"));
+        assert_eq!(history.redo().as_deref(), Some("c"));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn pushing_after_undo_drops_the_redo_states() {
+        let history = ProjectHistory::default();
+        pushed(&history, &["a", "b", "c"]);
+        history.undo();
+        pushed(&history, &["d", "d"]);
+
+        assert!(!history.can_redo());
+        assert_eq!(history.undo().as_deref(), Some("b"));
+        assert_eq!(history.undo().as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn keeps_only_the_newest_states_and_clears() {
+        let history = ProjectHistory::new(2);
+        pushed(&history, &["a", "b", "c", "d"]);
+
+        assert_eq!(history.undo().as_deref(), Some("c"));
+        assert_eq!(history.undo().as_deref(), Some("b"));
+        assert!(!history.can_undo());
+
+        history.clear();
+        assert!(!history.can_redo());
+    }
+}
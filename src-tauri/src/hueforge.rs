@@ -0,0 +1,291 @@
+//! Import of HueForge project files.
+//!
+//! A HueForge project is JSON. The fields with a Layerforge counterpart are
+//! read: the filament set (brand, name, color and transmission distance),
+//! the slider layer at which each filament starts, the layer and base
+//! heights, the depth range and the print size. Every other field is
+//! reported as a warning rather than failing the import, and the result
+//! goes through the same validation as a loaded project.
+
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::error::CommandError;
+use crate::project::{ProjectFile, ProjectLoadResult, CURRENT_SCHEMA_VERSION};
+use crate::validation::normalize_hex;
+
+/// Newest HueForge project version this importer understands.
+pub const MAX_HUEFORGE_VERSION: u64 = 2;
+
+/// HueForge's transmission distance is the thickness that blocks almost all
+/// light; Layerforge's `td` is the attenuation that makes that thickness let
+/// through 1 in this many parts.
+const OPAQUE_RATIO: f64 = 20.0;
+
+/// Color given to filaments whose color can't be read.
+const FALLBACK_COLOR: &str = "#808080";
+
+#[derive(Debug, Deserialize)]
+struct HueforgeProject {
+    #[serde(default)]
+    filament_set: Vec<HueforgeFilament>,
+    /// Slider layer, counted from the build plate, at which each filament
+    /// of the set starts.
+    #[serde(default)]
+    slider_values: Option<Vec<f64>>,
+    layer_height: Option<f64>,
+    base_layer_height: Option<f64>,
+    min_depth: Option<f64>,
+    max_depth: Option<f64>,
+    print_width: Option<f64>,
+    print_height: Option<f64>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HueforgeFilament {
+    #[serde(default)]
+    brand: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    color: String,
+    transmissivity: Option<f64>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+/// Maps a HueForge project onto a Layerforge project. Settings HueForge
+/// doesn't have keep the app defaults.
+pub fn project_from_hueforge(json: &str) -> Result<ProjectLoadResult, CommandError> {
+    let value: Value = serde_json::from_str(json)?;
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(1);
+    if version > MAX_HUEFORGE_VERSION {
+        return Err(CommandError::invalid_settings(
+            "version",
+            format!(
+                "HueForge project version {} isn't supported, only up to {}",
+                version, MAX_HUEFORGE_VERSION
+            ),
+        ));
+    }
+    let hueforge: HueforgeProject = serde_json::from_value(value)?;
+    let mut warnings = Vec::new();
+
+    let layer_height = hueforge.layer_height.unwrap_or(0.08);
+    let base_layer = hueforge.base_layer_height.unwrap_or(0.16);
+    let mut min_depth = hueforge.min_depth.unwrap_or(0.48);
+    let mut max_depth = hueforge.max_depth.unwrap_or(2.24);
+    if min_depth > max_depth {
+        warnings.push(format!(
+            "Minimum depth {} mm is above the maximum {} mm, the two were swapped",
+            min_depth, max_depth
+        ));
+        std::mem::swap(&mut min_depth, &mut max_depth);
+    }
+
+    let mut ignored_filament_fields = BTreeSet::new();
+    let mut filaments = Vec::with_capacity(hueforge.filament_set.len());
+    for (index, filament) in hueforge.filament_set.iter().enumerate() {
+        let name = format!("{} {}", filament.brand.trim(), filament.name.trim())
+            .trim()
+            .to_string();
+        let name = if name.is_empty() {
+            format!("Filament {}", index + 1)
+        } else {
+            name
+        };
+        let hex_color = normalize_hex(&filament.color).unwrap_or_else(|_| {
+            warnings.push(format!(
+                "{} has an unreadable color {:?}, using {}",
+                name, filament.color, FALLBACK_COLOR
+            ));
+            FALLBACK_COLOR.to_string()
+        });
+        let d95_mm = match filament.transmissivity {
+            Some(td) if td > 0.0 => td,
+            _ => {
+                warnings.push(format!("{} has no transmission distance, using 1 mm", name));
+                1.0
+            }
+        };
+        ignored_filament_fields.extend(filament.other.keys().cloned());
+        filaments.push(json!({
+            "id": format!("hueforge-{}", index + 1),
+            "name": name,
+            "hexColor": hex_color,
+            "td": OPAQUE_RATIO.ln() / d95_mm,
+            "enabled": true,
+            "orderIndex": index,
+        }));
+    }
+
+    let thresholds = match &hueforge.slider_values {
+        Some(sliders) if sliders.len() == filaments.len() => sliders
+            .iter()
+            .skip(1)
+            .map(|&layer| layer * layer_height - base_layer)
+            .chain(std::iter::once(max_depth))
+            .map(|z| (z.clamp(min_depth, max_depth) * 1000.0).round() / 1000.0)
+            .collect(),
+        sliders => {
+            if sliders.is_some() {
+                warnings.push(
+                    "Slider positions don't match the filament set, color stops were spread \
+                     evenly"
+                        .to_string(),
+                );
+            }
+            let count = filaments.len() as f64;
+            (1..=filaments.len())
+                .map(|i| {
+                    let z = min_depth + (max_depth - min_depth) * i as f64 / count;
+                    (z * 1000.0).round() / 1000.0
+                })
+                .collect::<Vec<f64>>()
+        }
+    };
+    let stops: Vec<Value> = thresholds
+        .iter()
+        .enumerate()
+        .map(|(index, threshold)| {
+            json!({
+                "filamentId": format!("hueforge-{}", index + 1),
+                "thresholdZMm": threshold,
+            })
+        })
+        .collect();
+
+    for key in hueforge
+        .other
+        .keys()
+        .filter(|key| key.as_str() != "version")
+    {
+        warnings.push(format!(
+            "HueForge setting {:?} has no equivalent and was ignored",
+            key
+        ));
+    }
+    for key in ignored_filament_fields {
+        warnings.push(format!("HueForge filament field {:?} was ignored", key));
+    }
+
+    let project: ProjectFile = serde_json::from_value(json!({
+        "schemaVersion": CURRENT_SCHEMA_VERSION,
+        "imagePath": null,
+        "filaments": filaments,
+        "modelGeometry": {
+            "minDepthMm": min_depth,
+            "maxDepthMm": max_depth,
+            "gamma": 1.0,
+            "contrast": 1.0,
+            "offset": 0.0,
+            "smoothing": 0.0,
+            "spikeRemoval": "none",
+            "luminanceMethod": "rec601",
+            "toneMappingMode": "gamma",
+            "dynamicDepth": false,
+            "invert": false,
+        },
+        "printSettings": {
+            "layerHeightMm": layer_height,
+            "baseLayerMm": base_layer,
+            "widthMm": hueforge.print_width.unwrap_or(100.0),
+            "heightMm": hueforge.print_height.unwrap_or(100.0),
+            "borderWidthMm": 0.0,
+            "borderDepthMm": 0.0,
+            "hasBorder": false,
+        },
+        "colorPlan": {
+            "mode": "transmission",
+            "stops": stops,
+        },
+    }))?;
+    warnings.extend(project.validate());
+    Ok(ProjectLoadResult {
+        project,
+        meta: None,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ColorStop, Filament};
+
+    #[test]
+    fn maps_filaments_and_sliders_onto_a_project() {
+        let json = r##"{
+            "version": 2,
+            "filament_set": [
+                {"Brand": "Acme", "Name": "Black", "Color": "#000", "Transmissivity": 0.6},
+                {"Brand": "Acme", "Name": "White", "Color": "#ffffff", "Transmissivity": 2.5,
+                 "Type": "PLA"}
+            ],
+            "slider_values": [1, 12],
+            "layer_height": 0.08,
+            "base_layer_height": 0.16,
+            "min_depth": 0.48,
+            "max_depth": 1.6,
+            "image_data": "..."
+        }"##;
+
+        let result = project_from_hueforge(json).unwrap();
+        let filaments: Vec<Filament> = result.project.typed_section(&["filaments"]).unwrap();
+        assert_eq!(filaments[0].name, "Acme Black");
+        assert_eq!(filaments[0].hex_color, "#000000");
+        assert!((filaments[1].td - 20f64.ln() / 2.5).abs() < 1e-9);
+
+        let stops: Vec<ColorStop> = result
+            .project
+            .typed_section(&["colorPlan", "stops"])
+            .unwrap();
+        // Layer 12 starts at 0.96 mm from the plate, 0.8 mm above the base
+        assert_eq!(stops[0].threshold_z_mm, 0.8);
+        assert_eq!(stops[1].threshold_z_mm, 1.6);
+
+        assert_eq!(result.warnings.len(), 2, "{:?}", result.warnings);
+        assert!(result.warnings.iter().any(|w| w.contains("image_data")));
+        assert!(result.warnings.iter().any(|w| w.contains("Type")));
+    }
+
+    #[test]
+    fn rejects_newer_versions_and_spreads_stops_without_sliders() {
+        let newer = format!(r#"{{"version": {}}}"#, MAX_HUEFORGE_VERSION + 1);
+        assert!(matches!(
+            project_from_hueforge(&newer),
+            Err(CommandError::InvalidSettings { .. })
+        ));
+
+        let json = r#"{"filament_set": [{"Name": "A", "Color": "nope"}, {"Name": "B"}],
+                       "min_depth": 0.4, "max_depth": 2.0}"#;
+        let result = project_from_hueforge(json).unwrap();
+        let stops: Vec<ColorStop> = result
+            .project
+            .typed_section(&["colorPlan", "stops"])
+            .unwrap();
+        assert_eq!(stops[0].threshold_z_mm, 1.2);
+        assert_eq!(stops[1].threshold_z_mm, 2.0);
+        // Two unreadable colors and two missing transmission distances
+        assert_eq!(result.warnings.len(), 4, "{:?}", result.warnings);
+    }
+
+    #[test]
+    fn swaps_an_inverted_depth_range() {
+        let json = r##"{"filament_set": [{"Name": "A", "Color": "#000000", "Transmissivity": 1}],
+                        "slider_values": [1], "min_depth": 2.0, "max_depth": 0.4}"##;
+        let result = project_from_hueforge(json).unwrap();
+        let stops: Vec<ColorStop> = result
+            .project
+            .typed_section(&["colorPlan", "stops"])
+            .unwrap();
+        assert_eq!(stops[0].threshold_z_mm, 2.0);
+        assert_eq!(result.warnings.len(), 1, "{:?}", result.warnings);
+        assert!(result.warnings[0].contains("swapped"));
+    }
+}
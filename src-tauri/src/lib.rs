@@ -1,24 +1,173 @@
+mod autosave;
+mod bundle;
+mod cache;
+mod color;
+mod color_plan;
 mod commands;
+mod csv;
+mod error;
+mod export_dirs;
+mod fs_util;
+mod heightmap;
+mod history;
+mod hueforge;
+mod library;
+mod models;
+mod plan;
+mod presets;
+mod project;
+mod project_diff;
+mod project_lock;
+mod recent;
+mod settings;
+mod sidecar;
+mod sidecar_log;
+mod sidecar_versions;
+mod snapshot;
+#[cfg(test)]
+mod test_util;
+mod tone_curve;
+mod usage;
+mod validation;
 
 use tauri::Manager;
 
+use cache::HeightmapCache;
+use history::ProjectHistory;
+use settings::{read_settings, SETTINGS_NAME};
+use sidecar::{SidecarHandle, DEFAULT_HEARTBEAT_INTERVAL};
+use sidecar_log::{SidecarLog, MAX_LOG_BYTES, SIDECAR_LOG_NAME};
+use sidecar_versions::{read_active, resolve_version, ACTIVE_SIDECAR_NAME, SIDECAR_VERSIONS_DIR};
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .manage(SidecarHandle::default())
+        .manage(HeightmapCache::default())
+        .manage(ProjectHistory::default())
         .invoke_handler(tauri::generate_handler![
             commands::process_image,
+            commands::analyze_image,
+            commands::compare_presets,
+            commands::import_stl_heightmap,
+            commands::measure_td,
             commands::generate_mesh,
+            commands::preview_mesh_stats,
+            commands::validate_mesh,
+            commands::export_tone_curve,
             commands::compute_preview,
+            commands::preview_band,
+            commands::preview_diff,
+            commands::export_turntable,
+            commands::generate_thumbnail,
             commands::compute_swaps,
+            commands::compute_swap_layers,
+            commands::snap_stops_to_layers,
+            commands::batch_generate,
+            commands::suggest_stops,
+            commands::optimize_swap_count,
+            commands::suggest_palette,
+            commands::sort_filaments_by_td,
+            commands::find_closest_filaments,
+            commands::summarize_swaps,
+            commands::merge_close_stops,
+            commands::validate_filament_order,
+            commands::compute_color_resolution,
+            commands::estimate_usage,
+            commands::estimate_print_time,
             commands::export_stl,
+            commands::export_slicer_project,
+            commands::export_heightmap,
             commands::export_plan,
+            commands::export_instructions,
+            commands::generate_legend,
+            commands::pick_export_path,
+            commands::reveal_in_file_manager,
+            commands::cancel_operation,
+            commands::ping_sidecar,
+            commands::get_capabilities,
+            commands::set_heartbeat_interval,
+            commands::list_sidecar_versions,
+            commands::set_active_sidecar,
+            commands::get_log_path,
             commands::save_project,
             commands::load_project,
+            commands::release_lock,
+            commands::load_project_from_bytes,
+            commands::import_hueforge,
+            commands::save_project_to_bytes,
+            commands::rescale_project,
+            commands::restore_backup,
+            commands::push_history,
+            commands::undo,
+            commands::redo,
+            commands::can_undo,
+            commands::can_redo,
+            commands::diff_projects,
+            commands::save_project_bundle,
+            commands::load_project_bundle,
+            commands::export_snapshot,
+            commands::autosave,
+            commands::check_for_recovery,
+            commands::add_recent_file,
+            commands::get_recent_files,
+            commands::clear_recent_files,
+            commands::get_settings,
+            commands::save_settings,
+            commands::save_filament_library,
+            commands::load_filament_library,
+            commands::delete_filament_from_library,
+            commands::import_filament_library_csv,
+            commands::export_filament_library_csv,
+            commands::list_geometry_presets,
+            commands::get_geometry_preset,
+            commands::save_geometry_preset,
         ])
         .setup(|app| {
+            match app.path().app_log_dir() {
+                Ok(dir) => {
+                    app.manage(SidecarLog::new(dir.join(SIDECAR_LOG_NAME), MAX_LOG_BYTES));
+                }
+                Err(e) => eprintln!("No app log directory, sidecar output isn't logged: {}", e),
+            }
+
+            // Apply the saved sidecar timeout and launch the core version picked
+            // last time, as long as it is still there
+            if let (Ok(config_dir), Ok(data_dir)) =
+                (app.path().app_config_dir(), app.path().app_data_dir())
+            {
+                let sidecar = app.state::<SidecarHandle>();
+                match read_settings(&config_dir.join(SETTINGS_NAME)) {
+                    Ok(settings) => sidecar.set_default_timeout(settings.sidecar_timeout()),
+                    Err(e) => eprintln!("Using default settings: {}", e),
+                }
+                let id = read_active(&config_dir.join(ACTIVE_SIDECAR_NAME));
+                match resolve_version(&data_dir.join(SIDECAR_VERSIONS_DIR), &id) {
+                    Ok(binary) => sidecar.set_binary(binary),
+                    Err(e) => eprintln!("Using the bundled sidecar instead: {}", e),
+                }
+            }
+
+            // Warm up the Python core so the first command doesn't pay its startup
+            // cost, and watch it for hangs if it can tell us it is still alive
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = handle.state::<SidecarHandle>().start(&handle).await {
+                    eprintln!("{}", e);
+                    return;
+                }
+                match commands::get_capabilities(handle.clone()).await {
+                    Ok(capabilities) if capabilities.heartbeat => handle
+                        .state::<SidecarHandle>()
+                        .set_heartbeat_interval(Some(DEFAULT_HEARTBEAT_INTERVAL)),
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Could not read sidecar capabilities: {}", e),
+                }
+            });
+
             #[cfg(debug_assertions)]
             {
                 let window = app.get_webview_window("main").unwrap();
@@ -26,6 +175,12 @@ pub fn run() {
             }
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Don't leave the Python core running after the app quits
+            if let tauri::RunEvent::Exit = event {
+                tauri::async_runtime::block_on(app.state::<SidecarHandle>().shutdown());
+            }
+        });
 }
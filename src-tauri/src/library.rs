@@ -0,0 +1,260 @@
+//! Filament library kept in the app config directory, independent of any
+//! project.
+//!
+//! Only a filament's identity and optics are stored; `enabled` and
+//! `order_index` belong to a project and are filled in when the library is
+//! loaded.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::csv::{csv_field, parse_csv};
+use crate::error::CommandError;
+use crate::fs_util::{now_millis, write_atomic};
+use crate::models::Filament;
+use crate::validation::{normalize_filament_colors, normalize_hex};
+
+pub const FILAMENT_LIBRARY_NAME: &str = "filament-library.json";
+
+/// Transmission distance given to CSV rows that leave `td` blank, the same
+/// default the sidecar uses.
+pub const DEFAULT_TD: f64 = 1.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LibraryEntry {
+    id: String,
+    name: String,
+    hex_color: String,
+    td: f64,
+}
+
+/// Reads the library at `path`, enabled and in stored order. A missing
+/// library is empty; an unreadable one is an error so it isn't silently
+/// overwritten.
+pub fn read_library(path: &Path) -> Result<Vec<Filament>, CommandError> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(CommandError::io(path.to_string_lossy(), e)),
+    };
+    let entries: Vec<LibraryEntry> = serde_json::from_str(&json)?;
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| Filament {
+            id: entry.id,
+            name: entry.name,
+            hex_color: entry.hex_color,
+            td: entry.td,
+            enabled: true,
+            order_index: index as i32,
+        })
+        .collect())
+}
+
+/// Replaces the library at `path` with `filaments`. Colors are normalized
+/// and a repeated id keeps its last definition.
+pub fn write_library(path: &Path, mut filaments: Vec<Filament>) -> Result<(), CommandError> {
+    normalize_filament_colors(&mut filaments)?;
+    let mut entries: Vec<LibraryEntry> = Vec::with_capacity(filaments.len());
+    for filament in filaments {
+        entries.retain(|entry| entry.id != filament.id);
+        entries.push(LibraryEntry {
+            id: filament.id,
+            name: filament.name,
+            hex_color: filament.hex_color,
+            td: filament.td,
+        });
+    }
+    let json = serde_json::to_string_pretty(&entries)?;
+    write_atomic(path, json.as_bytes()).map_err(|e| CommandError::io(path.to_string_lossy(), e))
+}
+
+/// Removes the filament with `id` and returns what is left.
+pub fn delete_from_library(path: &Path, id: &str) -> Result<Vec<Filament>, CommandError> {
+    let mut filaments = read_library(path)?;
+    filaments.retain(|filament| filament.id != id);
+    write_library(path, filaments)?;
+    read_library(path)
+}
+
+/// Column positions of a library CSV, from its header row if it has one.
+struct CsvColumns {
+    name: usize,
+    hex_color: usize,
+    td: Option<usize>,
+}
+
+impl CsvColumns {
+    fn from_header(row: &[String]) -> Option<Result<Self, CommandError>> {
+        let find = |names: &[&str]| {
+            row.iter()
+                .position(|cell| names.contains(&cell.to_ascii_lowercase().as_str()))
+        };
+        let name = find(&["name"])?;
+        let Some(hex_color) = find(&["hex_color", "hexcolor", "hex", "color"]) else {
+            return Some(Err(CommandError::invalid_settings(
+                "csv",
+                "header has no hex_color column",
+            )));
+        };
+        Some(Ok(CsvColumns {
+            name,
+            hex_color,
+            td: find(&["td"]),
+        }))
+    }
+}
+
+/// Reads `name, hex_color, td` rows into new filaments with generated ids.
+/// A header row is optional and may put the columns in any order.
+pub fn filaments_from_csv(text: &str) -> Result<Vec<Filament>, CommandError> {
+    let mut rows = parse_csv(text);
+    let columns = match rows.first().and_then(|row| CsvColumns::from_header(row)) {
+        Some(columns) => {
+            rows.remove(0);
+            columns?
+        }
+        None => CsvColumns {
+            name: 0,
+            hex_color: 1,
+            td: Some(2),
+        },
+    };
+
+    let stamp = now_millis();
+    rows.iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let cell = |column: usize| row.get(column).map_or("", String::as_str);
+            let row_error = |reason: String| {
+                CommandError::invalid_settings("csv", format!("row {}: {}", index + 1, reason))
+            };
+            let hex_color =
+                normalize_hex(cell(columns.hex_color)).map_err(|e| row_error(e.to_string()))?;
+            let td = match columns.td.map(cell).unwrap_or("") {
+                "" => DEFAULT_TD,
+                td => td
+                    .parse()
+                    .map_err(|_| row_error(format!("td {:?} is not a number", td)))?,
+            };
+            let name = match cell(columns.name) {
+                "" => format!("Filament {}", index + 1),
+                name => name.to_string(),
+            };
+            Ok(Filament {
+                id: format!("filament_{}_{}", stamp, index),
+                name,
+                hex_color,
+                td,
+                enabled: true,
+                order_index: index as i32,
+            })
+        })
+        .collect()
+}
+
+/// Writes `filaments` as `name, hex_color, td` rows under a header.
+pub fn filaments_to_csv(filaments: &[Filament]) -> String {
+    let mut out = String::from("name,hex_color,td\n");
+    for filament in filaments {
+        out.push_str(&format!(
+            "{},{},{}\n",
+            csv_field(&filament.name),
+            csv_field(&filament.hex_color),
+            filament.td
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn filament(id: &str, hex_color: &str, td: f64) -> Filament {
+        Filament {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            hex_color: hex_color.to_string(),
+            td,
+            enabled: false,
+            order_index: 9,
+        }
+    }
+
+    #[test]
+    fn round_trips_optics_and_defaults_project_fields() {
+        let path = temp_dir("library").join(FILAMENT_LIBRARY_NAME);
+        assert!(read_library(&path).unwrap().is_empty());
+
+        write_library(
+            &path,
+            vec![filament("w", "#fff", 3.5), filament("k", "#000000", 0.4)],
+        )
+        .unwrap();
+        let library = read_library(&path).unwrap();
+
+        assert_eq!(library.len(), 2);
+        assert_eq!(library[0].hex_color, "#FFFFFF");
+        assert_eq!(library[0].name, "W");
+        assert_eq!(library[1].td, 0.4);
+        assert!(library.iter().all(|f| f.enabled));
+        assert_eq!(library[1].order_index, 1);
+    }
+
+    #[test]
+    fn imports_csv_with_or_without_a_header() {
+        let with_header = "TD,Name,Hex\n2.5,\"White, matte\",fff\n,Black,#000000\n";
+        let filaments = filaments_from_csv(with_header).unwrap();
+        assert_eq!(filaments.len(), 2);
+        assert_eq!(filaments[0].name, "White, matte");
+        assert_eq!(filaments[0].hex_color, "#FFFFFF");
+        assert_eq!(filaments[0].td, 2.5);
+        assert_eq!(filaments[1].td, DEFAULT_TD);
+        assert_ne!(filaments[0].id, filaments[1].id);
+
+        let without_header = filaments_from_csv("Red,#ff0000,1.2\n").unwrap();
+        assert_eq!(without_header[0].name, "Red");
+        assert_eq!(without_header[0].td, 1.2);
+    }
+
+    #[test]
+    fn csv_errors_name_the_row() {
+        let err =
+            filaments_from_csv("name,hex_color,td\nRed,#ff0000,1\nBlue,blue,1\n").unwrap_err();
+        assert!(err.to_string().contains("row 2"));
+        assert!(filaments_from_csv("Red,#ff0000,thick\n").is_err());
+    }
+
+    #[test]
+    fn exported_csv_imports_back() {
+        let filaments = vec![filament("w", "#FFFFFF", 3.5), filament("k", "#000000", 0.4)];
+        let imported = filaments_from_csv(&filaments_to_csv(&filaments)).unwrap();
+
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[1].name, "K");
+        assert_eq!(imported[1].hex_color, "#000000");
+        assert_eq!(imported[1].td, 0.4);
+    }
+
+    #[test]
+    fn deletes_by_id_and_keeps_the_last_duplicate() {
+        let path = temp_dir("library-delete").join(FILAMENT_LIBRARY_NAME);
+        write_library(
+            &path,
+            vec![
+                filament("w", "#FFFFFF", 3.5),
+                filament("k", "#000000", 0.4),
+                filament("w", "#EEEEEE", 3.0),
+            ],
+        )
+        .unwrap();
+
+        let library = delete_from_library(&path, "k").unwrap();
+        assert_eq!(library.len(), 1);
+        assert_eq!(library[0].hex_color, "#EEEEEE");
+    }
+}
@@ -0,0 +1,231 @@
+//! Data types shared by the commands, the sidecar protocol and project files.
+//!
+//! Fields serialize with the snake_case names the sidecar expects. The
+//! aliases let the same types read the camelCase names used in project files.
+
+use serde::{Deserialize, Serialize};
+
+fn default_luminance_method() -> String {
+    "rec601".to_string()
+}
+
+fn default_tone_mapping_mode() -> String {
+    "gamma".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferCurvePoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+fn default_transfer_curve() -> Vec<TransferCurvePoint> {
+    vec![
+        TransferCurvePoint { x: 0.0, y: 0.0 },
+        TransferCurvePoint { x: 0.25, y: 0.25 },
+        TransferCurvePoint { x: 0.5, y: 0.5 },
+        TransferCurvePoint { x: 0.75, y: 0.75 },
+        TransferCurvePoint { x: 1.0, y: 1.0 },
+    ]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModelGeometrySettings {
+    #[serde(alias = "minDepthMm")]
+    pub min_depth_mm: f64,
+    #[serde(alias = "maxDepthMm")]
+    pub max_depth_mm: f64,
+    pub gamma: f64,
+    pub contrast: f64,
+    pub offset: f64,
+    pub smoothing: f64,
+    #[serde(alias = "spikeRemoval")]
+    pub spike_removal: String,
+    #[serde(default = "default_luminance_method", alias = "luminanceMethod")]
+    pub luminance_method: String,
+    #[serde(default = "default_tone_mapping_mode", alias = "toneMappingMode")]
+    pub tone_mapping_mode: String,
+    #[serde(default = "default_transfer_curve", alias = "transferCurve")]
+    pub transfer_curve: Vec<TransferCurvePoint>,
+    #[serde(default, alias = "dynamicDepth")]
+    pub dynamic_depth: bool,
+    pub invert: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrintSettings {
+    #[serde(alias = "layerHeightMm")]
+    pub layer_height_mm: f64,
+    #[serde(alias = "baseLayerMm")]
+    pub base_layer_mm: f64,
+    #[serde(alias = "widthMm")]
+    pub width_mm: f64,
+    #[serde(alias = "heightMm")]
+    pub height_mm: f64,
+    #[serde(alias = "borderWidthMm")]
+    pub border_width_mm: f64,
+    #[serde(alias = "borderDepthMm")]
+    pub border_depth_mm: f64,
+    #[serde(alias = "hasBorder")]
+    pub has_border: bool,
+    /// Wall thickness of a hollowed relief. `None` extrudes a solid model.
+    #[serde(default, alias = "shellThicknessMm")]
+    pub shell_thickness_mm: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Filament {
+    pub id: String,
+    pub name: String,
+    #[serde(alias = "hexColor")]
+    pub hex_color: String,
+    pub td: f64,
+    pub enabled: bool,
+    #[serde(alias = "orderIndex")]
+    pub order_index: i32,
+}
+
+/// Region of the source image to process, in source pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorStop {
+    #[serde(alias = "filamentId")]
+    pub filament_id: String,
+    #[serde(alias = "thresholdZMm")]
+    pub threshold_z_mm: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwapEntry {
+    pub layer: i32,
+    #[serde(alias = "zMm")]
+    pub z_mm: f64,
+    #[serde(alias = "filamentId")]
+    pub filament_id: String,
+}
+
+/// Median filter the sidecar runs on the image before it becomes a
+/// heightmap. `ModelGeometrySettings::spike_removal` keeps it as a string:
+/// a level name or an odd kernel size in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpikeRemoval {
+    None,
+    Light,
+    Medium,
+    Strong,
+    Kernel(u32),
+}
+
+impl SpikeRemoval {
+    pub const MAX_KERNEL: u32 = 15;
+
+    /// Parses a level name, case-insensitively and accepting `aggressive`
+    /// for `strong`, or a kernel size. Sizes matching a level parse as it.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim().to_ascii_lowercase();
+        let kernel = match value.as_str() {
+            "none" | "off" | "" => return Ok(SpikeRemoval::None),
+            "light" => return Ok(SpikeRemoval::Light),
+            "medium" => return Ok(SpikeRemoval::Medium),
+            "strong" | "aggressive" => return Ok(SpikeRemoval::Strong),
+            other => other.parse::<u32>().map_err(|_| {
+                format!(
+                    "{:?} is not none, light, medium, strong or a kernel size",
+                    value
+                )
+            })?,
+        };
+        match kernel {
+            0 => Ok(SpikeRemoval::None),
+            3 => Ok(SpikeRemoval::Light),
+            5 => Ok(SpikeRemoval::Medium),
+            7 => Ok(SpikeRemoval::Strong),
+            k if k % 2 == 1 && k <= Self::MAX_KERNEL => Ok(SpikeRemoval::Kernel(k)),
+            k => Err(format!(
+                "kernel size must be odd and at most {}, got {}",
+                Self::MAX_KERNEL,
+                k
+            )),
+        }
+    }
+
+    /// The canonical string sent to the sidecar.
+    pub fn as_wire(self) -> String {
+        match self {
+            SpikeRemoval::None => "none".to_string(),
+            SpikeRemoval::Light => "light".to_string(),
+            SpikeRemoval::Medium => "medium".to_string(),
+            SpikeRemoval::Strong => "strong".to_string(),
+            SpikeRemoval::Kernel(size) => size.to_string(),
+        }
+    }
+}
+
+/// File format `generate_mesh` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshFormat {
+    #[default]
+    #[serde(rename = "stl_binary", alias = "StlBinary")]
+    StlBinary,
+    #[serde(rename = "stl_ascii", alias = "StlAscii")]
+    StlAscii,
+    #[serde(rename = "obj", alias = "Obj")]
+    Obj,
+    #[serde(rename = "3mf", alias = "ThreeMF")]
+    ThreeMF,
+}
+
+impl MeshFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            MeshFormat::StlBinary | MeshFormat::StlAscii => "stl",
+            MeshFormat::Obj => "obj",
+            MeshFormat::ThreeMF => "3mf",
+        }
+    }
+}
+
+/// What is being exported, which decides the save dialog's file filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportKind {
+    Stl,
+    #[serde(rename = "3mf")]
+    ThreeMF,
+    Obj,
+    Csv,
+    Gcode,
+    Project,
+}
+
+impl ExportKind {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportKind::Stl => "stl",
+            ExportKind::ThreeMF => "3mf",
+            ExportKind::Obj => "obj",
+            ExportKind::Csv => "csv",
+            ExportKind::Gcode => "gcode",
+            ExportKind::Project => "lfp",
+        }
+    }
+
+    /// Name of the save dialog's file filter.
+    pub fn filter_name(self) -> &'static str {
+        match self {
+            ExportKind::Stl => "STL mesh",
+            ExportKind::ThreeMF => "3MF model",
+            ExportKind::Obj => "OBJ mesh",
+            ExportKind::Csv => "CSV file",
+            ExportKind::Gcode => "G-code",
+            ExportKind::Project => "Layerforge project",
+        }
+    }
+}
@@ -0,0 +1,632 @@
+//! Swap plan exports rendered directly in Rust.
+//!
+//! A `SwapEntry` is measured from the top of the solid base: `layer` is the
+//! first relief layer printed with the new filament and `z_mm` its height
+//! above the base. Slicers count layers from the build plate, so the base
+//! layers are added when converting. `swap_layers` skips that step and
+//! places stops on slicer layers directly.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::csv::csv_field;
+use crate::error::CommandError;
+use crate::models::{ColorStop, Filament, ModelGeometrySettings, PrintSettings, SwapEntry};
+
+/// Number of layers needed to reach `height_mm`, tolerating float noise so
+/// that an exact multiple of the layer height doesn't round up an extra layer.
+pub fn layers_for_height(height_mm: f64, layer_height_mm: f64) -> i32 {
+    if height_mm <= 0.0 || layer_height_mm <= 0.0 {
+        return 0;
+    }
+    let layers = height_mm / layer_height_mm;
+    if (layers - layers.round()).abs() < 1e-4 {
+        layers.round() as i32
+    } else {
+        layers.ceil() as i32
+    }
+}
+
+/// 1-based slicer layer at which the swap's filament starts.
+pub fn slicer_layer(swap: &SwapEntry, print_settings: &PrintSettings) -> i32 {
+    layers_for_height(print_settings.base_layer_mm, print_settings.layer_height_mm) + swap.layer
+}
+
+/// Print Z (top of the layer) of the slicer layer at which the swap happens.
+pub fn slicer_z_mm(swap: &SwapEntry, print_settings: &PrintSettings) -> f64 {
+    slicer_layer(swap, print_settings) as f64 * print_settings.layer_height_mm
+}
+
+/// Swaps for `stops` counted from the build plate, the way the exports
+/// print them: `layer` is the 1-based slicer layer
+/// `base layers + ceil(threshold / layer height)` and `z_mm` its print Z.
+/// The entries already include the base, so they aren't meant to be fed
+/// back into `render_plan`. A stop landing on an earlier stop's layer is
+/// dropped.
+pub fn swap_layers(stops: &[ColorStop], print_settings: &PrintSettings) -> Vec<SwapEntry> {
+    let layer_height_mm = print_settings.layer_height_mm;
+    let base_layers = layers_for_height(print_settings.base_layer_mm, layer_height_mm);
+    let mut stops: Vec<&ColorStop> = stops.iter().collect();
+    stops.sort_by(|a, b| a.threshold_z_mm.total_cmp(&b.threshold_z_mm));
+
+    let mut swaps: Vec<SwapEntry> = Vec::new();
+    for stop in stops {
+        let layer = base_layers + layers_for_height(stop.threshold_z_mm, layer_height_mm);
+        if swaps.last().is_some_and(|last| layer <= last.layer) {
+            continue;
+        }
+        swaps.push(SwapEntry {
+            layer,
+            z_mm: (layer as f64 * layer_height_mm * 1000.0).round() / 1000.0,
+            filament_id: stop.filament_id.clone(),
+        });
+    }
+    swaps
+}
+
+/// Moves each stop's threshold to the nearest layer top the printer
+/// actually reaches. The relief sits on a base of `base_layer_mm`, so layer
+/// tops fall at multiples of the layer height above the build plate rather
+/// than above the base. A threshold is never moved below the first layer
+/// top at or above the base.
+pub fn snap_stops_to_layers(stops: &[ColorStop], print_settings: &PrintSettings) -> Vec<ColorStop> {
+    let layer_height_mm = print_settings.layer_height_mm;
+    let base_mm = print_settings.base_layer_mm;
+    let first_top = layers_for_height(base_mm, layer_height_mm);
+    stops
+        .iter()
+        .map(|stop| {
+            let layer = ((base_mm + stop.threshold_z_mm) / layer_height_mm).round() as i32;
+            let z = layer.max(first_top) as f64 * layer_height_mm - base_mm;
+            ColorStop {
+                filament_id: stop.filament_id.clone(),
+                threshold_z_mm: (z * 1000.0).round() / 1000.0,
+            }
+        })
+        .collect()
+}
+
+/// Renders `swaps` in one of the formats handled here, or returns `None` if
+/// the format is left to the sidecar.
+pub fn render_plan(
+    format: &str,
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+) -> Option<String> {
+    match format {
+        "prusa_gcode" => Some(render_prusa_gcode(swaps, filaments, print_settings)),
+        "bambu_md" => Some(render_bambu_md(swaps, filaments, print_settings)),
+        "bambu_json" => Some(render_bambu_json(swaps, filaments, print_settings)),
+        "csv" => Some(render_csv(swaps, filaments, print_settings)),
+        "heights" => Some(render_heights(swaps, filaments, print_settings, false)),
+        "heights_named" => Some(render_heights(swaps, filaments, print_settings, true)),
+        _ => None,
+    }
+}
+
+fn filament_label(filaments: &[Filament], id: &str) -> String {
+    match filaments.iter().find(|f| f.id == id) {
+        Some(filament) => format!("{} ({})", filament.name, filament.hex_color),
+        None => id.to_string(),
+    }
+}
+
+/// PrusaSlicer "Before layer change" custom G-code issuing an `M600` at the
+/// start of every swap layer. PrusaSlicer's `layer_num` is zero-based.
+fn render_prusa_gcode(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+) -> String {
+    let mut swaps: Vec<&SwapEntry> = swaps.iter().collect();
+    swaps.sort_by_key(|swap| swap.layer);
+
+    let mut out = String::new();
+    out.push_str("; Layerforge color changes for PrusaSlicer\n");
+    out.push_str("; Paste into Printer Settings > Custom G-code > Before layer change G-code\n");
+    out.push_str(&format!(
+        "; Layer height {} mm, base {} mm, {} color changes\n",
+        print_settings.layer_height_mm,
+        print_settings.base_layer_mm,
+        swaps.len()
+    ));
+    for swap in swaps {
+        let layer = slicer_layer(swap, print_settings);
+        out.push_str(&format!("{{if layer_num == {}}}\n", layer - 1));
+        out.push_str(&format!(
+            "; Layer {} (Z={:.3} mm): change to {}\n",
+            layer,
+            slicer_z_mm(swap, print_settings),
+            filament_label(filaments, &swap.filament_id)
+        ));
+        out.push_str("M600\n");
+        out.push_str("{endif}\n");
+    }
+    out
+}
+
+/// Markdown sheet to keep next to the printer: the model size, then every
+/// swap in print order with the filament to load.
+pub fn render_instructions(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+    geometry: &ModelGeometrySettings,
+) -> String {
+    let mut swaps: Vec<&SwapEntry> = swaps.iter().collect();
+    swaps.sort_by_key(|swap| swap.layer);
+
+    let mut out = String::new();
+    out.push_str("# Layerforge print instructions\n\n");
+    out.push_str(&format!(
+        "- Model: {} x {} mm, {:.2} mm tall\n",
+        print_settings.width_mm,
+        print_settings.height_mm,
+        print_settings.base_layer_mm + geometry.max_depth_mm
+    ));
+    out.push_str(&format!(
+        "- Layer height {} mm, base {} mm\n",
+        print_settings.layer_height_mm, print_settings.base_layer_mm
+    ));
+    out.push_str(&format!("- Filament swaps: {}\n\n", swaps.len()));
+    for (index, swap) in swaps.into_iter().enumerate() {
+        out.push_str(&format!(
+            "{}. At layer {} (z={:.3} mm), change to {}\n",
+            index + 1,
+            slicer_layer(swap, print_settings),
+            slicer_z_mm(swap, print_settings),
+            filament_label(filaments, &swap.filament_id)
+        ));
+    }
+    out
+}
+
+/// One swap joined against its filament, as listed in the tabular exports.
+#[derive(Debug, Serialize)]
+struct PlanRow<'a> {
+    layer: i32,
+    z_mm: f64,
+    pause_z_mm: f64,
+    filament_id: &'a str,
+    filament_name: Option<&'a str>,
+    hex_color: Option<&'a str>,
+    td: Option<f64>,
+    order_index: Option<i32>,
+}
+
+fn plan_rows<'a>(
+    swaps: &'a [SwapEntry],
+    filaments: &'a [Filament],
+    print_settings: &PrintSettings,
+) -> Vec<PlanRow<'a>> {
+    let mut swaps: Vec<&SwapEntry> = swaps.iter().collect();
+    swaps.sort_by(|a, b| a.z_mm.total_cmp(&b.z_mm));
+    swaps
+        .into_iter()
+        .map(|swap| {
+            let filament = filaments.iter().find(|f| f.id == swap.filament_id);
+            PlanRow {
+                layer: slicer_layer(swap, print_settings),
+                z_mm: swap.z_mm,
+                pause_z_mm: slicer_z_mm(swap, print_settings),
+                filament_id: &swap.filament_id,
+                filament_name: filament.map(|f| f.name.as_str()),
+                hex_color: filament.map(|f| f.hex_color.as_str()),
+                td: filament.map(|f| f.td),
+                order_index: filament.map(|f| f.order_index),
+            }
+        })
+        .collect()
+}
+
+/// Slicers `export_slicer_project` can write a project file for.
+pub const PROJECT_SLICERS: &[&str] = &["prusaslicer", "orcaslicer"];
+
+/// A color change as stored in a slicer project, made before printing the
+/// slicer layer whose top is at `print_z_mm`.
+#[derive(Debug, Serialize)]
+pub struct ColorChange {
+    pub layer: i32,
+    pub print_z_mm: f64,
+    pub hex_color: String,
+    pub filament_name: String,
+}
+
+/// Color changes for `swaps` in print order. Unlike the plan exports, a
+/// swap to a filament missing from `filaments` is an error, since the
+/// slicer needs a color for every change.
+pub fn color_changes(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+) -> Result<Vec<ColorChange>, CommandError> {
+    plan_rows(swaps, filaments, print_settings)
+        .into_iter()
+        .map(|row| match (row.filament_name, row.hex_color) {
+            (Some(filament_name), Some(hex_color)) => Ok(ColorChange {
+                layer: row.layer,
+                print_z_mm: (row.pause_z_mm * 1000.0).round() / 1000.0,
+                hex_color: hex_color.to_string(),
+                filament_name: filament_name.to_string(),
+            }),
+            _ => Err(CommandError::invalid_settings(
+                "swaps",
+                format!(
+                    "swap at layer {} uses unknown filament {}",
+                    row.layer, row.filament_id
+                ),
+            )),
+        })
+        .collect()
+}
+
+/// The swaps joined against their filaments, one JSON object per swap in
+/// print order, the same rows `bambu_json` lists.
+pub fn plan_table(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+) -> Value {
+    serde_json::json!(plan_rows(swaps, filaments, print_settings))
+}
+
+/// Markdown table to follow next to Bambu Studio's pause-at-height markers.
+fn render_bambu_md(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+) -> String {
+    let mut out = String::new();
+    out.push_str("# Layerforge filament swaps for Bambu Studio\n\n");
+    out.push_str(&format!(
+        "Layer height {} mm, base {} mm. Add a pause at each pause Z and load the listed filament.\n\n",
+        print_settings.layer_height_mm, print_settings.base_layer_mm
+    ));
+    out.push_str("| Layer | Z (mm) | Pause Z (mm) | Filament | Color | TD | Order |\n");
+    out.push_str("|---:|---:|---:|---|---|---:|---:|\n");
+    for swap in plan_rows(swaps, filaments, print_settings) {
+        out.push_str(&format!(
+            "| {} | {:.3} | {:.3} | {} | {} | {} | {} |\n",
+            swap.layer,
+            swap.z_mm,
+            swap.pause_z_mm,
+            swap.filament_name.unwrap_or(swap.filament_id),
+            swap.hex_color.unwrap_or(""),
+            swap.td.map(|td| td.to_string()).unwrap_or_default(),
+            swap.order_index.map(|i| i.to_string()).unwrap_or_default()
+        ));
+    }
+    out
+}
+
+fn render_bambu_json(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+) -> String {
+    let plan = serde_json::json!({
+        "generator": "Layerforge",
+        "layer_height_mm": print_settings.layer_height_mm,
+        "base_layer_mm": print_settings.base_layer_mm,
+        "swaps": plan_rows(swaps, filaments, print_settings),
+    });
+    serde_json::to_string_pretty(&plan).unwrap_or_default()
+}
+
+/// One pause Z per line, lowest first, for firmware that takes a plain
+/// list of pause heights. Swaps at the same height are listed once, under
+/// the filament of the last of them. With `named`, each line ends in a
+/// `;` comment naming the filament to load.
+fn render_heights(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+    named: bool,
+) -> String {
+    let mut lines: Vec<(String, &str)> = Vec::new();
+    for row in plan_rows(swaps, filaments, print_settings) {
+        let height = format!("{:.2}", row.pause_z_mm);
+        let name = row.filament_name.unwrap_or(row.filament_id);
+        match lines.last_mut() {
+            Some(last) if last.0 == height => last.1 = name,
+            _ => lines.push((height, name)),
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(height, name)| {
+            if named {
+                format!("{} ; {}\n", height, name)
+            } else {
+                format!("{}\n", height)
+            }
+        })
+        .collect()
+}
+
+/// One row per swap. Swaps whose filament isn't in `filaments` are still
+/// exported, with blank name and color and a note in the `warning` column.
+fn render_csv(
+    swaps: &[SwapEntry],
+    filaments: &[Filament],
+    print_settings: &PrintSettings,
+) -> String {
+    let mut out = String::from("layer,z_mm,filament_id,filament_name,hex_color,warning\n");
+    for row in plan_rows(swaps, filaments, print_settings) {
+        let warning = if row.filament_name.is_none() {
+            "unknown filament"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "{},{:.3},{},{},{},{}\n",
+            row.layer,
+            row.z_mm,
+            csv_field(row.filament_id),
+            csv_field(row.filament_name.unwrap_or("")),
+            csv_field(row.hex_color.unwrap_or("")),
+            warning
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_plan::{swaps_for_profile, LayerHeightSegment};
+    use crate::test_util::{geometry, print_settings};
+
+    fn swap(layer: i32, filament_id: &str) -> SwapEntry {
+        SwapEntry {
+            layer,
+            z_mm: layer as f64 * 0.08,
+            filament_id: filament_id.to_string(),
+        }
+    }
+
+    fn filament(id: &str, name: &str, hex_color: &str) -> Filament {
+        Filament {
+            id: id.to_string(),
+            name: name.to_string(),
+            hex_color: hex_color.to_string(),
+            td: 1.0,
+            enabled: true,
+            order_index: 0,
+        }
+    }
+
+    #[test]
+    fn layer_count_tolerates_float_noise() {
+        assert_eq!(layers_for_height(0.16, 0.08), 2);
+        assert_eq!(layers_for_height(0.24000000001, 0.08), 3);
+        assert_eq!(layers_for_height(0.17, 0.08), 3);
+        assert_eq!(layers_for_height(0.0, 0.08), 0);
+    }
+
+    #[test]
+    fn first_swap_is_offset_by_the_base_layers() {
+        let swap = swap(1, "a");
+        assert_eq!(slicer_layer(&swap, &print_settings()), 3);
+        assert!((slicer_z_mm(&swap, &print_settings()) - 0.24).abs() < 1e-9);
+    }
+
+    fn stop(threshold_z_mm: f64, filament_id: &str) -> ColorStop {
+        ColorStop {
+            filament_id: filament_id.to_string(),
+            threshold_z_mm,
+        }
+    }
+
+    #[test]
+    fn snaps_thresholds_to_layer_tops_above_the_plate() {
+        let stops = [stop(0.1, "a"), stop(0.13, "b"), stop(0.0, "c")];
+        let snapped = snap_stops_to_layers(&stops, &print_settings());
+        let thresholds: Vec<f64> = snapped.iter().map(|s| s.threshold_z_mm).collect();
+        assert_eq!(thresholds, vec![0.08, 0.16, 0.0]);
+        assert_eq!(snapped[1].filament_id, "b");
+
+        // A 0.2 mm base ends mid-layer, so the tops sit 0.04 mm above it
+        let settings = PrintSettings {
+            base_layer_mm: 0.2,
+            ..print_settings()
+        };
+        let snapped = snap_stops_to_layers(&stops, &settings);
+        let thresholds: Vec<f64> = snapped.iter().map(|s| s.threshold_z_mm).collect();
+        assert_eq!(thresholds, vec![0.12, 0.12, 0.04]);
+    }
+
+    #[test]
+    fn swap_layers_are_counted_from_the_build_plate() {
+        let swaps = swap_layers(&[stop(0.08, "a")], &print_settings());
+        assert_eq!(swaps[0].layer, 3);
+        assert!((swaps[0].z_mm - 0.24).abs() < 1e-9);
+        assert_eq!(
+            swaps[0].layer,
+            slicer_layer(&swap(1, "a"), &print_settings())
+        );
+    }
+
+    #[test]
+    fn swap_layers_at_exact_layer_heights_do_not_round_up() {
+        let swaps = swap_layers(
+            &[stop(0.8, "a"), stop(0.0, "b"), stop(0.24000000001, "c")],
+            &print_settings(),
+        );
+        let layers: Vec<i32> = swaps.iter().map(|swap| swap.layer).collect();
+        assert_eq!(layers, vec![2, 5, 12]);
+        assert!((swaps[2].z_mm - 0.96).abs() < 1e-9);
+    }
+
+    #[test]
+    fn swap_layers_just_past_a_boundary_round_up() {
+        let swaps = swap_layers(&[stop(0.81, "a"), stop(0.79, "b")], &print_settings());
+        let layers: Vec<i32> = swaps.iter().map(|swap| swap.layer).collect();
+        assert_eq!(layers, vec![12, 13]);
+    }
+
+    #[test]
+    fn swap_layers_drop_stops_sharing_a_layer() {
+        let swaps = swap_layers(&[stop(0.75, "a"), stop(0.78, "b")], &print_settings());
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].filament_id, "a");
+    }
+
+    #[test]
+    fn swap_layers_match_the_exported_gcode() {
+        // Swaps as compute_swaps assigns them for a constant layer height
+        for (layer_height_mm, threshold) in [(0.08, 0.81), (0.1, 0.3), (0.08, 0.8)] {
+            let settings = PrintSettings {
+                layer_height_mm,
+                ..print_settings()
+            };
+            let profile = [LayerHeightSegment {
+                from_z_mm: 0.0,
+                layer_height_mm,
+            }];
+            let (swaps, _) = swaps_for_profile(&[stop(threshold, "a")], &profile).unwrap();
+            let gcode = render_prusa_gcode(&swaps, &[], &settings);
+
+            let layer = swap_layers(&[stop(threshold, "a")], &settings)[0].layer;
+            assert!(
+                gcode.contains(&format!("; Layer {} (", layer)),
+                "{} mm at {} mm layers isn't on layer {}:\n{}",
+                threshold,
+                layer_height_mm,
+                layer,
+                gcode
+            );
+        }
+    }
+
+    #[test]
+    fn instructions_list_swaps_in_print_order() {
+        let geometry = ModelGeometrySettings {
+            max_depth_mm: 2.0,
+            ..geometry()
+        };
+        let filaments = [filament("w", "White", "#FFFFFF")];
+        let sheet = render_instructions(
+            &[swap(10, "missing"), swap(4, "w")],
+            &filaments,
+            &print_settings(),
+            &geometry,
+        );
+        assert!(sheet.contains("- Model: 100 x 80 mm, 2.16 mm tall\n"));
+        assert!(sheet.contains("- Filament swaps: 2\n"));
+        assert!(sheet.ends_with(
+            "1. At layer 6 (z=0.480 mm), change to White (#FFFFFF)\n\
+             2. At layer 12 (z=0.960 mm), change to missing\n"
+        ));
+    }
+
+    #[test]
+    fn prusa_gcode_lists_swaps_in_layer_order() {
+        let filaments = [filament("w", "White", "#FFFFFF")];
+        let gcode = render_plan(
+            "prusa_gcode",
+            &[swap(10, "missing"), swap(4, "w")],
+            &filaments,
+            &print_settings(),
+        )
+        .unwrap();
+
+        let white = gcode.find("{if layer_num == 5}").unwrap();
+        let missing = gcode.find("{if layer_num == 11}").unwrap();
+        assert!(white < missing);
+        assert!(gcode.contains("; Layer 6 (Z=0.480 mm): change to White (#FFFFFF)\nM600\n"));
+        assert!(gcode.contains("change to missing\n"));
+        assert_eq!(gcode.matches("M600").count(), 2);
+    }
+
+    #[test]
+    fn bambu_json_sorts_by_z_and_includes_filament_details() {
+        let mut white = filament("w", "White", "#FFFFFF");
+        white.td = 2.5;
+        white.order_index = 1;
+        let json = render_plan(
+            "bambu_json",
+            &[swap(10, "missing"), swap(4, "w")],
+            &[white],
+            &print_settings(),
+        )
+        .unwrap();
+        let plan: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let swaps = plan["swaps"].as_array().unwrap();
+        assert_eq!(swaps[0]["filament_name"], "White");
+        assert_eq!(swaps[0]["hex_color"], "#FFFFFF");
+        assert_eq!(swaps[0]["td"], 2.5);
+        assert_eq!(swaps[0]["order_index"], 1);
+        assert_eq!(swaps[0]["layer"], 6);
+        assert!(swaps[1]["filament_name"].is_null());
+    }
+
+    #[test]
+    fn color_changes_are_in_print_order_at_slicer_heights() {
+        let filaments = [
+            filament("w", "White", "#FFFFFF"),
+            filament("k", "Black", "#000000"),
+        ];
+        let changes = color_changes(
+            &[swap(10, "k"), swap(4, "w")],
+            &filaments,
+            &print_settings(),
+        )
+        .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].filament_name, "White");
+        assert_eq!(changes[0].layer, 6);
+        assert_eq!(changes[0].print_z_mm, 0.48);
+        assert_eq!(changes[1].hex_color, "#000000");
+
+        let err = color_changes(&[swap(4, "missing")], &filaments, &print_settings()).unwrap_err();
+        assert!(err.to_string().contains("unknown filament missing"));
+    }
+
+    #[test]
+    fn bambu_md_has_a_row_per_swap() {
+        let filaments = [filament("w", "White", "#FFFFFF")];
+        let md = render_plan("bambu_md", &[swap(4, "w")], &filaments, &print_settings()).unwrap();
+        assert!(md.contains("| 6 | 0.320 | 0.480 | White | #FFFFFF | 1 | 0 |\n"));
+    }
+
+    #[test]
+    fn csv_keeps_swaps_with_unknown_filaments() {
+        let filaments = [filament("w", "White, matte", "#FFFFFF")];
+        let csv = render_plan(
+            "csv",
+            &[swap(4, "w"), swap(10, "missing")],
+            &filaments,
+            &print_settings(),
+        )
+        .unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "layer,z_mm,filament_id,filament_name,hex_color,warning"
+        );
+        assert_eq!(lines[1], "6,0.320,w,\"White, matte\",#FFFFFF,");
+        assert_eq!(lines[2], "12,0.800,missing,,,unknown filament");
+    }
+
+    #[test]
+    fn heights_are_sorted_and_listed_once() {
+        let filaments = [
+            filament("w", "White", "#FFFFFF"),
+            filament("k", "Black", "#000000"),
+        ];
+        let swaps = [swap(10, "k"), swap(4, "w"), swap(10, "w")];
+        let plain = render_plan("heights", &swaps, &filaments, &print_settings()).unwrap();
+        assert_eq!(plain, "0.48\n0.96\n");
+
+        let named = render_plan("heights_named", &swaps, &filaments, &print_settings()).unwrap();
+        assert_eq!(named, "0.48 ; White\n0.96 ; White\n");
+    }
+
+    #[test]
+    fn other_formats_are_left_to_the_sidecar() {
+        assert!(render_plan("txt", &[], &[], &print_settings()).is_none());
+    }
+}
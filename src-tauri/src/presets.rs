@@ -0,0 +1,209 @@
+//! Named geometry presets: a few built in, the rest saved by the user in
+//! the app config directory.
+//!
+//! Built-in names are reserved so a preset always means the same settings
+//! on every machine.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::fs_util::write_atomic;
+use crate::models::ModelGeometrySettings;
+
+pub const GEOMETRY_PRESETS_NAME: &str = "geometry-presets.json";
+
+/// Settings of a built-in preset. Depths are in mm above the base,
+/// smoothing is a Gaussian sigma in pixels of the processed image.
+struct BuiltinPreset {
+    name: &'static str,
+    min_depth_mm: f64,
+    max_depth_mm: f64,
+    gamma: f64,
+    contrast: f64,
+    smoothing: f64,
+    spike_removal: &'static str,
+}
+
+const BUILTIN_PRESETS: [BuiltinPreset; 4] = [
+    // The app defaults: six 0.08 mm layers of minimum depth and a range
+    // that suits most white PLA
+    BuiltinPreset {
+        name: "standard",
+        min_depth_mm: 0.48,
+        max_depth_mm: 2.24,
+        gamma: 1.0,
+        contrast: 1.0,
+        smoothing: 0.0,
+        spike_removal: "none",
+    },
+    // Lifted midtones keep skin from printing muddy, and light smoothing
+    // hides sensor noise that would otherwise show as texture
+    BuiltinPreset {
+        name: "portrait",
+        min_depth_mm: 0.6,
+        max_depth_mm: 2.4,
+        gamma: 1.2,
+        contrast: 1.0,
+        smoothing: 0.5,
+        spike_removal: "light",
+    },
+    // A deeper range leaves room for distant detail; no smoothing, since
+    // foliage and rock are texture worth keeping
+    BuiltinPreset {
+        name: "landscape",
+        min_depth_mm: 0.48,
+        max_depth_mm: 2.8,
+        gamma: 0.9,
+        contrast: 1.1,
+        smoothing: 0.0,
+        spike_removal: "none",
+    },
+    // Logos, line art and text: a shallow range with strong contrast pushes
+    // most pixels to either end, and median filtering cleans up edges
+    BuiltinPreset {
+        name: "high-contrast",
+        min_depth_mm: 0.4,
+        max_depth_mm: 2.0,
+        gamma: 1.0,
+        contrast: 1.5,
+        smoothing: 0.0,
+        spike_removal: "medium",
+    },
+];
+
+impl BuiltinPreset {
+    fn settings(&self) -> ModelGeometrySettings {
+        serde_json::from_value(serde_json::json!({
+            "min_depth_mm": self.min_depth_mm,
+            "max_depth_mm": self.max_depth_mm,
+            "gamma": self.gamma,
+            "contrast": self.contrast,
+            "offset": 0.0,
+            "smoothing": self.smoothing,
+            "spike_removal": self.spike_removal,
+            "invert": false,
+        }))
+        .expect("built-in presets are complete geometry settings")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeometryPreset {
+    pub name: String,
+    /// Whether the preset ships with the app and can't be replaced.
+    #[serde(default)]
+    pub builtin: bool,
+    pub settings: ModelGeometrySettings,
+}
+
+/// Reads the saved presets at `path`. A missing file holds none; an
+/// unreadable one is an error so it isn't silently overwritten.
+fn read_saved(path: &Path) -> Result<Vec<GeometryPreset>, CommandError> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(CommandError::io(path.to_string_lossy(), e)),
+    }
+}
+
+/// The built-in presets followed by the ones saved at `path`.
+pub fn list_presets(path: &Path) -> Result<Vec<GeometryPreset>, CommandError> {
+    let mut presets: Vec<GeometryPreset> = BUILTIN_PRESETS
+        .iter()
+        .map(|preset| GeometryPreset {
+            name: preset.name.to_string(),
+            builtin: true,
+            settings: preset.settings(),
+        })
+        .collect();
+    presets.extend(read_saved(path)?);
+    Ok(presets)
+}
+
+/// Settings of the preset called `name`, built-in or saved at `path`.
+pub fn get_preset(path: &Path, name: &str) -> Result<ModelGeometrySettings, CommandError> {
+    if let Some(preset) = BUILTIN_PRESETS.iter().find(|preset| preset.name == name) {
+        return Ok(preset.settings());
+    }
+    read_saved(path)?
+        .into_iter()
+        .find(|preset| preset.name == name)
+        .map(|preset| preset.settings)
+        .ok_or_else(|| CommandError::invalid_settings("name", format!("no preset {:?}", name)))
+}
+
+/// Saves `settings` as the preset `name` at `path`, replacing a saved
+/// preset of that name.
+pub fn save_preset(
+    path: &Path,
+    name: &str,
+    settings: ModelGeometrySettings,
+) -> Result<(), CommandError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CommandError::invalid_settings("name", "must not be empty"));
+    }
+    if BUILTIN_PRESETS.iter().any(|preset| preset.name == name) {
+        return Err(CommandError::invalid_settings(
+            "name",
+            format!("{:?} is a built-in preset", name),
+        ));
+    }
+    settings.validate()?;
+
+    let mut presets = read_saved(path)?;
+    presets.retain(|preset| preset.name != name);
+    presets.push(GeometryPreset {
+        name: name.to_string(),
+        builtin: false,
+        settings,
+    });
+    let json = serde_json::to_string_pretty(&presets)?;
+    write_atomic(path, json.as_bytes()).map_err(|e| CommandError::io(path.to_string_lossy(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::path::PathBuf;
+
+    fn presets_path(name: &str) -> PathBuf {
+        temp_dir(name).join(GEOMETRY_PRESETS_NAME)
+    }
+
+    #[test]
+    fn built_in_presets_are_valid_and_listed_first() {
+        let path = presets_path("presets-builtin");
+        let presets = list_presets(&path).unwrap();
+        assert_eq!(presets.len(), BUILTIN_PRESETS.len());
+        for preset in &presets {
+            assert!(preset.builtin);
+            preset.settings.validate().unwrap();
+        }
+        assert_eq!(get_preset(&path, "portrait").unwrap().gamma, 1.2);
+        assert!(get_preset(&path, "nope").is_err());
+    }
+
+    #[test]
+    fn saves_custom_presets_under_free_names() {
+        let path = presets_path("presets-saved");
+        let mut settings = get_preset(&path, "standard").unwrap();
+        settings.max_depth_mm = 3.0;
+        save_preset(&path, " Cat ", settings.clone()).unwrap();
+        settings.max_depth_mm = 3.2;
+        save_preset(&path, "Cat", settings.clone()).unwrap();
+
+        assert_eq!(get_preset(&path, "Cat").unwrap().max_depth_mm, 3.2);
+        let presets = list_presets(&path).unwrap();
+        assert_eq!(presets.len(), BUILTIN_PRESETS.len() + 1);
+        assert!(!presets.last().unwrap().builtin);
+
+        assert!(save_preset(&path, "portrait", settings.clone()).is_err());
+        assert!(save_preset(&path, "", settings.clone()).is_err());
+        settings.gamma = 0.0;
+        assert!(save_preset(&path, "Flat", settings).is_err());
+    }
+}
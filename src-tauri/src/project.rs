@@ -0,0 +1,706 @@
+//! Project file handling beyond plain reads and writes.
+//!
+//! Project files are the camelCase JSON written by the frontend. They are
+//! handled as JSON maps rather than fully typed structs so fields the backend
+//! doesn't know about survive a load/save round trip untouched.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::error::CommandError;
+use crate::fs_util::write_atomic;
+use crate::models::{ColorStop, Filament, ModelGeometrySettings, PrintSettings};
+use crate::snapshot::reject_snapshot;
+
+pub const DEFAULT_MAX_BACKUPS: usize = 3;
+
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// Key of the [`ProjectMeta`] section.
+const META_KEY: &str = "meta";
+
+/// Files written before versioning was introduced count as version 1.
+fn legacy_schema_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectFile {
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub data: Map<String, Value>,
+}
+
+/// Provenance recorded in the project file. Timestamps are milliseconds
+/// since the Unix epoch, with 0 meaning unknown.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectMeta {
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub modified_at: u64,
+    #[serde(default)]
+    pub app_version: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades a project from version `n + 1` to `n + 2`.
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2];
+
+/// Version 2 added `Filament.orderIndex` and `ModelGeometry.spikeRemoval`.
+fn migrate_v1_to_v2(data: &mut Map<String, Value>) {
+    if let Some(Value::Array(filaments)) = data.get_mut("filaments") {
+        for (index, filament) in filaments.iter_mut().enumerate() {
+            if let Value::Object(filament) = filament {
+                filament
+                    .entry("orderIndex")
+                    .or_insert_with(|| Value::from(index));
+            }
+        }
+    }
+    if let Some(Value::Object(geometry)) = data.get_mut("modelGeometry") {
+        geometry
+            .entry("spikeRemoval")
+            .or_insert_with(|| Value::from("none"));
+    }
+}
+
+impl ProjectFile {
+    pub fn parse(json: &str) -> Result<Self, CommandError> {
+        let project: Self = serde_json::from_str(json)?;
+        reject_snapshot(&project.data)?;
+        Ok(project)
+    }
+
+    /// Parses JSON coming from the frontend, which always has the current
+    /// shape even when it doesn't say so.
+    pub fn parse_current(json: &str) -> Result<Self, CommandError> {
+        let data: Map<String, Value> = serde_json::from_str(json)?;
+        reject_snapshot(&data)?;
+        let versioned = data.contains_key("schemaVersion");
+        let mut project: Self = serde_json::from_value(Value::Object(data))?;
+        if !versioned {
+            project.schema_version = CURRENT_SCHEMA_VERSION;
+        }
+        Ok(project)
+    }
+
+    /// Brings the project up to `CURRENT_SCHEMA_VERSION`.
+    pub fn migrate(&mut self) -> Result<(), CommandError> {
+        if self.schema_version > CURRENT_SCHEMA_VERSION {
+            return Err(CommandError::UnsupportedProjectVersion {
+                version: self.schema_version,
+                supported: CURRENT_SCHEMA_VERSION,
+            });
+        }
+        let first = self.schema_version.max(1) as usize - 1;
+        for migration in &MIGRATIONS[first..] {
+            migration(&mut self.data);
+        }
+        self.schema_version = CURRENT_SCHEMA_VERSION;
+        Ok(())
+    }
+
+    /// The project's metadata, if it has a readable `meta` section.
+    pub fn meta(&self) -> Option<ProjectMeta> {
+        self.typed_section(&[META_KEY]).ok()
+    }
+
+    /// Updates the metadata for a save at `now`. Without a `meta` section of
+    /// its own the project inherits `previous`, the metadata of the file it
+    /// replaces, and the creation time is only set when neither has one.
+    pub fn touch_meta(
+        &mut self,
+        previous: Option<ProjectMeta>,
+        now: u64,
+    ) -> Result<(), CommandError> {
+        let mut meta = self.meta().or(previous.clone()).unwrap_or_default();
+        if meta.created_at == 0 {
+            meta.created_at = previous
+                .map(|previous| previous.created_at)
+                .filter(|&created_at| created_at != 0)
+                .unwrap_or(now);
+        }
+        meta.modified_at = now;
+        meta.app_version = env!("CARGO_PKG_VERSION").to_string();
+        self.data
+            .insert(META_KEY.to_string(), serde_json::to_value(meta)?);
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, CommandError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Checks the typed sections of the project and describes every problem
+    /// found. None of them prevent the project from loading.
+    pub fn validate(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let filaments: Option<Vec<Filament>> = self.section(&["filaments"], &mut warnings);
+        let geometry: Option<ModelGeometrySettings> =
+            self.section(&["modelGeometry"], &mut warnings);
+        let print_settings: Option<PrintSettings> = self.section(&["printSettings"], &mut warnings);
+        let stops: Option<Vec<ColorStop>> = self.section(&["colorPlan", "stops"], &mut warnings);
+
+        if let (Some(filaments), Some(stops)) = (&filaments, &stops) {
+            for stop in stops {
+                if !filaments.iter().any(|f| f.id == stop.filament_id) {
+                    warnings.push(format!(
+                        "Color stop at {} mm references unknown filament '{}'",
+                        stop.threshold_z_mm, stop.filament_id
+                    ));
+                }
+            }
+        }
+        if let Some(print_settings) = &print_settings {
+            if print_settings.layer_height_mm <= 0.0 {
+                warnings.push(format!(
+                    "Layer height must be positive, got {} mm",
+                    print_settings.layer_height_mm
+                ));
+            }
+        }
+        if let Some(geometry) = &geometry {
+            if geometry.min_depth_mm > geometry.max_depth_mm {
+                warnings.push(format!(
+                    "Minimum depth {} mm is greater than maximum depth {} mm",
+                    geometry.min_depth_mm, geometry.max_depth_mm
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Resizes the print to `new_width_mm` wide. With `lock_aspect` the
+    /// height and border width follow the width; otherwise only the width
+    /// changes. Depths are left alone unless `scale_depth` is set, since a
+    /// wider print needs the same thickness to show the same tones. When it
+    /// is set, the depth range, the color stops and the border depth scale
+    /// by the same factor as the width. Layer height and base thickness are
+    /// print settings and never change.
+    pub fn rescale(
+        &mut self,
+        new_width_mm: f64,
+        lock_aspect: bool,
+        scale_depth: bool,
+    ) -> Result<(), CommandError> {
+        if !new_width_mm.is_finite() || new_width_mm <= 0.0 {
+            return Err(CommandError::invalid_settings(
+                "new_width_mm",
+                format!("must be positive, got {} mm", new_width_mm),
+            ));
+        }
+        let print_settings: PrintSettings = self.typed_section(&["printSettings"])?;
+        if !print_settings.width_mm.is_finite() || print_settings.width_mm <= 0.0 {
+            return Err(CommandError::invalid_settings(
+                "width_mm",
+                format!(
+                    "project width must be positive to rescale, got {} mm",
+                    print_settings.width_mm
+                ),
+            ));
+        }
+        let factor = new_width_mm / print_settings.width_mm;
+
+        if let Some(Value::Object(settings)) = self.data.get_mut("printSettings") {
+            settings.insert("widthMm".to_string(), Value::from(new_width_mm));
+            if lock_aspect {
+                scale_field(settings.get_mut("heightMm"), factor);
+                scale_field(settings.get_mut("borderWidthMm"), factor);
+            }
+            if scale_depth {
+                scale_field(settings.get_mut("borderDepthMm"), factor);
+            }
+        }
+        if !scale_depth {
+            return Ok(());
+        }
+        if let Some(Value::Object(geometry)) = self.data.get_mut("modelGeometry") {
+            scale_field(geometry.get_mut("minDepthMm"), factor);
+            scale_field(geometry.get_mut("maxDepthMm"), factor);
+        }
+        let stops = self
+            .data
+            .get_mut("colorPlan")
+            .and_then(|plan| plan.get_mut("stops"));
+        if let Some(Value::Array(stops)) = stops {
+            for stop in stops {
+                scale_field(stop.get_mut("thresholdZMm"), factor);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the section at `path` into its typed form, failing when it is
+    /// missing or malformed.
+    pub fn typed_section<T: DeserializeOwned>(&self, path: &[&str]) -> Result<T, CommandError> {
+        let name = path.join(".");
+        let mut value = self.data.get(path[0]);
+        for key in &path[1..] {
+            value = value.and_then(|v| v.get(key));
+        }
+        let value =
+            value.ok_or_else(|| CommandError::serialization(format!("Project has no {}", name)))?;
+        T::deserialize(value)
+            .map_err(|e| CommandError::serialization(format!("Could not read {}: {}", name, e)))
+    }
+
+    /// Like [`Self::typed_section`], but records a warning instead of
+    /// failing.
+    fn section<T: DeserializeOwned>(&self, path: &[&str], warnings: &mut Vec<String>) -> Option<T> {
+        match self.typed_section(path) {
+            Ok(section) => Some(section),
+            Err(e) => {
+                warnings.push(e.to_string());
+                None
+            }
+        }
+    }
+}
+
+/// Multiplies a numeric field by `factor`, rounded to the micrometre.
+/// Missing and non-numeric fields are left as they are.
+fn scale_field(field: Option<&mut Value>, factor: f64) {
+    if let Some(field) = field {
+        if let Some(value) = field.as_f64() {
+            *field = Value::from((value * factor * 1000.0).round() / 1000.0);
+        }
+    }
+}
+
+/// A migrated project plus the recoverable problems found in it.
+#[derive(Debug, Serialize)]
+pub struct ProjectLoadResult {
+    pub project: ProjectFile,
+    pub meta: Option<ProjectMeta>,
+    pub warnings: Vec<String>,
+}
+
+impl ProjectLoadResult {
+    /// Parses, migrates and validates a project. Only JSON that can't be
+    /// parsed at all, or a version newer than this app, is an error.
+    pub fn from_json(json: &str) -> Result<Self, CommandError> {
+        let mut project = ProjectFile::parse(json)?;
+        project.migrate()?;
+        let warnings = project.validate();
+        let meta = project.meta();
+        Ok(Self {
+            project,
+            meta,
+            warnings,
+        })
+    }
+
+    /// Like `from_json`, for a project handed over as raw bytes rather than
+    /// read from a file. A leading UTF-8 byte order mark is skipped.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CommandError> {
+        let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+        let json = std::str::from_utf8(bytes).map_err(|e| {
+            CommandError::serialization(format!("Project is not UTF-8 text: {}", e))
+        })?;
+        Self::from_json(json)
+    }
+}
+
+/// Path of the `index`-th backup of `project_path`; `.bak1` is the newest.
+pub fn backup_path(project_path: &Path, index: usize) -> PathBuf {
+    let mut name = project_path.as_os_str().to_owned();
+    name.push(format!(".bak{}", index));
+    PathBuf::from(name)
+}
+
+/// Copies the current contents of `project_path` to `.bak1`, shifting older
+/// backups up by one and deleting any beyond `max_backups`.
+///
+/// Does nothing if the project doesn't exist yet. The original is copied
+/// rather than moved so the project stays in place until it is replaced.
+pub fn rotate_backups(project_path: &Path, max_backups: usize) -> io::Result<()> {
+    if !project_path.exists() {
+        return Ok(());
+    }
+
+    // Drop the oldest slot, which the shift below refills, along with any
+    // backups left over from a previously larger limit
+    let mut index = max_backups.max(1);
+    while backup_path(project_path, index).exists() {
+        fs::remove_file(backup_path(project_path, index))?;
+        index += 1;
+    }
+    if max_backups == 0 {
+        return Ok(());
+    }
+
+    for index in (1..max_backups).rev() {
+        let from = backup_path(project_path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(project_path, index + 1))?;
+        }
+    }
+    fs::copy(project_path, backup_path(project_path, 1))?;
+    Ok(())
+}
+
+/// Path of the SHA-256 checksum kept next to `project_path`.
+pub fn checksum_path(project_path: &Path) -> PathBuf {
+    let mut name = project_path.as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Writes the checksum of `json` next to `project_path`, in the format
+/// `sha256sum -c` reads.
+pub fn write_checksum(project_path: &Path, json: &str) -> io::Result<()> {
+    let name = project_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let line = format!("{}  {}\n", sha256_hex(json.as_bytes()), name);
+    write_atomic(&checksum_path(project_path), line.as_bytes())
+}
+
+/// Replaces `project_path` with `json` and writes its checksum.
+///
+/// The old checksum is removed before the project is touched. A crash part
+/// way through then leaves a project without a checksum, which still loads,
+/// rather than a new project next to the old checksum, which wouldn't.
+pub fn write_project(project_path: &Path, json: &str) -> Result<(), CommandError> {
+    match fs::remove_file(checksum_path(project_path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            return Err(CommandError::io(
+                checksum_path(project_path).to_string_lossy(),
+                e,
+            ));
+        }
+        _ => {}
+    }
+    write_atomic(project_path, json.as_bytes())
+        .map_err(|e| CommandError::io(project_path.to_string_lossy(), e))?;
+    if let Err(e) = write_checksum(project_path, json) {
+        eprintln!("Could not write project checksum: {}", e);
+        let _ = fs::remove_file(checksum_path(project_path));
+    }
+    Ok(())
+}
+
+/// Checks `json` against the checksum next to `project_path`. Projects
+/// without one, such as those saved before checksums were introduced, pass.
+pub fn verify_checksum(project_path: &Path, json: &str) -> Result<(), CommandError> {
+    let Ok(saved) = fs::read_to_string(checksum_path(project_path)) else {
+        return Ok(());
+    };
+    let expected = saved.split_whitespace().next().unwrap_or_default();
+    if expected.eq_ignore_ascii_case(&sha256_hex(json.as_bytes())) {
+        Ok(())
+    } else {
+        Err(CommandError::ChecksumMismatch {
+            path: project_path.to_string_lossy().into_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    const V1_FIXTURE: &str = r##"{
+        "imagePath": "/photos/cat.png",
+        "filaments": [
+            { "id": "a", "name": "White", "hexColor": "#FFFFFF", "td": 3.5, "enabled": true },
+            { "id": "b", "name": "Black", "hexColor": "#000000", "td": 0.5, "enabled": true }
+        ],
+        "modelGeometry": { "minDepthMm": 0.6, "maxDepthMm": 3.0, "gamma": 1.0 },
+        "lighting": { "mode": "backlit" }
+    }"##;
+
+    #[test]
+    fn migrates_v1_fixture_to_current_version() {
+        let mut project = ProjectFile::parse(V1_FIXTURE).unwrap();
+        assert_eq!(project.schema_version, 1);
+
+        project.migrate().unwrap();
+
+        assert_eq!(project.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(project.data["filaments"][0]["orderIndex"], 0);
+        assert_eq!(project.data["filaments"][1]["orderIndex"], 1);
+        assert_eq!(project.data["modelGeometry"]["spikeRemoval"], "none");
+        // Fields no migration touches are carried over as-is
+        assert_eq!(project.data["lighting"]["mode"], "backlit");
+        assert_eq!(project.data["imagePath"], "/photos/cat.png");
+    }
+
+    #[test]
+    fn migration_keeps_existing_values() {
+        let json = r#"{ "schemaVersion": 1, "filaments": [{ "id": "a", "orderIndex": 7 }],
+                        "modelGeometry": { "spikeRemoval": "strong" } }"#;
+        let mut project = ProjectFile::parse(json).unwrap();
+        project.migrate().unwrap();
+
+        assert_eq!(project.data["filaments"][0]["orderIndex"], 7);
+        assert_eq!(project.data["modelGeometry"]["spikeRemoval"], "strong");
+    }
+
+    #[test]
+    fn rejects_projects_from_newer_versions() {
+        let json = format!(r#"{{ "schemaVersion": {} }}"#, CURRENT_SCHEMA_VERSION + 1);
+        let err = ProjectFile::parse(&json).unwrap().migrate().unwrap_err();
+        assert!(matches!(
+            err,
+            CommandError::UnsupportedProjectVersion { .. }
+        ));
+    }
+
+    const VALID_PROJECT: &str = r##"{
+        "schemaVersion": 2,
+        "filaments": [
+            { "id": "a", "name": "White", "hexColor": "#FFFFFF", "td": 3.5, "enabled": true, "orderIndex": 0 }
+        ],
+        "modelGeometry": {
+            "minDepthMm": 0.6, "maxDepthMm": 3.0, "gamma": 1.0, "contrast": 1.0,
+            "offset": 0.0, "smoothing": 0.0, "spikeRemoval": "none", "invert": false
+        },
+        "printSettings": {
+            "layerHeightMm": 0.08, "baseLayerMm": 0.16, "widthMm": 100, "heightMm": 80,
+            "borderWidthMm": 3, "borderDepthMm": 2, "hasBorder": true
+        },
+        "colorPlan": { "mode": "transmission", "stops": [{ "filamentId": "a", "thresholdZMm": 1.2 }] }
+    }"##;
+
+    fn edited(edits: &[(&str, Value)]) -> String {
+        let mut project: Value = serde_json::from_str(VALID_PROJECT).unwrap();
+        for (pointer, value) in edits {
+            *project.pointer_mut(pointer).unwrap() = value.clone();
+        }
+        project.to_string()
+    }
+
+    #[test]
+    fn valid_project_loads_without_warnings() {
+        let result = ProjectLoadResult::from_json(VALID_PROJECT).unwrap();
+        assert_eq!(result.warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn loads_from_bytes_like_from_json() {
+        let mut bytes = b"\xEF\xBB\xBF".to_vec();
+        bytes.extend_from_slice(V1_FIXTURE.as_bytes());
+        let from_bytes = ProjectLoadResult::from_bytes(&bytes).unwrap();
+        let from_json = ProjectLoadResult::from_json(V1_FIXTURE).unwrap();
+        assert_eq!(from_bytes.project.data, from_json.project.data);
+        assert_eq!(from_bytes.warnings, from_json.warnings);
+
+        let err = ProjectLoadResult::from_bytes(b"{\xFF}").unwrap_err();
+        assert!(err.to_string().contains("not UTF-8"));
+    }
+
+    #[test]
+    fn flags_stop_with_unknown_filament() {
+        let json = edited(&[("/colorPlan/stops/0/filamentId", "ghost".into())]);
+        let result = ProjectLoadResult::from_json(&json).unwrap();
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("'ghost'"));
+    }
+
+    #[test]
+    fn flags_bad_layer_height_and_inverted_depths() {
+        let json = edited(&[
+            ("/printSettings/layerHeightMm", (-0.1).into()),
+            ("/modelGeometry/minDepthMm", 5.0.into()),
+        ]);
+        let warnings = ProjectLoadResult::from_json(&json).unwrap().warnings;
+        assert!(warnings.iter().any(|w| w.contains("Layer height")));
+        assert!(warnings.iter().any(|w| w.contains("Minimum depth")));
+    }
+
+    #[test]
+    fn malformed_section_is_a_warning_not_an_error() {
+        let json = edited(&[("/printSettings", "oops".into())]);
+        let result = ProjectLoadResult::from_json(&json).unwrap();
+        assert!(result.warnings[0].starts_with("Could not read printSettings"));
+    }
+
+    #[test]
+    fn unparseable_json_is_an_error() {
+        assert!(ProjectLoadResult::from_json("{ not json").is_err());
+    }
+
+    #[test]
+    fn frontend_json_without_version_is_current() {
+        let project = ProjectFile::parse_current(r#"{ "filaments": [] }"#).unwrap();
+        assert_eq!(project.schema_version, CURRENT_SCHEMA_VERSION);
+        assert!(project.to_json().unwrap().contains("\"schemaVersion\": 2"));
+    }
+
+    #[test]
+    fn first_save_sets_creation_time_and_later_saves_keep_it() {
+        let mut project = ProjectFile::parse_current(r#"{ "filaments": [] }"#).unwrap();
+        project.touch_meta(None, 1000).unwrap();
+        let meta = project.meta().unwrap();
+        assert_eq!((meta.created_at, meta.modified_at), (1000, 1000));
+        assert_eq!(meta.app_version, env!("CARGO_PKG_VERSION"));
+
+        // The frontend may send the project back without the section
+        let mut resaved = ProjectFile::parse_current(r#"{ "filaments": [] }"#).unwrap();
+        resaved.touch_meta(Some(meta), 2000).unwrap();
+        let meta = resaved.meta().unwrap();
+        assert_eq!((meta.created_at, meta.modified_at), (1000, 2000));
+    }
+
+    #[test]
+    fn edited_meta_is_kept_through_save_and_migration() {
+        let json = r#"{ "meta": { "author": "Sam", "notes": "gift" } }"#;
+        let mut project = ProjectFile::parse_current(json).unwrap();
+        let previous = ProjectMeta {
+            author: Some("Old".to_string()),
+            created_at: 500,
+            ..ProjectMeta::default()
+        };
+        project.touch_meta(Some(previous), 1000).unwrap();
+        assert_eq!(project.meta().unwrap().created_at, 500);
+
+        // Saved projects load back with their metadata, even as version 1
+        let mut data: Value = serde_json::from_str(&project.to_json().unwrap()).unwrap();
+        data["schemaVersion"] = 1.into();
+        let result = ProjectLoadResult::from_json(&data.to_string()).unwrap();
+        let meta = result.meta.unwrap();
+        assert_eq!(meta.author.as_deref(), Some("Sam"));
+        assert_eq!(meta.notes.as_deref(), Some("gift"));
+        assert_eq!((meta.created_at, meta.modified_at), (500, 1000));
+    }
+
+    fn rescaled(lock_aspect: bool, scale_depth: bool) -> ProjectFile {
+        let mut project = ProjectFile::parse_current(VALID_PROJECT).unwrap();
+        project.rescale(150.0, lock_aspect, scale_depth).unwrap();
+        project
+    }
+
+    #[test]
+    fn aspect_locked_rescale_scales_height_and_border_but_not_depth() {
+        let project = rescaled(true, false);
+        let settings = &project.data["printSettings"];
+        assert_eq!(settings["widthMm"], 150.0);
+        assert_eq!(settings["heightMm"], 120.0);
+        assert_eq!(settings["borderWidthMm"], 4.5);
+        assert_eq!(settings["borderDepthMm"], 2);
+        assert_eq!(settings["layerHeightMm"], 0.08);
+        assert_eq!(project.data["modelGeometry"]["maxDepthMm"], 3.0);
+        assert_eq!(project.data["colorPlan"]["stops"][0]["thresholdZMm"], 1.2);
+    }
+
+    #[test]
+    fn free_rescale_only_changes_the_width_and_scaled_depths() {
+        let project = rescaled(false, true);
+        let settings = &project.data["printSettings"];
+        assert_eq!(settings["widthMm"], 150.0);
+        assert_eq!(settings["heightMm"], 80);
+        assert_eq!(settings["borderWidthMm"], 3);
+        assert_eq!(settings["borderDepthMm"], 3.0);
+        assert_eq!(settings["baseLayerMm"], 0.16);
+        assert_eq!(project.data["modelGeometry"]["minDepthMm"], 0.9);
+        assert_eq!(project.data["modelGeometry"]["maxDepthMm"], 4.5);
+        assert_eq!(project.data["colorPlan"]["stops"][0]["thresholdZMm"], 1.8);
+        assert!(project.validate().is_empty());
+    }
+
+    #[test]
+    fn rescale_rejects_non_positive_widths() {
+        let mut project = ProjectFile::parse_current(VALID_PROJECT).unwrap();
+        assert!(matches!(
+            project.rescale(0.0, true, false),
+            Err(CommandError::InvalidSettings { .. })
+        ));
+        let mut project =
+            ProjectFile::parse_current(&edited(&[("/printSettings/widthMm", 0.0.into())])).unwrap();
+        assert!(project.rescale(150.0, true, false).is_err());
+    }
+
+    fn temp_project(name: &str) -> PathBuf {
+        temp_dir(name).join("art.lfp")
+    }
+
+    #[test]
+    fn detects_changes_since_the_checksum_was_written() {
+        let project = temp_project("checksum");
+        assert!(verify_checksum(&project, "{}").is_ok());
+
+        write_checksum(&project, "{}").unwrap();
+        let saved = fs::read_to_string(checksum_path(&project)).unwrap();
+        assert_eq!(
+            saved,
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a  art.lfp\n"
+        );
+        assert!(verify_checksum(&project, "{}").is_ok());
+        assert!(matches!(
+            verify_checksum(&project, "{ }"),
+            Err(CommandError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn saving_never_leaves_a_stale_checksum_behind() {
+        let project = temp_project("write-project");
+        write_project(&project, "{}").unwrap();
+        assert!(verify_checksum(&project, "{}").is_ok());
+
+        write_project(&project, "{ }").unwrap();
+        assert_eq!(fs::read_to_string(&project).unwrap(), "{ }");
+        assert!(verify_checksum(&project, "{ }").is_ok());
+
+        // A save that fails part way must not leave the old checksum in place
+        let blocked = temp_project("write-project-blocked");
+        write_checksum(&blocked, "{}").unwrap();
+        fs::create_dir_all(blocked.join("in-the-way")).unwrap();
+        assert!(write_project(&blocked, "{ }").is_err());
+        assert!(!checksum_path(&blocked).exists());
+    }
+
+    #[test]
+    fn keeps_the_most_recent_backups_newest_first() {
+        let project = temp_project("backups");
+        for version in 1..=5 {
+            rotate_backups(&project, 3).unwrap();
+            fs::write(&project, format!("v{}", version)).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(backup_path(&project, 1)).unwrap(), "v4");
+        assert_eq!(fs::read_to_string(backup_path(&project, 2)).unwrap(), "v3");
+        assert_eq!(fs::read_to_string(backup_path(&project, 3)).unwrap(), "v2");
+        assert!(!backup_path(&project, 4).exists());
+    }
+
+    #[test]
+    fn prunes_backups_beyond_a_lowered_limit() {
+        let project = temp_project("backups-prune");
+        fs::write(&project, "current").unwrap();
+        for index in 1..=4 {
+            fs::write(backup_path(&project, index), "old").unwrap();
+        }
+
+        rotate_backups(&project, 2).unwrap();
+
+        assert!(backup_path(&project, 2).exists());
+        assert!(!backup_path(&project, 3).exists());
+        assert!(!backup_path(&project, 4).exists());
+    }
+}
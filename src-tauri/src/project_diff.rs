@@ -0,0 +1,243 @@
+//! Structured differences between two saved projects, so variants can be
+//! told apart without opening them.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::CommandError;
+use crate::models::{ColorStop, Filament, ModelGeometrySettings, PrintSettings};
+use crate::project::ProjectFile;
+
+/// Thresholds closer than this count as unchanged.
+const THRESHOLD_EPSILON_MM: f64 = 1e-6;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// A color stop whose threshold moved. A stop only in the first project
+/// has no `after_z_mm`, one only in the second has no `before_z_mm`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct StopChange {
+    pub filament_id: String,
+    pub before_z_mm: Option<f64>,
+    pub after_z_mm: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectDiff {
+    pub geometry: Vec<FieldChange>,
+    pub print_settings: Vec<FieldChange>,
+    pub added_filaments: Vec<Filament>,
+    pub removed_filaments: Vec<Filament>,
+    pub stops: Vec<StopChange>,
+}
+
+/// The typed sections a project must have to be compared.
+struct Sections {
+    geometry: ModelGeometrySettings,
+    print_settings: PrintSettings,
+    filaments: Vec<Filament>,
+    stops: Vec<ColorStop>,
+}
+
+impl Sections {
+    fn read(project: &ProjectFile) -> Result<Self, CommandError> {
+        Ok(Sections {
+            geometry: project.typed_section(&["modelGeometry"])?,
+            print_settings: project.typed_section(&["printSettings"])?,
+            filaments: project.typed_section(&["filaments"])?,
+            stops: project.typed_section(&["colorPlan", "stops"])?,
+        })
+    }
+}
+
+/// Fields of two serialized structs that differ, in field name order.
+fn field_changes(
+    before: &impl Serialize,
+    after: &impl Serialize,
+) -> Result<Vec<FieldChange>, CommandError> {
+    let (Value::Object(before), Value::Object(after)) =
+        (serde_json::to_value(before)?, serde_json::to_value(after)?)
+    else {
+        return Ok(Vec::new());
+    };
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+    Ok(fields
+        .into_iter()
+        .filter_map(|field| {
+            let old = before.get(field).cloned().unwrap_or(Value::Null);
+            let new = after.get(field).cloned().unwrap_or(Value::Null);
+            (old != new).then(|| FieldChange {
+                field: field.clone(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect())
+}
+
+/// Pairs each filament's stops in threshold order and reports the ones
+/// that moved, appeared or disappeared.
+fn stop_changes(before: &[ColorStop], after: &[ColorStop]) -> Vec<StopChange> {
+    let mut by_filament: BTreeMap<&str, (Vec<f64>, Vec<f64>)> = BTreeMap::new();
+    for stop in before {
+        by_filament
+            .entry(&stop.filament_id)
+            .or_default()
+            .0
+            .push(stop.threshold_z_mm);
+    }
+    for stop in after {
+        by_filament
+            .entry(&stop.filament_id)
+            .or_default()
+            .1
+            .push(stop.threshold_z_mm);
+    }
+
+    let mut changes = Vec::new();
+    for (filament_id, (mut old, mut new)) in by_filament {
+        old.sort_by(f64::total_cmp);
+        new.sort_by(f64::total_cmp);
+        for i in 0..old.len().max(new.len()) {
+            let (before_z_mm, after_z_mm) = (old.get(i).copied(), new.get(i).copied());
+            let moved = match (before_z_mm, after_z_mm) {
+                (Some(a), Some(b)) => (a - b).abs() > THRESHOLD_EPSILON_MM,
+                _ => true,
+            };
+            if moved {
+                changes.push(StopChange {
+                    filament_id: filament_id.to_string(),
+                    before_z_mm,
+                    after_z_mm,
+                });
+            }
+        }
+    }
+    changes
+}
+
+/// Compares two migrated projects. Fails if either lacks one of the typed
+/// sections being compared.
+pub fn diff_projects(a: &ProjectFile, b: &ProjectFile) -> Result<ProjectDiff, CommandError> {
+    let (a, b) = (Sections::read(a)?, Sections::read(b)?);
+    let missing_from = |filaments: &[Filament], others: &[Filament]| -> Vec<Filament> {
+        filaments
+            .iter()
+            .filter(|f| !others.iter().any(|other| other.id == f.id))
+            .cloned()
+            .collect()
+    };
+
+    Ok(ProjectDiff {
+        geometry: field_changes(&a.geometry, &b.geometry)?,
+        print_settings: field_changes(&a.print_settings, &b.print_settings)?,
+        added_filaments: missing_from(&b.filaments, &a.filaments),
+        removed_filaments: missing_from(&a.filaments, &b.filaments),
+        stops: stop_changes(&a.stops, &b.stops),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROJECT: &str = r##"{
+        "schemaVersion": 2,
+        "filaments": [
+            { "id": "a", "name": "White", "hexColor": "#FFFFFF", "td": 3.5, "enabled": true, "orderIndex": 0 },
+            { "id": "r", "name": "Red", "hexColor": "#FF0000", "td": 1.5, "enabled": true, "orderIndex": 1 }
+        ],
+        "modelGeometry": {
+            "minDepthMm": 0.6, "maxDepthMm": 3.0, "gamma": 1.8, "contrast": 1.0,
+            "offset": 0.0, "smoothing": 0.0, "spikeRemoval": "none", "invert": false
+        },
+        "printSettings": {
+            "layerHeightMm": 0.08, "baseLayerMm": 0.16, "widthMm": 100, "heightMm": 80,
+            "borderWidthMm": 3, "borderDepthMm": 2, "hasBorder": true
+        },
+        "colorPlan": { "stops": [
+            { "filamentId": "a", "thresholdZMm": 1.2 },
+            { "filamentId": "r", "thresholdZMm": 2.0 }
+        ] }
+    }"##;
+
+    fn project(edits: &[(&str, Value)]) -> ProjectFile {
+        let mut value: Value = serde_json::from_str(PROJECT).unwrap();
+        for (pointer, new) in edits {
+            *value.pointer_mut(pointer).unwrap() = new.clone();
+        }
+        ProjectFile::parse(&value.to_string()).unwrap()
+    }
+
+    #[test]
+    fn identical_projects_have_no_differences() {
+        let diff = diff_projects(&project(&[]), &project(&[])).unwrap();
+        assert!(diff.geometry.is_empty());
+        assert!(diff.print_settings.is_empty());
+        assert!(diff.added_filaments.is_empty() && diff.removed_filaments.is_empty());
+        assert!(diff.stops.is_empty());
+    }
+
+    #[test]
+    fn reports_settings_filaments_and_stops() {
+        let b = project(&[
+            ("/modelGeometry/gamma", 2.2.into()),
+            ("/printSettings/hasBorder", false.into()),
+            ("/filaments/1/id", "k".into()),
+            ("/filaments/1/name", "Black".into()),
+            ("/colorPlan/stops/0/thresholdZMm", 1.4.into()),
+            ("/colorPlan/stops/1/filamentId", "k".into()),
+        ]);
+        let diff = diff_projects(&project(&[]), &b).unwrap();
+
+        assert_eq!(
+            diff.geometry,
+            vec![FieldChange {
+                field: "gamma".to_string(),
+                before: 1.8.into(),
+                after: 2.2.into(),
+            }]
+        );
+        assert_eq!(diff.print_settings[0].field, "has_border");
+        assert_eq!(diff.removed_filaments[0].name, "Red");
+        assert_eq!(diff.added_filaments[0].name, "Black");
+        assert_eq!(
+            diff.stops,
+            vec![
+                StopChange {
+                    filament_id: "a".to_string(),
+                    before_z_mm: Some(1.2),
+                    after_z_mm: Some(1.4),
+                },
+                StopChange {
+                    filament_id: "k".to_string(),
+                    before_z_mm: None,
+                    after_z_mm: Some(2.0),
+                },
+                StopChange {
+                    filament_id: "r".to_string(),
+                    before_z_mm: Some(2.0),
+                    after_z_mm: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn projects_without_typed_sections_are_an_error() {
+        let other = ProjectFile::parse(r#"{ "hello": "world" }"#).unwrap();
+        assert!(matches!(
+            diff_projects(&project(&[]), &other),
+            Err(CommandError::Serialization { .. })
+        ));
+    }
+}
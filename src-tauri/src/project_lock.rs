@@ -0,0 +1,177 @@
+//! Advisory locks that keep a project from being open for editing in two
+//! windows at once.
+//!
+//! Opening a project writes a lock file next to it naming the process and
+//! window that holds it. Another window finding a live lock gets
+//! `CommandError::Locked` and can open the project read-only or take the
+//! lock over. A lock whose process is gone is stale and is replaced.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::fs_util::{now_millis, write_atomic};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ProjectLock {
+    pid: u32,
+    /// Label of the window holding the lock, since every window of the app
+    /// shares one process.
+    window: String,
+    /// When the lock was taken, in milliseconds since the Unix epoch.
+    since: u64,
+}
+
+impl ProjectLock {
+    fn held_by(&self, pid: u32, window: &str) -> bool {
+        self.pid == pid && self.window == window
+    }
+}
+
+/// Path of the lock kept next to `project_path`.
+pub fn lock_path(project_path: &Path) -> PathBuf {
+    let mut name = project_path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Takes the lock on `project_path` for `window` of this process. Fails
+/// with `CommandError::Locked` if another window holds a live lock, unless
+/// `force` is set.
+pub fn acquire_lock(project_path: &Path, window: &str, force: bool) -> Result<(), CommandError> {
+    acquire(project_path, std::process::id(), window, force)
+}
+
+/// Fails with `CommandError::Locked` if a window other than `window` of
+/// this process holds a live lock on `project_path`.
+pub fn check_lock(project_path: &Path, window: &str) -> Result<(), CommandError> {
+    check(project_path, std::process::id(), window)
+}
+
+/// Removes the lock on `project_path` if `window` of this process holds it.
+/// Returns whether a lock was removed.
+pub fn release_lock(project_path: &Path, window: &str) -> Result<bool, CommandError> {
+    release(project_path, std::process::id(), window)
+}
+
+fn acquire(project_path: &Path, pid: u32, window: &str, force: bool) -> Result<(), CommandError> {
+    if !force {
+        check(project_path, pid, window)?;
+    }
+    let path = lock_path(project_path);
+    let lock = ProjectLock {
+        pid,
+        window: window.to_string(),
+        since: now_millis(),
+    };
+    let json = serde_json::to_string(&lock)?;
+    write_atomic(&path, json.as_bytes()).map_err(|e| CommandError::io(path.to_string_lossy(), e))
+}
+
+fn check(project_path: &Path, pid: u32, window: &str) -> Result<(), CommandError> {
+    match read_lock(&lock_path(project_path))? {
+        Some(lock) if !lock.held_by(pid, window) && process_alive(lock.pid) => {
+            Err(CommandError::Locked {
+                held_by_pid: lock.pid,
+                since: lock.since,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn release(project_path: &Path, pid: u32, window: &str) -> Result<bool, CommandError> {
+    let path = lock_path(project_path);
+    match read_lock(&path)? {
+        Some(lock) if lock.held_by(pid, window) => {
+            std::fs::remove_file(&path).map_err(|e| CommandError::io(path.to_string_lossy(), e))?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The lock at `path`, or `None` if there is none. A lock that can't be
+/// parsed is treated like a stale one.
+fn read_lock(path: &Path) -> Result<Option<ProjectLock>, CommandError> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json).ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(CommandError::io(path.to_string_lossy(), e)),
+    }
+}
+
+/// Whether a process with `pid` is running. If that can't be checked the
+/// lock is assumed live, so it is never cleared by mistake.
+pub fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    if cfg!(target_os = "windows") {
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .map_or(true, |output| {
+                String::from_utf8_lossy(&output.stdout).contains(&pid.to_string())
+            })
+    } else {
+        // Signal 0 only checks that the process exists
+        Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(Stdio::null())
+            .status()
+            .map_or(true, |status| status.success())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn project_in(name: &str) -> PathBuf {
+        temp_dir(&format!("lock-{}", name)).join("cat.lfp")
+    }
+
+    #[test]
+    fn a_second_window_is_refused_until_the_lock_is_released() {
+        let project = project_in("windows");
+        acquire_lock(&project, "main", false).unwrap();
+        // Reopening in the same window keeps the lock
+        acquire_lock(&project, "main", false).unwrap();
+
+        let refused = acquire_lock(&project, "second", false);
+        assert!(matches!(
+            refused,
+            Err(CommandError::Locked { held_by_pid, .. }) if held_by_pid == std::process::id()
+        ));
+        assert!(check_lock(&project, "second").is_err());
+        assert!(!release_lock(&project, "second").unwrap());
+
+        assert!(release_lock(&project, "main").unwrap());
+        assert!(!lock_path(&project).exists());
+        acquire_lock(&project, "second", false).unwrap();
+        acquire_lock(&project, "main", true).unwrap();
+        check_lock(&project, "main").unwrap();
+    }
+
+    #[test]
+    fn locks_of_dead_processes_are_cleared() {
+        let project = project_in("stale");
+        // The test binary itself, which exits right away when asked to list
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+
+        acquire(&project, dead_pid, "main", false).unwrap();
+        acquire_lock(&project, "main", false).unwrap();
+        std::fs::write(lock_path(&project), "not json").unwrap();
+        check_lock(&project, "second").unwrap();
+    }
+}
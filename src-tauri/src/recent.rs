@@ -0,0 +1,113 @@
+//! Recently opened and saved projects, kept in the app config directory.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::fs_util::{now_millis, write_atomic};
+
+pub const MAX_RECENT_FILES: usize = 15;
+pub const RECENT_FILES_NAME: &str = "recent-files.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentEntry {
+    pub path: String,
+    pub name: String,
+    /// Milliseconds since the Unix epoch.
+    pub last_opened: u64,
+    /// Whether the file still exists. Checked on read, not stored.
+    #[serde(default, skip_deserializing)]
+    pub exists: bool,
+}
+
+/// Reads the list stored at `list_path`, newest first. A missing or
+/// unreadable list is treated as empty.
+pub fn read_recent(list_path: &Path) -> Vec<RecentEntry> {
+    let mut entries: Vec<RecentEntry> = std::fs::read_to_string(list_path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    for entry in &mut entries {
+        entry.exists = Path::new(&entry.path).is_file();
+    }
+    entries
+}
+
+fn write_recent(list_path: &Path, entries: &[RecentEntry]) -> Result<(), CommandError> {
+    let json = serde_json::to_string_pretty(entries)?;
+    write_atomic(list_path, json.as_bytes())
+        .map_err(|e| CommandError::io(list_path.to_string_lossy(), e))
+}
+
+/// Moves `file_path` to the front of the list, dropping older duplicates
+/// and anything past [`MAX_RECENT_FILES`].
+pub fn add_recent(list_path: &Path, file_path: &str) -> Result<Vec<RecentEntry>, CommandError> {
+    let mut entries = read_recent(list_path);
+    entries.retain(|entry| entry.path != file_path);
+    let name = Path::new(file_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file_path.to_string());
+    entries.insert(
+        0,
+        RecentEntry {
+            path: file_path.to_string(),
+            name,
+            last_opened: now_millis(),
+            exists: Path::new(file_path).is_file(),
+        },
+    );
+    entries.truncate(MAX_RECENT_FILES);
+    write_recent(list_path, &entries)?;
+    Ok(entries)
+}
+
+pub fn clear_recent(list_path: &Path) -> Result<(), CommandError> {
+    write_recent(list_path, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn newest_first_without_duplicates() {
+        let dir = temp_dir("recent");
+        let list = dir.join(RECENT_FILES_NAME);
+        let a = dir.join("a.lfp");
+        std::fs::write(&a, "{}").unwrap();
+        let a = a.to_string_lossy().into_owned();
+
+        add_recent(&list, &a).unwrap();
+        add_recent(&list, "/gone/b.lfp").unwrap();
+        add_recent(&list, &a).unwrap();
+
+        let entries = read_recent(&list);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, a);
+        assert_eq!(entries[0].name, "a.lfp");
+        assert!(entries[0].exists);
+        assert!(!entries[1].exists);
+    }
+
+    #[test]
+    fn caps_the_list_and_clears() {
+        let dir = temp_dir("recent-cap");
+        let list = dir.join(RECENT_FILES_NAME);
+        for i in 0..MAX_RECENT_FILES + 3 {
+            add_recent(&list, &format!("/projects/{}.lfp", i)).unwrap();
+        }
+
+        let entries = read_recent(&list);
+        assert_eq!(entries.len(), MAX_RECENT_FILES);
+        assert_eq!(
+            entries[0].path,
+            format!("/projects/{}.lfp", MAX_RECENT_FILES + 2)
+        );
+
+        clear_recent(&list).unwrap();
+        assert!(read_recent(&list).is_empty());
+    }
+}
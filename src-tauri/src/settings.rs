@@ -0,0 +1,162 @@
+//! App-wide defaults kept in the app config directory, consulted by commands
+//! whenever the caller leaves the matching value out.
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::fs_util::write_atomic;
+use crate::models::{MeshFormat, PrintSettings};
+
+/// File name of the settings inside the app config directory.
+pub const SETTINGS_NAME: &str = "settings.json";
+
+/// Every field is optional, and a missing one falls back to the value the
+/// app uses without settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Directory output paths given as bare file names are written to.
+    pub default_output_dir: Option<String>,
+    pub default_print_settings: Option<PrintSettings>,
+    /// Mesh format for requests that don't name one.
+    pub export_format: Option<MeshFormat>,
+    /// Replaces the timeout of ordinary sidecar calls; mesh generation and
+    /// health checks keep theirs.
+    pub sidecar_timeout_secs: Option<u64>,
+}
+
+impl AppSettings {
+    pub fn validate(&self) -> Result<(), CommandError> {
+        if let Some(dir) = &self.default_output_dir {
+            if !Path::new(dir).is_absolute() {
+                return Err(CommandError::invalid_settings(
+                    "default_output_dir",
+                    format!("must be an absolute path, got {:?}", dir),
+                ));
+            }
+        }
+        if let Some(print_settings) = &self.default_print_settings {
+            print_settings.validate()?;
+        }
+        if self.sidecar_timeout_secs == Some(0) {
+            return Err(CommandError::invalid_settings(
+                "sidecar_timeout_secs",
+                "must be at least 1 second",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn sidecar_timeout(&self) -> Option<Duration> {
+        self.sidecar_timeout_secs.map(Duration::from_secs)
+    }
+
+    /// `output_path` inside the default output directory when it is a
+    /// relative path and a directory is set, otherwise unchanged.
+    pub fn output_path(&self, output_path: &str) -> String {
+        match &self.default_output_dir {
+            Some(dir) if Path::new(output_path).is_relative() => Path::new(dir)
+                .join(output_path)
+                .to_string_lossy()
+                .into_owned(),
+            _ => output_path.to_string(),
+        }
+    }
+
+    /// `provided`, or the default print settings when it is `None`.
+    pub fn print_settings(
+        &self,
+        provided: Option<PrintSettings>,
+    ) -> Result<PrintSettings, CommandError> {
+        provided
+            .or_else(|| self.default_print_settings.clone())
+            .ok_or_else(|| {
+                CommandError::invalid_settings(
+                    "print_settings",
+                    "not given and no default print settings are saved",
+                )
+            })
+    }
+}
+
+/// Reads the settings at `path`. Missing settings are all defaults; an
+/// unreadable file is an error so it isn't silently overwritten.
+pub fn read_settings(path: &Path) -> Result<AppSettings, CommandError> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AppSettings::default()),
+        Err(e) => Err(CommandError::io(path.to_string_lossy(), e)),
+    }
+}
+
+pub fn write_settings(path: &Path, settings: &AppSettings) -> Result<(), CommandError> {
+    settings.validate()?;
+    let json = serde_json::to_string_pretty(settings)?;
+    write_atomic(path, json.as_bytes()).map_err(|e| CommandError::io(path.to_string_lossy(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn print_settings(layer_height_mm: f64) -> PrintSettings {
+        PrintSettings {
+            layer_height_mm,
+            ..crate::test_util::print_settings()
+        }
+    }
+
+    #[test]
+    fn round_trips_and_defaults_when_missing() {
+        let dir = temp_dir("settings");
+        let path = dir.join(SETTINGS_NAME);
+        assert!(read_settings(&path).unwrap().export_format.is_none());
+
+        let settings = AppSettings {
+            default_output_dir: Some(dir.to_string_lossy().into_owned()),
+            default_print_settings: Some(print_settings(0.08)),
+            export_format: Some(MeshFormat::ThreeMF),
+            sidecar_timeout_secs: Some(300),
+        };
+        write_settings(&path, &settings).unwrap();
+        let read = read_settings(&path).unwrap();
+        assert_eq!(read.export_format, Some(MeshFormat::ThreeMF));
+        assert_eq!(read.sidecar_timeout(), Some(Duration::from_secs(300)));
+        assert_eq!(read.print_settings(None).unwrap().layer_height_mm, 0.08);
+
+        let invalid = AppSettings {
+            sidecar_timeout_secs: Some(0),
+            ..AppSettings::default()
+        };
+        assert!(write_settings(&path, &invalid).is_err());
+        std::fs::write(&path, "not json").unwrap();
+        assert!(read_settings(&path).is_err());
+    }
+
+    #[test]
+    fn fills_in_values_the_caller_left_out() {
+        let settings = AppSettings {
+            default_output_dir: Some("/exports".to_string()),
+            default_print_settings: Some(print_settings(0.08)),
+            ..AppSettings::default()
+        };
+        assert_eq!(
+            Path::new(&settings.output_path("cat.stl")),
+            Path::new("/exports").join("cat.stl")
+        );
+        assert_eq!(settings.output_path("/tmp/cat.stl"), "/tmp/cat.stl");
+        let given = settings.print_settings(Some(print_settings(0.12))).unwrap();
+        assert_eq!(given.layer_height_mm, 0.12);
+
+        let empty = AppSettings::default();
+        assert_eq!(empty.output_path("cat.stl"), "cat.stl");
+        assert!(matches!(
+            empty.print_settings(None),
+            Err(CommandError::InvalidSettings { .. })
+        ));
+    }
+}
@@ -0,0 +1,1218 @@
+//! Long-lived handle to the Python core sidecar.
+//!
+//! The core is spawned once at startup and reused by every command. Requests
+//! are written to its stdin as one JSON object per line, each carrying an
+//! incrementing `id` next to `method` and `params`. The core echoes that `id`
+//! on every frame it writes back, so late frames of an abandoned call are
+//! told apart from the current one. Before its result frame a call may
+//! produce any number of progress frames of the form
+//! `{"id": 7, "progress": 0.42, "stage": "triangulating"}`.
+//!
+//! The core works through its requests one at a time, so each [`CallClass`]
+//! gets a core process of its own and sends it one call at a time. A long
+//! mesh export then runs next to previews instead of ahead of them, and a
+//! call's timeout only starts once the core is actually working on it.
+//!
+//! Success or failure is decided by the result frame alone: it either carries
+//! the result or an explicit `error` field. Stdout is read raw and split on
+//! newlines here, so a frame arriving in several chunks is reassembled, and
+//! lines that aren't JSON frames, such as stray prints, are logged and
+//! skipped. Anything the core writes to
+//! stderr is diagnostic output and is returned alongside the frame. It is
+//! also appended to the persistent [`SidecarLog`] when one is managed.
+//!
+//! Cores that advertise the `heartbeat` feature can be watched for hangs.
+//! Requests then carry `heartbeat_ms`, and the core writes
+//! `{"id": 7, "heartbeat": true}` as it starts the request and at that
+//! interval while it works. A call that has had its first frame and then
+//! goes [`HEARTBEAT_MISSES`] intervals without another is treated as hung
+//! and its process killed, even if the timeout hasn't passed yet.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::async_runtime::Receiver;
+use tauri::{Emitter, Manager};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent, TerminatedPayload};
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::{oneshot, Mutex, Notify, Semaphore};
+
+use crate::error::CommandError;
+use crate::sidecar_log::SidecarLog;
+
+const SIDECAR_NAME: &str = "layerforge-core";
+
+/// Environment variable with a command to run instead of the bundled core,
+/// such as `python -m layerforge.cli`, for working on the core from source.
+pub const SIDECAR_CMD_ENV: &str = "LAYERFORGE_SIDECAR_CMD";
+
+/// Timeout for ordinary calls such as image processing and previews.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Timeout for health checks, which only have to cover interpreter startup.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Timeout for mesh generation, which legitimately runs much longer.
+pub const MESH_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Heartbeat interval used when the core supports heartbeats.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Missed heartbeats after which a call counts as hung.
+pub const HEARTBEAT_MISSES: u32 = 3;
+
+/// How many times starting the core is tried before a call gives up.
+const SPAWN_ATTEMPTS: u32 = 3;
+
+/// Wait before the second attempt, doubled before each one after it.
+const SPAWN_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Emitted before each spawn retry so the UI can show that the backend is
+/// still starting up.
+pub const SPAWN_RETRY_EVENT: &str = "sidecar-spawn-retry";
+
+/// How long the core gets to exit on its own after a shutdown request.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// Core process a call runs on. Each class has its own, so a long mesh
+/// export never holds up a preview.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CallClass {
+    /// Quick calls the UI waits on, such as previews and swap plans.
+    #[default]
+    Interactive,
+    /// Mesh work that can run for minutes.
+    Heavy,
+}
+
+/// Per-command settings for a sidecar call.
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    pub timeout: Duration,
+    pub class: CallClass,
+    /// Event that progress frames are forwarded to, if any.
+    pub progress_event: Option<&'static str>,
+    /// Caller-chosen id under which the call can be cancelled.
+    pub op_id: Option<String>,
+    /// Calls sharing this key coalesce while queued: a new one supersedes
+    /// the one still waiting, so only the latest parameters get computed.
+    pub coalesce_key: Option<&'static str>,
+}
+
+impl CallOptions {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            class: CallClass::default(),
+            progress_event: None,
+            op_id: None,
+            coalesce_key: None,
+        }
+    }
+
+    pub fn heavy(mut self) -> Self {
+        self.class = CallClass::Heavy;
+        self
+    }
+
+    pub fn with_progress(mut self, event: &'static str) -> Self {
+        self.progress_event = Some(event);
+        self
+    }
+
+    pub fn with_op_id(mut self, op_id: Option<String>) -> Self {
+        self.op_id = op_id;
+        self
+    }
+
+    pub fn coalescing(mut self, key: &'static str) -> Self {
+        self.coalesce_key = Some(key);
+        self
+    }
+}
+
+/// Result frame of a call together with the stderr written while producing it.
+#[derive(Debug)]
+pub struct SidecarReply {
+    pub frame: serde_json::Value,
+    pub stderr: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpawnRetryPayload {
+    /// The attempt that just failed, starting at 1.
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressPayload {
+    pub method: String,
+    pub progress: f64,
+    pub stage: Option<String>,
+}
+
+type ReplySender = oneshot::Sender<Result<SidecarReply, CommandError>>;
+
+struct PendingCall {
+    sender: ReplySender,
+    method: String,
+    progress_event: Option<&'static str>,
+    stderr: Vec<u8>,
+    /// Signalled on every progress or heartbeat frame.
+    alive: Arc<Notify>,
+}
+
+/// Calls awaiting a result from one sidecar process.
+///
+/// `exited` is set under the same lock that registers calls, so a call can
+/// never be registered with a process whose reader has already given up.
+#[derive(Default)]
+struct PendingCalls {
+    exited: bool,
+    calls: HashMap<u64, PendingCall>,
+}
+
+type SharedPending = Arc<std::sync::Mutex<PendingCalls>>;
+
+/// The running core as [`SidecarProcess`] drives it: a stdin to write
+/// frames to, and a way to kill it.
+trait CoreChild: Send + Sync {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()>;
+    fn kill(self: Box<Self>);
+}
+
+impl CoreChild for CommandChild {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        CommandChild::write(self, bytes).map_err(std::io::Error::other)
+    }
+
+    fn kill(self: Box<Self>) {
+        let _ = CommandChild::kill(*self);
+    }
+}
+
+struct SidecarProcess {
+    child: Box<dyn CoreChild>,
+    pending: SharedPending,
+}
+
+impl SidecarProcess {
+    /// Starts `binary`, or the bundled core when it is `None`, unless
+    /// [`SIDECAR_CMD_ENV`] overrides both.
+    fn spawn(app: &tauri::AppHandle, binary: Option<&Path>) -> Result<Self, String> {
+        let override_words = std::env::var(SIDECAR_CMD_ENV)
+            .map(|line| split_command(&line))
+            .unwrap_or_default();
+        let sidecar = match (override_words.split_first(), binary) {
+            (Some((program, args)), _) => {
+                eprintln!(
+                    "Starting sidecar from {}: {:?}",
+                    SIDECAR_CMD_ENV, override_words
+                );
+                app.shell().command(program).args(args)
+            }
+            (None, Some(binary)) => {
+                eprintln!("Starting sidecar from {}", binary.display());
+                app.shell().command(binary)
+            }
+            (None, None) => app
+                .shell()
+                .sidecar(SIDECAR_NAME)
+                .map_err(|e| format!("Failed to create sidecar command: {}", e))?,
+        };
+
+        let (events, child) = sidecar
+            .set_raw_out(true)
+            .spawn()
+            .map_err(|e| format!("Failed to spawn sidecar: {}", e))?;
+
+        let pending = SharedPending::default();
+        tauri::async_runtime::spawn(dispatch_events(app.clone(), events, pending.clone()));
+
+        Ok(Self {
+            child: Box::new(child),
+            pending,
+        })
+    }
+
+    /// Spawns the core, retrying with exponential backoff because a cold
+    /// start can fail transiently, e.g. while antivirus scans a fresh
+    /// install.
+    async fn spawn_with_retry(
+        app: &tauri::AppHandle,
+        binary: Option<&Path>,
+    ) -> Result<Self, CommandError> {
+        let mut delay = SPAWN_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            let message = match Self::spawn(app, binary) {
+                Ok(process) => {
+                    if attempt > 1 {
+                        eprintln!("Sidecar started on attempt {}/{}", attempt, SPAWN_ATTEMPTS);
+                    }
+                    return Ok(process);
+                }
+                Err(message) => message,
+            };
+            eprintln!(
+                "Sidecar spawn attempt {}/{} failed: {}",
+                attempt, SPAWN_ATTEMPTS, message
+            );
+            if attempt == SPAWN_ATTEMPTS {
+                return Err(CommandError::SidecarSpawn {
+                    message,
+                    attempts: attempt,
+                });
+            }
+
+            let payload = SpawnRetryPayload {
+                attempt,
+                max_attempts: SPAWN_ATTEMPTS,
+                message,
+            };
+            let _ = app.emit(SPAWN_RETRY_EVENT, payload);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// Writes one newline-delimited request frame to the core's stdin.
+    fn send(&mut self, request: &serde_json::Value) -> std::io::Result<()> {
+        self.child.write(format!("{}\n", request).as_bytes())
+    }
+
+    fn has_exited(&self) -> bool {
+        self.pending.lock().unwrap().exited
+    }
+}
+
+/// One core process and the slot its calls take turns in. The core only
+/// ever has the call holding the slot in flight.
+struct Core {
+    process: Mutex<Option<SidecarProcess>>,
+    slot: Semaphore,
+}
+
+impl Default for Core {
+    fn default() -> Self {
+        Self {
+            process: Mutex::default(),
+            slot: Semaphore::new(1),
+        }
+    }
+}
+
+/// Shared sidecar processes, managed as Tauri state.
+///
+/// Calls of each [`CallClass`] go to their own core and run there one at a
+/// time; the rest queue for that core's slot. The process lock is only held
+/// while spawning and writing a request; waiting for the result happens on
+/// a per-call channel, so calls of different classes overlap.
+#[derive(Default)]
+pub struct SidecarHandle {
+    interactive: Core,
+    heavy: Core,
+    next_id: AtomicU64,
+    /// Reply channels of calls still waiting for a slot.
+    queued: std::sync::Mutex<HashMap<u64, ReplySender>>,
+    /// Newest call for each coalesce key.
+    latest: std::sync::Mutex<HashMap<&'static str, u64>>,
+    /// Heartbeat interval requested from the core; `None` turns hang
+    /// detection off.
+    heartbeat_interval: std::sync::Mutex<Option<Duration>>,
+    /// Timeout used instead of [`DEFAULT_TIMEOUT`] by calls that ask for it.
+    default_timeout: std::sync::Mutex<Option<Duration>>,
+    /// Core binary launched instead of the bundled one.
+    binary: std::sync::Mutex<Option<PathBuf>>,
+    /// Cancellable calls, keyed by op id. The process is `None` while the
+    /// call is queued.
+    operations: std::sync::Mutex<HashMap<String, (Option<SharedPending>, u64)>>,
+}
+
+impl SidecarHandle {
+    fn core(&self, class: CallClass) -> &Core {
+        match class {
+            CallClass::Interactive => &self.interactive,
+            CallClass::Heavy => &self.heavy,
+        }
+    }
+
+    fn cores(&self) -> [&Core; 2] {
+        [&self.interactive, &self.heavy]
+    }
+
+    /// Turns hang detection on for later calls, or off with `None`. Only
+    /// enable it for a core that advertises the `heartbeat` feature, or
+    /// every call running longer than a few intervals will be killed.
+    pub fn set_heartbeat_interval(&self, interval: Option<Duration>) {
+        *self.heartbeat_interval.lock().unwrap() = interval;
+    }
+
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        *self.heartbeat_interval.lock().unwrap()
+    }
+
+    /// Makes later calls with [`DEFAULT_TIMEOUT`] wait `timeout` instead, or
+    /// restores the built-in value with `None`.
+    pub fn set_default_timeout(&self, timeout: Option<Duration>) {
+        *self.default_timeout.lock().unwrap() = timeout;
+    }
+
+    /// The binary later spawns launch, `None` meaning the bundled core.
+    pub fn binary(&self) -> Option<PathBuf> {
+        self.binary.lock().unwrap().clone()
+    }
+
+    /// Launches `binary` from the next spawn on, without touching a core
+    /// that is already running.
+    pub fn set_binary(&self, binary: Option<PathBuf>) {
+        *self.binary.lock().unwrap() = binary;
+    }
+
+    /// Switches to `binary` by shutting the running cores down, so the next
+    /// call starts the new one. Calls still in flight fail.
+    pub async fn switch_binary(&self, binary: Option<PathBuf>) {
+        self.set_binary(binary);
+        self.shutdown().await;
+    }
+
+    /// Spawns the interactive core if it is not already running. The heavy
+    /// core is left to start with the first heavy call.
+    pub async fn start(&self, app: &tauri::AppHandle) -> Result<(), CommandError> {
+        let mut process = self.interactive.process.lock().await;
+        if process.as_ref().is_none_or(|process| process.has_exited()) {
+            let binary = self.binary();
+            *process = Some(SidecarProcess::spawn_with_retry(app, binary.as_deref()).await?);
+        }
+        Ok(())
+    }
+
+    /// Sends one request to the core of its class and waits for its result
+    /// frame.
+    ///
+    /// The call first waits for that core's slot, during which it can
+    /// already be cancelled or superseded. A process that died since the
+    /// previous call is transparently respawned before the request is
+    /// written. If no result arrives within the timeout the process is
+    /// killed and respawned on the next call; the other core keeps running.
+    pub async fn call(
+        &self,
+        app: &tauri::AppHandle,
+        method: &str,
+        params: serde_json::Value,
+        options: CallOptions,
+    ) -> Result<SidecarReply, CommandError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Some(op_id) = &options.op_id {
+            let mut operations = self.operations.lock().unwrap();
+            operations.insert(op_id.clone(), (None, id));
+        }
+
+        let binary = self.binary();
+        let spawn = || SidecarProcess::spawn_with_retry(app, binary.as_deref());
+        let result = self
+            .queue_and_call(spawn, id, method, params, &options)
+            .await;
+
+        if let Some(op_id) = &options.op_id {
+            let mut operations = self.operations.lock().unwrap();
+            if operations.get(op_id).is_some_and(|(_, op)| *op == id) {
+                operations.remove(op_id);
+            }
+        }
+        result
+    }
+
+    async fn queue_and_call<Spawn, Spawned>(
+        &self,
+        spawn: Spawn,
+        id: u64,
+        method: &str,
+        params: serde_json::Value,
+        options: &CallOptions,
+    ) -> Result<SidecarReply, CommandError>
+    where
+        Spawn: FnOnce() -> Spawned,
+        Spawned: std::future::Future<Output = Result<SidecarProcess, CommandError>>,
+    {
+        let core = self.core(options.class);
+        let heartbeat = *self.heartbeat_interval.lock().unwrap();
+        let mut request = serde_json::json!({
+            "id": id,
+            "method": method,
+            "params": params
+        });
+        if let Some(interval) = heartbeat {
+            request["heartbeat_ms"] = serde_json::Value::from(interval.as_millis() as u64);
+        }
+        let alive = Arc::new(Notify::new());
+        let (sender, mut receiver) = oneshot::channel();
+        self.queued.lock().unwrap().insert(id, sender);
+        if let Some(key) = options.coalesce_key {
+            let previous = self.latest.lock().unwrap().insert(key, id);
+            let superseded =
+                previous.and_then(|previous| self.queued.lock().unwrap().remove(&previous));
+            if let Some(superseded) = superseded {
+                let _ = superseded.send(Err(CommandError::Superseded {
+                    method: method.to_string(),
+                }));
+            }
+        }
+
+        let _slot = tokio::select! {
+            slot = core.slot.acquire() => slot.expect("sidecar slots are never closed"),
+            // Only a cancel or a newer call resolves the channel of a queued call
+            reply = &mut receiver => return received(reply),
+        };
+
+        let pending = {
+            let mut guard = core.process.lock().await;
+            if guard.as_ref().is_none_or(|process| process.has_exited()) {
+                match spawn().await {
+                    Ok(process) => *guard = Some(process),
+                    Err(e) => {
+                        self.queued.lock().unwrap().remove(&id);
+                        return Err(e);
+                    }
+                }
+            }
+            let process = guard.as_mut().expect("sidecar process was just spawned");
+
+            // Moving the call from the queue to the process and recording
+            // where it went happen under the operations lock, so a cancel
+            // always finds the call in one place or the other.
+            {
+                let mut operations = self.operations.lock().unwrap();
+                let Some(sender) = self.queued.lock().unwrap().remove(&id) else {
+                    // Cancelled or superseded just as the slot was granted
+                    drop(operations);
+                    return received(receiver.await);
+                };
+                process.pending.lock().unwrap().calls.insert(
+                    id,
+                    PendingCall {
+                        sender,
+                        method: method.to_string(),
+                        progress_event: options.progress_event,
+                        stderr: Vec::new(),
+                        alive: alive.clone(),
+                    },
+                );
+                if let Some(op_id) = &options.op_id {
+                    if let Some(operation) = operations.get_mut(op_id).filter(|(_, op)| *op == id) {
+                        operation.0 = Some(process.pending.clone());
+                    }
+                }
+            }
+
+            // Write request to stdin
+            if let Err(e) = process.send(&request) {
+                process.pending.lock().unwrap().calls.remove(&id);
+                return Err(CommandError::sidecar(format!(
+                    "Failed to write to stdin: {}",
+                    e
+                )));
+            }
+            process.pending.clone()
+        };
+
+        let timeout = match *self.default_timeout.lock().unwrap() {
+            Some(timeout) if options.timeout == DEFAULT_TIMEOUT => timeout,
+            _ => options.timeout,
+        };
+        let deadline = tokio::time::Instant::now() + timeout;
+        // Until the core's first frame for the call arrives there is no
+        // heartbeat to miss, so the hang window only opens after it
+        let mut watch_for_hangs = false;
+        let hang_window = heartbeat.unwrap_or_default() * HEARTBEAT_MISSES;
+        let error = loop {
+            tokio::select! {
+                reply = &mut receiver => return received(reply),
+                _ = tokio::time::sleep_until(deadline) => {
+                    break CommandError::SidecarTimeout {
+                        method: method.to_string(),
+                        seconds: timeout.as_secs(),
+                    };
+                }
+                _ = alive.notified() => watch_for_hangs = heartbeat.is_some(),
+                _ = tokio::time::sleep(hang_window), if watch_for_hangs => {
+                    break CommandError::SidecarHung {
+                        method: method.to_string(),
+                        seconds: hang_window.as_secs(),
+                    };
+                }
+            }
+        };
+        pending.lock().unwrap().calls.remove(&id);
+        self.kill_process(core, &pending).await;
+        Err(error)
+    }
+
+    /// Resolves the call registered under `op_id` with `Cancelled` and, if
+    /// it was already sent, asks the core to abandon it. The core reads
+    /// cancels while it works: it skips the call if it hasn't started and
+    /// otherwise stops it at its next progress stage. Returns false if no
+    /// such call is queued or in flight.
+    pub async fn cancel(&self, op_id: &str) -> bool {
+        let cancelled = Err(CommandError::Cancelled {
+            op_id: op_id.to_string(),
+        });
+        let (pending, id) = {
+            let mut operations = self.operations.lock().unwrap();
+            match operations.remove(op_id) {
+                Some((Some(pending), id)) => (pending, id),
+                Some((None, id)) => {
+                    let queued = self.queued.lock().unwrap().remove(&id);
+                    return queued.is_some_and(|sender| sender.send(cancelled).is_ok());
+                }
+                None => return false,
+            }
+        };
+        let Some(call) = pending.lock().unwrap().calls.remove(&id) else {
+            return false;
+        };
+        let _ = call.sender.send(cancelled);
+
+        // Best effort: the core may already be done, and a cancel gets no
+        // answer of its own.
+        for core in self.cores() {
+            let mut guard = core.process.lock().await;
+            if let Some(process) = guard.as_mut() {
+                if Arc::ptr_eq(&process.pending, &pending) {
+                    let request = serde_json::json!({
+                        "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+                        "method": "cancel",
+                        "params": { "request_id": id }
+                    });
+                    let _ = process.send(&request);
+                }
+            }
+        }
+        true
+    }
+
+    /// Asks the cores to exit and kills any still running after a short
+    /// grace period. Calls still in flight fail as the processes exit.
+    pub async fn shutdown(&self) {
+        let [interactive, heavy] = self.cores();
+        tokio::join!(self.shut_down(interactive), self.shut_down(heavy));
+    }
+
+    async fn shut_down(&self, core: &Core) {
+        let Some(mut process) = core.process.lock().await.take() else {
+            return;
+        };
+
+        let request = serde_json::json!({
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": "shutdown",
+            "params": {}
+        });
+        if process.send(&request).is_ok() {
+            let exited = tokio::time::timeout(SHUTDOWN_GRACE, async {
+                while !process.has_exited() {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            });
+            if exited.await.is_ok() {
+                return;
+            }
+        }
+        process.child.kill();
+    }
+
+    /// Kills the process owning `pending`, unless it was already replaced.
+    async fn kill_process(&self, core: &Core, pending: &SharedPending) {
+        let mut guard = core.process.lock().await;
+        let is_current = guard
+            .as_ref()
+            .is_some_and(|process| Arc::ptr_eq(&process.pending, pending));
+        if is_current {
+            // Killing goes through the plugin's shared child, whose wait
+            // thread reaps the process, so nothing is left as a zombie.
+            if let Some(process) = guard.take() {
+                process.child.kill();
+            }
+        }
+    }
+}
+
+/// Routes every frame the process writes to the call it belongs to, and
+/// fails the remaining calls once the process exits.
+async fn dispatch_events(
+    app: tauri::AppHandle,
+    mut events: Receiver<CommandEvent>,
+    pending: SharedPending,
+) {
+    let mut stdout = LineBuffer::default();
+    let mut stderr = LineBuffer::default();
+    let mut status = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            CommandEvent::Stdout(chunk) => {
+                for line in stdout.push(&chunk) {
+                    dispatch_stdout(&app, &pending, &line);
+                }
+            }
+            CommandEvent::Stderr(chunk) => {
+                for line in stderr.push(&chunk) {
+                    dispatch_stderr(&app, &pending, &line);
+                }
+            }
+            CommandEvent::Terminated(payload) => {
+                status = Some(payload);
+                break;
+            }
+            _ => {}
+        }
+    }
+    if let Some(line) = stdout.finish() {
+        dispatch_stdout(&app, &pending, &line);
+    }
+    if let Some(line) = stderr.finish() {
+        dispatch_stderr(&app, &pending, &line);
+    }
+
+    let mut pending = pending.lock().unwrap();
+    pending.exited = true;
+    for (_, call) in pending.calls.drain() {
+        let _ = call.sender.send(Err(exit_error(
+            status.as_ref(),
+            collected_stderr(&call.stderr),
+        )));
+    }
+}
+
+/// SIGKILL, which is what the Linux OOM killer sends.
+const SIGKILL: i32 = 9;
+
+/// Error for a call still in flight when the process exited with `status`.
+/// A clean exit is reported as such; anything else is a crash, flagged as
+/// likely out of memory when the process was killed or Python said so.
+fn exit_error(status: Option<&TerminatedPayload>, stderr: Option<String>) -> CommandError {
+    let (code, signal) = status.map_or((None, None), |s| (s.code, s.signal));
+    if code == Some(0) && signal.is_none() {
+        return CommandError::Sidecar {
+            message: "Sidecar exited before responding".to_string(),
+            stderr,
+        };
+    }
+    let out_of_memory = signal == Some(SIGKILL)
+        || stderr
+            .as_deref()
+            .is_some_and(|stderr| stderr.contains("MemoryError"));
+    CommandError::SidecarCrashed {
+        code,
+        signal,
+        out_of_memory,
+        stderr,
+    }
+}
+
+/// Reassembles newline-terminated lines from raw pipe chunks, which can end
+/// mid-line or hold several lines at once.
+#[derive(Default)]
+struct LineBuffer {
+    partial: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Appends `chunk` and returns the lines it completed, without their
+    /// line endings.
+    fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        self.partial.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.partial.drain(..=end).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Returns what's left once the stream ends without a final newline.
+    fn finish(&mut self) -> Option<Vec<u8>> {
+        (!self.partial.is_empty()).then(|| std::mem::take(&mut self.partial))
+    }
+}
+
+fn dispatch_stdout(app: &tauri::AppHandle, pending: &SharedPending, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+    let frame: serde_json::Value = match serde_json::from_str(line) {
+        Ok(frame) => frame,
+        Err(_) => {
+            eprintln!("Ignoring non-JSON sidecar output: {}", line);
+            if let Some(log) = app.try_state::<SidecarLog>() {
+                log.append("stdout", line.as_bytes());
+            }
+            return;
+        }
+    };
+    let routed = route_frame(&mut pending.lock().unwrap(), frame);
+    match routed {
+        Routed::NoId => eprintln!("Ignoring sidecar frame without an id: {}", line),
+        Routed::Alive(Some((event, payload))) => {
+            let _ = app.emit(event, payload);
+        }
+        Routed::Alive(None) | Routed::Resolved | Routed::Stale => {}
+    }
+}
+
+/// What [`route_frame`] did with a frame.
+#[derive(Debug)]
+enum Routed {
+    /// The frame had no id, so it can't belong to any call.
+    NoId,
+    /// No call waits for the id; the caller already gave up on it.
+    Stale,
+    /// A progress or heartbeat frame, with the event and payload to emit
+    /// for progress the caller asked to see.
+    Alive(Option<(&'static str, ProgressPayload)>),
+    /// A result frame, handed to its call.
+    Resolved,
+}
+
+/// Hands `frame` to the call whose id it carries. A heartbeat or progress
+/// frame marks the call alive; anything else is its result and resolves it.
+fn route_frame(pending: &mut PendingCalls, frame: serde_json::Value) -> Routed {
+    let Some(id) = frame.get("id").and_then(|id| id.as_u64()) else {
+        return Routed::NoId;
+    };
+    let Some(call) = pending.calls.get(&id) else {
+        return Routed::Stale;
+    };
+
+    if frame.get("heartbeat").is_some() {
+        call.alive.notify_one();
+        return Routed::Alive(None);
+    }
+    if let Some(payload) = progress_frame(&call.method, &frame) {
+        call.alive.notify_one();
+        return Routed::Alive(call.progress_event.map(|event| (event, payload)));
+    }
+
+    let call = pending
+        .calls
+        .remove(&id)
+        .expect("pending call was just found");
+    let _ = call.sender.send(Ok(SidecarReply {
+        frame,
+        stderr: collected_stderr(&call.stderr),
+    }));
+    Routed::Resolved
+}
+
+fn dispatch_stderr(app: &tauri::AppHandle, pending: &SharedPending, line: &[u8]) {
+    // Stderr isn't tagged with an id, so every call in flight sees it
+    let mut methods = Vec::new();
+    for call in pending.lock().unwrap().calls.values_mut() {
+        call.stderr.extend_from_slice(line);
+        call.stderr.push(b'\n');
+        methods.push(call.method.clone());
+    }
+    if let Some(log) = app.try_state::<SidecarLog>() {
+        methods.sort();
+        let context = if methods.is_empty() {
+            "idle".to_string()
+        } else {
+            methods.join(",")
+        };
+        log.append(&context, line);
+    }
+}
+
+fn received(
+    reply: Result<Result<SidecarReply, CommandError>, oneshot::error::RecvError>,
+) -> Result<SidecarReply, CommandError> {
+    reply.unwrap_or_else(|_| Err(CommandError::sidecar("Sidecar dropped the request")))
+}
+
+/// Splits a command line into words at whitespace. Single or double quotes
+/// group words containing spaces, such as Windows paths.
+fn split_command(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+fn collected_stderr(stderr: &[u8]) -> Option<String> {
+    let stderr = String::from_utf8_lossy(stderr).trim().to_string();
+    (!stderr.is_empty()).then_some(stderr)
+}
+
+fn progress_frame(method: &str, frame: &serde_json::Value) -> Option<ProgressPayload> {
+    let progress = frame.get("progress")?.as_f64()?;
+    Some(ProgressPayload {
+        method: method.to_string(),
+        progress,
+        stage: frame
+            .get("stage")
+            .and_then(|s| s.as_str())
+            .map(|s| s.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_override_commands_into_words() {
+        assert_eq!(
+            split_command("  python -m layerforge.cli "),
+            ["python", "-m", "layerforge.cli"]
+        );
+        assert_eq!(
+            split_command(r#""C:\Program Files\Python\python.exe" -m 'layer forge' """#),
+            [
+                r"C:\Program Files\Python\python.exe",
+                "-m",
+                "layer forge",
+                ""
+            ]
+        );
+        assert!(split_command("   ").is_empty());
+    }
+
+    #[test]
+    fn exit_status_decides_between_exit_and_crash() {
+        let status = |code, signal| TerminatedPayload { code, signal };
+
+        let clean = exit_error(Some(&status(Some(0), None)), None);
+        assert!(matches!(clean, CommandError::Sidecar { .. }));
+
+        let killed = exit_error(Some(&status(None, Some(SIGKILL))), None);
+        assert!(matches!(
+            killed,
+            CommandError::SidecarCrashed {
+                signal: Some(9),
+                out_of_memory: true,
+                ..
+            }
+        ));
+
+        let traceback = "Traceback (most recent call last):\nMemoryError".to_string();
+        let raised = exit_error(Some(&status(Some(1), None)), Some(traceback));
+        assert!(matches!(
+            raised,
+            CommandError::SidecarCrashed {
+                code: Some(1),
+                out_of_memory: true,
+                ..
+            }
+        ));
+
+        let failed = exit_error(Some(&status(Some(2), None)), None);
+        assert!(matches!(
+            failed,
+            CommandError::SidecarCrashed {
+                out_of_memory: false,
+                ..
+            }
+        ));
+        assert!(matches!(
+            exit_error(None, None),
+            CommandError::SidecarCrashed { .. }
+        ));
+    }
+
+    fn pending_call(
+        method: &str,
+        progress_event: Option<&'static str>,
+    ) -> (
+        PendingCall,
+        oneshot::Receiver<Result<SidecarReply, CommandError>>,
+    ) {
+        let (sender, receiver) = oneshot::channel();
+        let call = PendingCall {
+            sender,
+            method: method.to_string(),
+            progress_event,
+            stderr: b"warming up".to_vec(),
+            alive: Arc::new(Notify::new()),
+        };
+        (call, receiver)
+    }
+
+    #[test]
+    fn frames_reach_the_call_with_their_id() {
+        let mut pending = PendingCalls::default();
+        let (mesh, mut mesh_reply) = pending_call("generate_mesh", Some("mesh-progress"));
+        let (preview, mut preview_reply) = pending_call("compute_preview", None);
+        pending.calls.insert(1, mesh);
+        pending.calls.insert(2, preview);
+        let frame = |json: &str| serde_json::from_str(json).unwrap();
+
+        assert!(matches!(
+            route_frame(&mut pending, frame(r#"{"id": 1, "progress": 0.5, "stage": "writing"}"#)),
+            Routed::Alive(Some(("mesh-progress", ProgressPayload { progress, .. }))) if progress == 0.5
+        ));
+        assert!(matches!(
+            route_frame(&mut pending, frame(r#"{"id": 2, "progress": 0.1}"#)),
+            Routed::Alive(None)
+        ));
+        assert!(matches!(
+            route_frame(&mut pending, frame(r#"{"id": 1, "heartbeat": true}"#)),
+            Routed::Alive(None)
+        ));
+
+        // Results resolve their own call and leave the other one waiting
+        assert!(matches!(
+            route_frame(&mut pending, frame(r#"{"id": 2, "preview_base64": "x"}"#)),
+            Routed::Resolved
+        ));
+        let reply = preview_reply.try_recv().unwrap().unwrap();
+        assert_eq!(reply.frame["preview_base64"], "x");
+        assert_eq!(reply.stderr.as_deref(), Some("warming up"));
+        assert!(mesh_reply.try_recv().is_err());
+        assert!(pending.calls.contains_key(&1));
+
+        assert!(matches!(
+            route_frame(&mut pending, frame(r#"{"id": 2, "error": "late"}"#)),
+            Routed::Stale
+        ));
+        assert!(matches!(
+            route_frame(&mut pending, frame(r#"{"path": "/tmp/cat.stl"}"#)),
+            Routed::NoId
+        ));
+        assert!(mesh_reply.try_recv().is_err());
+    }
+
+    #[test]
+    fn line_buffer_reassembles_frames_split_across_chunks() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push(br#"{"id": 1, "res"#).is_empty());
+        assert_eq!(
+            buffer.push(b"ult\": 2}\r\nloading model\n{\"id\""),
+            [
+                br#"{"id": 1, "result": 2}"#.to_vec(),
+                b"loading model".to_vec()
+            ]
+        );
+        assert_eq!(buffer.push(b": 2}\n"), [br#"{"id": 2}"#.to_vec()]);
+        assert!(buffer.finish().is_none());
+    }
+
+    #[test]
+    fn line_buffer_keeps_an_unterminated_last_line() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push(b"\n\n").iter().all(|line| line.is_empty()));
+        assert!(buffer.push(b"Traceback").is_empty());
+        assert_eq!(buffer.finish(), Some(b"Traceback".to_vec()));
+        assert!(buffer.finish().is_none());
+    }
+
+    /// A shell process standing in for the core. Like the plugin's child,
+    /// a watcher reaps it and flags the exit on `pending`, and the frames it
+    /// prints are routed to the calls on `pending`.
+    struct StubCore {
+        child: Arc<std::sync::Mutex<std::process::Child>>,
+    }
+
+    impl StubCore {
+        fn spawn(script: &str, pending: &SharedPending) -> (Self, u32) {
+            let mut child = std::process::Command::new("sh")
+                .args(["-c", script])
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            let pid = child.id();
+            let stdout = child.stdout.take().unwrap();
+            let routed = pending.clone();
+            std::thread::spawn(move || {
+                use std::io::BufRead;
+                for line in std::io::BufReader::new(stdout).lines() {
+                    let Ok(frame) = serde_json::from_str(&line.unwrap_or_default()) else {
+                        continue;
+                    };
+                    route_frame(&mut routed.lock().unwrap(), frame);
+                }
+            });
+            let child = Arc::new(std::sync::Mutex::new(child));
+            let (watched, pending) = (child.clone(), pending.clone());
+            std::thread::spawn(move || loop {
+                if let Ok(Some(_)) = watched.lock().unwrap().try_wait() {
+                    pending.lock().unwrap().exited = true;
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            });
+            (Self { child }, pid)
+        }
+    }
+
+    impl CoreChild for StubCore {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            use std::io::Write;
+            let mut child = self.child.lock().unwrap();
+            child.stdin.as_mut().unwrap().write_all(bytes)
+        }
+
+        fn kill(self: Box<Self>) {
+            let _ = self.child.lock().unwrap().kill();
+        }
+    }
+
+    /// Runs a stub core for `script` as the process of `core`.
+    fn install_stub(core: &Core, script: &str) -> u32 {
+        let pending = SharedPending::default();
+        let (stub, pid) = StubCore::spawn(script, &pending);
+        *core.process.blocking_lock() = Some(SidecarProcess {
+            child: Box::new(stub),
+            pending,
+        });
+        pid
+    }
+
+    /// Shell snippet answering the request read into `$line` with a result
+    /// frame carrying its id.
+    const STUB_REPLY: &str =
+        r#"echo "{\"id\": $(echo "$line" | sed 's/.*"id":\([0-9]*\).*/\1/'), \"done\": true}""#;
+
+    #[test]
+    fn heavy_calls_run_alongside_interactive_ones() {
+        if cfg!(target_os = "windows") {
+            return;
+        }
+        let handle = SidecarHandle::default();
+        install_stub(
+            &handle.heavy,
+            &format!("read line; sleep 2; {}", STUB_REPLY),
+        );
+        install_stub(
+            &handle.interactive,
+            &format!("while read line; do {}; done", STUB_REPLY),
+        );
+        let no_spawn = || async { Err::<SidecarProcess, _>(CommandError::sidecar("no spawns")) };
+
+        let heavy = CallOptions::new(MESH_TIMEOUT).heavy();
+        tauri::async_runtime::block_on(async {
+            let mesh =
+                handle.queue_and_call(no_spawn, 1, "generate_mesh", serde_json::json!({}), &heavy);
+            let swaps = async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                let started = std::time::Instant::now();
+                let reply = handle
+                    .queue_and_call(
+                        no_spawn,
+                        2,
+                        "compute_swaps",
+                        serde_json::json!({}),
+                        &CallOptions::new(Duration::from_secs(1)),
+                    )
+                    .await;
+                (reply, started.elapsed())
+            };
+            let (mesh, (swaps, waited)) = tokio::join!(mesh, swaps);
+
+            // The swaps call would time out and take the mesh down with it
+            // if it had to wait for the mesh
+            assert_eq!(swaps.unwrap().frame["id"], 2);
+            assert!(waited < Duration::from_secs(1));
+            assert_eq!(mesh.unwrap().frame["id"], 1);
+        });
+    }
+
+    /// Result of one interactive call to a stub core running `script`,
+    /// watched for hangs at 100 ms heartbeats.
+    fn call_stub_with_heartbeats(script: &str) -> Result<SidecarReply, CommandError> {
+        let handle = SidecarHandle::default();
+        handle.set_heartbeat_interval(Some(Duration::from_millis(100)));
+        install_stub(&handle.interactive, script);
+        let no_spawn = || async { Err::<SidecarProcess, _>(CommandError::sidecar("no spawns")) };
+        let options = CallOptions::new(Duration::from_secs(5));
+        tauri::async_runtime::block_on(handle.queue_and_call(
+            no_spawn,
+            1,
+            "compute_swaps",
+            serde_json::json!({}),
+            &options,
+        ))
+    }
+
+    #[test]
+    fn calls_the_core_has_not_started_are_not_hung() {
+        if cfg!(target_os = "windows") {
+            return;
+        }
+        let reply = call_stub_with_heartbeats(&format!("read line; sleep 1; {}", STUB_REPLY));
+        assert_eq!(reply.unwrap().frame["done"], true);
+    }
+
+    #[test]
+    fn started_calls_that_stop_beating_are_hung() {
+        if cfg!(target_os = "windows") {
+            return;
+        }
+        let reply = call_stub_with_heartbeats(
+            r#"read line; echo '{"id": 1, "heartbeat": true}'; exec sleep 30"#,
+        );
+        assert!(matches!(reply, Err(CommandError::SidecarHung { .. })));
+    }
+
+    /// Shuts down a stub core running `script` and returns how long it
+    /// took, once the stub's PID is gone.
+    fn shut_down_stub(script: &str) -> Duration {
+        let handle = SidecarHandle::default();
+        let pid = install_stub(&handle.interactive, script);
+
+        let started = std::time::Instant::now();
+        tauri::async_runtime::block_on(handle.shutdown());
+        let gone = std::time::Instant::now() + Duration::from_secs(5);
+        while crate::project_lock::process_alive(pid) {
+            assert!(
+                std::time::Instant::now() < gone,
+                "core {} outlived shutdown",
+                pid
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(handle.interactive.process.blocking_lock().is_none());
+        started.elapsed()
+    }
+
+    #[test]
+    fn shutdown_lets_the_core_exit_on_its_own() {
+        if cfg!(target_os = "windows") {
+            return;
+        }
+        assert!(shut_down_stub("read line; exit 0") < SHUTDOWN_GRACE);
+    }
+
+    #[test]
+    fn shutdown_kills_a_core_that_ignores_it() {
+        if cfg!(target_os = "windows") {
+            return;
+        }
+        assert!(shut_down_stub("exec sleep 30") >= SHUTDOWN_GRACE);
+    }
+}
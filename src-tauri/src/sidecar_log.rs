@@ -0,0 +1,175 @@
+//! Persistent log of the sidecar's diagnostic output, for bug reports.
+//!
+//! Every line the core writes to stderr is appended with a UTC timestamp and
+//! the methods that were in flight at the time. Once the log would grow past
+//! its size cap it is moved to `.1`, shifting older logs up and keeping at
+//! most [`LOG_ROTATIONS`] of them.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::fs_util::now_millis;
+
+/// File name of the log inside the app log directory.
+pub const SIDECAR_LOG_NAME: &str = "sidecar.log";
+pub const MAX_LOG_BYTES: u64 = 2 * 1024 * 1024;
+pub const LOG_ROTATIONS: usize = 2;
+
+/// Append-only sidecar log, managed as Tauri state.
+pub struct SidecarLog {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Mutex<Option<File>>,
+}
+
+impl SidecarLog {
+    pub fn new(path: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            path,
+            max_bytes,
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends one line written while `context` was running. Failures are
+    /// reported on our own stderr; logging never fails a call.
+    pub fn append(&self, context: &str, line: &[u8]) {
+        if let Err(e) = self.try_append(context, line) {
+            eprintln!("Could not write sidecar log {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn try_append(&self, context: &str, line: &[u8]) -> io::Result<()> {
+        let entry = format!(
+            "{} [{}] {}\n",
+            format_utc(now_millis()),
+            context,
+            String::from_utf8_lossy(line).trim_end()
+        );
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            *file = Some(self.open()?);
+        }
+
+        let len = file
+            .as_ref()
+            .map_or(Ok(0), |f| f.metadata().map(|m| m.len()))?;
+        if len > 0 && len + entry.len() as u64 > self.max_bytes {
+            *file = None;
+            self.rotate()?;
+            *file = Some(self.open()?);
+        }
+        file.as_mut()
+            .expect("log file was just opened")
+            .write_all(entry.as_bytes())
+    }
+
+    fn open(&self) -> io::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+    }
+
+    /// Path of the `index`-th rotated log; `.1` is the newest.
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        let oldest = self.rotated_path(LOG_ROTATIONS);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for index in (1..LOG_ROTATIONS).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+}
+
+/// Formats milliseconds since the Unix epoch as an ISO 8601 UTC timestamp.
+fn format_utc(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn temp_log(name: &str, max_bytes: u64) -> SidecarLog {
+        SidecarLog::new(temp_dir(name).join(SIDECAR_LOG_NAME), max_bytes)
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_utc(1_700_000_000_123), "2023-11-14T22:13:20.123Z");
+        assert_eq!(format_utc(951_782_400_000), "2000-02-29T00:00:00.000Z");
+    }
+
+    #[test]
+    fn appends_lines_with_their_context() {
+        let log = temp_log("sidecar-log", MAX_LOG_BYTES);
+        log.append("process_image", b"warming up\n");
+        log.append("idle", b"done");
+
+        let contents = fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("Z [process_image] warming up"));
+        assert!(lines[1].ends_with("[idle] done"));
+    }
+
+    #[test]
+    fn rotates_once_the_cap_is_reached() {
+        let log = temp_log("sidecar-log-rotate", 100);
+        for i in 0..12 {
+            log.append("generate_mesh", format!("line {}", i).as_bytes());
+        }
+
+        assert!(fs::metadata(log.path()).unwrap().len() <= 100);
+        assert!(log.rotated_path(LOG_ROTATIONS).exists());
+        assert!(!log.rotated_path(LOG_ROTATIONS + 1).exists());
+        let newest = fs::read_to_string(log.path()).unwrap();
+        assert!(newest.trim_end().ends_with("line 11"));
+    }
+}
@@ -0,0 +1,151 @@
+//! Core binaries users can run instead of the bundled one, to try a core
+//! release before it ships with the app.
+//!
+//! Every file in the [`SIDECAR_VERSIONS_DIR`] directory of the app data
+//! directory is a version, identified by its file name, listed after the
+//! bundled core. The chosen id is kept in [`ACTIVE_SIDECAR_NAME`] in the app
+//! config directory. `LAYERFORGE_SIDECAR_CMD` still takes precedence over
+//! both, for working on the core from source.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::fs_util::write_atomic;
+
+/// Id of the core shipped with the app.
+pub const BUNDLED_SIDECAR_ID: &str = "bundled";
+
+/// Directory inside the app data directory holding user-provided cores.
+pub const SIDECAR_VERSIONS_DIR: &str = "sidecars";
+
+/// File name of the active version inside the app config directory.
+pub const ACTIVE_SIDECAR_NAME: &str = "active-sidecar.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SidecarVersion {
+    pub id: String,
+    /// Binary launched for this version; `None` for the bundled core.
+    pub path: Option<String>,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ActiveSidecar {
+    id: String,
+}
+
+/// The bundled core followed by the files in `dir`, sorted by name, with
+/// `active_id` marked. A missing directory only lists the bundled core.
+pub fn list_versions(dir: &Path, active_id: &str) -> Vec<SidecarVersion> {
+    let mut provided: Vec<(String, PathBuf)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| Some((entry.file_name().into_string().ok()?, entry.path())))
+        .collect();
+    provided.sort();
+
+    let bundled = SidecarVersion {
+        id: BUNDLED_SIDECAR_ID.to_string(),
+        path: None,
+        active: active_id == BUNDLED_SIDECAR_ID,
+    };
+    std::iter::once(bundled)
+        .chain(provided.into_iter().map(|(id, path)| SidecarVersion {
+            active: id == active_id,
+            id,
+            path: Some(path.to_string_lossy().into_owned()),
+        }))
+        .collect()
+}
+
+/// Binary to launch for `id`: `None` for the bundled core, otherwise the
+/// file of that name in `dir`, which has to exist.
+pub fn resolve_version(dir: &Path, id: &str) -> Result<Option<PathBuf>, CommandError> {
+    if id == BUNDLED_SIDECAR_ID {
+        return Ok(None);
+    }
+    let is_file_name = Path::new(id).file_name().is_some_and(|name| name == id);
+    let path = dir.join(id);
+    if !is_file_name || !path.is_file() {
+        return Err(CommandError::invalid_settings(
+            "version_id",
+            format!("no sidecar named {:?} in {}", id, dir.display()),
+        ));
+    }
+    Ok(Some(path))
+}
+
+/// The active version id stored at `config_path`, or the bundled core's if
+/// none is stored or it can't be read.
+pub fn read_active(config_path: &Path) -> String {
+    std::fs::read_to_string(config_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<ActiveSidecar>(&json).ok())
+        .map_or_else(|| BUNDLED_SIDECAR_ID.to_string(), |active| active.id)
+}
+
+pub fn write_active(config_path: &Path, id: &str) -> Result<(), CommandError> {
+    let json = serde_json::to_string_pretty(&ActiveSidecar { id: id.to_string() })?;
+    write_atomic(config_path, json.as_bytes())
+        .map_err(|e| CommandError::io(config_path.to_string_lossy(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn lists_the_bundled_core_first_and_marks_the_active_one() {
+        let dir = temp_dir("sidecar-versions-list");
+        std::fs::write(dir.join("core-0.3.0"), b"").unwrap();
+        std::fs::write(dir.join("core-0.2.1"), b"").unwrap();
+        std::fs::create_dir(dir.join("not-a-binary")).unwrap();
+
+        let versions = list_versions(&dir, "core-0.3.0");
+        let ids: Vec<&str> = versions.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, ["bundled", "core-0.2.1", "core-0.3.0"]);
+        let active: Vec<bool> = versions.iter().map(|v| v.active).collect();
+        assert_eq!(active, [false, false, true]);
+        assert_eq!(versions[0].path, None);
+
+        let missing = list_versions(&dir.join("missing"), BUNDLED_SIDECAR_ID);
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].active);
+    }
+
+    #[test]
+    fn resolves_only_binaries_inside_the_directory() {
+        let dir = temp_dir("sidecar-versions-resolve");
+        std::fs::write(dir.join("core-0.3.0"), b"").unwrap();
+
+        assert_eq!(resolve_version(&dir, BUNDLED_SIDECAR_ID).unwrap(), None);
+        assert_eq!(
+            resolve_version(&dir, "core-0.3.0").unwrap(),
+            Some(dir.join("core-0.3.0"))
+        );
+        for id in ["core-9.9.9", "../resolve/core-0.3.0", ""] {
+            assert!(matches!(
+                resolve_version(&dir, id),
+                Err(CommandError::InvalidSettings { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn active_version_defaults_to_the_bundled_core() {
+        let dir = temp_dir("sidecar-versions-active");
+        let config_path = dir.join(ACTIVE_SIDECAR_NAME);
+        assert_eq!(read_active(&config_path), BUNDLED_SIDECAR_ID);
+
+        write_active(&config_path, "core-0.3.0").unwrap();
+        assert_eq!(read_active(&config_path), "core-0.3.0");
+
+        std::fs::write(&config_path, "not json").unwrap();
+        assert_eq!(read_active(&config_path), BUNDLED_SIDECAR_ID);
+    }
+}
@@ -0,0 +1,153 @@
+//! Read-only project snapshots for sharing.
+//!
+//! A snapshot is one JSON file holding the settings, the filaments, the
+//! swap plan and a preview image, so others can see how a print was made.
+//! Unlike a bundle it leaves the source image out: only the sections listed
+//! in `SHARED_SECTIONS` are copied, which also drops the inline
+//! `imageData` the frontend keeps in the project. Loading a snapshot as a
+//! project is refused.
+
+use base64::Engine;
+use serde_json::{json, Map, Value};
+
+use crate::bundle::decode_image_data;
+use crate::color_plan::{drop_unusable_stops, swaps_for_profile, LayerHeightSegment};
+use crate::error::CommandError;
+use crate::fs_util::now_millis;
+use crate::models::{ColorStop, Filament, PrintSettings};
+use crate::plan::plan_table;
+use crate::project::ProjectFile;
+
+/// Value of a snapshot's `format` field.
+pub const SNAPSHOT_FORMAT: &str = "layerforge-snapshot";
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Project sections copied into a snapshot. Everything else, the image
+/// path and data in particular, stays out.
+const SHARED_SECTIONS: &[&str] = &[
+    "imageAspectRatio",
+    "filaments",
+    "modelGeometry",
+    "printSettings",
+    "colorPlan",
+    "lighting",
+];
+
+/// MIME type of a PNG or JPEG image, from its magic bytes.
+fn image_mime(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xFF\xD8\xFF") {
+        Some("image/jpeg")
+    } else {
+        None
+    }
+}
+
+/// Builds the snapshot of `project` with `preview` (plain base64 or a data
+/// URL of a PNG or JPEG) embedded as a data URL.
+pub fn build_snapshot(project: &ProjectFile, preview: &str) -> Result<Value, CommandError> {
+    let preview = decode_image_data(preview)?;
+    let mime = image_mime(&preview).ok_or_else(|| {
+        CommandError::serialization("Preview is not a PNG or JPEG image".to_string())
+    })?;
+
+    let filaments: Vec<Filament> = project.typed_section(&["filaments"])?;
+    let print_settings: PrintSettings = project.typed_section(&["printSettings"])?;
+    let stops: Vec<ColorStop> = project.typed_section(&["colorPlan", "stops"])?;
+    let (usable, _) = drop_unusable_stops(stops, &filaments);
+    let profile = [LayerHeightSegment {
+        from_z_mm: 0.0,
+        layer_height_mm: print_settings.layer_height_mm,
+    }];
+    let (swaps, _) = swaps_for_profile(&usable, &profile)?;
+
+    let settings: Map<String, Value> = SHARED_SECTIONS
+        .iter()
+        .filter_map(|&key| Some((key.to_string(), project.data.get(key)?.clone())))
+        .collect();
+    Ok(json!({
+        "format": SNAPSHOT_FORMAT,
+        "formatVersion": SNAPSHOT_VERSION,
+        "description": "Read-only Layerforge snapshot. The source image is not included, \
+                        so it can't be opened as a project.",
+        "createdAt": now_millis(),
+        "schemaVersion": project.schema_version,
+        "project": settings,
+        "swapPlan": plan_table(&swaps, &filaments, &print_settings),
+        "preview": format!(
+            "data:{};base64,{}",
+            mime,
+            base64::engine::general_purpose::STANDARD.encode(&preview)
+        ),
+    }))
+}
+
+/// Fails if `data` is a snapshot rather than a project.
+pub fn reject_snapshot(data: &Map<String, Value>) -> Result<(), CommandError> {
+    if data.get("format").and_then(Value::as_str) == Some(SNAPSHOT_FORMAT) {
+        return Err(CommandError::serialization(
+            "This is a read-only snapshot; open the original project to edit it".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::print_settings;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\nrest of the image";
+
+    fn project() -> ProjectFile {
+        let project = json!({
+            "imagePath": "/photos/cat.png",
+            "imageData": "data:image/png;base64,c291cmNl",
+            "filaments": [
+                {"id": "k", "name": "Black", "hexColor": "#000000", "td": 0.5,
+                 "enabled": true, "orderIndex": 0},
+                {"id": "w", "name": "White", "hexColor": "#FFFFFF", "td": 3.0,
+                 "enabled": true, "orderIndex": 1}
+            ],
+            "printSettings": print_settings(),
+            "colorPlan": {"mode": "transmission", "stops": [
+                {"filamentId": "k", "thresholdZMm": 0.0},
+                {"filamentId": "w", "thresholdZMm": 0.8}
+            ]}
+        });
+        ProjectFile::parse_current(&project.to_string()).unwrap()
+    }
+
+    #[test]
+    fn keeps_settings_and_plan_but_not_the_source_image() {
+        let preview = base64::engine::general_purpose::STANDARD.encode(PNG);
+        let snapshot = build_snapshot(&project(), &preview).unwrap();
+
+        assert_eq!(snapshot["format"], SNAPSHOT_FORMAT);
+        let shared = snapshot["project"].as_object().unwrap();
+        assert!(!shared.contains_key("imagePath"));
+        assert!(!shared.contains_key("imageData"));
+        assert_eq!(shared["filaments"].as_array().unwrap().len(), 2);
+
+        let plan = snapshot["swapPlan"].as_array().unwrap();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan[1]["filament_name"], "White");
+        assert_eq!(plan[1]["layer"], 12);
+        assert!(snapshot["preview"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:image/png;base64,"));
+
+        assert!(reject_snapshot(snapshot.as_object().unwrap()).is_err());
+        assert!(reject_snapshot(&project().data).is_ok());
+    }
+
+    #[test]
+    fn rejects_previews_that_are_not_images() {
+        let preview = base64::engine::general_purpose::STANDARD.encode(b"not an image");
+        assert!(build_snapshot(&project(), &preview).is_err());
+        assert!(build_snapshot(&project(), "***").is_err());
+    }
+}
@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::PathBuf;
+
+use crate::models::{ModelGeometrySettings, PrintSettings};
+
+/// A fresh, empty directory for the test `name`, unique to this test run.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("layerforge-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The app's default geometry: a 0.48 to 2.24 mm relief with neutral tone
+/// settings.
+pub fn geometry() -> ModelGeometrySettings {
+    serde_json::from_value(serde_json::json!({
+        "minDepthMm": 0.48, "maxDepthMm": 2.24, "gamma": 1.0, "contrast": 1.0,
+        "offset": 0.0, "smoothing": 0.0, "spikeRemoval": "none", "invert": false
+    }))
+    .unwrap()
+}
+
+/// A solid 100 x 80 mm print of 0.08 mm layers on a 0.16 mm base, without
+/// a border.
+pub fn print_settings() -> PrintSettings {
+    PrintSettings {
+        layer_height_mm: 0.08,
+        base_layer_mm: 0.16,
+        width_mm: 100.0,
+        height_mm: 80.0,
+        border_width_mm: 0.0,
+        border_depth_mm: 0.0,
+        has_border: false,
+        shell_thickness_mm: None,
+    }
+}
@@ -0,0 +1,158 @@
+//! The luminance to depth mapping of `ModelGeometrySettings`, replicated
+//! from the sidecar's image processor so it can be plotted without an image.
+//!
+//! Only the per-pixel steps are covered: tone mapping, `invert` and the
+//! scaling to `[min_depth_mm, max_depth_mm]`. Spike removal and smoothing
+//! depend on neighbouring pixels, and `dynamic_depth` stretches by the
+//! image's own percentiles, so none of them are part of the curve.
+
+use crate::error::CommandError;
+use crate::models::{ModelGeometrySettings, TransferCurvePoint};
+
+/// Largest number of samples `tone_curve` returns.
+pub const MAX_TONE_CURVE_SAMPLES: usize = 4096;
+
+/// `samples` evenly spaced input luminances from 0 to 1, each paired with
+/// the depth in mm the sidecar would give a pixel of that luminance.
+pub fn tone_curve(
+    geometry: &ModelGeometrySettings,
+    samples: usize,
+) -> Result<Vec<(f64, f64)>, CommandError> {
+    if !(2..=MAX_TONE_CURVE_SAMPLES).contains(&samples) {
+        return Err(CommandError::invalid_settings(
+            "samples",
+            format!("{} is outside 2..={}", samples, MAX_TONE_CURVE_SAMPLES),
+        ));
+    }
+    let curve = match geometry.tone_mapping_mode.as_str() {
+        "gamma" => None,
+        "curve" => Some(curve_points(&geometry.transfer_curve)),
+        other => {
+            return Err(CommandError::invalid_geometry(
+                "tone_mapping_mode",
+                format!("{:?} is not gamma or curve", other),
+            ))
+        }
+    };
+
+    let range = geometry.max_depth_mm - geometry.min_depth_mm;
+    let pairs = (0..samples)
+        .map(|i| {
+            let input = i as f64 / (samples - 1) as f64;
+            let mut value = match &curve {
+                Some((xs, ys)) => interpolate(input, xs, ys),
+                None => apply_gamma(input, geometry),
+            };
+            if geometry.invert {
+                value = 1.0 - value;
+            }
+            (input, geometry.min_depth_mm + value * range)
+        })
+        .collect();
+    Ok(pairs)
+}
+
+/// Gamma, then contrast around the midpoint, then offset, clamped.
+fn apply_gamma(input: f64, geometry: &ModelGeometrySettings) -> f64 {
+    let value = input.clamp(0.0, 1.0).powf(geometry.gamma);
+    let value = (value - 0.5) * geometry.contrast + 0.5 + geometry.offset;
+    value.clamp(0.0, 1.0)
+}
+
+/// The curve's inner points, sorted, between forced endpoints at (0, 0)
+/// and (1, 1). Points sharing an x keep the last one.
+fn curve_points(points: &[TransferCurvePoint]) -> (Vec<f64>, Vec<f64>) {
+    let mut inner: Vec<(f64, f64)> = points
+        .iter()
+        .filter(|p| p.x.is_finite() && p.y.is_finite())
+        .map(|p| (p.x.clamp(0.0, 1.0), p.y.clamp(0.0, 1.0)))
+        .filter(|(x, _)| *x > 0.0 && *x < 1.0)
+        .collect();
+    inner.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut xs: Vec<f64> = Vec::new();
+    let mut ys: Vec<f64> = Vec::new();
+    let all = std::iter::once((0.0, 0.0))
+        .chain(inner)
+        .chain(std::iter::once((1.0, 1.0)));
+    for (x, y) in all {
+        if xs.last().is_some_and(|last| (last - x).abs() < 1e-9) {
+            *xs.last_mut().unwrap() = x;
+            *ys.last_mut().unwrap() = y;
+        } else {
+            xs.push(x);
+            ys.push(y);
+        }
+    }
+    (xs, ys)
+}
+
+/// Piecewise-linear interpolation through increasing `xs`, like `np.interp`.
+fn interpolate(input: f64, xs: &[f64], ys: &[f64]) -> f64 {
+    let input = input.clamp(0.0, 1.0);
+    let segment = xs.windows(2).position(|w| input <= w[1]);
+    let value = match segment {
+        Some(i) if xs[i + 1] > xs[i] => {
+            let t = (input - xs[i]) / (xs[i + 1] - xs[i]);
+            ys[i] + t * (ys[i + 1] - ys[i])
+        }
+        Some(i) => ys[i + 1],
+        None => *ys.last().unwrap_or(&1.0),
+    };
+    value.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::geometry;
+
+    /// Shared with the sidecar's tests, which check its image processor
+    /// against the same expected depths.
+    const FIXTURE: &str = include_str!("../../python-core/tests/fixtures/tone_curve.json");
+
+    #[test]
+    fn matches_the_sidecar_fixture() {
+        let fixture: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        let inputs = fixture["inputs"].as_array().unwrap();
+        for case in fixture["cases"].as_array().unwrap() {
+            let geometry: ModelGeometrySettings =
+                serde_json::from_value(case["geometry"].clone()).unwrap();
+            let curve = tone_curve(&geometry, inputs.len()).unwrap();
+            let depths = case["depths_mm"].as_array().unwrap();
+            for ((input, depth), expected) in curve.iter().zip(depths) {
+                let expected = expected.as_f64().unwrap();
+                assert!(
+                    (depth - expected).abs() < 1e-6,
+                    "{} at {}: {} != {}",
+                    case["name"],
+                    input,
+                    depth,
+                    expected
+                );
+            }
+            let sampled: Vec<f64> = curve.iter().map(|(input, _)| *input).collect();
+            let expected: Vec<f64> = inputs.iter().map(|v| v.as_f64().unwrap()).collect();
+            assert_eq!(sampled, expected);
+        }
+    }
+
+    #[test]
+    fn curve_points_keep_the_last_duplicate_and_force_endpoints() {
+        let point = |x: f64, y: f64| TransferCurvePoint { x, y };
+        let (xs, ys) = curve_points(&[point(0.7, 0.9), point(0.3, 0.1), point(0.3, 0.2)]);
+        assert_eq!(xs, [0.0, 0.3, 0.7, 1.0]);
+        assert_eq!(ys, [0.0, 0.2, 0.9, 1.0]);
+    }
+
+    #[test]
+    fn rejects_too_few_samples_and_unknown_modes() {
+        let mut geometry = geometry();
+        assert!(tone_curve(&geometry, 1).is_err());
+        geometry.tone_mapping_mode = "filmic".to_string();
+        assert!(matches!(
+            tone_curve(&geometry, 16),
+            Err(CommandError::InvalidGeometry { .. })
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+//! Filament usage and print-time estimates built from what the sidecar
+//! measures on the heightmap.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::models::{ColorStop, Filament, PrintSettings};
+use crate::plan::layers_for_height;
+
+/// PLA, used when no density is given for a filament.
+pub const DEFAULT_DENSITY_G_CM3: f64 = 1.24;
+pub const DEFAULT_FILAMENT_DIAMETER_MM: f64 = 1.75;
+
+/// Optional per-filament material properties used for the estimate.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilamentMaterial {
+    #[serde(alias = "filamentId")]
+    pub filament_id: String,
+    #[serde(default, alias = "densityGCm3")]
+    pub density_g_cm3: Option<f64>,
+    #[serde(default, alias = "pricePerKg")]
+    pub price_per_kg: Option<f64>,
+}
+
+/// Volumes returned by the sidecar's `estimate_usage` method. `volumes_mm3`
+/// covers the relief band of each filament; the solid base is reported
+/// separately.
+#[derive(Debug, Deserialize)]
+pub struct BandVolumes {
+    #[serde(default)]
+    pub volumes_mm3: HashMap<String, f64>,
+    #[serde(default)]
+    pub base_volume_mm3: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilamentUsage {
+    pub filament_id: String,
+    pub name: String,
+    pub volume_mm3: f64,
+    pub grams: f64,
+    pub length_m: f64,
+    pub cost: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub filaments: Vec<FilamentUsage>,
+    pub total_grams: f64,
+    pub total_length_m: f64,
+    /// Only set when every filament that is used has a price.
+    pub total_cost: Option<f64>,
+}
+
+impl UsageReport {
+    /// Converts band volumes into grams, length and cost per filament. The
+    /// base volume is attributed to the filament of the lowest stop.
+    pub fn from_volumes(
+        volumes: &BandVolumes,
+        filaments: &[Filament],
+        stops: &[ColorStop],
+        materials: &[FilamentMaterial],
+    ) -> Self {
+        let bottom = stops
+            .iter()
+            .min_by(|a, b| a.threshold_z_mm.total_cmp(&b.threshold_z_mm))
+            .map(|stop| stop.filament_id.as_str());
+
+        let mut ids: Vec<&str> = Vec::new();
+        for stop in stops {
+            if !ids.contains(&stop.filament_id.as_str()) {
+                ids.push(&stop.filament_id);
+            }
+        }
+        for id in volumes.volumes_mm3.keys() {
+            if !ids.contains(&id.as_str()) {
+                ids.push(id);
+            }
+        }
+
+        let mut report = UsageReport {
+            filaments: Vec::new(),
+            total_grams: 0.0,
+            total_length_m: 0.0,
+            total_cost: Some(0.0),
+        };
+        for id in ids {
+            let mut volume_mm3 = volumes.volumes_mm3.get(id).copied().unwrap_or(0.0);
+            if Some(id) == bottom {
+                volume_mm3 += volumes.base_volume_mm3;
+            }
+            let material = materials.iter().find(|m| m.filament_id == id);
+            let density = material
+                .and_then(|m| m.density_g_cm3)
+                .unwrap_or(DEFAULT_DENSITY_G_CM3);
+            let grams = volume_mm3 / 1000.0 * density;
+            let length_m = volume_mm3 / filament_cross_section_mm2() / 1000.0;
+            let cost = material
+                .and_then(|m| m.price_per_kg)
+                .map(|price| grams / 1000.0 * price);
+
+            report.total_grams += grams;
+            report.total_length_m += length_m;
+            report.total_cost = match (report.total_cost, cost) {
+                (Some(total), Some(cost)) => Some(total + cost),
+                (total, None) if volume_mm3 <= 0.0 => total,
+                _ => None,
+            };
+            report.filaments.push(FilamentUsage {
+                filament_id: id.to_string(),
+                name: filaments
+                    .iter()
+                    .find(|f| f.id == id)
+                    .map(|f| f.name.clone())
+                    .unwrap_or_else(|| id.to_string()),
+                volume_mm3,
+                grams,
+                length_m,
+                cost,
+            });
+        }
+        report
+    }
+}
+
+fn filament_cross_section_mm2() -> f64 {
+    let radius = DEFAULT_FILAMENT_DIAMETER_MM / 2.0;
+    std::f64::consts::PI * radius * radius
+}
+
+/// Layer counts for the model and a rough time to print them. The sidecar
+/// counts the layers it timed, border included, and its estimate is for
+/// nominal speed, scaled here by `speed_factor`.
+#[derive(Debug, Serialize)]
+pub struct PrintTimeEstimate {
+    pub total_layers: i32,
+    pub base_layers: i32,
+    pub relief_layers: i32,
+    pub estimated_minutes: f64,
+    /// Average time per layer, for comparing layer heights.
+    pub minutes_per_layer: f64,
+}
+
+/// Ensures `speed_factor` can scale a print time.
+pub fn validate_speed_factor(speed_factor: f64) -> Result<(), CommandError> {
+    if speed_factor.is_nan() || speed_factor <= 0.0 {
+        return Err(CommandError::invalid_settings(
+            "speed_factor",
+            format!("must be positive, got {}", speed_factor),
+        ));
+    }
+    Ok(())
+}
+
+impl PrintTimeEstimate {
+    pub fn new(
+        print_settings: &PrintSettings,
+        total_layers: i32,
+        nominal_minutes: f64,
+        speed_factor: f64,
+    ) -> Self {
+        let base_layers =
+            layers_for_height(print_settings.base_layer_mm, print_settings.layer_height_mm)
+                .min(total_layers);
+        let estimated_minutes = nominal_minutes / speed_factor;
+        PrintTimeEstimate {
+            total_layers,
+            base_layers,
+            relief_layers: total_layers - base_layers,
+            estimated_minutes,
+            minutes_per_layer: if total_layers > 0 {
+                estimated_minutes / total_layers as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::print_settings;
+
+    fn stop(filament_id: &str, threshold_z_mm: f64) -> ColorStop {
+        ColorStop {
+            filament_id: filament_id.to_string(),
+            threshold_z_mm,
+        }
+    }
+
+    fn volumes(entries: &[(&str, f64)], base_volume_mm3: f64) -> BandVolumes {
+        BandVolumes {
+            volumes_mm3: entries
+                .iter()
+                .map(|(id, volume)| (id.to_string(), *volume))
+                .collect(),
+            base_volume_mm3,
+        }
+    }
+
+    #[test]
+    fn base_volume_goes_to_the_lowest_stop() {
+        let stops = [stop("top", 1.0), stop("bottom", 0.0)];
+        let report = UsageReport::from_volumes(
+            &volumes(&[("top", 1000.0), ("bottom", 1000.0)], 2000.0),
+            &[],
+            &stops,
+            &[],
+        );
+
+        let bottom = report
+            .filaments
+            .iter()
+            .find(|f| f.filament_id == "bottom")
+            .unwrap();
+        assert_eq!(bottom.volume_mm3, 3000.0);
+        assert!((bottom.grams - 3.0 * DEFAULT_DENSITY_G_CM3).abs() < 1e-9);
+        assert!((report.total_grams - 4.0 * DEFAULT_DENSITY_G_CM3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn length_follows_the_filament_cross_section() {
+        let area = filament_cross_section_mm2();
+        let report = UsageReport::from_volumes(
+            &volumes(&[("a", area * 1000.0)], 0.0),
+            &[],
+            &[stop("a", 0.0)],
+            &[],
+        );
+        assert!((report.total_length_m - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cost_uses_density_and_price_and_total_needs_every_price() {
+        let materials = [FilamentMaterial {
+            filament_id: "a".to_string(),
+            density_g_cm3: Some(1.0),
+            price_per_kg: Some(20.0),
+        }];
+        let stops = [stop("a", 0.0), stop("b", 1.0)];
+
+        let priced =
+            UsageReport::from_volumes(&volumes(&[("a", 10_000.0)], 0.0), &[], &stops, &materials);
+        assert_eq!(priced.filaments[0].grams, 10.0);
+        assert!((priced.total_cost.unwrap() - 0.2).abs() < 1e-9);
+
+        let unpriced = UsageReport::from_volumes(
+            &volumes(&[("a", 10_000.0), ("b", 10.0)], 0.0),
+            &[],
+            &stops,
+            &materials,
+        );
+        assert!(unpriced.total_cost.is_none());
+    }
+
+    #[test]
+    fn print_time_counts_base_and_relief_layers() {
+        let estimate = PrintTimeEstimate::new(&print_settings(), 30, 90.0, 1.5);
+        assert_eq!(estimate.base_layers, 2);
+        assert_eq!(estimate.relief_layers, 28);
+        assert_eq!(estimate.total_layers, 30);
+        assert_eq!(estimate.estimated_minutes, 60.0);
+        assert_eq!(estimate.minutes_per_layer, 2.0);
+    }
+
+    #[test]
+    fn speed_factors_must_be_positive() {
+        assert!(validate_speed_factor(0.5).is_ok());
+        for speed_factor in [0.0, -1.0, f64::NAN] {
+            assert!(matches!(
+                validate_speed_factor(speed_factor),
+                Err(CommandError::InvalidSettings { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,586 @@
+//! Checks run on command inputs before anything is sent to the sidecar, so
+//! bad input fails fast with a specific message instead of a Python traceback.
+
+use std::path::Path;
+
+use crate::error::CommandError;
+use crate::models::{
+    CropRect, Filament, MeshFormat, ModelGeometrySettings, PrintSettings, SpikeRemoval,
+};
+
+const SUPPORTED_IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "tif", "tiff", "bmp"];
+
+/// Relative difference between the aspect ratios of a mask and the region
+/// it covers that still counts as a match.
+const MASK_ASPECT_TOLERANCE: f64 = 0.01;
+
+/// Ensures `path` is a readable file with a supported image extension.
+pub fn validate_image_path(path: &str) -> Result<(), CommandError> {
+    let metadata = std::fs::metadata(path).map_err(|e| CommandError::io(path, e))?;
+    if !metadata.is_file() {
+        return Err(CommandError::InvalidImage {
+            path: path.to_string(),
+            reason: "not a file".to_string(),
+        });
+    }
+
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let supported = extension
+        .as_deref()
+        .is_some_and(|ext| SUPPORTED_IMAGE_EXTENSIONS.contains(&ext));
+    if !supported {
+        return Err(CommandError::InvalidImage {
+            path: path.to_string(),
+            reason: format!(
+                "unsupported format, expected one of: {}",
+                SUPPORTED_IMAGE_EXTENSIONS.join(", ")
+            ),
+        });
+    }
+
+    std::fs::File::open(path).map_err(|e| CommandError::io(path, e))?;
+    Ok(())
+}
+
+/// Canonicalizes a hex color to uppercase `#RRGGBB`. The `#` is optional
+/// and 3-digit shorthand is expanded.
+pub fn normalize_hex(hex: &str) -> Result<String, CommandError> {
+    let trimmed = hex.trim();
+    let digits = trimmed.strip_prefix('#').unwrap_or(trimmed);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(CommandError::invalid_settings(
+            "hex_color",
+            format!("{:?} is not a hex color", hex),
+        ));
+    }
+    let expanded: String = match digits.len() {
+        3 => digits.chars().flat_map(|c| [c, c]).collect(),
+        6 => digits.to_string(),
+        _ => {
+            return Err(CommandError::invalid_settings(
+                "hex_color",
+                format!("{:?} is not a #RGB or #RRGGBB color", hex),
+            ))
+        }
+    };
+    Ok(format!("#{}", expanded.to_ascii_uppercase()))
+}
+
+/// Normalizes every filament's `hex_color` in place, failing on the first
+/// one that isn't a valid color.
+pub fn normalize_filament_colors(filaments: &mut [Filament]) -> Result<(), CommandError> {
+    for filament in filaments {
+        filament.hex_color = normalize_hex(&filament.hex_color).map_err(|_| {
+            CommandError::invalid_settings(
+                "hex_color",
+                format!(
+                    "filament {} has color {:?}, expected #RRGGBB",
+                    filament.name, filament.hex_color
+                ),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Ensures `crop` is non-empty and lies inside the image at `path`. Only
+/// the image header is read.
+pub fn validate_crop(path: &str, crop: &CropRect) -> Result<(), CommandError> {
+    if crop.width == 0 || crop.height == 0 {
+        return Err(CommandError::invalid_settings(
+            "crop",
+            "width and height must be greater than 0",
+        ));
+    }
+    let size = image_size(path)?;
+    let right = crop.x as u64 + crop.width as u64;
+    let bottom = crop.y as u64 + crop.height as u64;
+    if right > size.width as u64 || bottom > size.height as u64 {
+        return Err(CommandError::invalid_settings(
+            "crop",
+            format!(
+                "{}x{} at ({}, {}) extends past the {}x{} image",
+                crop.width, crop.height, crop.x, crop.y, size.width, size.height
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Ensures the mask at `mask_path` is a readable image, and returns a
+/// warning if its shape differs from the region it covers: `crop` when
+/// given, otherwise the whole image at `image_path`. The sidecar stretches
+/// the mask over the region either way.
+pub fn validate_mask(
+    image_path: &str,
+    crop: Option<&CropRect>,
+    mask_path: &str,
+) -> Result<Option<String>, CommandError> {
+    validate_image_path(mask_path)?;
+    let (width, height) = match crop {
+        Some(crop) => (crop.width, crop.height),
+        None => {
+            let size = image_size(image_path)?;
+            (size.width as u32, size.height as u32)
+        }
+    };
+    let mask = image_size(mask_path)?;
+    let aspect = width as f64 / height as f64;
+    let mask_aspect = mask.width as f64 / mask.height as f64;
+    if (mask_aspect / aspect - 1.0).abs() <= MASK_ASPECT_TOLERANCE {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "The {}x{} mask has a different shape than the {}x{} image and will be stretched to fit",
+        mask.width, mask.height, width, height
+    )))
+}
+
+fn image_size(path: &str) -> Result<imagesize::ImageSize, CommandError> {
+    imagesize::size(path).map_err(|e| CommandError::InvalidImage {
+        path: path.to_string(),
+        reason: format!("could not read image size: {}", e),
+    })
+}
+
+/// Refuses to write to an existing `path` unless `overwrite` is set.
+pub fn check_overwrite(path: &str, overwrite: bool) -> Result<(), CommandError> {
+    if !overwrite && Path::new(path).exists() {
+        return Err(CommandError::WouldOverwrite {
+            path: path.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Ensures `path` is a readable `.stl` file.
+pub fn validate_stl_path(path: &str) -> Result<(), CommandError> {
+    let metadata = std::fs::metadata(path).map_err(|e| CommandError::io(path, e))?;
+    let is_stl = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("stl"));
+    if !metadata.is_file() || !is_stl {
+        return Err(CommandError::invalid_settings(
+            "stl_path",
+            format!("{} is not an STL file", path),
+        ));
+    }
+    std::fs::File::open(path).map_err(|e| CommandError::io(path, e))?;
+    Ok(())
+}
+
+/// Ensures the output path ends in `.{extension}`, ignoring case.
+pub fn check_output_extension(output_path: &str, extension: &str) -> Result<(), CommandError> {
+    let matches = Path::new(output_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension));
+    if !matches {
+        return Err(CommandError::invalid_settings(
+            "output_path",
+            format!("{} doesn't end in .{}", output_path, extension),
+        ));
+    }
+    Ok(())
+}
+
+/// Warning for an output path whose extension doesn't match `format`. The
+/// file is still written as requested.
+pub fn mesh_extension_warning(output_path: &str, format: MeshFormat) -> Option<String> {
+    let extension = Path::new(output_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    if extension.as_deref() == Some(format.extension()) {
+        return None;
+    }
+    Some(format!(
+        "{} is written as {:?} but doesn't end in .{}",
+        output_path,
+        format,
+        format.extension()
+    ))
+}
+
+impl ModelGeometrySettings {
+    /// Rejects values the sidecar would fail on or silently misuse.
+    pub fn validate(&self) -> Result<(), CommandError> {
+        let fields = [
+            ("min_depth_mm", self.min_depth_mm),
+            ("max_depth_mm", self.max_depth_mm),
+            ("gamma", self.gamma),
+            ("contrast", self.contrast),
+            ("offset", self.offset),
+            ("smoothing", self.smoothing),
+        ];
+        for (field, value) in fields {
+            if !value.is_finite() {
+                return Err(CommandError::invalid_geometry(field, "must be a number"));
+            }
+        }
+        if self.min_depth_mm < 0.0 {
+            return Err(CommandError::invalid_geometry(
+                "min_depth_mm",
+                format!("must not be negative, got {} mm", self.min_depth_mm),
+            ));
+        }
+        if self.min_depth_mm > self.max_depth_mm {
+            return Err(CommandError::invalid_geometry(
+                "min_depth_mm",
+                format!(
+                    "{} mm is greater than max_depth_mm ({} mm)",
+                    self.min_depth_mm, self.max_depth_mm
+                ),
+            ));
+        }
+        if self.gamma <= 0.0 {
+            return Err(CommandError::invalid_geometry(
+                "gamma",
+                format!("must be greater than 0, got {}", self.gamma),
+            ));
+        }
+        if self.contrast <= 0.0 {
+            return Err(CommandError::invalid_geometry(
+                "contrast",
+                format!("must be greater than 0, got {}", self.contrast),
+            ));
+        }
+        if self.smoothing < 0.0 {
+            return Err(CommandError::invalid_geometry(
+                "smoothing",
+                format!("must not be negative, got {}", self.smoothing),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl PrintSettings {
+    /// Rejects dimensions no printer or mesh can use.
+    pub fn validate(&self) -> Result<(), CommandError> {
+        let positive = [
+            ("layer_height_mm", self.layer_height_mm),
+            ("width_mm", self.width_mm),
+            ("height_mm", self.height_mm),
+        ];
+        for (field, value) in positive {
+            if value.is_nan() || value <= 0.0 {
+                return Err(CommandError::invalid_settings(
+                    field,
+                    format!("must be greater than 0, got {} mm", value),
+                ));
+            }
+        }
+        let non_negative = [
+            ("base_layer_mm", self.base_layer_mm),
+            ("border_width_mm", self.border_width_mm),
+            ("border_depth_mm", self.border_depth_mm),
+        ];
+        for (field, value) in non_negative {
+            if value.is_nan() || value < 0.0 {
+                return Err(CommandError::invalid_settings(
+                    field,
+                    format!("must not be negative, got {} mm", value),
+                ));
+            }
+        }
+        if self.layer_height_mm > self.height_mm {
+            return Err(CommandError::invalid_settings(
+                "layer_height_mm",
+                format!(
+                    "{} mm is larger than the model height ({} mm)",
+                    self.layer_height_mm, self.height_mm
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites `geometry.spike_removal` in its canonical form, so every
+/// command sends the sidecar the same spelling and caches match.
+pub fn canonicalize_spike_removal(
+    geometry: &mut ModelGeometrySettings,
+) -> Result<(), CommandError> {
+    let level = SpikeRemoval::parse(&geometry.spike_removal)
+        .map_err(|reason| CommandError::invalid_settings("spike_removal", reason))?;
+    geometry.spike_removal = level.as_wire();
+    Ok(())
+}
+
+/// Ensures a hollow shell is no thicker than the thinnest part of the
+/// relief, the base plus `min_depth_mm`.
+pub fn validate_shell_thickness(
+    geometry: &ModelGeometrySettings,
+    print: &PrintSettings,
+) -> Result<(), CommandError> {
+    let Some(shell) = print.shell_thickness_mm else {
+        return Ok(());
+    };
+    let thinnest = print.base_layer_mm + geometry.min_depth_mm;
+    if shell.is_nan() || shell <= 0.0 {
+        return Err(CommandError::invalid_settings(
+            "shell_thickness_mm",
+            "must be greater than 0",
+        ));
+    }
+    if shell > thinnest + 1e-9 {
+        return Err(CommandError::invalid_settings(
+            "shell_thickness_mm",
+            format!(
+                "{} mm is thicker than the thinnest wall of the relief ({} mm)",
+                shell, thinnest
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{geometry, print_settings, temp_dir};
+
+    fn write_file(dir: &Path, name: &str, contents: &[u8]) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn accepts_supported_extensions_case_insensitively() {
+        let dir = temp_dir("image-extensions");
+        for name in ["a.png", "b.JPG", "c.jpeg", "d.tiff", "e.Tif", "f.bmp"] {
+            let path = write_file(&dir, name, b"not really an image");
+            assert!(validate_image_path(&path).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_extension() {
+        let path = write_file(&temp_dir("gif-image"), "photo.gif", b"GIF89a");
+        let err = validate_image_path(&path).unwrap_err();
+        assert!(matches!(err, CommandError::InvalidImage { .. }));
+    }
+
+    #[test]
+    fn reports_missing_file_as_io_error_with_path() {
+        let err = validate_image_path("/definitely/not/here.png").unwrap_err();
+        match err {
+            CommandError::Io { path, .. } => {
+                assert_eq!(path.as_deref(), Some("/definitely/not/here.png"))
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn normalizes_hex_colors() {
+        assert_eq!(normalize_hex("#fff").unwrap(), "#FFFFFF");
+        assert_eq!(normalize_hex("1a2b3c").unwrap(), "#1A2B3C");
+        assert_eq!(normalize_hex(" #A0b").unwrap(), "#AA00BB");
+        for bad in ["rgb(255, 255, 255)", "#ffff", "", "#ggg"] {
+            assert!(normalize_hex(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn names_the_filament_with_a_bad_color() {
+        let filament = |name: &str, hex_color: &str| Filament {
+            id: name.to_lowercase(),
+            name: name.to_string(),
+            hex_color: hex_color.to_string(),
+            td: 1.0,
+            enabled: true,
+            order_index: 0,
+        };
+        let mut filaments = vec![filament("White", "fff"), filament("Red", "rgb(255,0,0)")];
+
+        let err = normalize_filament_colors(&mut filaments).unwrap_err();
+        assert!(err.to_string().contains("filament Red"));
+        assert_eq!(filaments[0].hex_color, "#FFFFFF");
+    }
+
+    /// Just enough of a PNG for its size to be read.
+    fn png_header(dir: &Path, name: &str, width: u32, height: u32) -> String {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_file(dir, name, &bytes)
+    }
+
+    #[test]
+    fn crop_must_fit_inside_the_image() {
+        let path = png_header(&temp_dir("crop"), "crop.png", 200, 100);
+        let crop = |x, y, width, height| CropRect {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        assert!(validate_crop(&path, &crop(0, 0, 200, 100)).is_ok());
+        assert!(validate_crop(&path, &crop(50, 20, 100, 80)).is_ok());
+        for bad in [crop(150, 0, 51, 10), crop(0, 99, 10, 2), crop(10, 10, 0, 5)] {
+            assert!(matches!(
+                validate_crop(&path, &bad),
+                Err(CommandError::InvalidSettings { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn masks_of_another_shape_are_warned_about() {
+        let dir = temp_dir("mask-shape");
+        let image = png_header(&dir, "photo.png", 200, 100);
+        let wide = png_header(&dir, "wide-mask.png", 400, 200);
+        let square = png_header(&dir, "square-mask.png", 100, 100);
+
+        assert!(validate_mask(&image, None, &wide).unwrap().is_none());
+        let warning = validate_mask(&image, None, &square).unwrap().unwrap();
+        assert!(warning.contains("100x100 mask"));
+        let crop = CropRect {
+            x: 0,
+            y: 0,
+            width: 50,
+            height: 50,
+        };
+        assert!(validate_mask(&image, Some(&crop), &square)
+            .unwrap()
+            .is_none());
+        assert!(validate_mask(&image, None, "/definitely/not/here.png").is_err());
+    }
+
+    #[test]
+    fn existing_outputs_need_overwrite() {
+        let existing = write_file(&temp_dir("overwrite"), "existing.stl", b"solid");
+        assert!(matches!(
+            check_overwrite(&existing, false),
+            Err(CommandError::WouldOverwrite { .. })
+        ));
+        assert!(check_overwrite(&existing, true).is_ok());
+        assert!(check_overwrite("/definitely/not/here.stl", false).is_ok());
+    }
+
+    #[test]
+    fn stl_path_must_be_an_stl_file() {
+        let dir = temp_dir("stl-path");
+        assert!(validate_stl_path(&write_file(&dir, "model.STL", b"solid")).is_ok());
+        assert!(matches!(
+            validate_stl_path(&write_file(&dir, "model.obj", b"o model")),
+            Err(CommandError::InvalidSettings { .. })
+        ));
+        assert!(matches!(
+            validate_stl_path("/definitely/not/here.stl"),
+            Err(CommandError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn warns_when_mesh_extension_does_not_match_format() {
+        assert!(mesh_extension_warning("out.STL", MeshFormat::StlAscii).is_none());
+        assert!(mesh_extension_warning("out.3mf", MeshFormat::ThreeMF).is_none());
+        assert!(mesh_extension_warning("out.stl", MeshFormat::Obj).is_some());
+        assert!(mesh_extension_warning("out", MeshFormat::StlBinary).is_some());
+    }
+
+    #[test]
+    fn output_must_have_the_expected_extension() {
+        assert!(check_output_extension("depth.PNG", "png").is_ok());
+        assert!(matches!(
+            check_output_extension("depth.tif", "png"),
+            Err(CommandError::InvalidSettings { .. })
+        ));
+        assert!(check_output_extension("png", "png").is_err());
+    }
+
+    #[test]
+    fn shell_must_fit_inside_the_thinnest_wall() {
+        let geometry = geometry();
+        let print = |shell: Option<f64>| PrintSettings {
+            shell_thickness_mm: shell,
+            ..print_settings()
+        };
+
+        assert!(validate_shell_thickness(&geometry, &print(None)).is_ok());
+        assert!(validate_shell_thickness(&geometry, &print(Some(0.64))).is_ok());
+        for shell in [0.8, 0.0, -1.0] {
+            assert!(matches!(
+                validate_shell_thickness(&geometry, &print(Some(shell))),
+                Err(CommandError::InvalidSettings { .. })
+            ));
+        }
+    }
+
+    fn invalid_field(result: Result<(), CommandError>) -> String {
+        match result {
+            Err(CommandError::InvalidGeometry { field, .. })
+            | Err(CommandError::InvalidSettings { field, .. }) => field,
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn geometry_names_the_offending_field() {
+        let invalid = |change: fn(&mut ModelGeometrySettings)| {
+            let mut geometry = geometry();
+            change(&mut geometry);
+            invalid_field(geometry.validate())
+        };
+        assert!(geometry().validate().is_ok());
+        assert_eq!(invalid(|g| g.smoothing = -1.0), "smoothing");
+        assert_eq!(invalid(|g| g.gamma = 0.0), "gamma");
+        assert_eq!(invalid(|g| g.min_depth_mm = 3.0), "min_depth_mm");
+        assert_eq!(invalid(|g| g.contrast = f64::NAN), "contrast");
+        assert_eq!(invalid(|g| g.max_depth_mm = f64::INFINITY), "max_depth_mm");
+    }
+
+    #[test]
+    fn print_settings_need_positive_sizes_and_non_negative_borders() {
+        let invalid = |change: fn(&mut PrintSettings)| {
+            let mut print = print_settings();
+            change(&mut print);
+            invalid_field(print.validate())
+        };
+        assert!(print_settings().validate().is_ok());
+        assert_eq!(invalid(|p| p.layer_height_mm = 0.0), "layer_height_mm");
+        assert_eq!(invalid(|p| p.border_width_mm = -2.0), "border_width_mm");
+        assert_eq!(invalid(|p| p.width_mm = f64::NAN), "width_mm");
+        assert_eq!(invalid(|p| p.layer_height_mm = 90.0), "layer_height_mm");
+    }
+
+    #[test]
+    fn canonicalizes_accepted_spike_removal_values() {
+        let accepted = [
+            ("none", "none"),
+            ("", "none"),
+            ("0", "none"),
+            ("Light", "light"),
+            ("3", "light"),
+            ("medium", "medium"),
+            ("strong", "strong"),
+            (" Aggressive ", "strong"),
+            ("7", "strong"),
+            ("9", "9"),
+            ("15", "15"),
+        ];
+        let mut geometry = geometry();
+        for (value, canonical) in accepted {
+            geometry.spike_removal = value.to_string();
+            canonicalize_spike_removal(&mut geometry).unwrap();
+            assert_eq!(geometry.spike_removal, canonical, "{:?}", value);
+        }
+        for bad in ["extreme", "4", "17", "-3", "2.5"] {
+            geometry.spike_removal = bad.to_string();
+            assert!(matches!(
+                canonicalize_spike_removal(&mut geometry),
+                Err(CommandError::InvalidSettings { .. })
+            ));
+        }
+    }
+}